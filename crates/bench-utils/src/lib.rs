@@ -1,6 +1,7 @@
 pub mod draw;
 pub mod json;
 pub mod sheet;
+pub mod workload;
 
 use arbitrary::{Arbitrary, Unstructured};
 use enum_as_inner::EnumAsInner;
@@ -13,7 +14,7 @@ use std::{
 use flate2::read::GzDecoder;
 use serde_json::Value;
 
-#[derive(Arbitrary)]
+#[derive(Arbitrary, Debug, Clone, PartialEq, Eq)]
 pub struct TextAction {
     pub pos: usize,
     pub ins: String,
@@ -24,6 +25,11 @@ pub trait ActionTrait: Clone + std::fmt::Debug {
     fn normalize(&mut self);
 }
 
+impl ActionTrait for TextAction {
+    /// The positions depend on the length of the text, so they're checked when applied
+    fn normalize(&mut self) {}
+}
+
 pub fn get_automerge_actions() -> Vec<TextAction> {
     const RAW_DATA: &[u8; 901823] =
         include_bytes!("../../loro-internal/benches/automerge-paper.json.gz");
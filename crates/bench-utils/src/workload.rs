@@ -0,0 +1,141 @@
+//! Synthetic text editing traces.
+//!
+//! The automerge paper trace is a single author typing an essay. [gen_text_workload]
+//! generates traces with the shape of other apps, e.g. many peers typing concurrently
+//! or pasting a lot, so that the benchmarks can be run on a workload resembling a given
+//! app. The traces only depend on the [WorkloadConfig], so they're reproducible.
+use std::ops::Range;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{Action, TextAction};
+
+/// The parameters of a synthetic trace. The probabilities must be in `0.0..=1.0`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkloadConfig {
+    pub seed: u64,
+    /// The number of text edits in the trace, excluding the syncs
+    pub action_num: usize,
+    /// The number of peers editing concurrently
+    pub peer_num: usize,
+    /// The keystrokes per second of each peer
+    pub typing_speed: f64,
+    /// The time between two syncs of all the peers, in milliseconds.
+    ///
+    /// With `typing_speed` it decides how many edits a peer makes before it sees the edits
+    /// of the others.
+    pub sync_interval_ms: u64,
+    /// The probability that an edit is a paste
+    pub paste_frequency: f64,
+    /// The length of a pasted string
+    pub paste_len: Range<usize>,
+    /// The probability that an edit is a deletion. Most deletions are backspaces, and
+    /// the rest delete a selection of up to `paste_len.end` chars
+    pub delete_ratio: f64,
+    /// The probability that a peer moves its caret to a random position before an edit
+    pub jump_frequency: f64,
+}
+
+impl Default for WorkloadConfig {
+    /// A few peers typing a document together in realtime
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            action_num: 10_000,
+            peer_num: 3,
+            typing_speed: 5.0,
+            sync_interval_ms: 200,
+            paste_frequency: 0.01,
+            paste_len: 10..200,
+            delete_ratio: 0.15,
+            jump_frequency: 0.02,
+        }
+    }
+}
+
+impl WorkloadConfig {
+    /// The number of edits a peer makes between two syncs
+    pub fn actions_per_sync(&self) -> usize {
+        let n = self.typing_speed * self.sync_interval_ms as f64 / 1000.0;
+        (n.round() as usize).max(1)
+    }
+}
+
+const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz     \n";
+
+/// Generate a text editing trace with the shape given by `config`.
+///
+/// The positions of a peer's edits are valid in its own doc, if the peers apply the
+/// edits in order and all import each other's updates at [Action::SyncAll].
+pub fn gen_text_workload(config: &WorkloadConfig) -> Vec<Action<TextAction>> {
+    assert!(config.peer_num > 0);
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut ans = Vec::with_capacity(config.action_num * 11 / 10);
+    // The length of the text at the last sync, and the change of each peer since then.
+    // Concurrent deletions of the same chars are counted twice, so the length can only be
+    // underestimated, and the positions stay valid.
+    let mut synced_len = 0;
+    let mut len_diff = vec![0isize; config.peer_num];
+    let mut carets = vec![0; config.peer_num];
+    let round_len = config.actions_per_sync() * config.peer_num;
+    for i in 0..config.action_num {
+        if i > 0 && i % round_len == 0 {
+            ans.push(Action::SyncAll);
+            synced_len = (synced_len as isize + len_diff.iter().sum::<isize>()).max(0) as usize;
+            len_diff.iter_mut().for_each(|x| *x = 0);
+            // Remote edits move the carets, but they're rarely far off
+            carets.iter_mut().for_each(|x| *x = (*x).min(synced_len));
+        }
+
+        let peer = rng.gen_range(0..config.peer_num);
+        let len = (synced_len as isize + len_diff[peer]).max(0) as usize;
+        let caret = &mut carets[peer];
+        if rng.gen_bool(config.jump_frequency) {
+            *caret = rng.gen_range(0..=len);
+        }
+
+        let action = if len > 0 && rng.gen_bool(config.delete_ratio) {
+            if *caret > 0 && rng.gen_bool(0.9) {
+                *caret -= 1;
+                TextAction {
+                    pos: *caret,
+                    ins: String::new(),
+                    del: 1,
+                }
+            } else {
+                *caret = (*caret).min(len - 1);
+                let del = rng
+                    .gen_range(1..=config.paste_len.end.max(1))
+                    .min(len - *caret);
+                TextAction {
+                    pos: *caret,
+                    ins: String::new(),
+                    del,
+                }
+            }
+        } else {
+            let paste = rng.gen_bool(config.paste_frequency) && !config.paste_len.is_empty();
+            let ins_len = if paste {
+                rng.gen_range(config.paste_len.clone())
+            } else {
+                1
+            };
+            let ins: String = (0..ins_len)
+                .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+                .collect();
+            let action = TextAction {
+                pos: *caret,
+                ins,
+                del: 0,
+            };
+            *caret += ins_len;
+            action
+        };
+
+        len_diff[peer] += action.ins.len() as isize - action.del as isize;
+        ans.push(Action::Action { peer, action });
+    }
+
+    ans.push(Action::SyncAll);
+    ans
+}
@@ -0,0 +1,38 @@
+use bench_utils::workload::WorkloadConfig;
+use criterion::{criterion_group, criterion_main, Criterion};
+use examples::text::run_text_workload;
+
+fn bench_text_workload(c: &mut Criterion) {
+    let mut b = c.benchmark_group("synthetic text workload");
+    b.sample_size(10);
+    let workloads = [
+        ("realtime 3 peers", WorkloadConfig::default()),
+        (
+            "offline 10 peers",
+            WorkloadConfig {
+                peer_num: 10,
+                sync_interval_ms: 60_000,
+                ..Default::default()
+            },
+        ),
+        (
+            "paste heavy",
+            WorkloadConfig {
+                paste_frequency: 0.2,
+                delete_ratio: 0.3,
+                ..Default::default()
+            },
+        ),
+    ];
+    for (name, config) in workloads {
+        b.bench_function(name, |b| {
+            b.iter(|| {
+                let (actors, _) = run_text_workload(&config);
+                actors.docs[0].doc.export_snapshot()
+            });
+        });
+    }
+}
+
+criterion_group!(benches, bench_text_workload);
+criterion_main!(benches);
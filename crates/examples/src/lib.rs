@@ -12,6 +12,7 @@ pub mod draw;
 pub mod json;
 pub mod list;
 pub mod sheet;
+pub mod text;
 pub mod test_preload {
     pub use bench_utils::json::JsonAction::*;
     pub use bench_utils::json::LoroValue::*;
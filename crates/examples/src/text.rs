@@ -0,0 +1,57 @@
+use std::time::Instant;
+
+use bench_utils::{
+    workload::{gen_text_workload, WorkloadConfig},
+    TextAction,
+};
+use loro::{LoroDoc, LoroText};
+
+use crate::{ActorGroup, ActorTrait};
+
+pub struct TextActor {
+    pub doc: LoroDoc,
+    text: LoroText,
+}
+
+impl ActorTrait for TextActor {
+    type ActionKind = TextAction;
+
+    fn create(peer_id: u64) -> Self {
+        let doc = LoroDoc::new();
+        doc.set_peer_id(peer_id).unwrap();
+        let text = doc.get_text("text");
+        Self { doc, text }
+    }
+
+    fn apply_action(&mut self, action: &mut Self::ActionKind) {
+        let len = self.text.len_unicode();
+        let pos = action.pos.min(len);
+        let del = action.del.min(len - pos);
+        if del > 0 {
+            self.text.delete(pos, del).unwrap();
+        }
+        if !action.ins.is_empty() {
+            self.text.insert(pos, &action.ins).unwrap();
+        }
+        self.doc.commit();
+    }
+
+    fn doc(&self) -> &LoroDoc {
+        &self.doc
+    }
+}
+
+/// Replay the synthetic trace given by `config` on a group of peers.
+///
+/// It returns the peers and the time when the replay started, so the caller can measure
+/// the replay without the generation of the trace.
+pub fn run_text_workload(config: &WorkloadConfig) -> (ActorGroup<TextActor>, Instant) {
+    let mut actions = gen_text_workload(config);
+    let mut actors = ActorGroup::<TextActor>::new(config.peer_num);
+    let start = Instant::now();
+    for action in actions.iter_mut() {
+        actors.apply_action(action);
+    }
+
+    (actors, start)
+}
@@ -0,0 +1,26 @@
+use bench_utils::{
+    workload::{gen_text_workload, WorkloadConfig},
+    Action,
+};
+use examples::text::run_text_workload;
+
+#[test]
+fn text_workload_converges() {
+    let config = WorkloadConfig {
+        action_num: 2000,
+        peer_num: 4,
+        paste_frequency: 0.1,
+        delete_ratio: 0.4,
+        jump_frequency: 0.2,
+        ..Default::default()
+    };
+    let actions = gen_text_workload(&config);
+    assert_eq!(actions, gen_text_workload(&config));
+    let edits = actions
+        .iter()
+        .filter(|a| matches!(a, Action::Action { .. }))
+        .count();
+    assert_eq!(edits, config.action_num);
+    let (actors, _) = run_text_workload(&config);
+    actors.check_sync();
+}
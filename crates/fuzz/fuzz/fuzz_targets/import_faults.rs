@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use fuzz::fault::{sample_blobs, test_faults, Fault};
+
+fuzz_target!(|input: (u8, Vec<Fault>, bool)| {
+    let (blob_index, faults, resign) = input;
+    let blobs = sample_blobs();
+    let blob = &blobs[blob_index as usize % blobs.len()];
+    test_faults(blob, &faults, resign);
+});
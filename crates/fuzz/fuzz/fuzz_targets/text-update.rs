@@ -7,9 +7,9 @@ fuzz_target!(|data: [&str; 3]| {
     let (old, new, new1) = (data[0], data[1], data[2]);
     let doc = LoroDoc::new();
     let text = doc.get_text("text");
-    text.update(old);
-    text.update(new);
+    text.update(old).unwrap();
+    text.update(new).unwrap();
     assert_eq!(&text.to_string(), new);
-    text.update(new1);
+    text.update(new1).unwrap();
     assert_eq!(&text.to_string(), new1);
 });
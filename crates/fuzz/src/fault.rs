@@ -0,0 +1,163 @@
+//! Fault injection for the binary encoding.
+//!
+//! A [Fault] corrupts a valid blob exported by [sample_blobs]. Importing the corrupted
+//! blob must never panic:
+//!
+//! - If the checksum is left as is, the import must fail, because the checksum covers
+//!   everything after it.
+//! - If the checksum is recomputed, the corruption reaches the decoder. The import may
+//!   succeed, since the blob can still be valid, but then the doc must be consistent:
+//!   its own snapshot must import to the same value.
+//!
+//! The seed corpus of the `import_faults` fuzz target is in `fuzz/corpus/import_faults`. It's
+//! replayed by the tests, so its faults stay covered without running the fuzzer.
+use arbitrary::Arbitrary;
+use loro::{LoroDoc, LoroResult, LoroValue, ToJson};
+
+/// The magic bytes and the checksum
+const CHECKSUM_END: usize = 20;
+
+#[derive(Arbitrary, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Fault {
+    FlipBit {
+        pos: usize,
+        bit: u8,
+    },
+    SetByte {
+        pos: usize,
+        value: u8,
+    },
+    Truncate {
+        len: usize,
+    },
+    /// Remove the byte at `pos`
+    Remove {
+        pos: usize,
+    },
+    /// Duplicate the byte at `pos`
+    Duplicate {
+        pos: usize,
+    },
+}
+
+impl Fault {
+    /// Apply the fault. The positions wrap around the length of the blob.
+    pub fn apply(&self, blob: &mut Vec<u8>) {
+        if blob.is_empty() {
+            return;
+        }
+
+        let len = blob.len();
+        match *self {
+            Fault::FlipBit { pos, bit } => blob[pos % len] ^= 1 << (bit % 8),
+            Fault::SetByte { pos, value } => blob[pos % len] = value,
+            Fault::Truncate { len: new_len } => blob.truncate(new_len % len),
+            Fault::Remove { pos } => {
+                blob.remove(pos % len);
+            }
+            Fault::Duplicate { pos } => {
+                let byte = blob[pos % len];
+                blob.insert(pos % len, byte);
+            }
+        }
+    }
+}
+
+/// Recompute the checksum of a blob whose body has been changed.
+pub fn fix_checksum(blob: &mut [u8]) {
+    if blob.len() < CHECKSUM_END {
+        return;
+    }
+
+    let checksum = md5::compute(&blob[CHECKSUM_END..]).0;
+    blob[4..CHECKSUM_END].copy_from_slice(&checksum);
+}
+
+/// Apply `faults` to `blob` and check that importing the result is handled gracefully.
+///
+/// It panics if the import panics, if it succeeds with a wrong checksum, or if it
+/// succeeds with a doc that can't round trip its own snapshot.
+pub fn test_faults(blob: &[u8], faults: &[Fault], resign: bool) {
+    let mut corrupted = blob.to_vec();
+    for fault in faults {
+        fault.apply(&mut corrupted);
+    }
+
+    if resign {
+        fix_checksum(&mut corrupted);
+    }
+
+    if corrupted == blob {
+        return;
+    }
+
+    let doc = LoroDoc::new();
+    let result = doc.import(&corrupted);
+    if !resign {
+        assert!(
+            result.is_err(),
+            "a corrupted blob without a valid checksum was imported, faults: {:?}",
+            faults
+        );
+        return;
+    }
+
+    if result.is_ok() {
+        let value = doc.get_deep_value();
+        let new_doc = LoroDoc::new();
+        new_doc.import(&doc.export_snapshot()).unwrap();
+        assert_eq!(
+            new_doc.get_deep_value().to_json_value(),
+            value.to_json_value(),
+            "the imported doc is inconsistent, faults: {:?}",
+            faults
+        );
+    }
+}
+
+/// Valid blobs that cover every container type, as snapshots and as updates.
+///
+/// They only depend on the peer ids and the edits, so they're the same on every run.
+pub fn sample_blobs() -> Vec<Vec<u8>> {
+    let doc = sample_doc().unwrap();
+    let mut blobs = vec![doc.export_snapshot(), doc.export_from(&Default::default())];
+    let v1 = doc.oplog_vv();
+    doc.get_text("text").delete(0, 2).unwrap();
+    doc.get_map("map").insert("key", LoroValue::Null).unwrap();
+    doc.commit();
+    blobs.push(doc.export_from(&v1));
+    blobs
+}
+
+fn sample_doc() -> LoroResult<LoroDoc> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    let text = doc.get_text("text");
+    text.insert(0, "Hello world")?;
+    text.mark(0..5, "bold", true)?;
+    let map = doc.get_map("map");
+    map.insert("key", "value")?;
+    map.insert("number", 1.5)?;
+    let list = doc.get_list("list");
+    list.push(1)?;
+    list.push("two")?;
+    let movable_list = doc.get_movable_list("movable_list");
+    movable_list.push(1)?;
+    movable_list.push(2)?;
+    movable_list.mov(0, 1)?;
+    let tree = doc.get_tree("tree");
+    let root = tree.create(None)?;
+    tree.create(root)?;
+    doc.get_counter("counter").increment(2.)?;
+    doc.commit();
+
+    // Concurrent edits from another peer, so that the blobs have more than one peer
+    let other = LoroDoc::new();
+    other.set_peer_id(2)?;
+    other.import(&doc.export_snapshot())?;
+    other.get_text("text").insert(5, ",")?;
+    other.get_list("list").delete(0, 1)?;
+    other.commit();
+    doc.import(&other.export_from(&doc.oplog_vv()))?;
+    Ok(doc)
+}
@@ -2,6 +2,7 @@ pub mod actions;
 pub mod actor;
 pub mod container;
 pub mod crdt_fuzzer;
+pub mod fault;
 mod macros;
 mod value;
 pub use crdt_fuzzer::{test_multi_sites, Action, FuzzTarget};
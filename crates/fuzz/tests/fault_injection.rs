@@ -0,0 +1,73 @@
+use arbtest::arbitrary::{self, Arbitrary, Unstructured};
+use fuzz::fault::{sample_blobs, test_faults, Fault};
+
+#[test]
+fn every_truncation_is_rejected() {
+    for blob in sample_blobs() {
+        for len in 0..blob.len() {
+            test_faults(&blob, &[Fault::Truncate { len }], false);
+        }
+    }
+}
+
+#[test]
+fn every_bit_flip_is_rejected() {
+    for blob in sample_blobs() {
+        for pos in 0..blob.len() {
+            for bit in 0..8 {
+                test_faults(&blob, &[Fault::FlipBit { pos, bit }], false);
+            }
+        }
+    }
+}
+
+#[test]
+fn every_bit_flip_with_valid_checksum() {
+    for blob in sample_blobs() {
+        for pos in 0..blob.len() {
+            for bit in 0..8 {
+                test_faults(&blob, &[Fault::FlipBit { pos, bit }], true);
+            }
+        }
+    }
+}
+
+#[test]
+fn every_truncation_with_valid_checksum() {
+    for blob in sample_blobs() {
+        for len in 0..blob.len() {
+            test_faults(&blob, &[Fault::Truncate { len }], true);
+        }
+    }
+}
+
+fn prop(u: &mut Unstructured<'_>, blobs: &[Vec<u8>]) -> arbitrary::Result<()> {
+    let blob = u.choose(blobs)?;
+    let faults = u.arbitrary::<Vec<Fault>>()?;
+    let resign = u.arbitrary::<bool>()?;
+    test_faults(blob, &faults, resign);
+    Ok(())
+}
+
+#[test]
+fn random_faults() {
+    let blobs = sample_blobs();
+    arbtest::builder().budget_ms(1000).run(|u| prop(u, &blobs))
+}
+
+/// The inputs of the `import_faults` fuzz target in `fuzz/corpus/import_faults`
+#[test]
+fn seed_corpus() {
+    let blobs = sample_blobs();
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fuzz/corpus/import_faults");
+    let mut count = 0;
+    for entry in std::fs::read_dir(dir).unwrap() {
+        let bytes = std::fs::read(entry.unwrap().path()).unwrap();
+        let (blob_index, faults, resign) =
+            <(u8, Vec<Fault>, bool)>::arbitrary_take_rest(Unstructured::new(&bytes)).unwrap();
+        test_faults(&blobs[blob_index as usize % blobs.len()], &faults, resign);
+        count += 1;
+    }
+
+    assert!(count > 0);
+}
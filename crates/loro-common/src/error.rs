@@ -74,6 +74,17 @@ pub enum LoroError {
     UTF16InUnicodeCodePoint { pos: usize },
     #[error("The end index cannot be less than the start index")]
     EndIndexLessThanStartIndex { start: usize, end: usize },
+    #[error("Importing the data needs at least {needed} bytes of memory, which exceeds the budget of {budget} bytes")]
+    BudgetExceeded { needed: usize, budget: usize },
+    /// `unit` is what the limit counts, i.e. "ops", "bytes" or "containers"
+    #[error("The peer {peer} has reached the limit of {limit} {unit} per peer of the doc")]
+    OpQuotaExceeded {
+        peer: PeerID,
+        limit: usize,
+        unit: &'static str,
+    },
+    #[error("The op is rejected by the pre-commit hook: {0}")]
+    OpRejected(Box<str>),
 }
 
 #[derive(Error, Debug)]
@@ -88,6 +99,136 @@ pub enum LoroTreeError {
     IndexOutOfBound { len: usize, index: usize },
 }
 
+/// The category of a [LoroError], see [LoroError::category].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCategory {
+    /// The input data cannot be decoded
+    Decode,
+    /// The arguments of the call are invalid
+    InvalidArgument,
+    /// The target cannot be found in the doc
+    NotFound,
+    /// The call is not allowed in the current state of the doc or the container
+    InvalidState,
+    /// The movable tree rejects the operation
+    Tree,
+    /// Errors from the host environment or unexpected internal errors
+    Internal,
+}
+
+impl ErrorCategory {
+    /// The name of the category used in logs and bindings.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCategory::Decode => "decode",
+            ErrorCategory::InvalidArgument => "invalid_argument",
+            ErrorCategory::NotFound => "not_found",
+            ErrorCategory::InvalidState => "invalid_state",
+            ErrorCategory::Tree => "tree",
+            ErrorCategory::Internal => "internal",
+        }
+    }
+}
+
+impl LoroError {
+    /// A numeric code of the error that is stable across versions and FFI boundaries.
+    ///
+    /// # Stability
+    ///
+    /// - The code of an existing variant never changes, even if the variant is renamed.
+    /// - The code of a removed variant is never reused.
+    /// - New variants get new codes. Tree errors use the `100..200` range, see
+    ///   [LoroTreeError::code].
+    pub fn code(&self) -> u16 {
+        match self {
+            LoroError::UnmatchedContext { .. } => 1,
+            LoroError::DecodeVersionVectorError => 2,
+            LoroError::DecodeError(_) => 3,
+            LoroError::DecodeDataCorruptionError => 4,
+            LoroError::DecodeChecksumMismatchError => 5,
+            LoroError::IncompatibleFutureEncodingError(_) => 6,
+            LoroError::JsError(_) => 7,
+            LoroError::LockError => 8,
+            LoroError::DuplicatedTransactionError => 9,
+            LoroError::NotFoundError(_) => 10,
+            LoroError::TransactionError(_) => 11,
+            LoroError::OutOfBound { .. } => 12,
+            LoroError::UsedOpID { .. } => 13,
+            LoroError::TreeError(e) => e.code(),
+            LoroError::ArgErr(_) => 14,
+            LoroError::AutoCommitNotStarted => 15,
+            LoroError::StyleConfigMissing(_) => 16,
+            LoroError::Unknown(_) => 17,
+            LoroError::FrontiersNotFound(_) => 18,
+            LoroError::ImportWhenInTxn => 19,
+            LoroError::MisuseDetachedContainer { .. } => 20,
+            LoroError::NotImplemented(_) => 21,
+            LoroError::ReattachAttachedContainer => 22,
+            LoroError::EditWhenDetached => 23,
+            LoroError::UndoInvalidIdSpan(_) => 24,
+            LoroError::UndoWithDifferentPeerId { .. } => 25,
+            LoroError::InvalidJsonSchema => 26,
+            LoroError::UTF8InUnicodeCodePoint { .. } => 27,
+            LoroError::UTF16InUnicodeCodePoint { .. } => 28,
+            LoroError::EndIndexLessThanStartIndex { .. } => 29,
+            LoroError::BudgetExceeded { .. } => 30,
+            LoroError::OpQuotaExceeded { .. } => 31,
+            LoroError::OpRejected(_) => 32,
+        }
+    }
+
+    /// The category of the error. Like [LoroError::code], it's stable across versions.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            LoroError::DecodeVersionVectorError
+            | LoroError::DecodeError(_)
+            | LoroError::DecodeDataCorruptionError
+            | LoroError::DecodeChecksumMismatchError
+            | LoroError::IncompatibleFutureEncodingError(_)
+            | LoroError::InvalidJsonSchema
+            | LoroError::BudgetExceeded { .. } => ErrorCategory::Decode,
+            LoroError::OutOfBound { .. }
+            | LoroError::ArgErr(_)
+            | LoroError::UTF8InUnicodeCodePoint { .. }
+            | LoroError::UTF16InUnicodeCodePoint { .. }
+            | LoroError::EndIndexLessThanStartIndex { .. }
+            | LoroError::UsedOpID { .. }
+            | LoroError::OpRejected(_) => ErrorCategory::InvalidArgument,
+            LoroError::NotFoundError(_)
+            | LoroError::FrontiersNotFound(_)
+            | LoroError::UndoInvalidIdSpan(_) => ErrorCategory::NotFound,
+            LoroError::UnmatchedContext { .. }
+            | LoroError::DuplicatedTransactionError
+            | LoroError::TransactionError(_)
+            | LoroError::AutoCommitNotStarted
+            | LoroError::StyleConfigMissing(_)
+            | LoroError::ImportWhenInTxn
+            | LoroError::MisuseDetachedContainer { .. }
+            | LoroError::ReattachAttachedContainer
+            | LoroError::EditWhenDetached
+            | LoroError::UndoWithDifferentPeerId { .. }
+            | LoroError::OpQuotaExceeded { .. } => ErrorCategory::InvalidState,
+            LoroError::TreeError(_) => ErrorCategory::Tree,
+            LoroError::JsError(_)
+            | LoroError::LockError
+            | LoroError::Unknown(_)
+            | LoroError::NotImplemented(_) => ErrorCategory::Internal,
+        }
+    }
+}
+
+impl LoroTreeError {
+    /// A numeric code of the error, in the `100..200` range of [LoroError::code].
+    pub fn code(&self) -> u16 {
+        match self {
+            LoroTreeError::CyclicMoveError => 101,
+            LoroTreeError::TreeNodeParentNotFound(_) => 102,
+            LoroTreeError::TreeNodeNotExist(_) => 103,
+            LoroTreeError::IndexOutOfBound { .. } => 104,
+        }
+    }
+}
+
 #[cfg(feature = "wasm")]
 pub mod wasm {
     use wasm_bindgen::JsValue;
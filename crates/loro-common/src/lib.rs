@@ -1,3 +1,9 @@
+//! The types shared by the crates of Loro: [ID], [IdSpan], [ContainerID], [ContainerType],
+//! [LoroValue], [VersionVector] and [Frontiers].
+//!
+//! It only has light dependencies and all the types can be serialized with serde, so a tool
+//! that reads or writes Loro data, e.g. a sync server or an indexer, can use them without
+//! depending on the whole engine.
 use std::{fmt::Display, sync::Arc};
 
 use arbitrary::Arbitrary;
@@ -12,13 +18,15 @@ mod internal_string;
 mod macros;
 mod span;
 mod value;
+mod version;
 
-pub use error::{LoroError, LoroResult, LoroTreeError};
+pub use error::{ErrorCategory, LoroError, LoroResult, LoroTreeError};
 #[doc(hidden)]
 pub use fxhash::FxHashMap;
 pub use internal_string::InternalString;
 pub use span::*;
-pub use value::{to_value, LoroValue};
+pub use value::{canonicalize_f64, to_value, LoroValue, ValueRef};
+pub use version::{are_frontiers_eq, Frontiers, VersionVector, VersionVectorDiff};
 
 /// Unique id for each peer. It's a random u64 by default.
 pub type PeerID = u64;
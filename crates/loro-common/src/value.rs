@@ -100,6 +100,37 @@ impl LoroValue {
     pub fn is_too_deep(&self) -> bool {
         self.get_depth() > MAX_DEPTH
     }
+
+    /// Whether the value or any of its nested values is NaN or infinite.
+    ///
+    /// Such numbers can't be inserted into a container. NaN isn't equal to itself, and
+    /// neither can be represented in JSON, so the replicas could disagree on the state.
+    pub fn has_non_finite_number(&self) -> bool {
+        let mut values = vec![self];
+        while let Some(value) = values.pop() {
+            match value {
+                LoroValue::Double(v) if !v.is_finite() => return true,
+                LoroValue::List(list) => values.extend(list.iter()),
+                LoroValue::Map(map) => values.extend(map.values()),
+                _ => {}
+            }
+        }
+
+        false
+    }
+}
+
+/// Convert every NaN to the same bit pattern.
+///
+/// NaN can't be inserted locally, but the data exported by old versions may contain NaNs
+/// with any payload. The decoders canonicalize them so that the replicas agree on the bytes
+/// of the state.
+pub fn canonicalize_f64(v: f64) -> f64 {
+    if v.is_nan() {
+        f64::NAN
+    } else {
+        v
+    }
 }
 
 impl Index<&str> for LoroValue {
@@ -221,6 +252,8 @@ impl Hash for LoroValue {
                 state.write_u8(*v as u8);
             }
             LoroValue::Double(v) => {
+                // Equal values must have the same hash, and `0.0 == -0.0`
+                let v = if *v == 0.0 { 0.0 } else { canonicalize_f64(*v) };
                 state.write_u64(v.to_bits());
             }
             LoroValue::I64(v) => {
@@ -353,13 +386,62 @@ impl From<ContainerID> for LoroValue {
     }
 }
 
+/// A borrowed view of a [LoroValue].
+///
+/// It's returned by the APIs that read a value in place, so reading it doesn't clone
+/// anything. Use [ValueRef::to_value] to get an owned [LoroValue].
+#[derive(Debug, PartialEq, Clone, Copy, EnumAsInner)]
+pub enum ValueRef<'a> {
+    Null,
+    Bool(bool),
+    Double(f64),
+    I64(i64),
+    Binary(&'a [u8]),
+    String(&'a str),
+    List(&'a [LoroValue]),
+    Map(&'a FxHashMap<String, LoroValue>),
+    Container(&'a ContainerID),
+}
+
+impl ValueRef<'_> {
+    pub fn to_value(&self) -> LoroValue {
+        match *self {
+            ValueRef::Null => LoroValue::Null,
+            ValueRef::Bool(b) => LoroValue::Bool(b),
+            ValueRef::Double(d) => LoroValue::Double(d),
+            ValueRef::I64(i) => LoroValue::I64(i),
+            ValueRef::Binary(b) => LoroValue::Binary(Arc::new(b.to_vec())),
+            ValueRef::String(s) => LoroValue::String(Arc::new(s.to_string())),
+            ValueRef::List(l) => LoroValue::List(Arc::new(l.to_vec())),
+            ValueRef::Map(m) => LoroValue::Map(Arc::new(m.clone())),
+            ValueRef::Container(c) => LoroValue::Container(c.clone()),
+        }
+    }
+}
+
+impl<'a> From<&'a LoroValue> for ValueRef<'a> {
+    fn from(v: &'a LoroValue) -> Self {
+        match v {
+            LoroValue::Null => ValueRef::Null,
+            LoroValue::Bool(b) => ValueRef::Bool(*b),
+            LoroValue::Double(d) => ValueRef::Double(*d),
+            LoroValue::I64(i) => ValueRef::I64(*i),
+            LoroValue::Binary(b) => ValueRef::Binary(b),
+            LoroValue::String(s) => ValueRef::String(s),
+            LoroValue::List(l) => ValueRef::List(l),
+            LoroValue::Map(m) => ValueRef::Map(m),
+            LoroValue::Container(c) => ValueRef::Container(c),
+        }
+    }
+}
+
 #[cfg(feature = "wasm")]
 pub mod wasm {
     use std::sync::Arc;
 
     use fxhash::FxHashMap;
     use js_sys::{Array, Object, Uint8Array};
-    use wasm_bindgen::{JsCast, JsValue, __rt::IntoJsResult};
+    use wasm_bindgen::{__rt::IntoJsResult, JsCast, JsValue};
 
     use crate::{ContainerID, LoroError, LoroValue};
 
@@ -493,6 +575,9 @@ impl Serialize for LoroValue {
             match self {
                 LoroValue::Null => serializer.serialize_unit(),
                 LoroValue::Bool(b) => serializer.serialize_bool(*b),
+                // JSON has no NaN or infinity. They are `null` in every human-readable
+                // format, whatever the serializer would do with them.
+                LoroValue::Double(d) if !d.is_finite() => serializer.serialize_unit(),
                 LoroValue::Double(d) => serializer.serialize_f64(*d),
                 LoroValue::I64(i) => serializer.serialize_i64(*i),
                 LoroValue::String(s) => serializer.serialize_str(s),
@@ -703,7 +788,9 @@ impl<'de> serde::de::Visitor<'de> for LoroValueEnumVisitor {
                 Ok(LoroValue::Null)
             }
             (LoroValueFields::Bool, v) => v.newtype_variant().map(LoroValue::Bool),
-            (LoroValueFields::Double, v) => v.newtype_variant().map(LoroValue::Double),
+            (LoroValueFields::Double, v) => v
+                .newtype_variant()
+                .map(|v| LoroValue::Double(canonicalize_f64(v))),
             (LoroValueFields::I32, v) => v.newtype_variant().map(LoroValue::I64),
             (LoroValueFields::String, v) => {
                 v.newtype_variant().map(|x| LoroValue::String(Arc::new(x)))
@@ -0,0 +1,62 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+#[cfg(feature = "test_utils")]
+mod paste {
+    use super::*;
+    use criterion::Throughput;
+    use loro_internal::LoroDoc;
+
+    fn paste_content(size: usize) -> String {
+        "Hello, Loro! 你好 🦜\n"
+            .chars()
+            .cycle()
+            .take(size)
+            .collect()
+    }
+
+    pub fn large_paste(c: &mut Criterion) {
+        let mut b = c.benchmark_group("large paste");
+        b.sample_size(10);
+        let content = paste_content(5_000_000);
+        b.throughput(Throughput::Bytes(content.len() as u64));
+        b.bench_function("paste 5M chars", |b| {
+            b.iter(|| {
+                let doc = LoroDoc::new_auto_commit();
+                let text = doc.get_text("text");
+                text.insert(0, &content).unwrap();
+                doc.commit_then_renew();
+            })
+        });
+
+        b.bench_function("paste 5M chars into the middle of a doc", |b| {
+            let doc = LoroDoc::new_auto_commit();
+            let text = doc.get_text("text");
+            text.insert(0, &paste_content(10_000)).unwrap();
+            doc.commit_then_renew();
+            let snapshot = doc.export_snapshot();
+            b.iter(|| {
+                let doc = LoroDoc::from_snapshot(&snapshot).unwrap();
+                doc.start_auto_commit();
+                let text = doc.get_text("text");
+                text.insert(5_000, &content).unwrap();
+                doc.commit_then_renew();
+            })
+        });
+
+        b.bench_function("export snapshot after paste 5M chars", |b| {
+            let doc = LoroDoc::new_auto_commit();
+            let text = doc.get_text("text");
+            text.insert(0, &content).unwrap();
+            doc.commit_then_renew();
+            b.iter(|| doc.export_snapshot())
+        });
+    }
+}
+
+pub fn dumb(_c: &mut Criterion) {}
+
+#[cfg(feature = "test_utils")]
+criterion_group!(benches, paste::large_paste);
+#[cfg(not(feature = "test_utils"))]
+criterion_group!(benches, dumb);
+criterion_main!(benches);
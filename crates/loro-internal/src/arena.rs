@@ -7,7 +7,7 @@ use std::{
 };
 
 use append_only_bytes::BytesSlice;
-use fxhash::FxHashMap;
+use fxhash::{FxHashMap, FxHashSet};
 use loro_common::PeerID;
 
 use crate::{
@@ -37,6 +37,8 @@ struct InnerSharedArena {
     parents: Mutex<FxHashMap<ContainerIdx, Option<ContainerIdx>>>,
     values: Mutex<Vec<LoroValue>>,
     root_c_idx: Mutex<Vec<ContainerIdx>>,
+    /// The containers that have ops, see [SharedArena::mark_written]
+    written: Mutex<FxHashSet<ContainerIdx>>,
     str: Mutex<StrArena>,
 }
 
@@ -76,6 +78,7 @@ impl SharedArena {
                 parents: Mutex::new(self.inner.parents.lock().unwrap().clone()),
                 values: Mutex::new(self.inner.values.lock().unwrap().clone()),
                 root_c_idx: Mutex::new(self.inner.root_c_idx.lock().unwrap().clone()),
+                written: Mutex::new(self.inner.written.lock().unwrap().clone()),
                 str: Mutex::new(self.inner.str.lock().unwrap().clone()),
             }),
         }
@@ -428,6 +431,24 @@ impl SharedArena {
         self.inner.root_c_idx.lock().unwrap().clone()
     }
 
+    /// Mark the container as written, i.e. it has a local or an imported op.
+    ///
+    /// Getting a handler registers its container, but the container is only materialized
+    /// by its first op. A root that is neither written nor has a non-empty state is not
+    /// part of the doc value.
+    pub(crate) fn mark_written(&self, idx: ContainerIdx) {
+        self.inner.written.lock().unwrap().insert(idx);
+    }
+
+    /// Undo [SharedArena::mark_written] when the ops of the container are rolled back
+    pub(crate) fn unmark_written(&self, idx: ContainerIdx) {
+        self.inner.written.lock().unwrap().remove(&idx);
+    }
+
+    pub(crate) fn is_written(&self, idx: ContainerIdx) -> bool {
+        self.inner.written.lock().unwrap().contains(&idx)
+    }
+
     // TODO: this can return a u16 directly now, since the depths are always valid
     pub(crate) fn get_depth(&self, container: ContainerIdx) -> Option<NonZeroU16> {
         get_depth(
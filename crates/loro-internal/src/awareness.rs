@@ -1,3 +1,5 @@
+use std::sync::Arc;
+
 use fxhash::FxHashMap;
 use loro_common::{LoroValue, PeerID};
 use serde::{Deserialize, Serialize};
@@ -10,13 +12,42 @@ use crate::change::get_sys_timestamp;
 ///
 /// The state of a specific peer is expected to be removed after a specified timeout. Use
 /// `remove_outdated` to eliminate outdated states.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Awareness {
     peer: PeerID,
     peers: FxHashMap<PeerID, PeerInfo>,
     timeout: i64,
+    on_change: Option<OnAwarenessChange>,
+}
+
+impl std::fmt::Debug for Awareness {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Awareness")
+            .field("peer", &self.peer)
+            .field("peers", &self.peers)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
 }
 
+/// The peers whose states are changed by an operation on [Awareness].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AwarenessChange {
+    pub added: Vec<PeerID>,
+    pub updated: Vec<PeerID>,
+    pub removed: Vec<PeerID>,
+    /// Whether the change is caused by [Awareness::set_local_state]
+    pub by_local: bool,
+}
+
+impl AwarenessChange {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.updated.is_empty() && self.removed.is_empty()
+    }
+}
+
+pub type OnAwarenessChange = Arc<dyn Fn(&AwarenessChange) + Send + Sync>;
+
 #[derive(Debug, Clone)]
 pub struct PeerInfo {
     pub state: LoroValue,
@@ -38,6 +69,23 @@ impl Awareness {
             peer,
             timeout,
             peers: FxHashMap::default(),
+            on_change: None,
+        }
+    }
+
+    /// Set the callback that is invoked whenever the states of the peers are added,
+    /// updated or removed.
+    pub fn set_on_change(&mut self, on_change: Option<OnAwarenessChange>) {
+        self.on_change = on_change;
+    }
+
+    fn emit(&self, change: AwarenessChange) {
+        if change.is_empty() {
+            return;
+        }
+
+        if let Some(on_change) = &self.on_change {
+            on_change(&change);
         }
     }
 
@@ -110,6 +158,12 @@ impl Awareness {
             }
         }
 
+        self.emit(AwarenessChange {
+            added: added_peers.clone(),
+            updated: changed_peers.clone(),
+            removed: Vec::new(),
+            by_local: false,
+        });
         (changed_peers, added_peers)
     }
 
@@ -118,6 +172,7 @@ impl Awareness {
     }
 
     fn _set_local_state(&mut self, value: LoroValue) {
+        let is_new = !self.peers.contains_key(&self.peer);
         let peer = self.peers.entry(self.peer).or_insert_with(|| PeerInfo {
             state: Default::default(),
             counter: 0,
@@ -127,6 +182,16 @@ impl Awareness {
         peer.state = value;
         peer.counter += 1;
         peer.timestamp = get_sys_timestamp();
+        let mut change = AwarenessChange {
+            by_local: true,
+            ..Default::default()
+        };
+        if is_new {
+            change.added.push(self.peer);
+        } else {
+            change.updated.push(self.peer);
+        }
+        self.emit(change);
     }
 
     pub fn get_local_state(&self) -> Option<LoroValue> {
@@ -145,6 +210,10 @@ impl Awareness {
             }
         });
 
+        self.emit(AwarenessChange {
+            removed: removed.clone(),
+            ..Default::default()
+        });
         removed
     }
 
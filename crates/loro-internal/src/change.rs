@@ -11,10 +11,13 @@ use crate::{
     span::{HasId, HasLamport},
     version::Frontiers,
 };
-use loro_common::{HasCounter, HasCounterSpan, PeerID};
+use fxhash::FxHashMap;
+use loro_common::{HasCounter, HasCounterSpan, LoroError, LoroResult, PeerID};
 use num::traits::AsPrimitive;
 use rle::{HasIndex, HasLength, Mergable, RleVec, Sliceable};
+use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
+use std::sync::Arc;
 
 pub type Timestamp = i64;
 pub type Lamport = u32;
@@ -34,6 +37,8 @@ pub struct Change<O = Op> {
     /// [Unix time](https://en.wikipedia.org/wiki/Unix_time)
     /// It is the number of seconds that have elapsed since 00:00:00 UTC on 1 January 1970.
     pub(crate) timestamp: Timestamp,
+    /// The commit message of the change
+    pub(crate) commit_msg: Option<Arc<str>>,
     /// if it has dependents, it cannot merge with new changes
     pub(crate) has_dependents: bool,
 }
@@ -52,6 +57,7 @@ impl<O> Change<O> {
             id,
             lamport,
             timestamp,
+            commit_msg: None,
             has_dependents: false,
         }
     }
@@ -86,12 +92,143 @@ impl<O> Change<O> {
         self.id
     }
 
+    #[inline]
+    pub fn message(&self) -> Option<&Arc<str>> {
+        self.commit_msg.as_ref()
+    }
+
     #[inline]
     pub fn deps_on_self(&self) -> bool {
         self.deps.len() == 1 && self.deps[0].peer == self.id.peer
     }
 }
 
+/// The metadata of a [Change], without its ops.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeMeta {
+    /// id of the first op in the change
+    pub id: ID,
+    pub lamport: Lamport,
+    /// The Unix timestamp of the change, see [Change::timestamp]
+    pub timestamp: Timestamp,
+    pub message: Option<Arc<str>>,
+    pub deps: Frontiers,
+    /// The number of ops in the change
+    pub len: usize,
+    /// The display name of the peer of the change in the current state,
+    /// see [crate::LoroDoc::set_peer_metadata]
+    pub author: Option<Arc<str>>,
+}
+
+impl ChangeMeta {
+    pub fn from_change(change: &Change) -> Self {
+        Self {
+            id: change.id,
+            lamport: change.lamport,
+            timestamp: change.timestamp,
+            message: change.commit_msg.clone(),
+            deps: change.deps.clone(),
+            len: change.atom_len(),
+            author: None,
+        }
+    }
+}
+
+/// The position after a page of [crate::LoroDoc::changes_page].
+///
+/// It's the key of the last change of the page, not an index, so it stays valid when
+/// changes are imported between the pages. It can be serialized to be kept by a client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangesCursor {
+    pub(crate) lamport: Lamport,
+    pub(crate) peer: PeerID,
+}
+
+/// The causal dependency graph of the changes in a doc.
+///
+/// It's meant for external tools that validate the causal order of a doc independently,
+/// so it can be encoded as compact binary with [DepGraph::encode] or as JSON with
+/// [DepGraph::to_json].
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct DepGraph {
+    /// The changes, sorted by Lamport timestamp and then by peer
+    pub nodes: Vec<DepNode>,
+}
+
+/// A change in a [DepGraph]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DepNode {
+    /// id of the first op in the change
+    pub id: ID,
+    /// The number of ops in the change
+    pub len: usize,
+    /// The Lamport timestamp of the first op in the change
+    pub lamport: Lamport,
+    /// The ops the change depends on. The previous op of the same peer is implicitly a dep.
+    pub deps: Frontiers,
+}
+
+impl DepGraph {
+    pub fn encode(&self) -> Vec<u8> {
+        postcard::to_allocvec(self).unwrap()
+    }
+
+    pub fn decode(bytes: &[u8]) -> LoroResult<Self> {
+        postcard::from_bytes(bytes).map_err(|_| {
+            LoroError::DecodeError("Decode DepGraph error".to_string().into_boxed_str())
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn from_json(json: &str) -> LoroResult<Self> {
+        serde_json::from_str(json).map_err(|_| LoroError::InvalidJsonSchema)
+    }
+
+    /// Check that every change comes after the changes it depends on, including the
+    /// previous change of its peer, and has a greater Lamport timestamp than them.
+    ///
+    /// Return the id of the first change that breaks the causal order.
+    pub fn check_causal_order(&self) -> Result<(), ID> {
+        // The span and the Lamport timestamp of the changes of each peer, in order
+        let mut seen: FxHashMap<PeerID, Vec<(Counter, Counter, Lamport)>> = FxHashMap::default();
+        let lamport_of = |seen: &FxHashMap<PeerID, Vec<(Counter, Counter, Lamport)>>, id: ID| {
+            let spans = seen.get(&id.peer)?;
+            let &(start, _, lamport) = spans
+                .iter()
+                .find(|(start, end, _)| *start <= id.counter && id.counter < *end)?;
+            Some(lamport + (id.counter - start) as Lamport)
+        };
+
+        for node in self.nodes.iter() {
+            let end = node.id.counter + node.len as Counter;
+            let prev_end = seen
+                .get(&node.id.peer)
+                .and_then(|x| x.last())
+                .map_or(0, |x| x.1);
+            if node.len == 0 || node.id.counter != prev_end {
+                return Err(node.id);
+            }
+
+            let prev = (node.id.counter > 0).then(|| node.id.inc(-1));
+            for dep in node.deps.iter().copied().chain(prev) {
+                match lamport_of(&seen, dep) {
+                    Some(lamport) if lamport < node.lamport => {}
+                    _ => return Err(node.id),
+                }
+            }
+
+            seen.entry(node.id.peer)
+                .or_default()
+                .push((node.id.counter, end, node.lamport));
+        }
+
+        Ok(())
+    }
+}
+
 impl<O: Mergable + HasLength + HasIndex + Debug> HasIndex for Change<O> {
     type Int = Counter;
 
@@ -190,6 +327,7 @@ impl<O: Mergable + HasLength + HasIndex + Sliceable + HasCounter + Debug> Slicea
             id: self.id.inc(from as Counter),
             lamport: self.lamport + from as Lamport,
             timestamp: self.timestamp,
+            commit_msg: self.commit_msg.clone(),
             has_dependents: self.has_dependents,
         }
     }
@@ -1,3 +1,5 @@
+use loro_common::{LoroError, LoroResult};
+
 pub use crate::container::richtext::config::{StyleConfig, StyleConfigMap};
 
 #[derive(Clone, Debug)]
@@ -5,8 +7,129 @@ pub struct Configure {
     pub(crate) text_style_config: Arc<RwLock<StyleConfigMap>>,
     record_timestamp: Arc<AtomicBool>,
     merge_interval: Arc<AtomicI64>,
+    /// 0 means there is no limit
+    auto_commit_ops_limit: Arc<AtomicUsize>,
     /// do not use `jitter` by default
     pub(crate) tree_position_jitter: Arc<AtomicU8>,
+    dedup_snapshot_text: Arc<AtomicBool>,
+    implicit_op_counters: Arc<AtomicBool>,
+    /// 0 means the op contents are not compressed
+    op_compression_threshold: Arc<AtomicUsize>,
+    /// 0 means there is no limit
+    max_ops_per_peer: Arc<AtomicUsize>,
+    /// 0 means there is no limit
+    max_bytes_per_peer: Arc<AtomicUsize>,
+    /// 0 means there is no limit
+    max_containers_per_peer: Arc<AtomicUsize>,
+    /// Whether the doc has any op, see [ConfigOptions]
+    has_ops: Arc<AtomicBool>,
+    on_change: ConfigObserver,
+}
+
+/// The options of [Configure] that can be inspected and updated at runtime.
+///
+/// When it's used as an update, the `None` fields are left unchanged.
+///
+/// The per-peer limits (`max_ops_per_peer`, `max_bytes_per_peer` and
+/// `max_containers_per_peer`) can't be changed after the doc has its first op, because they
+/// decide which ops are valid, and the ops that are already in the doc were accepted by the
+/// old limits. The other options only affect the ops created or exported after the change.
+///
+/// There is no option for the index mode or the history retention: the unit of the text
+/// indexes in the events is decided by the `wasm` feature at compile time, and the doc
+/// always keeps its whole history.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConfigOptions {
+    pub record_timestamp: Option<bool>,
+    /// In milliseconds, it must not be negative
+    pub merge_interval: Option<i64>,
+    /// `Some(None)` removes the limit. The limit must not be 0.
+    pub auto_commit_ops_limit: Option<Option<usize>>,
+    pub fractional_index_jitter: Option<u8>,
+    pub dedup_snapshot_text: Option<bool>,
+    pub implicit_op_counters: Option<bool>,
+    /// In bytes, `Some(None)` disables the compression. The threshold must not be 0.
+    pub op_compression_threshold: Option<Option<usize>>,
+    /// `Some(None)` removes the limit. The limit must not be 0.
+    pub max_ops_per_peer: Option<Option<usize>>,
+    /// `Some(None)` removes the limit. The limit must not be 0.
+    pub max_bytes_per_peer: Option<Option<usize>>,
+    /// `Some(None)` removes the limit. The limit must not be 0.
+    pub max_containers_per_peer: Option<Option<usize>>,
+}
+
+impl ConfigOptions {
+    fn validate(&self, config: &Configure) -> LoroResult<()> {
+        if self.merge_interval.is_some_and(|x| x < 0) {
+            return Err(LoroError::ArgErr(
+                "merge_interval must not be negative".into(),
+            ));
+        }
+
+        if self.auto_commit_ops_limit == Some(Some(0)) {
+            return Err(LoroError::ArgErr(
+                "auto_commit_ops_limit must not be 0, use None to remove the limit".into(),
+            ));
+        }
+
+        if self.op_compression_threshold == Some(Some(0)) {
+            return Err(LoroError::ArgErr(
+                "op_compression_threshold must not be 0, use None to disable the compression"
+                    .into(),
+            ));
+        }
+
+        let per_peer_limits = [
+            (
+                "max_ops_per_peer",
+                self.max_ops_per_peer,
+                config.max_ops_per_peer(),
+            ),
+            (
+                "max_bytes_per_peer",
+                self.max_bytes_per_peer,
+                config.max_bytes_per_peer(),
+            ),
+            (
+                "max_containers_per_peer",
+                self.max_containers_per_peer,
+                config.max_containers_per_peer(),
+            ),
+        ];
+        for (name, limit, current) in per_peer_limits {
+            if limit == Some(Some(0)) {
+                return Err(LoroError::ArgErr(
+                    format!("{name} must not be 0, use None to remove the limit").into(),
+                ));
+            }
+
+            if config.has_ops() && limit.is_some_and(|x| x != current) {
+                return Err(LoroError::ArgErr(
+                    format!("{name} can't be changed after the doc has ops").into(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Called with the options that were actually changed.
+pub type OnConfigChange = Arc<dyn Fn(&ConfigOptions) + Send + Sync>;
+
+#[derive(Clone, Default)]
+struct ConfigObserver(Arc<Mutex<Option<OnConfigChange>>>);
+
+impl std::fmt::Debug for ConfigObserver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("ConfigObserver")
+            .field(&self.0.lock().unwrap().is_some())
+            .finish()
+    }
 }
 
 impl Default for Configure {
@@ -15,12 +138,24 @@ impl Default for Configure {
             text_style_config: Arc::new(RwLock::new(StyleConfigMap::default_rich_text_config())),
             record_timestamp: Arc::new(AtomicBool::new(false)),
             merge_interval: Arc::new(AtomicI64::new(1000 * 1000)),
+            auto_commit_ops_limit: Arc::new(AtomicUsize::new(0)),
             tree_position_jitter: Arc::new(AtomicU8::new(0)),
+            dedup_snapshot_text: Arc::new(AtomicBool::new(false)),
+            implicit_op_counters: Arc::new(AtomicBool::new(false)),
+            op_compression_threshold: Arc::new(AtomicUsize::new(0)),
+            max_ops_per_peer: Arc::new(AtomicUsize::new(0)),
+            max_bytes_per_peer: Arc::new(AtomicUsize::new(0)),
+            max_containers_per_peer: Arc::new(AtomicUsize::new(0)),
+            has_ops: Arc::new(AtomicBool::new(false)),
+            on_change: Default::default(),
         }
     }
 }
 
 impl Configure {
+    /// Create a copy of the configuration that is not shared with this one.
+    ///
+    /// The change callback is not copied.
     pub fn fork(&self) -> Self {
         Self {
             text_style_config: Arc::new(RwLock::new(
@@ -34,11 +169,167 @@ impl Configure {
                 self.merge_interval
                     .load(std::sync::atomic::Ordering::Relaxed),
             )),
+            auto_commit_ops_limit: Arc::new(AtomicUsize::new(
+                self.auto_commit_ops_limit
+                    .load(std::sync::atomic::Ordering::Relaxed),
+            )),
             tree_position_jitter: Arc::new(AtomicU8::new(
                 self.tree_position_jitter
                     .load(std::sync::atomic::Ordering::Relaxed),
             )),
+            dedup_snapshot_text: Arc::new(AtomicBool::new(
+                self.dedup_snapshot_text
+                    .load(std::sync::atomic::Ordering::Relaxed),
+            )),
+            implicit_op_counters: Arc::new(AtomicBool::new(
+                self.implicit_op_counters
+                    .load(std::sync::atomic::Ordering::Relaxed),
+            )),
+            op_compression_threshold: Arc::new(AtomicUsize::new(
+                self.op_compression_threshold
+                    .load(std::sync::atomic::Ordering::Relaxed),
+            )),
+            max_ops_per_peer: Arc::new(AtomicUsize::new(
+                self.max_ops_per_peer
+                    .load(std::sync::atomic::Ordering::Relaxed),
+            )),
+            max_bytes_per_peer: Arc::new(AtomicUsize::new(
+                self.max_bytes_per_peer
+                    .load(std::sync::atomic::Ordering::Relaxed),
+            )),
+            max_containers_per_peer: Arc::new(AtomicUsize::new(
+                self.max_containers_per_peer
+                    .load(std::sync::atomic::Ordering::Relaxed),
+            )),
+            has_ops: Arc::new(AtomicBool::new(self.has_ops())),
+            on_change: Default::default(),
+        }
+    }
+
+    /// Get the current values of all the options.
+    ///
+    /// The result can be passed to [Configure::update] of another doc to copy them.
+    pub fn options(&self) -> ConfigOptions {
+        ConfigOptions {
+            record_timestamp: Some(self.record_timestamp()),
+            merge_interval: Some(self.merge_interval()),
+            auto_commit_ops_limit: Some(self.auto_commit_ops_limit()),
+            fractional_index_jitter: Some(
+                self.tree_position_jitter
+                    .load(std::sync::atomic::Ordering::Relaxed),
+            ),
+            dedup_snapshot_text: Some(self.dedup_snapshot_text()),
+            implicit_op_counters: Some(self.implicit_op_counters()),
+            op_compression_threshold: Some(self.op_compression_threshold()),
+            max_ops_per_peer: Some(self.max_ops_per_peer()),
+            max_bytes_per_peer: Some(self.max_bytes_per_peer()),
+            max_containers_per_peer: Some(self.max_containers_per_peer()),
+        }
+    }
+
+    /// Update the options that are `Some` in `options`.
+    ///
+    /// All the options are validated before any of them is changed, so an invalid update
+    /// changes nothing. The change callback is called once with the changed options.
+    pub fn update(&self, options: &ConfigOptions) -> LoroResult<()> {
+        options.validate(self)?;
+        self.update_unchecked(options);
+        Ok(())
+    }
+
+    /// Whether the doc has any op. Some options can't be changed after that, see
+    /// [ConfigOptions].
+    pub fn has_ops(&self) -> bool {
+        self.has_ops.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub(crate) fn mark_has_ops(&self) {
+        self.has_ops
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Only for the options that have no invalid value
+    fn update_unchecked(&self, options: &ConfigOptions) {
+        use std::sync::atomic::Ordering::Relaxed;
+        let mut changed = ConfigOptions::default();
+        if let Some(record) = options.record_timestamp {
+            if self.record_timestamp.swap(record, Relaxed) != record {
+                changed.record_timestamp = Some(record);
+            }
+        }
+
+        if let Some(interval) = options.merge_interval {
+            if self.merge_interval.swap(interval, Relaxed) != interval {
+                changed.merge_interval = Some(interval);
+            }
+        }
+
+        if let Some(limit) = options.auto_commit_ops_limit {
+            if self.auto_commit_ops_limit.swap(limit.unwrap_or(0), Relaxed) != limit.unwrap_or(0) {
+                changed.auto_commit_ops_limit = Some(limit);
+            }
+        }
+
+        if let Some(jitter) = options.fractional_index_jitter {
+            if self.tree_position_jitter.swap(jitter, Relaxed) != jitter {
+                changed.fractional_index_jitter = Some(jitter);
+            }
+        }
+
+        if let Some(dedup) = options.dedup_snapshot_text {
+            if self.dedup_snapshot_text.swap(dedup, Relaxed) != dedup {
+                changed.dedup_snapshot_text = Some(dedup);
+            }
+        }
+
+        if let Some(implicit) = options.implicit_op_counters {
+            if self.implicit_op_counters.swap(implicit, Relaxed) != implicit {
+                changed.implicit_op_counters = Some(implicit);
+            }
+        }
+
+        if let Some(threshold) = options.op_compression_threshold {
+            let value = threshold.unwrap_or(0);
+            if self.op_compression_threshold.swap(value, Relaxed) != value {
+                changed.op_compression_threshold = Some(threshold);
+            }
+        }
+
+        if let Some(limit) = options.max_ops_per_peer {
+            let value = limit.unwrap_or(0);
+            if self.max_ops_per_peer.swap(value, Relaxed) != value {
+                changed.max_ops_per_peer = Some(limit);
+            }
+        }
+
+        if let Some(limit) = options.max_bytes_per_peer {
+            let value = limit.unwrap_or(0);
+            if self.max_bytes_per_peer.swap(value, Relaxed) != value {
+                changed.max_bytes_per_peer = Some(limit);
+            }
         }
+
+        if let Some(limit) = options.max_containers_per_peer {
+            let value = limit.unwrap_or(0);
+            if self.max_containers_per_peer.swap(value, Relaxed) != value {
+                changed.max_containers_per_peer = Some(limit);
+            }
+        }
+
+        if changed.is_empty() {
+            return;
+        }
+
+        // Release the lock before calling, so the callback can update the config
+        let on_change = self.on_change.0.lock().unwrap().clone();
+        if let Some(on_change) = on_change {
+            on_change(&changed);
+        }
+    }
+
+    /// Set the callback called after the options are changed at runtime.
+    pub fn set_on_change(&self, on_change: Option<OnConfigChange>) {
+        *self.on_change.0.lock().unwrap() = on_change;
     }
 
     pub fn text_style_config(&self) -> &Arc<RwLock<StyleConfigMap>> {
@@ -51,13 +342,17 @@ impl Configure {
     }
 
     pub fn set_record_timestamp(&self, record: bool) {
-        self.record_timestamp
-            .store(record, std::sync::atomic::Ordering::Relaxed);
+        self.update_unchecked(&ConfigOptions {
+            record_timestamp: Some(record),
+            ..Default::default()
+        });
     }
 
     pub fn set_fractional_index_jitter(&self, jitter: u8) {
-        self.tree_position_jitter
-            .store(jitter, std::sync::atomic::Ordering::Relaxed);
+        self.update_unchecked(&ConfigOptions {
+            fractional_index_jitter: Some(jitter),
+            ..Default::default()
+        });
     }
 
     pub fn merge_interval(&self) -> i64 {
@@ -65,9 +360,147 @@ impl Configure {
             .load(std::sync::atomic::Ordering::Relaxed)
     }
 
-    pub fn set_merge_interval(&self, interval: i64) {
-        self.merge_interval
-            .store(interval, std::sync::atomic::Ordering::Relaxed);
+    pub fn set_merge_interval(&self, interval: i64) -> LoroResult<()> {
+        self.update(&ConfigOptions {
+            merge_interval: Some(interval),
+            ..Default::default()
+        })
+    }
+
+    /// The max number of ops in the implicit transaction of the auto-commit mode.
+    ///
+    /// `None` means the transaction is only committed explicitly.
+    pub fn auto_commit_ops_limit(&self) -> Option<usize> {
+        match self
+            .auto_commit_ops_limit
+            .load(std::sync::atomic::Ordering::Relaxed)
+        {
+            0 => None,
+            limit => Some(limit),
+        }
+    }
+
+    pub fn set_auto_commit_ops_limit(&self, limit: Option<usize>) -> LoroResult<()> {
+        self.update(&ConfigOptions {
+            auto_commit_ops_limit: Some(limit),
+            ..Default::default()
+        })
+    }
+
+    pub fn dedup_snapshot_text(&self) -> bool {
+        self.dedup_snapshot_text
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub fn set_dedup_snapshot_text(&self, dedup: bool) {
+        self.update_unchecked(&ConfigOptions {
+            dedup_snapshot_text: Some(dedup),
+            ..Default::default()
+        });
+    }
+
+    /// Whether the exported updates and snapshots only store the gap of each op counter
+    /// from the end of the previous op of the same peer.
+    pub fn implicit_op_counters(&self) -> bool {
+        self.implicit_op_counters
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub fn set_implicit_op_counters(&self, implicit: bool) {
+        self.update_unchecked(&ConfigOptions {
+            implicit_op_counters: Some(implicit),
+            ..Default::default()
+        });
+    }
+
+    /// The min byte length of the inserted text that is compressed when it's exported.
+    ///
+    /// `None` means the op contents are not compressed.
+    pub fn op_compression_threshold(&self) -> Option<usize> {
+        match self
+            .op_compression_threshold
+            .load(std::sync::atomic::Ordering::Relaxed)
+        {
+            0 => None,
+            threshold => Some(threshold),
+        }
+    }
+
+    pub fn set_op_compression_threshold(&self, threshold: Option<usize>) -> LoroResult<()> {
+        self.update(&ConfigOptions {
+            op_compression_threshold: Some(threshold),
+            ..Default::default()
+        })
+    }
+
+    /// The max number of ops of each peer, i.e. the ops with a counter at or above it are
+    /// rejected on import, and the local edits beyond it fail.
+    ///
+    /// `None` means there is no limit.
+    pub fn max_ops_per_peer(&self) -> Option<usize> {
+        match self
+            .max_ops_per_peer
+            .load(std::sync::atomic::Ordering::Relaxed)
+        {
+            0 => None,
+            limit => Some(limit),
+        }
+    }
+
+    pub fn set_max_ops_per_peer(&self, limit: Option<usize>) -> LoroResult<()> {
+        self.update(&ConfigOptions {
+            max_ops_per_peer: Some(limit),
+            ..Default::default()
+        })
+    }
+
+    /// The max size of the content inserted by each peer, see [crate::LoroDoc::set_max_bytes_per_peer]
+    /// for how it's measured.
+    ///
+    /// `None` means there is no limit.
+    pub fn max_bytes_per_peer(&self) -> Option<usize> {
+        match self
+            .max_bytes_per_peer
+            .load(std::sync::atomic::Ordering::Relaxed)
+        {
+            0 => None,
+            limit => Some(limit),
+        }
+    }
+
+    pub fn set_max_bytes_per_peer(&self, limit: Option<usize>) -> LoroResult<()> {
+        self.update(&ConfigOptions {
+            max_bytes_per_peer: Some(limit),
+            ..Default::default()
+        })
+    }
+
+    /// The max number of containers created by each peer, including the metadata maps of
+    /// the tree nodes.
+    ///
+    /// `None` means there is no limit.
+    pub fn max_containers_per_peer(&self) -> Option<usize> {
+        match self
+            .max_containers_per_peer
+            .load(std::sync::atomic::Ordering::Relaxed)
+        {
+            0 => None,
+            limit => Some(limit),
+        }
+    }
+
+    pub fn set_max_containers_per_peer(&self, limit: Option<usize>) -> LoroResult<()> {
+        self.update(&ConfigOptions {
+            max_containers_per_peer: Some(limit),
+            ..Default::default()
+        })
+    }
+
+    /// Whether any of the per-peer limits is set
+    pub(crate) fn has_peer_quota(&self) -> bool {
+        self.max_ops_per_peer().is_some()
+            || self.max_bytes_per_peer().is_some()
+            || self.max_containers_per_peer().is_some()
     }
 }
 
@@ -77,8 +510,8 @@ pub struct DefaultRandom;
 #[cfg(test)]
 use std::sync::atomic::AtomicU64;
 use std::sync::{
-    atomic::{AtomicBool, AtomicI64, AtomicU8},
-    Arc, RwLock,
+    atomic::{AtomicBool, AtomicI64, AtomicU8, AtomicUsize},
+    Arc, Mutex, RwLock,
 };
 #[cfg(test)]
 static mut TEST_RANDOM: AtomicU64 = AtomicU64::new(0);
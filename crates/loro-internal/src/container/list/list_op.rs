@@ -728,4 +728,26 @@ mod test {
         a_with_id.merge(&b_with_id, &());
         assert!(a_with_id.span.signed_len == -2);
     }
+
+    #[test]
+    fn merge_backspace_run() {
+        // Delete the chars at 499, 498, ..., 0 one by one
+        let mut a = DeleteSpanWithId::new(ID::new(0, 499), 499, 1);
+        for i in (0..499).rev() {
+            let b = DeleteSpanWithId::new(ID::new(0, i), i as isize, 1);
+            assert!(a.is_mergable(&b, &()));
+            a.merge(&b, &());
+        }
+
+        assert_eq!(a, DeleteSpanWithId::new(ID::new(0, 0), 499, -500));
+        assert_eq!(a.span.to_range(), 0..500);
+        assert_eq!(
+            a.slice(0, 1),
+            DeleteSpanWithId::new(ID::new(0, 499), 499, -1)
+        );
+        assert_eq!(
+            a.slice(499, 500),
+            DeleteSpanWithId::new(ID::new(0, 0), 0, -1)
+        );
+    }
 }
@@ -20,7 +20,7 @@ use crate::{change::Lamport, delta::StyleMeta, utils::string_slice::StringSlice,
 use fugue_span::*;
 use loro_common::{Counter, IdLp, LoroValue, PeerID, ID};
 use serde::{Deserialize, Serialize};
-use std::{fmt::Debug};
+use std::fmt::Debug;
 
 pub(crate) use fugue_span::{RichtextChunk, RichtextChunkValue};
 pub(crate) use richtext_state::RichtextState;
@@ -35,6 +35,21 @@ pub struct RichtextSpan {
     pub attributes: StyleMeta,
 }
 
+/// A span of text inserted by consecutive ops, with its position in the text.
+///
+/// It's used by the renderers that only draw the visible part of a long text, see
+/// [crate::handler::TextHandler::get_spans_in_range].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TextSpan {
+    /// The id of the first char
+    pub id: ID,
+    /// The event index of the first char
+    pub pos: usize,
+    /// The length in event index
+    pub len: usize,
+    pub text: String,
+}
+
 /// This is used to communicate with the frontend.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub struct Style {
@@ -23,6 +23,7 @@ use tracing::instrument;
 use crate::{
     container::richtext::style_range_map::EMPTY_STYLES,
     delta::{DeltaValue, StyleMeta},
+    state::state_hash::{id_span_hash, SetHash},
     utils::query_by_len::{EntityIndexQueryWithEventIndex, IndexQueryWithEntityIndex, QueryByLen},
 };
 
@@ -36,15 +37,17 @@ use self::{
 
 use super::{
     style_range_map::{IterAnchorItem, StyleRangeMap, Styles},
-    AnchorType, RichtextSpan, StyleOp,
+    AnchorType, RichtextSpan, StyleOp, TextSpan,
 };
 
-pub(crate) use query::PosType;
+pub use query::PosType;
 
 #[derive(Clone, Debug, Default)]
 pub(crate) struct RichtextState {
     tree: BTree<RichtextTreeTrait>,
     style_ranges: Option<Box<StyleRangeMap>>,
+    /// The hash of the ids of the chars and the style anchors, see [crate::state::state_hash]
+    hash: SetHash,
 }
 
 impl Display for RichtextState {
@@ -871,15 +874,18 @@ mod query {
 
     use super::*;
 
+    /// The unit of a position in a text.
     #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-    pub(crate) enum PosType {
-        #[allow(unused)]
+    pub enum PosType {
+        /// UTF-8 byte index
         Bytes,
-        #[allow(unused)]
+        /// Unicode code point index
         Unicode,
-        #[allow(unused)]
+        /// UTF-16 code unit index
         Utf16,
+        /// The index that also counts the style anchors. It's only used internally.
         Entity,
+        /// UTF-16 index if feature="wasm", Unicode index otherwise
         Event,
     }
 
@@ -1318,12 +1324,25 @@ mod cursor_cache {
 
 impl RichtextState {
     pub(crate) fn from_chunks<I: Iterator<Item = impl Into<RichtextStateChunk>>>(i: I) -> Self {
+        let mut hash = SetHash::default();
+        let tree = i
+            .map(|x| {
+                let chunk: RichtextStateChunk = x.into();
+                hash.insert(id_span_hash(chunk.get_id_lp_span()));
+                chunk
+            })
+            .collect();
         Self {
-            tree: i.collect(),
+            tree,
             style_ranges: Default::default(),
+            hash,
         }
     }
 
+    pub(crate) fn state_hash(&self) -> u64 {
+        self.hash.get()
+    }
+
     pub(crate) fn get_entity_index_for_text_insert(
         &mut self,
         pos: usize,
@@ -1405,6 +1424,7 @@ impl RichtextState {
         id: IdFull,
     ) -> Cursor {
         let elem = RichtextStateChunk::try_new(text, id).unwrap();
+        self.hash.insert(id_span_hash(elem.get_id_lp_span()));
         self.style_ranges
             .as_mut()
             .map(|x| x.insert(entity_index, elem.rle_len()));
@@ -1442,6 +1462,7 @@ impl RichtextState {
             .query_with_finder_return::<EntityIndexQueryWithEventIndex>(&entity_index);
         let cursor = c.map(|x| x.cursor);
         let event_index = f.event_index;
+        self.hash.insert(id_span_hash(elem.get_id_lp_span()));
 
         match cursor {
             Some(cursor) => {
@@ -1499,6 +1520,92 @@ impl RichtextState {
         }
     }
 
+    /// Convert the position at the cursor to an index of the given type.
+    fn cursor_to_index(&self, cursor: Cursor, pos_type: PosType) -> usize {
+        let mut ans = 0;
+        self.tree
+            .visit_previous_caches(cursor, |cache| match cache {
+                generic_btree::PreviousCache::NodeCache(c) => {
+                    ans += match pos_type {
+                        PosType::Bytes => c.bytes,
+                        PosType::Unicode => c.unicode_len,
+                        PosType::Utf16 => c.utf16_len,
+                        PosType::Entity => c.entity_len,
+                        PosType::Event => c.event_len(),
+                    } as usize;
+                }
+                generic_btree::PreviousCache::PrevSiblingElem(c) => match c {
+                    RichtextStateChunk::Text(s) => {
+                        ans += match pos_type {
+                            PosType::Bytes => s.utf8_len(),
+                            PosType::Unicode | PosType::Entity => s.unicode_len(),
+                            PosType::Utf16 => s.utf16_len(),
+                            PosType::Event => s.event_len(),
+                        } as usize;
+                    }
+                    RichtextStateChunk::Style { .. } => {
+                        if pos_type == PosType::Entity {
+                            ans += 1;
+                        }
+                    }
+                },
+                generic_btree::PreviousCache::ThisElemAndOffset { elem, offset } => match elem {
+                    RichtextStateChunk::Text(s) => {
+                        ans += match pos_type {
+                            PosType::Bytes => unicode_to_utf8_index(s.as_str(), offset).unwrap(),
+                            PosType::Unicode | PosType::Entity => offset,
+                            PosType::Utf16 => unicode_to_utf16_index(s.as_str(), offset).unwrap(),
+                            PosType::Event => s.convert_unicode_offset_to_event_offset(offset),
+                        };
+                    }
+                    RichtextStateChunk::Style { .. } => {
+                        if pos_type == PosType::Entity {
+                            ans += offset;
+                        }
+                    }
+                },
+            });
+        ans
+    }
+
+    fn len_of(&self, pos_type: PosType) -> usize {
+        match pos_type {
+            PosType::Bytes => self.len_utf8(),
+            PosType::Unicode => self.len_unicode(),
+            PosType::Utf16 => self.len_utf16(),
+            PosType::Entity => self.len_entity(),
+            PosType::Event => self.len_event(),
+        }
+    }
+
+    /// Convert an index of type `from` to the index of type `to` in O(log n).
+    ///
+    /// Return `None` if `index` is out of bound or it's not at the boundary of a code point.
+    pub(crate) fn convert_pos(&self, index: usize, from: PosType, to: PosType) -> Option<usize> {
+        if index > self.len_of(from) {
+            return None;
+        }
+
+        if index == 0 || self.tree.is_empty() {
+            return Some(0);
+        }
+
+        let cursor = match from {
+            PosType::Bytes => self.tree.query::<ByteQuery>(&index),
+            PosType::Unicode => self.tree.query::<UnicodeQuery>(&index),
+            PosType::Utf16 => self.tree.query::<Utf16Query>(&index),
+            PosType::Entity => self.tree.query::<EntityQuery>(&index),
+            PosType::Event => self.tree.query::<EventIndexQuery>(&index),
+        }?
+        .cursor;
+        if self.cursor_to_index(cursor, from) != index {
+            // The queries fall back to the previous boundary inside a code point
+            return None;
+        }
+
+        Some(self.cursor_to_index(cursor, to))
+    }
+
     pub(crate) fn cursor_to_unicode_index(&self, cursor: Cursor) -> usize {
         let mut ans = 0;
         self.tree
@@ -1891,6 +1998,74 @@ impl RichtextState {
         Ok(ans)
     }
 
+    /// Get the spans of text that overlap the event range `pos..pos + len`.
+    ///
+    /// It only visits the chunks in the range, so it doesn't depend on the length of the
+    /// text. The adjacent chunks with consecutive ids are merged.
+    pub(crate) fn get_spans_by_event_index(
+        &self,
+        pos: usize,
+        len: usize,
+    ) -> LoroResult<Vec<TextSpan>> {
+        if pos + len > self.len_event() {
+            return Err(LoroError::OutOfBound {
+                pos: pos + len,
+                len: self.len_event(),
+                info: format!("Position: {}:{}", file!(), line!()).into_boxed_str(),
+            });
+        }
+
+        let mut ans: Vec<TextSpan> = Vec::new();
+        if self.tree.is_empty() || len == 0 {
+            return Ok(ans);
+        }
+
+        let (start, end) = (
+            self.tree.query::<EventIndexQuery>(&pos).unwrap().cursor,
+            self.tree
+                .query::<EventIndexQuery>(&(pos + len))
+                .unwrap()
+                .cursor,
+        );
+
+        let mut event_index = pos;
+        // The id after the last span, to merge the chunks inserted by consecutive ops
+        let mut next_id: Option<ID> = None;
+        for span in self.tree.iter_range(start..end) {
+            let start = span.start.unwrap_or(0);
+            let end = span.end.unwrap_or(span.elem.rle_len());
+            if end == 0 {
+                break;
+            }
+
+            let RichtextStateChunk::Text(s) = span.elem else {
+                continue;
+            };
+            let Ok(text) = unicode_slice(s.as_str(), start, end) else {
+                return Err(LoroError::UTF16InUnicodeCodePoint { pos: pos + len });
+            };
+            let id = s.id().inc(start as Counter);
+            let event_len = s.entity_range_to_event_range(start..end).len();
+            match ans.last_mut() {
+                Some(last) if next_id == Some(id) => {
+                    last.len += event_len;
+                    last.text.push_str(text);
+                }
+                _ => ans.push(TextSpan {
+                    id,
+                    pos: event_index,
+                    len: event_len,
+                    text: text.to_string(),
+                }),
+            }
+
+            next_id = Some(id.inc((end - start) as Counter));
+            event_index += event_len;
+        }
+
+        Ok(ans)
+    }
+
     // PERF: can be splitted into two methods. One is without cursor_to_event_index
     // PERF: can be speed up a lot by detecting whether the range is in a single leaf first
     /// This is used to accept changes from DiffCalculator
@@ -1973,11 +2148,16 @@ impl RichtextState {
         if elem.rle_len() >= start_cursor.offset + len {
             // drop in place
             let mut event_len = 0;
+            let mut removed = 0;
             let mut updater = StyleRangeUpdater::new(self.style_ranges.as_mut(), pos);
             self.tree.update_leaf(start_cursor.leaf, |elem| {
                 updater.update(&*elem);
                 match elem {
                     RichtextStateChunk::Text(text) => {
+                        let id = text.idlp();
+                        let start = id.lamport + start_cursor.offset as Lamport;
+                        removed =
+                            id_span_hash(IdLpSpan::new(id.peer, start, start + len as Lamport));
                         if let Some(f) = f {
                             let span = text.slice(start_cursor.offset..start_cursor.offset + len);
                             f(RichtextStateChunk::Text(span));
@@ -1988,6 +2168,7 @@ impl RichtextState {
                         (true, next.map(RichtextStateChunk::Text), None)
                     }
                     RichtextStateChunk::Style { .. } => {
+                        removed = id_span_hash(elem.get_id_lp_span());
                         if let Some(f) = f {
                             let v = std::mem::replace(
                                 elem,
@@ -2002,6 +2183,7 @@ impl RichtextState {
                 }
             });
 
+            self.hash.remove(removed);
             let affected_range = updater.get_affected_range(pos);
             if let Some(s) = self.style_ranges.as_mut() {
                 s.delete(pos..pos + len);
@@ -2025,6 +2207,7 @@ impl RichtextState {
             let mut updater = StyleRangeUpdater::new(self.style_ranges.as_mut(), pos);
             for iter in generic_btree::iter::Drain::new(&mut self.tree, start, end) {
                 updater.update(&iter);
+                self.hash.remove(id_span_hash(iter.get_id_lp_span()));
                 if let Some(f) = f.as_mut() {
                     f(iter)
                 }
@@ -2159,6 +2342,50 @@ impl RichtextState {
         self.tree.iter()
     }
 
+    /// The text of the chunks, borrowed from the tree
+    pub(crate) fn iter_str(&self) -> impl Iterator<Item = &str> {
+        self.tree.iter().filter_map(|x| match x {
+            RichtextStateChunk::Text(s) => Some(s.as_str()),
+            RichtextStateChunk::Style { .. } => None,
+        })
+    }
+
+    /// Get the ids of the ops that inserted the text in the given unicode range.
+    ///
+    /// The adjacent chars inserted by the same op are merged into one span.
+    pub(crate) fn get_attribution(&self, range: Range<usize>) -> Vec<IdSpan> {
+        let mut ans: Vec<IdSpan> = Vec::new();
+        let mut index = 0;
+        for chunk in self.tree.iter() {
+            if index >= range.end {
+                break;
+            }
+
+            let RichtextStateChunk::Text(s) = chunk else {
+                continue;
+            };
+            let len = s.unicode_len() as usize;
+            let start = range.start.max(index);
+            let end = range.end.min(index + len);
+            if start < end {
+                let id = s.id().inc((start - index) as Counter);
+                let span = IdSpan::new(id.peer, id.counter, id.counter + (end - start) as Counter);
+                match ans.last_mut() {
+                    Some(last)
+                        if last.peer == span.peer && last.counter.end == span.counter.start =>
+                    {
+                        last.counter.end = span.counter.end;
+                    }
+                    _ => ans.push(span),
+                }
+            }
+
+            index += len;
+        }
+
+        ans
+    }
+
     pub fn get_richtext_value(&self) -> LoroValue {
         let mut ans: Vec<LoroValue> = Vec::new();
         let mut last_attributes: Option<LoroValue> = None;
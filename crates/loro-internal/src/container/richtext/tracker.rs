@@ -27,6 +27,12 @@ pub(crate) struct Tracker {
     current_vv: VersionVector,
     rope: CrdtRope,
     id_to_cursor: IdToCursor,
+    /// The leaf of the last inserted span and the position right after it.
+    ///
+    /// When typing, each insertion starts right after the previous one, so it can be
+    /// appended to the span without searching the rope. It's cleared whenever the rope
+    /// is changed in any other way.
+    last_insert: Option<(LeafIndex, usize)>,
 }
 
 impl Default for Tracker {
@@ -43,6 +49,7 @@ impl Tracker {
             id_to_cursor: IdToCursor::default(),
             applied_vv: Default::default(),
             current_vv: Default::default(),
+            last_insert: None,
         };
 
         let result = this.rope.tree.push(FugueSpan {
@@ -68,6 +75,7 @@ impl Tracker {
             id_to_cursor: IdToCursor::default(),
             applied_vv: Default::default(),
             current_vv: Default::default(),
+            last_insert: None,
         }
     }
 
@@ -104,29 +112,36 @@ impl Tracker {
     }
 
     fn _insert(&mut self, pos: usize, content: RichtextChunk, op_id: IdFull) {
-        let result = self.rope.insert(
-            pos,
-            FugueSpan {
-                content,
-                id: op_id,
-                real_id: if op_id.peer == UNKNOWN_PEER_ID {
-                    None
-                } else {
-                    Some(op_id.id().try_into().unwrap())
-                },
-                status: Status::default(),
-                diff_status: None,
-                origin_left: None,
-                origin_right: None,
+        let span = FugueSpan {
+            content,
+            id: op_id,
+            real_id: if op_id.peer == UNKNOWN_PEER_ID {
+                None
+            } else {
+                Some(op_id.id().try_into().unwrap())
             },
-            |id| self.id_to_cursor.get_insert(id).unwrap(),
-        );
+            status: Status::default(),
+            diff_status: None,
+            origin_left: None,
+            origin_right: None,
+        };
+
+        let leaf = match self.last_insert.take() {
+            Some((leaf, end)) if end == pos && self.rope.try_append(leaf, span) => leaf,
+            _ => {
+                let result = self
+                    .rope
+                    .insert(pos, span, |id| self.id_to_cursor.get_insert(id).unwrap());
+                self.update_insert_by_split(&result.splitted.arr);
+                result.leaf
+            }
+        };
+
         self.id_to_cursor.insert(
             op_id.id(),
-            id_to_cursor::Cursor::new_insert(result.leaf, content.len()),
+            id_to_cursor::Cursor::new_insert(leaf, content.len()),
         );
-
-        self.update_insert_by_split(&result.splitted.arr);
+        self.last_insert = Some((leaf, pos + content.len()));
 
         let end_id = op_id.inc(content.len() as Counter);
         self.current_vv.extend_to_include_end_id(end_id.id());
@@ -172,6 +187,7 @@ impl Tracker {
     }
 
     fn _delete(&mut self, target_start_id: ID, pos: usize, len: usize, reverse: bool, op_id: ID) {
+        self.last_insert = None;
         let mut ans = Vec::new();
         let split = self
             .rope
@@ -254,6 +270,7 @@ impl Tracker {
         //
         // > `id_to_cursor` only stores the mappings from **fake** insert id to the leaf index.
         // > **Fake** means the id may be a temporary placeholder, created with UNKNOWN_PEER_ID.
+        self.last_insert = None;
         let mut fake_delete_id = None;
         let split = self.rope.delete(deleted_id, from_pos, 1, false, &mut |s| {
             debug_assert_eq!(s.rle_len(), 1);
@@ -304,6 +321,7 @@ impl Tracker {
 
     fn _checkout(&mut self, vv: &VersionVector, on_diff_status: bool) {
         // tracing::info!("Checkout to {:?} from {:?}", vv, self.current_vv);
+        self.last_insert = None;
         if on_diff_status {
             self.rope.clear_diff_status();
         }
@@ -412,6 +430,7 @@ impl Tracker {
     }
 
     fn batch_update(&mut self, updates: Vec<crdt_rope::LeafUpdate>, on_diff_status: bool) {
+        self.last_insert = None;
         let leaf_indexes = self.rope.update(updates, on_diff_status);
         self.update_insert_by_split(&leaf_indexes);
     }
@@ -639,6 +658,33 @@ mod test {
         assert_eq!(t.rope.len(), 10);
     }
 
+    #[test]
+    fn test_typing_appends_to_last_span() {
+        let mut t = Tracker::new();
+        for i in 0..10 {
+            t.insert(
+                IdFull::new(1, i, i as Lamport),
+                i as usize,
+                RichtextChunk::new_text(i as u32..i as u32 + 1),
+            );
+        }
+        assert_eq!(t.rope.tree().iter().count(), 1);
+        assert_eq!(t.rope.len(), 10);
+        t.check();
+
+        // Not continuing the last span
+        t.insert(IdFull::new(1, 10, 10), 0, RichtextChunk::new_text(10..11));
+        t.insert(IdFull::new(2, 0, 11), 1, RichtextChunk::new_text(11..12));
+        assert_eq!(t.rope.tree().iter().count(), 3);
+        assert_eq!(t.rope.len(), 12);
+        t.check();
+
+        t.checkout(&vv!(1 => 5));
+        assert_eq!(t.rope.len(), 5);
+        t.checkout(&vv!(1 => 11, 2 => 1));
+        assert_eq!(t.rope.len(), 12);
+    }
+
     #[test]
     fn test_checkout_in_doc_with_del_span() {
         let mut t = Tracker::new();
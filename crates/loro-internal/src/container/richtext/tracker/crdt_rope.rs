@@ -1,7 +1,7 @@
 use std::cmp::Ordering;
 
 use generic_btree::{
-    rle::{CanRemove, HasLength, Sliceable},
+    rle::{CanRemove, HasLength, Mergeable, Sliceable},
     BTree, BTreeTrait, Cursor, FindResult, LeafIndex, Query, SplittedLeaves,
 };
 use itertools::Itertools;
@@ -226,6 +226,40 @@ impl CrdtRope {
         }
     }
 
+    /// Append `content` to the span in `leaf` if it continues the span, i.e. it has the
+    /// next id of the same peer and can be merged into it.
+    ///
+    /// The caller must make sure `content` is inserted right after the span, and the
+    /// rope has not been changed since the span was inserted. Then the origins of
+    /// `content` are the same as if it's inserted by [CrdtRope::insert].
+    pub(super) fn try_append(&mut self, leaf: LeafIndex, mut content: FugueSpan) -> bool {
+        let Some(elem) = self.tree.get_elem(leaf) else {
+            return false;
+        };
+
+        if elem.rle_len() == 0 || content.id.peer == UNKNOWN_PEER_ID {
+            return false;
+        }
+
+        content.origin_left = Some(
+            elem.id
+                .inc(elem.rle_len() as Counter - 1)
+                .id()
+                .try_into()
+                .unwrap(),
+        );
+        content.origin_right = elem.origin_right;
+        if !elem.can_merge(&content) {
+            return false;
+        }
+
+        let (still_valid, _) = self.tree.update_leaf(leaf, |elem| {
+            elem.merge_right(&content);
+            (true, None, None)
+        });
+        still_valid
+    }
+
     /// Delete the span at `pos` with `len`.
     ///
     /// The `start_id` is used to mark the real_id in the [FugueSpan].
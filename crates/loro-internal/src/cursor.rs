@@ -1,6 +1,25 @@
-use loro_common::{ContainerID, ID};
+//! The stable positions in the seq containers.
+//!
+//! A [Cursor] can be persisted with [Cursor::encode] or its string form, e.g. to store the
+//! selections of the users or the anchors of the comments. Both forms are versioned, and
+//! the later versions of Loro can always decode them:
+//!
+//! - Binary: `[MAGIC] [version] [side] [has id] ([peer] [counter]) [origin pos] [container]`,
+//!   where the numbers are LEB128, and the container is `[0] [name len] [name] [type]` for
+//!   a root container or `[1] [peer] [counter] [type]` for a normal one.
+//! - String: `c1:{side}:{origin pos}:{id or -}:{container id}`, e.g.
+//!   `c1:M:5:3@7:cid:root-text:Text`.
+//!
+//! The bytes encoded by the older versions, which use postcard, can still be decoded.
+use std::fmt::Display;
+
+use loro_common::{ContainerID, ContainerType, LoroError, LoroResult, ID};
 use serde::{Deserialize, Serialize};
 
+/// The first byte of an encoded cursor. The legacy encoding starts with 0 or 1.
+const MAGIC: u8 = 0xC5;
+const VERSION: u8 = 1;
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Cursor {
     // It's option because it's possible that the given container is empty.
@@ -11,8 +30,9 @@ pub struct Cursor {
     /// Side info can help to model the selection
     pub side: Side,
     /// The position of the cursor in the container when the cursor is created.
-    /// For text, this is the unicode codepoint index
-    /// This value is not encoded
+    /// For text, this is the unicode codepoint index.
+    ///
+    /// It's used as the fallback when the id is not in the history of the doc.
     pub(crate) origin_pos: usize,
 }
 
@@ -43,6 +63,10 @@ impl Side {
 pub struct PosQueryResult {
     pub update: Option<Cursor>,
     pub current: AbsolutePosition,
+    /// The id of the cursor is not in the history of the doc, e.g. the history was trimmed,
+    /// so `current` is the position the cursor was created at, clamped to the length of the
+    /// container. It may not be where the target is now.
+    pub approximate: bool,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -72,11 +96,182 @@ impl Cursor {
         }
     }
 
+    /// Encode the cursor in the binary format. See the [module docs](self).
     pub fn encode(&self) -> Vec<u8> {
-        postcard::to_allocvec(self).unwrap()
+        let mut out = vec![MAGIC, VERSION, (self.side.to_i32() + 1) as u8];
+        match self.id {
+            Some(id) => {
+                out.push(1);
+                write_u64(&mut out, id.peer);
+                write_u64(&mut out, id.counter as u32 as u64);
+            }
+            None => out.push(0),
+        }
+
+        write_u64(&mut out, self.origin_pos as u64);
+        match &self.container {
+            ContainerID::Root {
+                name,
+                container_type,
+            } => {
+                out.push(0);
+                write_u64(&mut out, name.len() as u64);
+                out.extend_from_slice(name.as_bytes());
+                out.push(container_type.to_u8());
+            }
+            ContainerID::Normal {
+                peer,
+                counter,
+                container_type,
+            } => {
+                out.push(1);
+                write_u64(&mut out, *peer);
+                write_u64(&mut out, *counter as u32 as u64);
+                out.push(container_type.to_u8());
+            }
+        }
+
+        out
+    }
+
+    /// Decode the cursor encoded by [Cursor::encode] of this or an older version.
+    pub fn decode(data: &[u8]) -> LoroResult<Self> {
+        match data.first() {
+            Some(&MAGIC) => {}
+            _ => {
+                let cursor: Cursor = postcard::from_bytes(data)
+                    .map_err(|e| LoroError::DecodeError(e.to_string().into_boxed_str()))?;
+                check_container(&cursor.container)?;
+                return Ok(cursor);
+            }
+        }
+
+        let mut input = &data[1..];
+        let version = read_u8(&mut input)?;
+        if version != VERSION {
+            return Err(LoroError::DecodeError(
+                format!("Unknown cursor encoding version {version}").into_boxed_str(),
+            ));
+        }
+
+        let side = Side::from_i32(read_u8(&mut input)? as i32 - 1).ok_or_else(corrupted)?;
+        let id = match read_u8(&mut input)? {
+            0 => None,
+            1 => {
+                let peer = read_u64(&mut input)?;
+                let counter = read_counter(&mut input)?;
+                Some(ID::new(peer, counter))
+            }
+            _ => return Err(corrupted()),
+        };
+
+        let origin_pos = read_u64(&mut input)? as usize;
+        let container = match read_u8(&mut input)? {
+            0 => {
+                let len = read_u64(&mut input)? as usize;
+                if len > input.len() {
+                    return Err(corrupted());
+                }
+
+                let name = std::str::from_utf8(&input[..len]).map_err(|_| corrupted())?;
+                input = &input[len..];
+                ContainerID::new_root(name, ContainerType::try_from_u8(read_u8(&mut input)?)?)
+            }
+            1 => {
+                let peer = read_u64(&mut input)?;
+                let counter = read_counter(&mut input)?;
+                let container_type = ContainerType::try_from_u8(read_u8(&mut input)?)?;
+                ContainerID::new_normal(ID::new(peer, counter), container_type)
+            }
+            _ => return Err(corrupted()),
+        };
+
+        if !input.is_empty() {
+            return Err(corrupted());
+        }
+
+        check_container(&container)?;
+        Ok(Cursor::new(id, container, side, origin_pos))
     }
+}
+
+fn corrupted() -> LoroError {
+    LoroError::DecodeError("Invalid cursor".into())
+}
+
+/// Only the sequence containers have cursors
+fn check_container(container: &ContainerID) -> LoroResult<()> {
+    match container.container_type() {
+        ContainerType::Text | ContainerType::List | ContainerType::MovableList => Ok(()),
+        _ => Err(corrupted()),
+    }
+}
+
+fn write_u64(out: &mut Vec<u8>, value: u64) {
+    leb128::write::unsigned(out, value).unwrap();
+}
+
+fn read_u64(input: &mut &[u8]) -> LoroResult<u64> {
+    leb128::read::unsigned(input).map_err(|_| corrupted())
+}
+
+fn read_counter(input: &mut &[u8]) -> LoroResult<i32> {
+    let counter = read_u64(input)?;
+    u32::try_from(counter)
+        .map(|x| x as i32)
+        .map_err(|_| corrupted())
+}
+
+fn read_u8(input: &mut &[u8]) -> LoroResult<u8> {
+    let (&first, rest) = input.split_first().ok_or_else(corrupted)?;
+    *input = rest;
+    Ok(first)
+}
+
+impl Display for Cursor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let side = match self.side {
+            Side::Left => 'L',
+            Side::Middle => 'M',
+            Side::Right => 'R',
+        };
+        write!(f, "c{VERSION}:{side}:{}:", self.origin_pos)?;
+        match self.id {
+            Some(id) => write!(f, "{id}")?,
+            None => f.write_str("-")?,
+        }
+        write!(f, ":{}", self.container)
+    }
+}
+
+impl TryFrom<&str> for Cursor {
+    type Error = LoroError;
+
+    /// Parse the string form of a cursor. See the [module docs](self).
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        // The name of a root container may contain ':', so the container id is the rest
+        let mut iter = value.splitn(5, ':');
+        let mut next = || iter.next().ok_or_else(corrupted);
+        let version = next()?;
+        if version != format!("c{VERSION}") {
+            return Err(LoroError::DecodeError(
+                format!("Unknown cursor format version {version}").into_boxed_str(),
+            ));
+        }
 
-    pub fn decode(data: &[u8]) -> Result<Self, postcard::Error> {
-        postcard::from_bytes(data)
+        let side = match next()? {
+            "L" => Side::Left,
+            "M" => Side::Middle,
+            "R" => Side::Right,
+            _ => return Err(corrupted()),
+        };
+        let origin_pos = next()?.parse::<usize>().map_err(|_| corrupted())?;
+        let id = match next()? {
+            "-" => None,
+            id => Some(ID::try_from(id)?),
+        };
+        let container = ContainerID::try_from(next()?).map_err(|_| corrupted())?;
+        check_container(&container)?;
+        Ok(Cursor::new(id, container, side, origin_pos))
     }
 }
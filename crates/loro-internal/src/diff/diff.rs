@@ -81,6 +81,10 @@ impl<D: DiffHandler> OperateProxy<D> {
         }
     }
 
+    pub fn into_handler(self) -> D {
+        self.handler
+    }
+
     pub fn flush_del_ins(&mut self) {
         if let Some((del_old_index, del_old_len)) = self.del.take() {
             if let Some((_, ins_new_index, ins_new_len)) = self.ins.take() {
@@ -76,7 +76,7 @@ impl TreeDiffCalculator {
             tracing::info!("checkout: to == current_vv");
             return;
         }
-        let to_frontiers = to.to_frontiers(&oplog.dag);
+        let to_frontiers = oplog.dag.vv_to_frontiers(to);
         let min_lamport = self.get_min_lamport_by_frontiers(&to_frontiers, oplog);
         // retreat
         let mut retreat_ops = vec![];
@@ -100,7 +100,7 @@ impl TreeDiffCalculator {
             ));
         }
         // forward and apply
-        let current_frontiers = tree_cache.current_vv.to_frontiers(&oplog.dag);
+        let current_frontiers = oplog.dag.vv_to_frontiers(&tree_cache.current_vv);
         let forward_min_lamport = self
             .get_min_lamport_by_frontiers(&current_frontiers, oplog)
             .min(min_lamport);
@@ -146,13 +146,13 @@ impl TreeDiffCalculator {
 
         let s = tracing::span!(tracing::Level::INFO, "checkout_diff");
         let _e = s.enter();
-        let to_frontiers = to.to_frontiers(&oplog.dag);
-        let from_frontiers = from.to_frontiers(&oplog.dag);
+        let to_frontiers = oplog.dag.vv_to_frontiers(to);
+        let from_frontiers = oplog.dag.vv_to_frontiers(from);
         let common_ancestors = oplog
             .dag
             .find_common_ancestor(&from_frontiers, &to_frontiers);
         let lca_vv = oplog.dag.frontiers_to_vv(&common_ancestors).unwrap();
-        let lca_frontiers = lca_vv.to_frontiers(&oplog.dag);
+        let lca_frontiers = oplog.dag.vv_to_frontiers(&lca_vv);
         tracing::info!(
             "from vv {:?} to vv {:?} current vv {:?} lca vv {:?}",
             from,
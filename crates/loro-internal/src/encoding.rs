@@ -1,6 +1,7 @@
 mod arena;
 mod encode_reordered;
 pub(crate) mod json_schema;
+mod lz;
 mod value;
 pub(crate) use value::OwnedValue;
 
@@ -8,11 +9,20 @@ use crate::op::OpWithId;
 use crate::version::Frontiers;
 use crate::LoroDoc;
 use crate::{oplog::OpLog, LoroError, VersionVector};
-use loro_common::{IdLpSpan, LoroResult, PeerID};
+use loro_common::{ContainerID, Counter, IdLpSpan, LoroResult, LoroValue, PeerID};
 use num_traits::{FromPrimitive, ToPrimitive};
 use rle::{HasLength, Sliceable};
 use serde::{Deserialize, Serialize};
 const MAGIC_BYTES: [u8; 4] = *b"loro";
+/// Set in the mode of the [EncodeMode::Rle] and [EncodeMode::Snapshot] blobs whose op
+/// counters are implicit, see [crate::LoroDoc::set_implicit_op_counters].
+///
+/// The counter column of the ops only stores the gap from the end of the previous op of
+/// the same peer, so the consecutive ops of a peer, e.g. typing, cost a single run of 0s
+/// however long they are. It's off by default, so the blobs keep the old layout that the
+/// deployed versions read. Those versions reject the blobs with the flag as a future
+/// encoding mode instead of misreading them.
+const IMPLICIT_COUNTERS_FLAG: u16 = 1 << 8;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(crate) enum EncodeMode {
@@ -20,6 +30,11 @@ pub(crate) enum EncodeMode {
     Auto = 255,
     Rle = 1,
     Snapshot = 2,
+    /// A sequence of update blobs split at stable boundaries, see [export_for_backup]
+    Chunked = 3,
+    /// The sections that can be decoded on their own, e.g. a state section and a history
+    /// section, see [split_sections]
+    Sectioned = 4,
 }
 
 impl num_traits::FromPrimitive for EncodeMode {
@@ -32,6 +47,10 @@ impl num_traits::FromPrimitive for EncodeMode {
             Some(EncodeMode::Rle)
         } else if n == EncodeMode::Snapshot as i64 {
             Some(EncodeMode::Snapshot)
+        } else if n == EncodeMode::Chunked as i64 {
+            Some(EncodeMode::Chunked)
+        } else if n == EncodeMode::Sectioned as i64 {
+            Some(EncodeMode::Sectioned)
         } else {
             None
         }
@@ -50,6 +69,8 @@ impl num_traits::ToPrimitive for EncodeMode {
             EncodeMode::Auto => EncodeMode::Auto as i64,
             EncodeMode::Rle => EncodeMode::Rle as i64,
             EncodeMode::Snapshot => EncodeMode::Snapshot as i64,
+            EncodeMode::Chunked => EncodeMode::Chunked as i64,
+            EncodeMode::Sectioned => EncodeMode::Sectioned as i64,
         })
     }
     #[inline]
@@ -144,29 +165,290 @@ pub(crate) fn encode_oplog(oplog: &OpLog, vv: &VersionVector, mode: EncodeMode)
         mode => mode,
     };
 
+    let implicit_counters = oplog.configure.implicit_op_counters();
     let body = match &mode {
-        EncodeMode::Rle => encode_reordered::encode_updates(oplog, vv),
+        EncodeMode::Rle => encode_reordered::encode_updates(oplog, vv, implicit_counters),
         _ => unreachable!(),
     };
 
-    encode_header_and_body(mode, body)
+    encode_header_and_ops_body(mode, implicit_counters, body)
+}
+
+/// Check that decoding the body is estimated to need at most `budget` bytes of memory.
+///
+/// The estimate is a heuristic, see [encode_reordered::check_decode_budget].
+pub(crate) fn check_decode_budget(parsed: &ParsedHeaderAndBody, budget: usize) -> LoroResult<()> {
+    if parsed.mode == EncodeMode::Sectioned {
+        // The state section has all the ops of the history section and the states
+        let state = parse_header_and_body(split_sections(parsed.body)?.state()?)?;
+        return check_decode_budget(&state, budget);
+    }
+
+    if parsed.mode != EncodeMode::Chunked {
+        return encode_reordered::check_decode_budget(parsed.body, budget).map(|_| ());
+    }
+
+    let mut needed = 0;
+    for chunk in split_chunks(parsed.body)? {
+        let chunk = parse_header_and_body(chunk)?;
+        needed += encode_reordered::check_decode_budget(chunk.body, budget - needed).map_err(
+            |e| match e {
+                LoroError::BudgetExceeded { needed: n, .. } => LoroError::BudgetExceeded {
+                    needed: needed + n,
+                    budget,
+                },
+                e => e,
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Export the ops from `start` to `end` as an update blob.
+pub(crate) fn encode_oplog_in_range(
+    oplog: &OpLog,
+    start: &VersionVector,
+    end: &VersionVector,
+) -> Vec<u8> {
+    let implicit_counters = oplog.configure.implicit_op_counters();
+    let body = encode_reordered::encode_updates_in_range(oplog, start, end, implicit_counters);
+    encode_header_and_ops_body(EncodeMode::Rle, implicit_counters, body)
 }
 
 pub(crate) fn decode_oplog(
     oplog: &mut OpLog,
     parsed: ParsedHeaderAndBody,
 ) -> Result<(), LoroError> {
-    let ParsedHeaderAndBody { mode, body, .. } = parsed;
+    let ParsedHeaderAndBody {
+        mode,
+        body,
+        implicit_counters,
+        ..
+    } = parsed;
     match mode {
-        EncodeMode::Rle | EncodeMode::Snapshot => encode_reordered::decode_updates(oplog, body),
+        EncodeMode::Rle | EncodeMode::Snapshot => {
+            encode_reordered::decode_updates(oplog, body, implicit_counters)
+        }
+        EncodeMode::Chunked => {
+            for chunk in split_chunks(body)? {
+                let parsed = parse_header_and_body(chunk)?;
+                if parsed.mode != EncodeMode::Rle {
+                    return Err(LoroError::DecodeError("Invalid chunk".into()));
+                }
+
+                encode_reordered::decode_updates(oplog, parsed.body, parsed.implicit_counters)?;
+            }
+
+            Ok(())
+        }
+        EncodeMode::Sectioned => {
+            let parsed = parse_header_and_body(split_sections(body)?.history()?)?;
+            if parsed.mode != EncodeMode::Rle {
+                return Err(LoroError::DecodeError("Invalid history section".into()));
+            }
+
+            encode_reordered::decode_updates(oplog, parsed.body, parsed.implicit_counters)
+        }
         EncodeMode::Auto => unreachable!(),
     }
 }
 
+/// A chunk ends after about this many ops on average
+const AVG_CHUNK_OPS: u64 = 1024;
+
+/// Whether a chunk of the ops of `peer` ends after the op at `counter`.
+///
+/// It only depends on the id of the op, so the boundaries of the ops that are already in
+/// the doc never move, and only the last chunk of each peer changes when the doc grows.
+fn is_chunk_boundary(peer: PeerID, counter: Counter) -> bool {
+    // splitmix64, so that the boundaries don't depend on the hasher of the std
+    let mut x = peer ^ (counter as u64).wrapping_mul(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^= x >> 31;
+    x.is_multiple_of(AVG_CHUNK_OPS)
+}
+
+/// Export all the ops as update blobs that are split at content-defined boundaries.
+///
+/// The chunks of a peer cover consecutive counter ranges, and the boundaries are decided
+/// by [is_chunk_boundary]. The encoding of a range only depends on the ops in it, so
+/// successive exports of a growing doc share all the chunks but the last one of each
+/// peer that has edited since, which is what rsync and dedup-based backups need.
+pub(crate) fn export_for_backup(oplog: &OpLog) -> Vec<u8> {
+    let mut peers: Vec<(PeerID, Counter)> = oplog.vv().iter().map(|(&p, &c)| (p, c)).collect();
+    peers.sort_unstable();
+    let mut body = Vec::new();
+    let mut write_chunk = |peer: PeerID, start: Counter, end: Counter| {
+        let start_vv: VersionVector = [(peer, start)].into_iter().collect();
+        let end_vv: VersionVector = [(peer, end)].into_iter().collect();
+        let chunk = encode_oplog_in_range(oplog, &start_vv, &end_vv);
+        leb128::write::unsigned(&mut body, chunk.len() as u64).unwrap();
+        body.extend_from_slice(&chunk);
+    };
+    for (peer, end) in peers {
+        let mut start = 0;
+        for counter in 0..end {
+            if is_chunk_boundary(peer, counter) {
+                write_chunk(peer, start, counter + 1);
+                start = counter + 1;
+            }
+        }
+
+        if start < end {
+            write_chunk(peer, start, end);
+        }
+    }
+
+    encode_header_and_body(EncodeMode::Chunked, body)
+}
+
+/// Split the body of a [EncodeMode::Chunked] blob into the chunks, each of which is an
+/// update blob with its own header.
+fn split_chunks(mut body: &[u8]) -> LoroResult<Vec<&[u8]>> {
+    let mut ans = Vec::new();
+    while !body.is_empty() {
+        let len = leb128::read::unsigned(&mut body)
+            .map_err(|_| LoroError::DecodeDataCorruptionError)? as usize;
+        if len > body.len() {
+            return Err(LoroError::DecodeDataCorruptionError);
+        }
+
+        let (chunk, rest) = body.split_at(len);
+        ans.push(chunk);
+        body = rest;
+    }
+
+    Ok(ans)
+}
+
+/// Export the current state and the history as two sections that can be decoded on their
+/// own, see [split_sections] for the layout.
+///
+/// The state section is a snapshot blob, so it inits a doc without replaying the history.
+/// The history section is an update blob from the empty version, so an archival tool can
+/// copy it out as is. Each of them has its own header and checksum.
+pub(crate) fn export_sectioned_snapshot(doc: &LoroDoc) -> Vec<u8> {
+    let state = export_snapshot(doc);
+    let history = encode_oplog(
+        &doc.oplog().lock().unwrap(),
+        &Default::default(),
+        EncodeMode::Rle,
+    );
+    let mut body = Vec::with_capacity(state.len() + history.len() + 16);
+    write_section(&mut body, STATE_SECTION, &state);
+    write_section(&mut body, HISTORY_SECTION, &history);
+    encode_header_and_body(EncodeMode::Sectioned, body)
+}
+
+/// Export the deep value of the doc for the services that only render a read-only preview,
+/// e.g. link previews and search indexing.
+///
+/// It's a [EncodeMode::Sectioned] blob with only a preview section, which is the deep
+/// value with the texts as rich text deltas, encoded by postcard. It has no history,
+/// tombstones or op metadata, so it's usually much smaller than a snapshot, but it can't
+/// be imported. It's read by [LoroDoc::decode_preview].
+pub(crate) fn export_preview(doc: &LoroDoc) -> LoroResult<Vec<u8>> {
+    let preview = postcard::to_allocvec(&doc.get_deep_value_with_richtext())
+        .map_err(|e| LoroError::Unknown(format!("Failed to encode the preview: {e}").into()))?;
+    let mut body = Vec::with_capacity(preview.len() + 8);
+    write_section(&mut body, PREVIEW_SECTION, &preview);
+    Ok(encode_header_and_body(EncodeMode::Sectioned, body))
+}
+
+fn preview_error() -> LoroError {
+    LoroError::DecodeError(
+        "A preview has no history, so it can't be imported. Read it with LoroDoc::decode_preview"
+            .into(),
+    )
+}
+
+/// The kind of the section that is a snapshot blob
+const STATE_SECTION: u8 = 0;
+/// The kind of the section that is an update blob from the empty version
+const HISTORY_SECTION: u8 = 1;
+/// The kind of the section that is the deep value for a preview, see [export_preview]
+const PREVIEW_SECTION: u8 = 2;
+
+fn write_section(body: &mut Vec<u8>, kind: u8, section: &[u8]) {
+    body.push(kind);
+    leb128::write::unsigned(body, section.len() as u64).unwrap();
+    body.extend_from_slice(section);
+}
+
+#[derive(Default)]
+struct Sections<'a> {
+    state: Option<&'a [u8]>,
+    history: Option<&'a [u8]>,
+    preview: Option<&'a [u8]>,
+}
+
+impl<'a> Sections<'a> {
+    fn state(&self) -> LoroResult<&'a [u8]> {
+        self.state.ok_or_else(|| self.missing("state"))
+    }
+
+    fn history(&self) -> LoroResult<&'a [u8]> {
+        self.history.ok_or_else(|| self.missing("history"))
+    }
+
+    fn missing(&self, section: &str) -> LoroError {
+        if self.preview.is_some() {
+            return preview_error();
+        }
+
+        LoroError::DecodeError(format!("The blob has no {section} section").into())
+    }
+}
+
+/// Split the body of a [EncodeMode::Sectioned] blob into its sections.
+///
+/// The body is a sequence of sections, each laid out as `[kind] [len] [section]`, where the
+/// kind is a byte and the len is LEB128. So a reader skips the sections it doesn't need
+/// without reading their bytes, and the sections of unknown kinds are skipped too.
+fn split_sections(mut body: &[u8]) -> LoroResult<Sections<'_>> {
+    let mut ans = Sections::default();
+    while let Some((&kind, rest)) = body.split_first() {
+        body = rest;
+        let len = leb128::read::unsigned(&mut body)
+            .map_err(|_| LoroError::DecodeDataCorruptionError)? as usize;
+        if len > body.len() {
+            return Err(LoroError::DecodeDataCorruptionError);
+        }
+
+        let (section, rest) = body.split_at(len);
+        body = rest;
+        let slot = match kind {
+            STATE_SECTION => &mut ans.state,
+            HISTORY_SECTION => &mut ans.history,
+            PREVIEW_SECTION => &mut ans.preview,
+            _ => continue,
+        };
+        if slot.replace(section).is_some() {
+            return Err(LoroError::DecodeDataCorruptionError);
+        }
+    }
+
+    Ok(ans)
+}
+
+/// The state section of a [EncodeMode::Sectioned] blob. Its checksum is checked when it's
+/// decoded.
+pub(crate) fn state_section<'a>(parsed: &ParsedHeaderAndBody<'a>) -> LoroResult<&'a [u8]> {
+    if parsed.mode != EncodeMode::Sectioned {
+        return Err(LoroError::DecodeError("Not a sectioned snapshot".into()));
+    }
+
+    split_sections(parsed.body)?.state()
+}
+
 pub(crate) struct ParsedHeaderAndBody<'a> {
     pub checksum: [u8; 16],
     pub checksum_body: &'a [u8],
     pub mode: EncodeMode,
+    /// See [IMPLICIT_COUNTERS_FLAG]
+    pub implicit_counters: bool,
     pub body: &'a [u8],
 }
 
@@ -183,6 +465,13 @@ impl ParsedHeaderAndBody<'_> {
 
 const MIN_HEADER_SIZE: usize = 22;
 pub(crate) fn parse_header_and_body(bytes: &[u8]) -> Result<ParsedHeaderAndBody, LoroError> {
+    let ans = parse_header_unchecked(bytes)?;
+    ans.check_checksum()?;
+    Ok(ans)
+}
+
+/// Parse the header without checking the checksum, which needs to read the whole body.
+fn parse_header_unchecked(bytes: &[u8]) -> Result<ParsedHeaderAndBody<'_>, LoroError> {
     let reader = &bytes;
     if bytes.len() < MIN_HEADER_SIZE {
         return Err(LoroError::DecodeError("Invalid import data".into()));
@@ -197,25 +486,47 @@ pub(crate) fn parse_header_and_body(bytes: &[u8]) -> Result<ParsedHeaderAndBody,
     let (checksum, reader) = reader.split_at(16);
     let checksum_body = reader;
     let (mode_bytes, reader) = reader.split_at(2);
-    let mode: EncodeMode = [mode_bytes[0], mode_bytes[1]].try_into()?;
+    let mode = u16::from_be_bytes([mode_bytes[0], mode_bytes[1]]);
+    let implicit_counters = mode & IMPLICIT_COUNTERS_FLAG != 0;
+    let mode: EncodeMode = (mode & !IMPLICIT_COUNTERS_FLAG).to_be_bytes().try_into()?;
+    if implicit_counters && !matches!(mode, EncodeMode::Rle | EncodeMode::Snapshot) {
+        return Err(LoroError::IncompatibleFutureEncodingError(
+            u16::from_be_bytes([mode_bytes[0], mode_bytes[1]]) as usize,
+        ));
+    }
 
     let ans = ParsedHeaderAndBody {
         mode,
+        implicit_counters,
         checksum_body,
         checksum: checksum.try_into().unwrap(),
         body: reader,
     };
 
-    ans.check_checksum()?;
     Ok(ans)
 }
 
 fn encode_header_and_body(mode: EncodeMode, body: Vec<u8>) -> Vec<u8> {
+    write_header_and_body(mode.to_bytes(), body)
+}
+
+/// Like [encode_header_and_body] for the [EncodeMode::Rle] and [EncodeMode::Snapshot]
+/// blobs, whose mode has [IMPLICIT_COUNTERS_FLAG] if the op counters are implicit.
+fn encode_header_and_ops_body(mode: EncodeMode, implicit_counters: bool, body: Vec<u8>) -> Vec<u8> {
+    let mut mode = mode.to_u16().unwrap();
+    if implicit_counters {
+        mode |= IMPLICIT_COUNTERS_FLAG;
+    }
+
+    write_header_and_body(mode.to_be_bytes(), body)
+}
+
+fn write_header_and_body(mode: [u8; 2], body: Vec<u8>) -> Vec<u8> {
     let mut ans = Vec::new();
     ans.extend(MAGIC_BYTES);
     let checksum = [0; 16];
     ans.extend(checksum);
-    ans.extend(mode.to_bytes());
+    ans.extend(mode);
     ans.extend(body);
     let checksum_body = &ans[20..];
     let checksum = md5::compute(checksum_body).0;
@@ -224,22 +535,26 @@ fn encode_header_and_body(mode: EncodeMode, body: Vec<u8>) -> Vec<u8> {
 }
 
 pub(crate) fn export_snapshot(doc: &LoroDoc) -> Vec<u8> {
+    let implicit_counters = doc.config().implicit_op_counters();
     let body = encode_reordered::encode_snapshot(
         &doc.oplog().try_lock().unwrap(),
         &doc.app_state().try_lock().unwrap(),
         &Default::default(),
+        doc.config().dedup_snapshot_text(),
+        implicit_counters,
     );
 
-    encode_header_and_body(EncodeMode::Snapshot, body)
+    encode_header_and_ops_body(EncodeMode::Snapshot, implicit_counters, body)
 }
 
 pub(crate) fn decode_snapshot(
     doc: &LoroDoc,
-    mode: EncodeMode,
-    body: &[u8],
+    parsed: &ParsedHeaderAndBody,
 ) -> Result<(), LoroError> {
-    match mode {
-        EncodeMode::Snapshot => encode_reordered::decode_snapshot(doc, body),
+    match parsed.mode {
+        EncodeMode::Snapshot => {
+            encode_reordered::decode_snapshot(doc, parsed.body, parsed.implicit_counters)
+        }
         _ => unreachable!(),
     }
 }
@@ -268,6 +583,251 @@ pub struct ImportBlobMetadata {
 impl LoroDoc {
     /// Decodes the metadata for an imported blob from the provided bytes.
     pub fn decode_import_blob_meta(blob: &[u8]) -> LoroResult<ImportBlobMetadata> {
-        encode_reordered::decode_import_blob_meta(blob)
+        let parsed = parse_header_and_body(blob)?;
+        if parsed.mode == EncodeMode::Sectioned {
+            // It's imported by its state section if the doc is empty, like a snapshot
+            return Self::decode_import_blob_meta(state_section(&parsed)?);
+        }
+
+        if parsed.mode != EncodeMode::Chunked {
+            return encode_reordered::decode_import_blob_meta(blob);
+        }
+
+        // The chunks cover all the ops from the empty version. A change that spans a
+        // boundary is counted once per chunk.
+        let mut ans = ImportBlobMetadata {
+            partial_start_vv: Default::default(),
+            partial_end_vv: Default::default(),
+            start_timestamp: i64::MAX,
+            start_frontiers: Default::default(),
+            end_timestamp: i64::MIN,
+            change_num: 0,
+            is_snapshot: false,
+        };
+        for chunk in split_chunks(parsed.body)? {
+            let meta = encode_reordered::decode_import_blob_meta(chunk)?;
+            ans.partial_end_vv.merge(&meta.partial_end_vv);
+            ans.start_timestamp = ans.start_timestamp.min(meta.start_timestamp);
+            ans.end_timestamp = ans.end_timestamp.max(meta.end_timestamp);
+            ans.change_num += meta.change_num;
+        }
+
+        Ok(ans)
+    }
+
+    /// Get the state section of a blob exported by [LoroDoc::export_sectioned_snapshot]
+    /// without reading the history section.
+    ///
+    /// It's a snapshot blob, which inits a doc on its own, e.g. by [LoroDoc::from_snapshot].
+    /// Its checksum is checked when it's imported.
+    pub fn state_section(blob: &[u8]) -> LoroResult<&[u8]> {
+        state_section(&parse_header_unchecked(blob)?)
+    }
+
+    /// Decode the state section of a blob exported by
+    /// [LoroDoc::export_sectioned_snapshot] into the deep value of the doc.
+    ///
+    /// Only the header and the state section are read, so it doesn't pay for the history
+    /// section.
+    pub fn decode_state_section(blob: &[u8]) -> LoroResult<LoroValue> {
+        let doc = LoroDoc::from_snapshot(Self::state_section(blob)?)?;
+        Ok(doc.get_deep_value())
+    }
+
+    /// Decode the deep value of the doc, with the texts as rich text deltas, from a blob
+    /// exported by [LoroDoc::export_preview].
+    ///
+    /// The state section of a blob exported by [LoroDoc::export_sectioned_snapshot] is
+    /// decoded too, so a preview service can read both.
+    pub fn decode_preview(blob: &[u8]) -> LoroResult<LoroValue> {
+        let parsed = parse_header_unchecked(blob)?;
+        if parsed.mode != EncodeMode::Sectioned {
+            return Err(LoroError::DecodeError("Not a preview".into()));
+        }
+
+        match split_sections(parsed.body)?.preview {
+            Some(preview) => {
+                parsed.check_checksum()?;
+                postcard::from_bytes(preview).map_err(|_| LoroError::DecodeDataCorruptionError)
+            }
+            None => {
+                let doc = LoroDoc::from_snapshot(state_section(&parsed)?)?;
+                Ok(doc.get_deep_value_with_richtext())
+            }
+        }
+    }
+
+    /// Get the history section of a blob exported by
+    /// [LoroDoc::export_sectioned_snapshot] without reading the state section.
+    ///
+    /// It's an update blob from the empty version, which can be stored or imported on its
+    /// own. Its checksum is checked when it's imported.
+    pub fn history_section(blob: &[u8]) -> LoroResult<&[u8]> {
+        let parsed = parse_header_unchecked(blob)?;
+        if parsed.mode != EncodeMode::Sectioned {
+            return Err(LoroError::DecodeError("Not a sectioned snapshot".into()));
+        }
+
+        split_sections(parsed.body)?.history()
+    }
+}
+
+/// A breakdown of an exported blob by section, see [explain].
+///
+/// All the sizes are in bytes.
+#[derive(Debug, Clone, Default)]
+pub struct EncodingReport {
+    pub is_snapshot: bool,
+    pub total: usize,
+    /// Magic bytes, checksum and encode mode
+    pub header: usize,
+    pub peer_num: usize,
+    pub change_num: usize,
+    pub op_num: usize,
+    /// Peer id arena
+    pub peers: usize,
+    /// Container id arena
+    pub containers: usize,
+    /// Map keys and root container names
+    pub keys: usize,
+    /// Deps of the changes
+    pub deps: usize,
+    /// Fractional indexes of the tree nodes
+    pub positions: usize,
+    pub tree_ids: usize,
+    pub change_columns: usize,
+    pub op_columns: usize,
+    pub delete_start_columns: usize,
+    /// It's empty when the blob is not a snapshot
+    pub state_columns: usize,
+    /// It's empty when the blob is not a snapshot
+    pub state_blob: usize,
+    /// Values, strings and other op contents
+    pub raw_values: usize,
+    /// The total bytes of the text inserted by the ops
+    pub text_bytes: usize,
+    /// The part of `text_bytes` encoded as back-references to identical text chunks
+    pub deduped_text_bytes: usize,
+    /// The part of `text_bytes` encoded in the compressed form, see
+    /// [`LoroDoc::set_op_compression_threshold`](crate::LoroDoc::set_op_compression_threshold)
+    pub compressed_text_bytes: usize,
+    /// The encoded bytes of the values set by the ops, e.g. map values, list elements and
+    /// counter increments. Text, deletions, marks and moves are not included.
+    pub value_bytes: usize,
+    pub container_states: Vec<ContainerEncodingReport>,
+}
+
+impl EncodingReport {
+    /// The content of the ops, i.e. the text they insert and the values they set
+    pub fn content_bytes(&self) -> usize {
+        self.text_bytes + self.value_bytes
+    }
+
+    /// The size of the blob relative to the content of its ops.
+    ///
+    /// Everything but the content is metadata: the ids, deps, timestamps and positions of
+    /// the ops, the deletions and the containers. It can be below 1 if the text is deduped.
+    pub fn metadata_overhead_ratio(&self) -> f64 {
+        self.total as f64 / self.content_bytes().max(1) as f64
+    }
+}
+
+/// The encoded size attributed to a single container
+#[derive(Debug, Clone)]
+pub struct ContainerEncodingReport {
+    pub container: ContainerID,
+    /// The number of encoded ops in the container
+    pub op_num: usize,
+    /// The length of the ops that constitute the container state in the snapshot
+    pub state_op_len: usize,
+    pub state_bytes: usize,
+}
+
+/// The dimension that [size_breakdown_by] groups the encoded ops by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dimension {
+    Container,
+    Peer,
+    OpType,
+}
+
+/// The key of a [SizeEntry], it matches the [Dimension] of the breakdown
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SizeKey {
+    Container(ContainerID),
+    Peer(PeerID),
+    /// e.g. `text_insert`, `delete`, `map_set` or `tree_move`
+    OpType(&'static str),
+}
+
+/// The encoded size attributed to one key of a [Dimension]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SizeEntry {
+    pub key: SizeKey,
+    pub op_num: usize,
+    /// The bytes of the op contents plus the share of the op columns
+    pub bytes: usize,
+}
+
+/// Attribute the encoded size of the ops in a blob to containers, peers or op types.
+///
+/// The bytes of an op's content are measured exactly. The op columns are compressed
+/// together, so they're split evenly among the ops. The other sections, e.g. the change
+/// metadata and the state blobs of a snapshot, are not attributed; see [explain] for them.
+///
+/// The entries are sorted by `bytes` in descending order.
+pub fn size_breakdown_by(bytes: &[u8], dimension: Dimension) -> LoroResult<Vec<SizeEntry>> {
+    let parsed = parse_header_and_body(bytes)?;
+    if parsed.mode == EncodeMode::Sectioned {
+        return size_breakdown_by(split_sections(parsed.body)?.history()?, dimension);
     }
+
+    reject_chunked(&parsed)?;
+    encode_reordered::size_breakdown_body(parsed.body, dimension)
+}
+
+/// Break down an exported blob by section to find out what takes up the space.
+///
+/// It works on both snapshots and updates.
+///
+/// The report of a sectioned snapshot is the one of its history section, with the state
+/// section counted in `state_blob`. The preview section of a preview is counted in
+/// `state_blob` too.
+pub fn explain(bytes: &[u8]) -> LoroResult<EncodingReport> {
+    let parsed = parse_header_and_body(bytes)?;
+    if parsed.mode == EncodeMode::Sectioned {
+        let sections = split_sections(parsed.body)?;
+        let (mut report, history_len) = match sections.history {
+            Some(history) => (explain(history)?, history.len()),
+            None => (EncodingReport::default(), 0),
+        };
+        let state_blob =
+            sections.state.map_or(0, |x| x.len()) + sections.preview.map_or(0, |x| x.len());
+        report.total = bytes.len();
+        report.header += bytes.len() - state_blob - history_len;
+        report.state_blob = state_blob;
+        return Ok(report);
+    }
+
+    reject_chunked(&parsed)?;
+    let mut report = EncodingReport {
+        is_snapshot: parsed.mode.is_snapshot(),
+        total: bytes.len(),
+        header: bytes.len() - parsed.body.len(),
+        ..Default::default()
+    };
+    encode_reordered::explain_body(parsed.body, &mut report)?;
+    Ok(report)
+}
+
+/// The inspection tools work on a single body, so a chunked blob has to be inspected by
+/// exporting the updates or a snapshot from a doc that imported it.
+fn reject_chunked(parsed: &ParsedHeaderAndBody) -> LoroResult<()> {
+    if parsed.mode == EncodeMode::Chunked {
+        return Err(LoroError::DecodeError(
+            "Chunked blobs can't be inspected as a whole".into(),
+        ));
+    }
+
+    Ok(())
 }
@@ -13,6 +13,8 @@ pub(super) fn encode_arena(
     registers: EncodedRegisters,
     dep_arena: DepsArena,
     state_blob_arena: &[u8],
+    msg_arena: &[u8],
+    rejections: &[(PeerID, Counter)],
 ) -> Vec<u8> {
     let EncodedRegisters {
         peer: mut peer_register,
@@ -48,6 +50,8 @@ pub(super) fn encode_arena(
         position_arena: &position_arena.encode(),
         tree_id_arena: &tree_id_arena.encode(),
         state_blob_arena,
+        msg_arena,
+        rejection_arena: &encode_rejections(rejections),
     };
 
     encoded.encode_arenas()
@@ -82,6 +86,10 @@ pub struct DecodedArenas<'a> {
     pub(super) positions: PositionArena<'a>,
     pub(super) tree_ids: TreeIDArena,
     pub state_blob_arena: &'a [u8],
+    /// The concatenated commit messages of the changes
+    pub msg_arena: &'a [u8],
+    /// The rejection markers of the oplog, see [crate::oplog::quota]
+    pub(super) rejections: Vec<(PeerID, Counter)>,
 }
 
 pub fn decode_arena(bytes: &[u8]) -> LoroResult<DecodedArenas> {
@@ -94,6 +102,33 @@ pub fn decode_arena(bytes: &[u8]) -> LoroResult<DecodedArenas> {
         positions: PositionArena::decode(arenas.position_arena)?,
         tree_ids: TreeIDArena::decode(arenas.tree_id_arena)?,
         state_blob_arena: arenas.state_blob_arena,
+        msg_arena: arenas.msg_arena,
+        rejections: decode_rejections(arenas.rejection_arena)?,
+    })
+}
+
+/// The byte length of each arena inside the encoded arenas section.
+#[derive(Debug, Default, Clone, Copy)]
+pub(super) struct ArenaSizes {
+    pub peer_ids: usize,
+    pub containers: usize,
+    pub keys: usize,
+    pub deps: usize,
+    pub positions: usize,
+    pub tree_ids: usize,
+    pub state_blob: usize,
+}
+
+pub(super) fn arena_sizes(bytes: &[u8]) -> LoroResult<ArenaSizes> {
+    let arenas = EncodedArenas::decode_arenas(bytes)?;
+    Ok(ArenaSizes {
+        peer_ids: arenas.peer_id_arena.len(),
+        containers: arenas.container_arena.len(),
+        keys: arenas.key_arena.len(),
+        deps: arenas.deps_arena.len(),
+        positions: arenas.position_arena.len(),
+        tree_ids: arenas.tree_id_arena.len(),
+        state_blob: arenas.state_blob_arena.len(),
     })
 }
 
@@ -105,6 +140,11 @@ struct EncodedArenas<'a> {
     position_arena: &'a [u8],
     tree_id_arena: &'a [u8],
     state_blob_arena: &'a [u8],
+    /// It's optional, because the blobs encoded by older versions don't have it
+    msg_arena: &'a [u8],
+    /// The rejection markers of a snapshot. It's optional like `msg_arena`, and it's only
+    /// written when it's not empty
+    rejection_arena: &'a [u8],
 }
 
 impl EncodedArenas<'_> {
@@ -126,6 +166,12 @@ impl EncodedArenas<'_> {
         write_arena(&mut ans, self.position_arena);
         write_arena(&mut ans, self.tree_id_arena);
         write_arena(&mut ans, self.state_blob_arena);
+        if !self.msg_arena.is_empty() || !self.rejection_arena.is_empty() {
+            write_arena(&mut ans, self.msg_arena);
+        }
+        if !self.rejection_arena.is_empty() {
+            write_arena(&mut ans, self.rejection_arena);
+        }
         ans
     }
 
@@ -136,7 +182,17 @@ impl EncodedArenas<'_> {
         let (deps_arena, rest) = read_arena(rest)?;
         let (position_arena, rest) = read_arena(rest)?;
         let (tree_id_arena, rest) = read_arena(rest)?;
-        let (state_blob_arena, _) = read_arena(rest)?;
+        let (state_blob_arena, rest) = read_arena(rest)?;
+        let (msg_arena, rest): (&[u8], _) = if rest.is_empty() {
+            (&[], rest)
+        } else {
+            read_arena(rest)?
+        };
+        let rejection_arena: &[u8] = if rest.is_empty() {
+            &[]
+        } else {
+            read_arena(rest)?.0
+        };
         Ok(EncodedArenas {
             peer_id_arena,
             container_arena,
@@ -145,10 +201,54 @@ impl EncodedArenas<'_> {
             position_arena,
             tree_id_arena,
             state_blob_arena,
+            msg_arena,
+            rejection_arena,
         })
     }
 }
 
+/// The rejection markers sorted by peer, each as a big-endian peer and a LEB128 counter
+fn encode_rejections(rejections: &[(PeerID, Counter)]) -> Vec<u8> {
+    if rejections.is_empty() {
+        return Vec::new();
+    }
+
+    let mut ans = Vec::with_capacity(rejections.len() * 10 + 1);
+    leb128::write::unsigned(&mut ans, rejections.len() as u64).unwrap();
+    for &(peer, counter) in rejections {
+        ans.extend_from_slice(&peer.to_be_bytes());
+        leb128::write::unsigned(&mut ans, counter as u64).unwrap();
+    }
+    ans
+}
+
+fn decode_rejections(mut reader: &[u8]) -> LoroResult<Vec<(PeerID, Counter)>> {
+    if reader.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let len =
+        leb128::read::unsigned(&mut reader).map_err(|_| LoroError::DecodeDataCorruptionError)?;
+    if len > MAX_DECODED_SIZE as u64 || len > reader.len() as u64 {
+        return Err(LoroError::DecodeDataCorruptionError);
+    }
+
+    let mut ans = Vec::with_capacity(len as usize);
+    for _ in 0..len {
+        if reader.len() < 8 {
+            return Err(LoroError::DecodeDataCorruptionError);
+        }
+        let peer = PeerID::from_be_bytes(reader[..8].try_into().unwrap());
+        reader = &reader[8..];
+        let counter = leb128::read::unsigned(&mut reader)
+            .map_err(|_| LoroError::DecodeDataCorruptionError)?;
+        let counter =
+            Counter::try_from(counter).map_err(|_| LoroError::DecodeDataCorruptionError)?;
+        ans.push((peer, counter));
+    }
+    Ok(ans)
+}
+
 #[derive(Serialize, Deserialize)]
 pub(super) struct PeerIdArena {
     pub(super) peer_ids: Vec<u64>,
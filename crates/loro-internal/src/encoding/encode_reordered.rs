@@ -29,7 +29,7 @@ use super::{
     arena::*,
     parse_header_and_body,
     value::{Value, ValueKind, ValueReader, ValueWriter},
-    ImportBlobMetadata,
+    ContainerEncodingReport, Dimension, EncodingReport, ImportBlobMetadata, SizeEntry, SizeKey,
 };
 
 #[allow(unused_imports)]
@@ -41,8 +41,32 @@ pub(super) const MAX_DECODED_SIZE: usize = 1 << 30;
 /// If any collection in the document is longer than this, we will not decode it.
 /// It will return an data corruption error instead.
 pub(super) const MAX_COLLECTION_SIZE: usize = 1 << 28;
+/// The rough memory of an empty container state with its entries in the doc state
+const CONTAINER_STATE_SIZE: usize = 256;
+/// A container state takes about this many times the bytes of its encoded state blob,
+/// e.g. for the ids and the tree nodes of its elements
+const STATE_BLOB_EXPANSION: usize = 8;
 
-pub(crate) fn encode_updates(oplog: &OpLog, vv: &VersionVector) -> Vec<u8> {
+pub(crate) fn encode_updates(
+    oplog: &OpLog,
+    vv: &VersionVector,
+    implicit_counters: bool,
+) -> Vec<u8> {
+    encode_updates_in_range(oplog, vv, oplog.vv(), implicit_counters)
+}
+
+/// Encode the ops from `vv` to `end_vv`.
+///
+/// `end_vv` should be included by the oplog, and it should be a causally closed version,
+/// otherwise the ops in the result may depend on ops that are not exported. If
+/// `implicit_counters` is true, the op counters are encoded as gaps, see
+/// [super::IMPLICIT_COUNTERS_FLAG].
+pub(crate) fn encode_updates_in_range(
+    oplog: &OpLog,
+    vv: &VersionVector,
+    end_vv: &VersionVector,
+    implicit_counters: bool,
+) -> Vec<u8> {
     // skip the ops that current oplog does not have
     let actual_start_vv: VersionVector = vv
         .iter()
@@ -51,7 +75,7 @@ pub(crate) fn encode_updates(oplog: &OpLog, vv: &VersionVector) -> Vec<u8> {
                 return None;
             }
 
-            let this_end = oplog.vv().get(&peer).cloned().unwrap_or(0);
+            let this_end = end_vv.get(&peer).cloned().unwrap_or(0);
             if this_end <= end_counter {
                 return Some((peer, this_end));
             }
@@ -62,7 +86,7 @@ pub(crate) fn encode_updates(oplog: &OpLog, vv: &VersionVector) -> Vec<u8> {
 
     let vv = &actual_start_vv;
     let mut peer_register: ValueRegister<PeerID> = ValueRegister::new();
-    let (start_counters, diff_changes) = init_encode(oplog, vv, &mut peer_register);
+    let (start_counters, diff_changes) = init_encode(oplog, vv, end_vv, &mut peer_register);
     let ExtractedContainer {
         containers,
         cid_idx_pairs: _,
@@ -83,12 +107,15 @@ pub(crate) fn encode_updates(oplog: &OpLog, vv: &VersionVector) -> Vec<u8> {
         position: either::Left(FxHashSet::default()),
     };
     let mut dep_arena = DepsArena::default();
+    let mut msg_arena = Vec::new();
     let mut value_writer = ValueWriter::new();
+    value_writer.set_compression_threshold(oplog.configure.op_compression_threshold());
     let mut ops: Vec<TempOp> = Vec::new();
     let arena = &oplog.arena;
     let changes = encode_changes(
         &diff_changes,
         &mut dep_arena,
+        &mut msg_arena,
         &mut |op| ops.push(op),
         &container2index,
         &mut registers,
@@ -103,7 +130,13 @@ pub(crate) fn encode_updates(oplog: &OpLog, vv: &VersionVector) -> Vec<u8> {
             .then_with(|| a.lamport.cmp(&b.lamport))
     });
 
-    let (encoded_ops, del_starts) = encode_ops(&ops, arena, &mut value_writer, &mut registers);
+    let (encoded_ops, del_starts) = encode_ops(
+        &ops,
+        arena,
+        &mut value_writer,
+        &mut registers,
+        implicit_counters,
+    );
 
     let frontiers = oplog
         .dag
@@ -118,7 +151,7 @@ pub(crate) fn encode_updates(oplog: &OpLog, vv: &VersionVector) -> Vec<u8> {
         states: Vec::new(),
         start_counters,
         raw_values: Cow::Owned(value_writer.finish()),
-        arenas: Cow::Owned(encode_arena(registers, dep_arena, &[])),
+        arenas: Cow::Owned(encode_arena(registers, dep_arena, &[], &msg_arena, &[])),
         start_frontiers: frontiers,
     };
 
@@ -126,7 +159,11 @@ pub(crate) fn encode_updates(oplog: &OpLog, vv: &VersionVector) -> Vec<u8> {
 }
 
 #[instrument(skip_all)]
-pub(crate) fn decode_updates(oplog: &mut OpLog, bytes: &[u8]) -> LoroResult<()> {
+pub(crate) fn decode_updates(
+    oplog: &mut OpLog,
+    bytes: &[u8],
+    implicit_counters: bool,
+) -> LoroResult<()> {
     let iter = serde_columnar::iter_from_bytes::<EncodedDoc>(bytes)?;
     let mut arenas = decode_arena(&iter.arenas)?;
     let ops_map = extract_ops(
@@ -136,15 +173,27 @@ pub(crate) fn decode_updates(oplog: &mut OpLog, bytes: &[u8]) -> LoroResult<()>
         &oplog.arena,
         &mut arenas,
         false,
+        implicit_counters,
     )?
     .ops_map;
     let DecodedArenas {
         peer_ids,
         deps,
         state_blob_arena: _,
+        msg_arena,
+        rejections,
         ..
     } = arenas;
-    let changes = decode_changes(iter.changes, iter.start_counters, &peer_ids, deps, ops_map)?;
+    let changes = decode_changes(
+        iter.changes,
+        iter.start_counters,
+        &peer_ids,
+        deps,
+        msg_arena,
+        ops_map,
+    )?;
+    // The snapshots imported like updates keep their rejection markers
+    oplog.merge_rejection_markers(&rejections);
     let (latest_ids, pending_changes) = import_changes_to_oplog(changes, oplog)?;
     if oplog.try_apply_pending(latest_ids).should_update && !oplog.batch_importing {
         oplog.dag.refresh_frontiers();
@@ -210,12 +259,259 @@ pub fn decode_import_blob_meta(bytes: &[u8]) -> LoroResult<ImportBlobMetadata> {
     })
 }
 
+/// Check that decoding `body` is estimated to need at most `budget` bytes of memory, and
+/// return the estimate.
+///
+/// It's a heuristic: the estimate is the sum of rough sizes of the decoded rows, the
+/// expanded strings and the container states, and the decoding itself isn't metered, so
+/// the actual peak may differ from it by a constant factor. It's meant to reject the blobs
+/// that expand far beyond their size, not to cap the memory exactly.
+///
+/// The columns are iterated without being collected, so the check itself takes constant
+/// memory even if the run-length encoded columns expand to a huge number of rows. It stops
+/// as soon as the estimate exceeds the budget.
+pub(super) fn check_decode_budget(body: &[u8], budget: usize) -> LoroResult<usize> {
+    let check = |needed: usize| {
+        if needed > budget {
+            Err(LoroError::BudgetExceeded { needed, budget })
+        } else {
+            Ok(())
+        }
+    };
+
+    // The values and arenas are copied out of the input while decoding
+    let mut needed = body.len() * 2;
+    check(needed)?;
+    let iter = serde_columnar::iter_from_bytes::<EncodedDoc>(body)?;
+    // Each op is kept in its encoded form, in the oplog and in the diff applied to the state
+    let op_size = std::mem::size_of::<EncodedOp>() + 2 * std::mem::size_of::<Op>();
+    let mut value_kinds = Vec::new();
+    for op in iter.ops {
+        value_kinds.push(op?.value_type);
+        needed += op_size;
+        check(needed)?;
+    }
+
+    let change_size = std::mem::size_of::<EncodedChange>() + std::mem::size_of::<Change>();
+    for change in iter.changes {
+        change?;
+        needed += change_size;
+        check(needed)?;
+    }
+
+    for delete_start in iter.delete_starts {
+        delete_start?;
+        needed += std::mem::size_of::<EncodedDeleteStartId>();
+        check(needed)?;
+    }
+
+    // The state blobs of a snapshot are decoded into the container states
+    for state in iter.states {
+        let state = state?;
+        needed += std::mem::size_of::<EncodedStateInfo>()
+            + CONTAINER_STATE_SIZE
+            + state.state_bytes_len as usize * STATE_BLOB_EXPANSION;
+        check(needed)?;
+    }
+
+    // The compressed strings are expanded when they're decoded
+    let is_compressed = |kind: u8| matches!(ValueKind::from_u8(kind), ValueKind::CompressedStr);
+    if value_kinds.iter().any(|&x| is_compressed(x)) {
+        let arenas = decode_arena(&iter.arenas)?;
+        let mut value_reader = ValueReader::new(&iter.raw_values);
+        for kind in value_kinds {
+            if is_compressed(kind) {
+                needed += value_reader.read_compressed_str_header()?.0;
+                check(needed)?;
+            } else {
+                let kind = ValueKind::from_u8(kind);
+                Value::decode(kind, &mut value_reader, &arenas, ID::NONE_ID)?;
+            }
+        }
+    }
+
+    Ok(needed)
+}
+
+/// Fill the body part of the [EncodingReport].
+///
+/// The column sizes are measured by re-encoding each column group separately,
+/// so they may differ from the original layout by a few bytes of framing.
+pub(super) fn explain_body(body: &[u8], report: &mut EncodingReport) -> LoroResult<()> {
+    let iter = serde_columnar::iter_from_bytes::<EncodedDoc>(body)?;
+    let ops: Vec<EncodedOp> = iter.ops.try_collect()?;
+    let changes: Vec<EncodedChange> = iter.changes.try_collect()?;
+    let delete_starts: Vec<EncodedDeleteStartId> = iter.delete_starts.try_collect()?;
+    let states: Vec<EncodedStateInfo> = iter.states.try_collect()?;
+    let sizes = arena_sizes(&iter.arenas)?;
+    let arenas = decode_arena(&iter.arenas)?;
+    let containers: Vec<ContainerID> = arenas
+        .containers
+        .iter()
+        .map(|x| x.as_container_id(&arenas))
+        .try_collect()?;
+
+    let mut container_reports: Vec<ContainerEncodingReport> = containers
+        .into_iter()
+        .map(|container| ContainerEncodingReport {
+            container,
+            op_num: 0,
+            state_op_len: 0,
+            state_bytes: 0,
+        })
+        .collect();
+    for op in ops.iter() {
+        let r = container_reports
+            .get_mut(op.container_index as usize)
+            .ok_or(LoroError::DecodeDataCorruptionError)?;
+        r.op_num += 1;
+    }
+    for s in states.iter() {
+        let r = container_reports
+            .get_mut(s.container_index as usize)
+            .ok_or(LoroError::DecodeDataCorruptionError)?;
+        r.state_op_len += s.op_len as usize;
+        r.state_bytes += s.state_bytes_len as usize;
+    }
+
+    let mut value_reader = ValueReader::new(&iter.raw_values);
+    for op in ops.iter() {
+        let peer = *arenas
+            .peer_ids
+            .get(op.peer_idx as usize)
+            .ok_or(LoroError::DecodeDataCorruptionError)?;
+        let kind = ValueKind::from_u8(op.value_type);
+        let is_ref = matches!(kind, ValueKind::StrRef);
+        let is_compressed = matches!(kind, ValueKind::CompressedStr);
+        let remaining = value_reader.remaining_len();
+        // The counter may be implicit, but the id only names the child containers, which
+        // doesn't change the size of the value
+        let value = Value::decode(kind, &mut value_reader, &arenas, ID::new(peer, op.counter))?;
+        match value {
+            Value::Str(text) => {
+                report.text_bytes += text.len();
+                if is_ref {
+                    report.deduped_text_bytes += text.len();
+                }
+                if is_compressed {
+                    report.compressed_text_bytes += text.len();
+                }
+            }
+            Value::Null
+            | Value::True
+            | Value::False
+            | Value::I64(_)
+            | Value::F64(_)
+            | Value::Binary(_)
+            | Value::DeltaInt(_)
+            | Value::LoroValue(_)
+            | Value::ListSet { .. } => {
+                report.value_bytes += remaining - value_reader.remaining_len();
+            }
+            _ => {}
+        }
+    }
+
+    report.peer_num = arenas.peer_ids.len();
+    report.change_num = changes.len();
+    report.op_num = ops.len();
+    report.peers = sizes.peer_ids;
+    report.containers = sizes.containers;
+    report.keys = sizes.keys;
+    report.deps = sizes.deps;
+    report.positions = sizes.positions;
+    report.tree_ids = sizes.tree_ids;
+    report.state_blob = sizes.state_blob;
+    report.raw_values = iter.raw_values.len();
+    report.change_columns = serde_columnar::to_vec(&ChangeColumns { changes })
+        .map(|x| x.len())
+        .unwrap_or(0);
+    report.op_columns = serde_columnar::to_vec(&OpColumns { ops })
+        .map(|x| x.len())
+        .unwrap_or(0);
+    report.delete_start_columns = serde_columnar::to_vec(&DeleteStartColumns { delete_starts })
+        .map(|x| x.len())
+        .unwrap_or(0);
+    report.state_columns = serde_columnar::to_vec(&StateColumns { states })
+        .map(|x| x.len())
+        .unwrap_or(0);
+    report.container_states = container_reports;
+    Ok(())
+}
+
+pub(super) fn size_breakdown_body(body: &[u8], dimension: Dimension) -> LoroResult<Vec<SizeEntry>> {
+    let iter = serde_columnar::iter_from_bytes::<EncodedDoc>(body)?;
+    let ops: Vec<EncodedOp> = iter.ops.try_collect()?;
+    let arenas = decode_arena(&iter.arenas)?;
+    let containers: Vec<ContainerID> = arenas
+        .containers
+        .iter()
+        .map(|x| x.as_container_id(&arenas))
+        .try_collect()?;
+    let op_num = ops.len();
+    let op_columns = serde_columnar::to_vec(&OpColumns { ops: ops.clone() })
+        .map(|x| x.len())
+        .unwrap_or(0);
+
+    let mut entries: FxHashMap<SizeKey, SizeEntry> = FxHashMap::default();
+    let mut value_reader = ValueReader::new(&iter.raw_values);
+    for (i, op) in ops.iter().enumerate() {
+        let peer = *arenas
+            .peer_ids
+            .get(op.peer_idx as usize)
+            .ok_or(LoroError::DecodeDataCorruptionError)?;
+        let container = containers
+            .get(op.container_index as usize)
+            .ok_or(LoroError::DecodeDataCorruptionError)?;
+        let kind = ValueKind::from_u8(op.value_type);
+        let key = match dimension {
+            Dimension::Container => SizeKey::Container(container.clone()),
+            Dimension::Peer => SizeKey::Peer(peer),
+            Dimension::OpType => SizeKey::OpType(op_type_name(container.container_type(), &kind)),
+        };
+
+        let remaining = value_reader.remaining_len();
+        // The counter may be implicit, but it doesn't change the size of the value
+        Value::decode(kind, &mut value_reader, &arenas, ID::new(peer, op.counter))?;
+        let value_bytes = remaining - value_reader.remaining_len();
+        // Split the columns so that the shares add up to the total
+        let column_bytes = op_columns * (i + 1) / op_num - op_columns * i / op_num;
+        let entry = entries.entry(key.clone()).or_insert_with(|| SizeEntry {
+            key,
+            op_num: 0,
+            bytes: 0,
+        });
+        entry.op_num += 1;
+        entry.bytes += value_bytes + column_bytes;
+    }
+
+    let mut entries: Vec<SizeEntry> = entries.into_values().collect();
+    entries.sort_by_key(|x| std::cmp::Reverse(x.bytes));
+    Ok(entries)
+}
+
+fn op_type_name(container_type: ContainerType, kind: &ValueKind) -> &'static str {
+    match (container_type, kind) {
+        (_, ValueKind::DeleteSeq | ValueKind::DeleteOnce) => "delete",
+        (ContainerType::Text, ValueKind::Str | ValueKind::StrRef) => "text_insert",
+        (ContainerType::Text, ValueKind::MarkStart) => "mark",
+        (ContainerType::Text, ValueKind::Null) => "mark_end",
+        (ContainerType::Map, _) => "map_set",
+        (ContainerType::List | ContainerType::MovableList, ValueKind::LoroValue) => "list_insert",
+        (ContainerType::MovableList, ValueKind::ListMove) => "list_move",
+        (ContainerType::MovableList, ValueKind::ListSet) => "list_set",
+        (ContainerType::Tree, _) => "tree_move",
+        _ => "other",
+    }
+}
+
 pub(crate) fn import_changes_to_oplog(
     changes: Vec<Change>,
     oplog: &mut OpLog,
 ) -> Result<(Vec<ID>, Vec<Change>), LoroError> {
     let mut pending_changes = Vec::new();
     let mut latest_ids = Vec::new();
+    let changes = oplog.remove_rejected_ops(changes);
     for mut change in changes {
         if change.ctr_end() <= oplog.vv().get(&change.id.peer).copied().unwrap_or(0) {
             // skip included changes
@@ -257,6 +553,7 @@ fn decode_changes<'a>(
     mut counters: Vec<i32>,
     peer_ids: &PeerIdArena,
     mut deps: impl Iterator<Item = Result<EncodedDep, ColumnarError>> + 'a,
+    mut msg_arena: &[u8],
     mut ops_map: std::collections::HashMap<
         u64,
         Vec<Op>,
@@ -271,12 +568,26 @@ fn decode_changes<'a>(
             timestamp,
             deps_len,
             dep_on_self,
-            msg_len: _,
+            msg_len,
         } = encoded_change?;
         if peer_ids.peer_ids.len() <= peer_idx || counters.len() <= peer_idx {
             return Err(LoroError::DecodeDataCorruptionError);
         }
 
+        let commit_msg = if msg_len > 0 {
+            let msg_len = msg_len as usize;
+            if msg_arena.len() < msg_len {
+                return Err(LoroError::DecodeDataCorruptionError);
+            }
+
+            let msg = std::str::from_utf8(&msg_arena[..msg_len])
+                .map_err(|_| LoroError::DecodeDataCorruptionError)?;
+            msg_arena = &msg_arena[msg_len..];
+            Some(msg.into())
+        } else {
+            None
+        };
+
         let counter = counters[peer_idx];
         counters[peer_idx] += len as Counter;
         let peer = peer_ids.peer_ids[peer_idx];
@@ -286,6 +597,7 @@ fn decode_changes<'a>(
             deps: Frontiers::with_capacity((deps_len + if dep_on_self { 1 } else { 0 }) as usize),
             lamport: 0,
             timestamp,
+            commit_msg,
             has_dependents: false,
         };
 
@@ -333,8 +645,10 @@ fn extract_ops(
     shared_arena: &SharedArena,
     arenas: &mut DecodedArenas<'_>,
     should_extract_ops_with_ids: bool,
+    implicit_counters: bool,
 ) -> LoroResult<ExtractedOps> {
     let mut value_reader = ValueReader::new(raw_values);
+    let mut next_counters: FxHashMap<u32, Counter> = FxHashMap::default();
     let mut ops_map: FxHashMap<PeerID, Vec<Op>> = FxHashMap::default();
     let containers: Vec<_> = arenas
         .containers
@@ -357,6 +671,14 @@ fn extract_ops(
             return Err(LoroError::DecodeDataCorruptionError);
         }
         let peer = arenas.peer_ids[peer_idx as usize];
+        let next_counter = next_counters.entry(peer_idx).or_insert(0);
+        let counter = if implicit_counters {
+            counter
+                .checked_add(*next_counter)
+                .ok_or(LoroError::DecodeDataCorruptionError)?
+        } else {
+            counter
+        };
         let cid = &containers[container_index as usize];
         let kind = ValueKind::from_u8(value_type);
         let value = Value::decode(kind, &mut value_reader, arenas, ID::new(peer, counter))?;
@@ -379,6 +701,9 @@ fn extract_ops(
             container,
             content,
         };
+        *next_counter = counter
+            .checked_add(op.atom_len() as Counter)
+            .ok_or(LoroError::DecodeDataCorruptionError)?;
 
         if should_extract_ops_with_ids {
             ops.push(OpWithId {
@@ -403,12 +728,23 @@ fn extract_ops(
     })
 }
 
-pub(crate) fn encode_snapshot(oplog: &OpLog, state: &DocState, vv: &VersionVector) -> Vec<u8> {
+/// Encode the snapshot of the doc.
+///
+/// If `dedup_text` is true, the text chunks that are identical to an earlier one are
+/// encoded as back-references to it. If `implicit_counters` is true, the op counters are
+/// encoded as gaps, see [super::IMPLICIT_COUNTERS_FLAG].
+pub(crate) fn encode_snapshot(
+    oplog: &OpLog,
+    state: &DocState,
+    vv: &VersionVector,
+    dedup_text: bool,
+    implicit_counters: bool,
+) -> Vec<u8> {
     assert!(!state.is_in_txn());
     assert_eq!(oplog.frontiers(), &state.frontiers);
 
     let mut peer_register: ValueRegister<PeerID> = ValueRegister::new();
-    let (start_counters, diff_changes) = init_encode(oplog, vv, &mut peer_register);
+    let (start_counters, diff_changes) = init_encode(oplog, vv, oplog.vv(), &mut peer_register);
     let ExtractedContainer {
         containers,
         cid_idx_pairs: c_pairs,
@@ -423,7 +759,13 @@ pub(crate) fn encode_snapshot(oplog: &OpLog, state: &DocState, vv: &VersionVecto
         &oplog.arena,
     );
     let mut dep_arena = DepsArena::default();
-    let mut value_writer = ValueWriter::new();
+    let mut msg_arena = Vec::new();
+    let mut value_writer = if dedup_text {
+        ValueWriter::with_str_dedup()
+    } else {
+        ValueWriter::new()
+    };
+    value_writer.set_compression_threshold(oplog.configure.op_compression_threshold());
     let registers = Rc::new(RefCell::new(EncodedRegisters {
         peer: peer_register,
         container: ValueRegister::from_existing(containers),
@@ -455,6 +797,7 @@ pub(crate) fn encode_snapshot(oplog: &OpLog, state: &DocState, vv: &VersionVecto
     let changes = encode_changes(
         &diff_changes,
         &mut dep_arena,
+        &mut msg_arena,
         &mut |op| {
             origin_ops.push(op);
         },
@@ -466,8 +809,13 @@ pub(crate) fn encode_snapshot(oplog: &OpLog, state: &DocState, vv: &VersionVecto
 
     registers.sort_fractional_index();
 
-    let (encoded_ops, del_starts) =
-        encode_ops(&ops, &oplog.arena, &mut value_writer, &mut registers);
+    let (encoded_ops, del_starts) = encode_ops(
+        &ops,
+        &oplog.arena,
+        &mut value_writer,
+        &mut registers,
+        implicit_counters,
+    );
 
     let doc = EncodedDoc {
         ops: encoded_ops,
@@ -476,7 +824,13 @@ pub(crate) fn encode_snapshot(oplog: &OpLog, state: &DocState, vv: &VersionVecto
         states,
         start_counters,
         raw_values: Cow::Owned(value_writer.finish()),
-        arenas: Cow::Owned(encode_arena(registers, dep_arena, &state_bytes)),
+        arenas: Cow::Owned(encode_arena(
+            registers,
+            dep_arena,
+            &state_bytes,
+            &msg_arena,
+            &oplog.rejection_markers(),
+        )),
         start_frontiers: Vec::new(),
     };
 
@@ -602,7 +956,11 @@ fn calc_sorted_ops_for_snapshot<'a>(
     ops
 }
 
-pub(crate) fn decode_snapshot(doc: &LoroDoc, bytes: &[u8]) -> LoroResult<()> {
+pub(crate) fn decode_snapshot(
+    doc: &LoroDoc,
+    bytes: &[u8],
+    implicit_counters: bool,
+) -> LoroResult<()> {
     let mut state = doc.app_state().try_lock().map_err(|_| {
         LoroError::DecodeError(
             "decode_snapshot: failed to lock app state"
@@ -620,13 +978,14 @@ pub(crate) fn decode_snapshot(doc: &LoroDoc, bytes: &[u8]) -> LoroResult<()> {
         )
     })?;
 
-    if !oplog.is_empty() {
-        unimplemented!("You can only import snapshot to a empty loro doc now");
+    if !oplog.is_empty() || !state.frontiers.is_empty() {
+        return Err(LoroError::DecodeError(
+            "You can only import snapshot to a empty loro doc now"
+                .to_string()
+                .into_boxed_str(),
+        ));
     }
 
-    assert!(state.frontiers.is_empty());
-    assert!(oplog.frontiers().is_empty());
-
     let iter = serde_columnar::iter_from_bytes::<EncodedDoc>(bytes)?;
     let mut arenas = decode_arena(&iter.arenas)?;
     let ExtractedOps {
@@ -640,16 +999,43 @@ pub(crate) fn decode_snapshot(doc: &LoroDoc, bytes: &[u8]) -> LoroResult<()> {
         &oplog.arena,
         &mut arenas,
         true,
+        implicit_counters,
     )?;
     let DecodedArenas {
         peer_ids,
         deps,
         state_blob_arena,
+        msg_arena,
+        rejections,
         ..
     } = arenas;
 
-    let changes = decode_changes(iter.changes, iter.start_counters, &peer_ids, deps, ops_map)?;
+    let changes = decode_changes(
+        iter.changes,
+        iter.start_counters,
+        &peer_ids,
+        deps,
+        msg_arena,
+        ops_map,
+    )?;
+    // A snapshot includes the whole history, so the deps of its changes must be in it.
+    // Check it before the oplog is changed.
+    let mut snapshot_vv = VersionVector::new();
+    for change in changes.iter() {
+        snapshot_vv.extend_to_include_end_id(change.id_end());
+    }
+    if changes
+        .iter()
+        .any(|change| change.deps.iter().any(|dep| !snapshot_vv.includes_id(*dep)))
+    {
+        return Err(LoroError::DecodeDataCorruptionError);
+    }
+
+    oplog.merge_rejection_markers(&rejections);
     let (new_ids, pending_changes) = import_changes_to_oplog(changes, &mut oplog)?;
+    if !pending_changes.is_empty() {
+        return Err(LoroError::DecodeDataCorruptionError);
+    }
 
     for op in ops.iter_mut() {
         // update op's lamport
@@ -665,10 +1051,8 @@ pub(crate) fn decode_snapshot(doc: &LoroDoc, bytes: &[u8]) -> LoroResult<()> {
         ops,
         &oplog,
         &peer_ids,
-    )
-    .unwrap();
+    )?;
 
-    assert!(pending_changes.is_empty());
     // we cannot assert this because frontiers of oplog is not updated yet when batch_importing
     // assert_eq!(&state.frontiers, oplog.frontiers());
     if !oplog.pending_changes.is_empty() {
@@ -810,7 +1194,10 @@ fn decode_snapshot_states(
             // There are two cases:
             // 1. The container is encoded as unknown, but now it's known. we should rebuild the state by `diff_calc`.
             // 2. The container is unknown, and it's still unknown. we should init an unknown state and emit an unknown event.
-            let container_id = containers[container_index as usize].clone();
+            let container_id = containers
+                .get(container_index as usize)
+                .ok_or(LoroError::DecodeDataCorruptionError)?
+                .clone();
             let container = state.arena.register_container(&container_id);
             unknown_containers.push(container);
             if container.is_unknown() {
@@ -822,34 +1209,28 @@ fn decode_snapshot_states(
             continue;
         }
 
-        if container_index >= containers.len() as u32 {
-            return Err(LoroError::DecodeDataCorruptionError);
-        }
-
-        let container_id = &containers[container_index as usize];
-
+        let container_id = containers
+            .get(container_index as usize)
+            .ok_or(LoroError::DecodeDataCorruptionError)?;
         let container = state.arena.register_container(container_id);
-
-        if state_blob_arena.len() < state_blob_index + state_bytes_len as usize {
-            return Err(LoroError::DecodeDataCorruptionError);
-        }
-
-        let state_bytes =
-            &state_blob_arena[state_blob_index..state_blob_index + state_bytes_len as usize];
-        state_blob_index += state_bytes_len as usize;
-
-        if ops.len() < ops_index {
-            return Err(LoroError::DecodeDataCorruptionError);
-        }
-
-        let mut next_ops = ops[ops_index..]
+        let state_blob_end = state_blob_index
+            .checked_add(state_bytes_len as usize)
+            .ok_or(LoroError::DecodeDataCorruptionError)?;
+        let state_bytes = state_blob_arena
+            .get(state_blob_index..state_blob_end)
+            .ok_or(LoroError::DecodeDataCorruptionError)?;
+        state_blob_index = state_blob_end;
+        let mut next_ops = ops
+            .get(ops_index..)
+            .ok_or(LoroError::DecodeDataCorruptionError)?
             .iter()
             .skip_while(|x| x.op.container != container)
             .take_while(|x| {
                 if op_len == 0 {
                     false
                 } else {
-                    op_len -= x.op.atom_len() as u32;
+                    // A corrupted length may end in the middle of an op
+                    op_len = op_len.saturating_sub(x.op.atom_len() as u32);
                     ops_index += 1;
                     true
                 }
@@ -877,7 +1258,7 @@ mod encode {
     #[allow(unused_imports)]
     use crate::encoding::value::FutureValue;
     use fxhash::FxHashMap;
-    use loro_common::{ContainerType, HasId, PeerID, ID};
+    use loro_common::{ContainerType, Counter, HasId, PeerID, ID};
     use rle::{HasLength, Sliceable};
     use std::borrow::Cow;
 
@@ -975,9 +1356,11 @@ mod encode {
         arena: &SharedArena,
         value_writer: &mut ValueWriter,
         registers: &mut EncodedRegisters<'p>,
+        implicit_counters: bool,
     ) -> (Vec<EncodedOp>, Vec<EncodedDeleteStartId>) {
         let mut encoded_ops = Vec::with_capacity(ops.len());
         let mut delete_start = Vec::new();
+        let mut next_counters: FxHashMap<u32, Counter> = FxHashMap::default();
         for TempOp {
             op,
             peer_idx,
@@ -987,13 +1370,22 @@ mod encode {
         {
             let value_type = encode_op(op, arena, &mut delete_start, value_writer, registers);
             let prop = get_op_prop(op, registers);
+            // Only the gap from the end of the previous op of the peer is stored, which
+            // is 0 for a run of consecutive ops, see [super::IMPLICIT_COUNTERS_FLAG]
+            let next_counter = next_counters.entry(*peer_idx).or_insert(0);
+            let counter = if implicit_counters {
+                op.counter - *next_counter
+            } else {
+                op.counter
+            };
             encoded_ops.push(EncodedOp {
                 container_index: *container_index,
                 peer_idx: *peer_idx,
-                counter: op.counter,
+                counter,
                 prop,
                 value_type: value_type.to_u8(),
             });
+            *next_counter = op.counter + op.atom_len() as Counter;
         }
 
         (encoded_ops, delete_start)
@@ -1002,6 +1394,7 @@ mod encode {
     pub(super) fn encode_changes<'p, 'a: 'p>(
         diff_changes: &'a [Cow<'a, Change>],
         dep_arena: &mut super::DepsArena,
+        msg_arena: &mut Vec<u8>,
         push_op: &mut impl FnMut(TempOp<'a>),
         container_idx2index: &FxHashMap<ContainerIdx, usize>,
         registers: &mut EncodedRegisters<'p>,
@@ -1020,13 +1413,20 @@ mod encode {
             }
 
             let peer_idx = registers.peer.register(&change.id.peer);
+            let msg_len = match change.commit_msg.as_ref() {
+                Some(msg) => {
+                    msg_arena.extend_from_slice(msg.as_bytes());
+                    msg.len() as i32
+                }
+                None => 0,
+            };
             changes.push(EncodedChange {
                 dep_on_self,
                 deps_len,
                 peer_idx,
                 len: change.atom_len(),
                 timestamp: change.timestamp,
-                msg_len: 0,
+                msg_len,
             });
 
             for op in change.ops().iter() {
@@ -1113,24 +1513,26 @@ mod encode {
     pub(super) fn init_encode<'a>(
         oplog: &'a OpLog,
         vv: &'_ VersionVector,
+        end_vv: &'_ VersionVector,
         peer_register: &mut ValueRegister<PeerID>,
     ) -> (Vec<i32>, Vec<Cow<'a, Change>>) {
-        let self_vv = oplog.vv();
-        let start_vv = vv.trim(oplog.vv());
+        let start_vv = vv.trim(end_vv);
         let mut start_counters = Vec::new();
 
         let mut diff_changes: Vec<Cow<'a, Change>> = Vec::new();
-        for change in oplog.iter_changes_peer_by_peer(&start_vv, self_vv) {
+        for change in oplog.iter_changes_peer_by_peer(&start_vv, end_vv) {
             let start_cnt = start_vv.get(&change.id.peer).copied().unwrap_or(0);
+            let end_cnt = end_vv.get(&change.id.peer).copied().unwrap_or(0);
             if !peer_register.contains(&change.id.peer) {
                 peer_register.register(&change.id.peer);
                 start_counters.push(start_cnt);
             }
-            if change.id.counter < start_cnt {
-                let offset = start_cnt - change.id.counter;
-                diff_changes.push(Cow::Owned(change.slice(offset as usize, change.atom_len())));
-            } else {
+            let start = (start_cnt - change.id.counter).max(0) as usize;
+            let end = ((end_cnt - change.id.counter) as usize).min(change.atom_len());
+            if start == 0 && end == change.atom_len() {
                 diff_changes.push(Cow::Borrowed(change));
+            } else {
+                diff_changes.push(Cow::Owned(change.slice(start, end)));
             }
         }
 
@@ -1219,7 +1621,9 @@ mod encode {
                     ..
                 } => {
                     // TODO: refactor this from_utf8 can be done internally without checking
-                    Value::Str(std::str::from_utf8(slice.as_bytes()).unwrap())
+                    Value::Str(Cow::Borrowed(
+                        std::str::from_utf8(slice.as_bytes()).unwrap(),
+                    ))
                 }
                 crate::container::list::list_op::InnerListOp::Delete(span) => {
                     delete_start.push(EncodedDeleteStartId {
@@ -1300,10 +1704,18 @@ fn decode_op(
     prop: i32,
     op_id: ID,
 ) -> LoroResult<crate::op::InnerContent> {
+    let peer = |peer_idx: usize| {
+        arenas
+            .peer_ids
+            .peer_ids
+            .get(peer_idx)
+            .copied()
+            .ok_or(LoroError::DecodeDataCorruptionError)
+    };
     let content = match cid.container_type() {
         ContainerType::Text => match value {
             Value::Str(s) => {
-                let (slice, result) = shared_arena.alloc_str_with_slice(s);
+                let (slice, result) = shared_arena.alloc_str_with_slice(&s);
                 crate::op::InnerContent::List(
                     crate::container::list::list_op::InnerListOp::InsertText {
                         slice,
@@ -1314,13 +1726,15 @@ fn decode_op(
                 )
             }
             Value::DeleteSeq => {
-                let del_start = del_iter.next().unwrap()?;
+                let del_start = del_iter
+                    .next()
+                    .ok_or(LoroError::DecodeDataCorruptionError)??;
                 let peer_idx = del_start.peer_idx;
                 let cnt = del_start.counter;
                 let len = del_start.len;
                 crate::op::InnerContent::List(crate::container::list::list_op::InnerListOp::Delete(
                     DeleteSpanWithId::new(
-                        ID::new(arenas.peer_ids.peer_ids[peer_idx], cnt as Counter),
+                        ID::new(peer(peer_idx)?, cnt as Counter),
                         prop as isize,
                         len,
                     ),
@@ -1338,7 +1752,7 @@ fn decode_op(
             Value::Null => crate::op::InnerContent::List(
                 crate::container::list::list_op::InnerListOp::StyleEnd,
             ),
-            _ => unreachable!(),
+            _ => return Err(LoroError::DecodeDataCorruptionError),
         },
         ContainerType::Map => {
             let key = arenas
@@ -1357,14 +1771,19 @@ fn decode_op(
                         value: Some(v.clone()),
                     })
                 }
-                _ => unreachable!(),
+                _ => return Err(LoroError::DecodeDataCorruptionError),
             }
         }
         ContainerType::List => {
             let pos = prop as usize;
             match value {
                 Value::LoroValue(arr) => {
-                    let range = shared_arena.alloc_values(arr.into_list().unwrap().iter().cloned());
+                    let range = shared_arena.alloc_values(
+                        arr.into_list()
+                            .map_err(|_| LoroError::DecodeDataCorruptionError)?
+                            .iter()
+                            .cloned(),
+                    );
                     crate::op::InnerContent::List(
                         crate::container::list::list_op::InnerListOp::Insert {
                             slice: SliceRange::new(range.start as u32..range.end as u32),
@@ -1373,21 +1792,23 @@ fn decode_op(
                     )
                 }
                 Value::DeleteSeq => {
-                    let del_start = del_iter.next().unwrap()?;
+                    let del_start = del_iter
+                        .next()
+                        .ok_or(LoroError::DecodeDataCorruptionError)??;
                     let peer_idx = del_start.peer_idx;
                     let cnt = del_start.counter;
                     let len = del_start.len;
                     crate::op::InnerContent::List(
                         crate::container::list::list_op::InnerListOp::Delete(
                             DeleteSpanWithId::new(
-                                ID::new(arenas.peer_ids[peer_idx], cnt as Counter),
+                                ID::new(peer(peer_idx)?, cnt as Counter),
                                 pos as isize,
                                 len,
                             ),
                         ),
                     )
                 }
-                _ => unreachable!(),
+                _ => return Err(LoroError::DecodeDataCorruptionError),
             }
         }
         ContainerType::Tree => match value {
@@ -1397,15 +1818,18 @@ fn decode_op(
                 &arenas.tree_ids.tree_ids,
                 op_id,
             )?),
-            _ => {
-                unreachable!()
-            }
+            _ => return Err(LoroError::DecodeDataCorruptionError),
         },
         ContainerType::MovableList => {
             let pos = prop as usize;
             match value {
                 Value::LoroValue(arr) => {
-                    let range = shared_arena.alloc_values(arr.into_list().unwrap().iter().cloned());
+                    let range = shared_arena.alloc_values(
+                        arr.into_list()
+                            .map_err(|_| LoroError::DecodeDataCorruptionError)?
+                            .iter()
+                            .cloned(),
+                    );
                     crate::op::InnerContent::List(
                         crate::container::list::list_op::InnerListOp::Insert {
                             slice: SliceRange::new(range.start as u32..range.end as u32),
@@ -1414,14 +1838,16 @@ fn decode_op(
                     )
                 }
                 Value::DeleteSeq => {
-                    let del_start = del_iter.next().unwrap()?;
+                    let del_start = del_iter
+                        .next()
+                        .ok_or(LoroError::DecodeDataCorruptionError)??;
                     let peer_idx = del_start.peer_idx;
                     let cnt = del_start.counter;
                     let len = del_start.len;
                     crate::op::InnerContent::List(
                         crate::container::list::list_op::InnerListOp::Delete(
                             DeleteSpanWithId::new(
-                                ID::new(arenas.peer_ids[peer_idx], cnt as Counter),
+                                ID::new(peer(peer_idx)?, cnt as Counter),
                                 pos as isize,
                                 len,
                             ),
@@ -1435,7 +1861,7 @@ fn decode_op(
                 } => crate::op::InnerContent::List(
                     crate::container::list::list_op::InnerListOp::Move {
                         from: from as u32,
-                        from_id: IdLp::new(arenas.peer_ids[from_idx], lamport as Lamport),
+                        from_id: IdLp::new(peer(from_idx)?, lamport as Lamport),
                         to: prop as u32,
                     },
                 ),
@@ -1445,18 +1871,18 @@ fn decode_op(
                     value,
                 } => crate::op::InnerContent::List(
                     crate::container::list::list_op::InnerListOp::Set {
-                        elem_id: IdLp::new(arenas.peer_ids[peer_idx], lamport as Lamport),
+                        elem_id: IdLp::new(peer(peer_idx)?, lamport as Lamport),
                         value,
                     },
                 ),
-                _ => unreachable!(),
+                _ => return Err(LoroError::DecodeDataCorruptionError),
             }
         }
         #[cfg(feature = "counter")]
         ContainerType::Counter => match value {
             Value::F64(c) => crate::op::InnerContent::Future(FutureInnerContent::Counter(c)),
             Value::I64(c) => crate::op::InnerContent::Future(FutureInnerContent::Counter(c as f64)),
-            _ => unreachable!(),
+            _ => return Err(LoroError::DecodeDataCorruptionError),
         },
         // NOTE: The future container type need also try to parse the unknown type
         ContainerType::Unknown(_) => crate::op::InnerContent::Future(FutureInnerContent::Unknown {
@@ -1619,6 +2045,33 @@ struct EncodedStateInfo {
     is_unknown: bool,
 }
 
+// The following column groups are only used to measure the size of each part
+// of [EncodedDoc] in [explain_body].
+
+#[columnar(ser, de)]
+struct OpColumns {
+    #[columnar(class = "vec", iter = "EncodedOp")]
+    ops: Vec<EncodedOp>,
+}
+
+#[columnar(ser, de)]
+struct ChangeColumns {
+    #[columnar(class = "vec", iter = "EncodedChange")]
+    changes: Vec<EncodedChange>,
+}
+
+#[columnar(ser, de)]
+struct DeleteStartColumns {
+    #[columnar(class = "vec", iter = "EncodedDeleteStartId")]
+    delete_starts: Vec<EncodedDeleteStartId>,
+}
+
+#[columnar(ser, de)]
+struct StateColumns {
+    #[columnar(class = "vec", iter = "EncodedStateInfo")]
+    states: Vec<EncodedStateInfo>,
+}
+
 #[cfg(test)]
 mod test {
     use std::sync::Arc;
@@ -1,6 +1,8 @@
 use std::{borrow::Cow, sync::Arc};
 
-use loro_common::{ContainerID, ContainerType, IdLp, LoroResult, LoroValue, PeerID, TreeID, ID};
+use loro_common::{
+    ContainerID, ContainerType, IdLp, LoroError, LoroResult, LoroValue, PeerID, TreeID, ID,
+};
 use rle::{HasLength, RleVec, Sliceable};
 
 use crate::{
@@ -60,6 +62,12 @@ pub(crate) fn export_json<'a, 'c: 'a>(
 }
 
 pub(crate) fn import_json(oplog: &mut OpLog, json: JsonSchema) -> LoroResult<()> {
+    if json.schema_version > SCHEMA_VERSION {
+        return Err(LoroError::IncompatibleFutureEncodingError(
+            json.schema_version as usize,
+        ));
+    }
+
     let changes = decode_changes(json, &oplog.arena)?;
     let (latest_ids, pending_changes) = import_changes_to_oplog(changes, oplog)?;
     if oplog.try_apply_pending(latest_ids).should_update && !oplog.batch_importing {
@@ -402,7 +410,7 @@ fn encode_changes(
                 .collect(),
             lamport: change.lamport,
             timestamp: change.timestamp,
-            msg: None,
+            msg: change.commit_msg.as_ref().map(|x| x.to_string()),
         };
         changes.push(c);
     }
@@ -417,7 +425,7 @@ fn decode_changes(json: JsonSchema, arena: &SharedArena) -> LoroResult<Vec<Chang
         timestamp,
         deps,
         lamport,
-        msg: _,
+        msg,
         ops: json_ops,
     } in changes
     {
@@ -433,6 +441,7 @@ fn decode_changes(json: JsonSchema, arena: &SharedArena) -> LoroResult<Vec<Chang
             deps: Frontiers::from_iter(deps.into_iter().map(|id| convert_id(&id, &peers))),
             lamport,
             ops,
+            commit_msg: msg.map(|x| x.into()),
             has_dependents: false,
         };
         ans.push(change);
@@ -0,0 +1,132 @@
+//! A minimal LZ77 codec for the long strings in the op values.
+//!
+//! The input is encoded as a list of sequences, each is a run of literal bytes followed
+//! by a back-reference to the output written so far:
+//!
+//! `[literal len] [literals] [match len - MIN_MATCH] [match offset]`
+//!
+//! All the numbers are LEB128. The last sequence only has the literals. The length of the
+//! decompressed data is stored by the caller, and decoding stops once it's reached.
+//!
+//! A match may overlap with its own output, so a run of repeated lines is encoded as one
+//! sequence.
+use loro_common::{LoroError, LoroResult};
+
+const MIN_MATCH: usize = 4;
+const HASH_BITS: u32 = 16;
+
+fn hash(bytes: &[u8]) -> usize {
+    let x = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    (x.wrapping_mul(0x9E37_79B1) >> (32 - HASH_BITS)) as usize
+}
+
+fn write_usize(out: &mut Vec<u8>, value: usize) {
+    leb128::write::unsigned(out, value as u64).unwrap();
+}
+
+fn read_usize(input: &mut &[u8]) -> LoroResult<usize> {
+    leb128::read::unsigned(input)
+        .map(|x| x as usize)
+        .map_err(|_| LoroError::DecodeDataCorruptionError)
+}
+
+pub(crate) fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    // The last position of each hash, plus one. 0 means there is none.
+    let mut table = vec![0usize; 1 << HASH_BITS];
+    let mut literal_start = 0;
+    let mut i = 0;
+    while i + MIN_MATCH <= input.len() {
+        let h = hash(&input[i..]);
+        let candidate = table[h];
+        table[h] = i + 1;
+        if candidate == 0 || input[candidate - 1..][..MIN_MATCH] != input[i..][..MIN_MATCH] {
+            i += 1;
+            continue;
+        }
+
+        let start = candidate - 1;
+        let mut len = MIN_MATCH;
+        while i + len < input.len() && input[start + len] == input[i + len] {
+            len += 1;
+        }
+
+        write_usize(&mut out, i - literal_start);
+        out.extend_from_slice(&input[literal_start..i]);
+        write_usize(&mut out, len - MIN_MATCH);
+        write_usize(&mut out, i - start);
+        i += len;
+        literal_start = i;
+    }
+
+    write_usize(&mut out, input.len() - literal_start);
+    out.extend_from_slice(&input[literal_start..]);
+    out
+}
+
+/// Decompress `input` into `len` bytes.
+///
+/// `len` comes from the encoded data, so the output grows as it's decoded instead of being
+/// allocated up front.
+pub(crate) fn decompress(mut input: &[u8], len: usize) -> LoroResult<Vec<u8>> {
+    let mut out = Vec::with_capacity(len.min(input.len().saturating_mul(4)));
+    loop {
+        let literal_len = read_usize(&mut input)?;
+        if literal_len > input.len() || out.len() + literal_len > len {
+            return Err(LoroError::DecodeDataCorruptionError);
+        }
+
+        out.extend_from_slice(&input[..literal_len]);
+        input = &input[literal_len..];
+        if out.len() == len {
+            break;
+        }
+
+        let match_len = read_usize(&mut input)?.saturating_add(MIN_MATCH);
+        let offset = read_usize(&mut input)?;
+        if offset == 0 || offset > out.len() || match_len > len - out.len() {
+            return Err(LoroError::DecodeDataCorruptionError);
+        }
+
+        for _ in 0..match_len {
+            out.push(out[out.len() - offset]);
+        }
+    }
+
+    if !input.is_empty() {
+        return Err(LoroError::DecodeDataCorruptionError);
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let lines = "line 你好\n".repeat(1000);
+        let inputs: [&[u8]; 4] = [b"", b"abc", b"abcabcabcabcabcabcabcabc", lines.as_bytes()];
+        for input in inputs {
+            let compressed = compress(input);
+            assert_eq!(decompress(&compressed, input.len()).unwrap(), input);
+        }
+    }
+
+    #[test]
+    fn repeated_lines() {
+        let input = "the same line\n".repeat(10000);
+        let compressed = compress(input.as_bytes());
+        assert!(compressed.len() < 64);
+    }
+
+    #[test]
+    fn corrupted() {
+        let compressed = compress(b"abcdabcdabcd");
+        assert!(decompress(&compressed, 13).is_err());
+        assert!(decompress(&compressed[..compressed.len() - 1], 12).is_err());
+        assert!(decompress(&[0, 0, 1], 4).is_err());
+        assert!(decompress(&compressed, usize::MAX).is_err());
+    }
+}
@@ -1,11 +1,11 @@
-use std::sync::Arc;
+use std::{borrow::Cow, sync::Arc};
 
 use enum_as_inner::EnumAsInner;
 use fractional_index::FractionalIndex;
 use fxhash::FxHashMap;
 use loro_common::{
-    ContainerID, ContainerType, Counter, InternalString, LoroError, LoroResult, LoroValue, TreeID,
-    ID,
+    canonicalize_f64, ContainerID, ContainerType, Counter, InternalString, LoroError, LoroResult,
+    LoroValue, TreeID, ID,
 };
 use serde::{Deserialize, Serialize};
 
@@ -34,6 +34,8 @@ pub enum ValueKind {
     TreeMove,      // 13
     ListMove,      // 14
     ListSet,       // 15
+    StrRef,        // 16
+    CompressedStr, // 17
     Future(FutureValueKind),
 }
 
@@ -107,6 +109,8 @@ impl ValueKind {
             ValueKind::TreeMove => 13,
             ValueKind::ListMove => 14,
             ValueKind::ListSet => 15,
+            ValueKind::StrRef => 16,
+            ValueKind::CompressedStr => 17,
             ValueKind::Future(future_value_kind) => match future_value_kind {
                 FutureValueKind::Unknown(u8) => *u8 | 0x80,
             },
@@ -132,6 +136,8 @@ impl ValueKind {
             13 => ValueKind::TreeMove,
             14 => ValueKind::ListMove,
             15 => ValueKind::ListSet,
+            16 => ValueKind::StrRef,
+            17 => ValueKind::CompressedStr,
             _ => ValueKind::Future(FutureValueKind::Unknown(kind)),
         }
     }
@@ -144,7 +150,7 @@ pub enum Value<'a> {
     False,
     I64(i64),
     F64(f64),
-    Str(&'a str),
+    Str(Cow<'a, str>),
     Binary(&'a [u8]),
     ContainerIdx(usize),
     DeleteOnce,
@@ -221,7 +227,7 @@ impl<'a> Value<'a> {
             OwnedValue::I64(x) => Value::I64(*x),
             OwnedValue::ContainerIdx(x) => Value::ContainerIdx(*x),
             OwnedValue::F64(x) => Value::F64(*x),
-            OwnedValue::Str(x) => Value::Str(x.as_str()),
+            OwnedValue::Str(x) => Value::Str(Cow::Borrowed(x.as_str())),
             OwnedValue::DeleteSeq => Value::DeleteSeq,
             OwnedValue::DeltaInt(x) => Value::DeltaInt(*x),
             OwnedValue::LoroValue(x) => Value::LoroValue(x.clone()),
@@ -264,7 +270,7 @@ impl<'a> Value<'a> {
             Value::I64(x) => OwnedValue::I64(x),
             Value::ContainerIdx(x) => OwnedValue::ContainerIdx(x),
             Value::F64(x) => OwnedValue::F64(x),
-            Value::Str(x) => OwnedValue::Str(Arc::new(x.to_owned())),
+            Value::Str(x) => OwnedValue::Str(Arc::new(x.into_owned())),
             Value::DeleteSeq => OwnedValue::DeleteSeq,
             Value::DeltaInt(x) => OwnedValue::DeltaInt(x),
             Value::LoroValue(x) => OwnedValue::LoroValue(x),
@@ -325,7 +331,9 @@ impl<'a> Value<'a> {
             ValueKind::False => Value::False,
             ValueKind::I64 => Value::I64(value_reader.read_i64()?),
             ValueKind::F64 => Value::F64(value_reader.read_f64()?),
-            ValueKind::Str => Value::Str(value_reader.read_str()?),
+            ValueKind::Str => Value::Str(Cow::Borrowed(value_reader.read_str()?)),
+            ValueKind::StrRef => Value::Str(Cow::Borrowed(value_reader.read_str_ref()?)),
+            ValueKind::CompressedStr => Value::Str(Cow::Owned(value_reader.read_compressed_str()?)),
             ValueKind::Binary => Value::Binary(value_reader.read_binary()?),
             ValueKind::ContainerType => Value::ContainerIdx(value_reader.read_usize()?),
             ValueKind::DeleteOnce => Value::DeleteOnce,
@@ -391,7 +399,7 @@ impl<'a> Value<'a> {
             Value::False => (ValueKind::False, 0),
             Value::I64(x) => (ValueKind::I64, value_writer.write_i64(x)),
             Value::F64(x) => (ValueKind::F64, value_writer.write_f64(x)),
-            Value::Str(x) => (ValueKind::Str, value_writer.write_str(x)),
+            Value::Str(x) => value_writer.write_str_or_ref(&x),
             Value::Binary(x) => (ValueKind::Binary, value_writer.write_binary(x)),
             Value::ContainerIdx(x) => (ValueKind::ContainerType, value_writer.write_usize(x)),
             Value::DeleteOnce => (ValueKind::DeleteOnce, 0),
@@ -555,17 +563,32 @@ impl EncodedTreeMove {
     }
 }
 
+/// Strings shorter than this are always written in place,
+/// because a back-reference would not be much shorter.
+const MIN_DEDUP_STR_LEN: usize = 8;
+
 pub struct ValueWriter {
     buffer: Vec<u8>,
+    /// The offsets of the strings written so far, if dedup is enabled
+    str_offsets: Option<FxHashMap<Box<str>, usize>>,
+    /// The strings of at least this many bytes are compressed, if it's set
+    compression_threshold: Option<usize>,
 }
 
 pub struct ValueReader<'a> {
     raw: &'a [u8],
+    /// The whole buffer, which back-references point into
+    origin: &'a [u8],
 }
 
 impl<'a> ValueReader<'a> {
     pub fn new(raw: &'a [u8]) -> Self {
-        ValueReader { raw }
+        ValueReader { raw, origin: raw }
+    }
+
+    /// The number of bytes that haven't been read
+    pub fn remaining_len(&self) -> usize {
+        self.raw.len()
     }
 
     pub fn read_value_type_and_content(
@@ -842,7 +865,7 @@ impl<'a> ValueReader<'a> {
         let mut bytes = [0; 8];
         bytes.copy_from_slice(&self.raw[..8]);
         self.raw = &self.raw[8..];
-        Ok(f64::from_be_bytes(bytes))
+        Ok(canonicalize_f64(f64::from_be_bytes(bytes)))
     }
 
     pub fn read_usize(&mut self) -> LoroResult<usize> {
@@ -856,11 +879,44 @@ impl<'a> ValueReader<'a> {
             return Err(LoroError::DecodeDataCorruptionError);
         }
 
-        let ans = std::str::from_utf8(&self.raw[..len]).unwrap();
+        let ans = std::str::from_utf8(&self.raw[..len])
+            .map_err(|_| LoroError::DecodeDataCorruptionError)?;
         self.raw = &self.raw[len..];
         Ok(ans)
     }
 
+    /// Read a back-reference written by [`ValueWriter::write_str_or_ref`] and return the
+    /// string it points to.
+    pub fn read_str_ref(&mut self) -> LoroResult<&'a str> {
+        let mut bytes = self.read_binary()?;
+        let offset = leb128::read::unsigned(&mut bytes)
+            .map_err(|_| LoroError::DecodeDataCorruptionError)? as usize;
+        let read = self.origin.len() - self.raw.len();
+        // It can only point to a string that is before it
+        if offset >= read {
+            return Err(LoroError::DecodeDataCorruptionError);
+        }
+
+        ValueReader::new(&self.origin[offset..read]).read_str()
+    }
+
+    /// Read a string written by [`ValueWriter::write_str_or_ref`] in the compressed form:
+    /// the length of the string followed by its [LZ77](super::lz) encoding.
+    pub fn read_compressed_str(&mut self) -> LoroResult<String> {
+        let (len, compressed) = self.read_compressed_str_header()?;
+        let bytes = super::lz::decompress(compressed, len)?;
+        String::from_utf8(bytes).map_err(|_| LoroError::DecodeDataCorruptionError)
+    }
+
+    /// Read the length of a compressed string and its compressed bytes, without
+    /// decompressing it.
+    pub fn read_compressed_str_header(&mut self) -> LoroResult<(usize, &'a [u8])> {
+        let mut bytes = self.read_binary()?;
+        let len = leb128::read::unsigned(&mut bytes)
+            .map_err(|_| LoroError::DecodeDataCorruptionError)? as usize;
+        Ok((len, bytes))
+    }
+
     fn read_u8(&mut self) -> LoroResult<u8> {
         if self.raw.is_empty() {
             return Err(LoroError::DecodeDataCorruptionError);
@@ -921,7 +977,27 @@ impl<'a> ValueReader<'a> {
 
 impl ValueWriter {
     pub fn new() -> Self {
-        ValueWriter { buffer: Vec::new() }
+        ValueWriter {
+            buffer: Vec::new(),
+            str_offsets: None,
+            compression_threshold: None,
+        }
+    }
+
+    /// Create a writer that writes the repeated strings as back-references
+    /// to their first occurrence.
+    pub fn with_str_dedup() -> Self {
+        ValueWriter {
+            buffer: Vec::new(),
+            str_offsets: Some(FxHashMap::default()),
+            compression_threshold: None,
+        }
+    }
+
+    /// Compress the strings of at least `threshold` bytes, if that makes them shorter.
+    /// `None` disables the compression.
+    pub fn set_compression_threshold(&mut self, threshold: Option<usize>) {
+        self.compression_threshold = threshold;
     }
 
     pub fn write_value_type_and_content(
@@ -1010,6 +1086,45 @@ impl ValueWriter {
         self.buffer.len() - len
     }
 
+    /// Write the string, or a back-reference to an identical string written before
+    /// if dedup is enabled, or its compressed form if it reaches the compression threshold.
+    ///
+    /// The back-reference and the compressed string are framed as binary data. Versions that
+    /// don't know [`ValueKind::StrRef`] or [`ValueKind::CompressedStr`] read them as future
+    /// values, but they can't decode an op with a future value instead of its text, so they
+    /// fail to import the blob.
+    fn write_str_or_ref(&mut self, value: &str) -> (ValueKind, usize) {
+        let offset = self.buffer.len();
+        if value.len() >= MIN_DEDUP_STR_LEN {
+            if let Some(offsets) = self.str_offsets.as_mut() {
+                if let Some(&prev) = offsets.get(value) {
+                    let mut bytes = Vec::with_capacity(4);
+                    leb128::write::unsigned(&mut bytes, prev as u64).unwrap();
+                    return (ValueKind::StrRef, self.write_binary(&bytes));
+                }
+            }
+        }
+
+        if self.compression_threshold.is_some_and(|x| value.len() >= x) {
+            let mut bytes = Vec::new();
+            leb128::write::unsigned(&mut bytes, value.len() as u64).unwrap();
+            bytes.extend(super::lz::compress(value.as_bytes()));
+            if bytes.len() < value.len() {
+                // It's not registered for dedup, because the references can only point
+                // to the strings written in place
+                return (ValueKind::CompressedStr, self.write_binary(&bytes));
+            }
+        }
+
+        if value.len() >= MIN_DEDUP_STR_LEN {
+            if let Some(offsets) = self.str_offsets.as_mut() {
+                offsets.insert(value.into(), offset);
+            }
+        }
+
+        (ValueKind::Str, self.write_str(value))
+    }
+
     fn write_u8(&mut self, value: u8) -> usize {
         let len = self.buffer.len();
         self.buffer.push(value);
@@ -1 +1 @@
-pub use loro_common::{LoroError, LoroResult};
+pub use loro_common::{ErrorCategory, LoroError, LoroResult};
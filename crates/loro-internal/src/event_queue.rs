@@ -0,0 +1,205 @@
+//! A bounded queue between the doc and a slow subscriber.
+//!
+//! The subscribers are called synchronously when the events are emitted, so a subscriber
+//! that hands the events over to a slower consumer, e.g. a UI thread or a connection, has
+//! to queue them. During a catch-up import such a queue can grow without bound.
+//!
+//! An [EventQueue] keeps at most `limit` events, and its [OverflowPolicy] decides what
+//! happens to a new event when it's full. The queue is subscribed to a doc with
+//! [LoroDoc::subscribe_queue](crate::LoroDoc::subscribe_queue), and the consumer pops the
+//! events from any thread.
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+};
+
+use loro_common::ContainerID;
+
+use crate::{
+    event::{Diff, DiffEvent, DocDiff},
+    obs::Subscriber,
+};
+
+/// What happens to a new event when the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Compose the new event into the last queued one, so the consumer can't tell the
+    /// merged events apart. The merged event has the origin and the trigger of the new one.
+    /// If a diff can't be composed, [EventQueue::take_needs_resync] is set.
+    Coalesce,
+    /// Drop the oldest queued event and set [EventQueue::take_needs_resync]. The consumer
+    /// must read the whole state of the doc again instead of applying the diffs.
+    DropOldest,
+    /// Block the thread that emits the event until the consumer pops one.
+    ///
+    /// The consumer must run on another thread, and it must not wait on the doc while
+    /// popping, otherwise it deadlocks.
+    Block,
+}
+
+/// An event in the queue.
+#[derive(Debug, Clone)]
+pub struct QueuedEvent {
+    /// The container subscribed to, `None` for a root subscription
+    pub current_target: Option<ContainerID>,
+    /// The diffs received by the subscription and the meta of the event
+    pub diff: DocDiff,
+}
+
+/// A bounded event queue that can be shared between threads. See the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct EventQueue(Arc<QueueInner>);
+
+#[derive(Debug)]
+struct QueueInner {
+    limit: usize,
+    policy: OverflowPolicy,
+    state: Mutex<QueueState>,
+    popped: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    events: VecDeque<QueuedEvent>,
+    needs_resync: bool,
+    peak_len: usize,
+}
+
+impl EventQueue {
+    /// Create a queue that keeps at most `limit` events.
+    ///
+    /// # Panics
+    ///
+    /// If `limit` is 0.
+    pub fn new(limit: usize, policy: OverflowPolicy) -> Self {
+        assert!(limit > 0, "the limit of an event queue must not be 0");
+        Self(Arc::new(QueueInner {
+            limit,
+            policy,
+            state: Default::default(),
+            popped: Condvar::new(),
+        }))
+    }
+
+    pub fn limit(&self) -> usize {
+        self.0.limit
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        self.0.policy
+    }
+
+    /// The number of queued events
+    pub fn len(&self) -> usize {
+        self.state().events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state().events.is_empty()
+    }
+
+    /// The max number of events that have been queued at the same time
+    pub fn peak_len(&self) -> usize {
+        self.state().peak_len
+    }
+
+    /// Whether events have been dropped since the last call, or a diff couldn't be
+    /// composed. Then the consumer has to read the state of the doc again.
+    pub fn take_needs_resync(&self) -> bool {
+        std::mem::take(&mut self.state().needs_resync)
+    }
+
+    /// Pop the oldest event
+    pub fn pop(&self) -> Option<QueuedEvent> {
+        let ans = self.state().events.pop_front();
+        if ans.is_some() {
+            self.0.popped.notify_all();
+        }
+        ans
+    }
+
+    /// Pop all the queued events
+    pub fn drain(&self) -> Vec<QueuedEvent> {
+        let ans: Vec<_> = self.state().events.drain(..).collect();
+        if !ans.is_empty() {
+            self.0.popped.notify_all();
+        }
+        ans
+    }
+
+    /// Create the subscriber that pushes the events to this queue.
+    pub fn subscriber(&self) -> Subscriber {
+        let queue = self.clone();
+        Arc::new(move |event: DiffEvent| queue.push(event))
+    }
+
+    /// Push an event, applying the overflow policy if the queue is full.
+    pub fn push(&self, event: DiffEvent) {
+        let event = QueuedEvent {
+            current_target: event.current_target,
+            diff: DocDiff {
+                from: event.event_meta.from.clone(),
+                to: event.event_meta.to.clone(),
+                origin: event.event_meta.origin.clone(),
+                by: event.event_meta.by,
+                diff: event.events.iter().map(|&x| x.clone()).collect(),
+            },
+        };
+
+        let mut state = self.state();
+        if state.events.len() >= self.0.limit {
+            match self.0.policy {
+                OverflowPolicy::Coalesce => {
+                    let last = state.events.back_mut().unwrap();
+                    if !coalesce(&mut last.diff, event.diff) {
+                        state.needs_resync = true;
+                    }
+                    return;
+                }
+                OverflowPolicy::DropOldest => {
+                    state.events.pop_front();
+                    state.needs_resync = true;
+                }
+                OverflowPolicy::Block => {
+                    state = self
+                        .0
+                        .popped
+                        .wait_while(state, |s| s.events.len() >= self.0.limit)
+                        .unwrap();
+                }
+            }
+        }
+
+        state.events.push_back(event);
+        state.peak_len = state.peak_len.max(state.events.len());
+    }
+
+    fn state(&self) -> MutexGuard<'_, QueueState> {
+        self.0.state.lock().unwrap()
+    }
+}
+
+/// Compose `new` into `last`. Returns false if a diff couldn't be composed and was lost.
+fn coalesce(last: &mut DocDiff, new: DocDiff) -> bool {
+    last.to = new.to;
+    last.origin = new.origin;
+    last.by = new.by;
+    let mut composed = true;
+    for diff in new.diff {
+        let Some(old) = last.diff.iter_mut().find(|x| x.id == diff.id) else {
+            last.diff.push(diff);
+            continue;
+        };
+
+        old.path = diff.path;
+        match std::mem::replace(&mut old.diff, Diff::Unknown).compose(diff.diff) {
+            Ok(x) => old.diff = x,
+            Err(x) => {
+                old.diff = x;
+                composed = false;
+            }
+        }
+    }
+
+    composed
+}
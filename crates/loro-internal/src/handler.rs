@@ -1,27 +1,30 @@
 use super::{state::DocState, txn::Transaction};
 use crate::{
     arena::SharedArena,
+    change::Timestamp,
     container::{
         idx::ContainerIdx,
         list::list_op::{DeleteSpan, DeleteSpanWithId, ListOp},
-        richtext::{richtext_state::PosType, RichtextState, StyleOp, TextStyleInfoFlag},
+        richtext::{RichtextState, StyleOp, TextSpan, TextStyleInfoFlag},
     },
     cursor::{Cursor, Side},
     delta::{DeltaItem, Meta, StyleMeta, TreeExternalDiff},
     diff::{myers_diff, DiffHandler, OperateProxy},
     event::{Diff, TextDiffItem},
+    loro::{system_root_id, TTL_MAP},
     op::ListSlice,
+    span::HasLamportSpan,
     state::{ContainerState, IndexType, State},
     txn::EventHint,
     utils::{string_slice::StringSlice, utf16::count_utf16_len},
 };
 use append_only_bytes::BytesSlice;
 use enum_as_inner::EnumAsInner;
-use fxhash::FxHashMap;
+use fxhash::{FxHashMap, FxHashSet};
 use generic_btree::rle::HasLength;
 use loro_common::{
-    ContainerID, ContainerType, IdFull, InternalString, LoroError, LoroResult, LoroValue, TreeID,
-    ID,
+    ContainerID, ContainerType, IdFull, IdLp, IdSpan, InternalString, Lamport, LoroError,
+    LoroResult, LoroValue, PeerID, TreeID, ValueRef, ID,
 };
 use serde::{Deserialize, Serialize};
 use std::{
@@ -29,53 +32,60 @@ use std::{
     cmp::Reverse,
     collections::BinaryHeap,
     fmt::Debug,
-    ops::Deref,
+    ops::{Deref, Range},
     sync::{Arc, Mutex, Weak},
 };
 use tracing::{debug, error, info, instrument};
 mod tree;
+pub use crate::container::richtext::richtext_state::PosType;
 pub use tree::TreeHandler;
 
 const INSERT_CONTAINER_VALUE_ARG_ERROR: &str =
     "Cannot insert a LoroValue::Container directly. To create child container, use insert_container";
+const NON_FINITE_NUMBER_ARG_ERROR: &str =
+    "Cannot insert NaN or an infinite number, they are not equal to themselves or not valid JSON";
 
+/// NaN and infinity are rejected when they are inserted, see [LoroValue::has_non_finite_number].
+fn check_finite(value: &LoroValue) -> LoroResult<()> {
+    if value.has_non_finite_number() {
+        return Err(LoroError::ArgErr(
+            NON_FINITE_NUMBER_ARG_ERROR.to_string().into_boxed_str(),
+        ));
+    }
+
+    Ok(())
+}
+/// Collect the edits that turn the text into `new`, as `(unicode pos, deleted len, inserted
+/// text)`. The positions assume that the edits before are applied as they are.
 struct DiffHook<'a> {
-    text: &'a TextHandler,
     new: &'a [char],
+    edits: Vec<(usize, usize, String)>,
 }
 
 impl<'a> DiffHook<'a> {
-    fn new(text: &'a TextHandler, new: &'a [char]) -> Self {
-        Self { text, new }
+    fn new(new: &'a [char]) -> Self {
+        Self {
+            new,
+            edits: Vec::new(),
+        }
     }
 }
 
 impl DiffHandler for DiffHook<'_> {
     fn insert(&mut self, old_index: usize, new_index: usize, new_len: usize) {
-        self.text
-            .insert_unicode(
-                old_index,
-                &self.new[new_index..new_index + new_len]
-                    .iter()
-                    .collect::<String>(),
-            )
-            .unwrap();
+        self.replace(old_index, 0, new_index, new_len);
     }
 
     fn delete(&mut self, old_index: usize, old_len: usize) {
-        self.text.delete_unicode(old_index, old_len).unwrap();
+        self.edits.push((old_index, old_len, String::new()));
     }
 
     fn replace(&mut self, old_index: usize, old_len: usize, new_index: usize, new_len: usize) {
-        self.text.delete_unicode(old_index, old_len).unwrap();
-        self.text
-            .insert_unicode(
-                old_index,
-                &self.new[new_index..new_index + new_len]
-                    .iter()
-                    .collect::<String>(),
-            )
-            .unwrap();
+        self.edits.push((
+            old_index,
+            old_len,
+            self.new[new_index..new_index + new_len].iter().collect(),
+        ));
     }
 }
 
@@ -491,7 +501,7 @@ impl HandlerTrait for MapHandler {
                 }
                 LoroValue::Map(Arc::new(map))
             }
-            MaybeDetached::Attached(a) => a.get_value(),
+            MaybeDetached::Attached(a) => remove_keys(a.get_value(), &self.expired_keys(a)),
         }
     }
 
@@ -505,7 +515,7 @@ impl HandlerTrait for MapHandler {
                 }
                 LoroValue::Map(Arc::new(map))
             }
-            MaybeDetached::Attached(a) => a.get_deep_value(),
+            MaybeDetached::Attached(a) => remove_keys(a.get_deep_value(), &self.expired_keys(a)),
         }
     }
 
@@ -761,13 +771,21 @@ impl HandlerTrait for ListHandler {
                 let mut l = l.try_lock().unwrap();
                 let inner = create_handler(parent, self_id);
                 let list = inner.into_list().unwrap();
-                for (index, v) in l.value.iter().enumerate() {
-                    match v {
+                let mut index = 0;
+                while index < l.value.len() {
+                    match &l.value[index] {
                         ValueOrHandler::Value(v) => {
                             list.insert_with_txn(txn, index, v.clone())?;
+                            index += 1;
                         }
-                        ValueOrHandler::Handler(h) => {
-                            list.insert_container_with_txn(txn, index, h.clone())?;
+                        ValueOrHandler::Handler(_) => {
+                            // The adjacent children are created by one op
+                            let children: Vec<Handler> = l.value[index..]
+                                .iter()
+                                .map_while(|v| v.as_handler().cloned())
+                                .collect();
+                            list.insert_containers_with_txn(txn, index, &children)?;
+                            index += children.len();
                         }
                     }
                 }
@@ -1402,6 +1420,22 @@ impl TextHandler {
         }
     }
 
+    /// Call `f` with an iterator over the chunks of the text, borrowed from the state.
+    ///
+    /// The doc state is locked while `f` runs, so `f` must not access the doc.
+    pub fn as_chunks<R>(&self, f: impl FnOnce(&mut dyn Iterator<Item = &str>) -> R) -> R {
+        match &self.inner {
+            MaybeDetached::Detached(t) => {
+                let t = t.try_lock().unwrap();
+                let mut chunks = t.value.iter_str();
+                f(&mut chunks)
+            }
+            MaybeDetached::Attached(a) => {
+                a.with_state(|state| f(&mut state.as_richtext_state_mut().unwrap().chunks()))
+            }
+        }
+    }
+
     /// `pos` is a Event Index:
     ///
     /// - if feature="wasm", pos is a UTF-16 index
@@ -1527,7 +1561,8 @@ impl TextHandler {
         }
     }
 
-    pub(crate) fn insert_unicode(&self, pos: usize, s: &str) -> LoroResult<()> {
+    /// Insert text at the given Unicode code point index.
+    pub fn insert_unicode(&self, pos: usize, s: &str) -> LoroResult<()> {
         match &self.inner {
             MaybeDetached::Detached(t) => {
                 let mut t = t.try_lock().unwrap();
@@ -1549,6 +1584,27 @@ impl TextHandler {
         }
     }
 
+    /// Insert text at the given UTF-16 index.
+    ///
+    /// Return an error if `pos` is inside a surrogate pair.
+    pub fn insert_utf16(&self, pos: usize, s: &str) -> LoroResult<()> {
+        let pos = self.utf16_to_unicode_pos(pos)?;
+        self.insert_unicode(pos, s)
+    }
+
+    fn utf16_to_unicode_pos(&self, pos: usize) -> LoroResult<usize> {
+        if pos > self.len_utf16() {
+            return Err(LoroError::OutOfBound {
+                pos,
+                len: self.len_utf16(),
+                info: format!("Position: {}:{}", file!(), line!()).into_boxed_str(),
+            });
+        }
+
+        self.convert_pos(pos, PosType::Utf16, PosType::Unicode)
+            .ok_or(LoroError::UTF16InUnicodeCodePoint { pos })
+    }
+
     /// `pos` is a Event Index:
     ///
     /// - if feature="wasm", pos is a UTF-16 index
@@ -1632,6 +1688,87 @@ impl TextHandler {
         }
     }
 
+    /// Delete `len` UTF-16 code units from the given UTF-16 index.
+    ///
+    /// Return an error if the range starts or ends inside a surrogate pair.
+    pub fn delete_utf16(&self, pos: usize, len: usize) -> LoroResult<()> {
+        let start = self.utf16_to_unicode_pos(pos)?;
+        let end = self.utf16_to_unicode_pos(pos + len)?;
+        self.delete_unicode(start, end - start)
+    }
+
+    /// Convert a position of type `from` to the position of type `to` in O(log n).
+    ///
+    /// Return `None` if `index` is out of bound or it's not at the boundary of a code point.
+    pub fn convert_pos(&self, index: usize, from: PosType, to: PosType) -> Option<usize> {
+        match &self.inner {
+            MaybeDetached::Detached(t) => {
+                let t = t.try_lock().unwrap();
+                t.value.convert_pos(index, from, to)
+            }
+            MaybeDetached::Attached(a) => a.with_state(|state| {
+                state
+                    .as_richtext_state_mut()
+                    .unwrap()
+                    .convert_pos(index, from, to)
+            }),
+        }
+    }
+
+    /// Attribute each char in the unicode range to the op that inserted it.
+    ///
+    /// It returns the id spans of the ops in the order of the text. The adjacent chars
+    /// inserted by the same op are merged into one span, and the peer of the author is
+    /// `span.peer`.
+    pub fn get_attribution(&self, range: Range<usize>) -> LoroResult<Vec<IdSpan>> {
+        let MaybeDetached::Attached(a) = &self.inner else {
+            return Err(LoroError::MisuseDetachedContainer {
+                method: "TextHandler::get_attribution",
+            });
+        };
+        let len = self.len_unicode();
+        if range.start > range.end || range.end > len {
+            return Err(LoroError::OutOfBound {
+                pos: range.end,
+                len,
+                info: format!("Position: {}:{}", file!(), line!()).into_boxed_str(),
+            });
+        }
+
+        Ok(a.with_state(|state| {
+            state
+                .as_richtext_state_mut()
+                .unwrap()
+                .get_attribution(range)
+        }))
+    }
+
+    /// Get the spans of text that overlap the event index range, with their ids and lengths.
+    ///
+    /// It only visits the part of the text in the range, so a renderer that draws the
+    /// visible part of a long text doesn't have to go through the whole text. The adjacent
+    /// chars inserted by consecutive ops of a peer are merged into one span.
+    pub fn get_spans_in_range(&self, range: Range<usize>) -> LoroResult<Vec<TextSpan>> {
+        let MaybeDetached::Attached(a) = &self.inner else {
+            return Err(LoroError::MisuseDetachedContainer {
+                method: "TextHandler::get_spans_in_range",
+            });
+        };
+        if range.end < range.start {
+            return Err(LoroError::EndIndexLessThanStartIndex {
+                start: range.start,
+                end: range.end,
+            });
+        }
+
+        a.with_state(|state| {
+            state
+                .as_richtext_state_mut()
+                .unwrap()
+                .get_spans_by_event_index(range.start, range.end - range.start)
+        })
+    }
+
     /// If attr is specified, it will be used as the atribute of the inserted text.
     /// It will override the existing attribute of the text.
     fn insert_with_txn_and_attr(
@@ -1828,7 +1965,25 @@ impl TextHandler {
                     });
                 }
             }
-            _ => (),
+            PosType::Unicode => {
+                if pos + len > self.len_unicode() {
+                    return Err(LoroError::OutOfBound {
+                        pos: pos + len,
+                        len: self.len_unicode(),
+                        info: format!("Position: {}:{}", file!(), line!()).into_boxed_str(),
+                    });
+                }
+            }
+            PosType::Utf16 => {
+                if pos + len > self.len_utf16() {
+                    return Err(LoroError::OutOfBound {
+                        pos: pos + len,
+                        len: self.len_utf16(),
+                        info: format!("Position: {}:{}", file!(), line!()).into_boxed_str(),
+                    });
+                }
+            }
+            PosType::Entity => (),
         }
 
         let inner = self.inner.try_attached_state()?;
@@ -1991,6 +2146,7 @@ impl TextHandler {
             ));
         }
 
+        check_finite(&value)?;
         let len = self.len_event();
         if end > len {
             return Err(LoroError::OutOfBound {
@@ -2095,17 +2251,34 @@ impl TextHandler {
         }
     }
 
+    /// Apply a Quill-style delta in the given transaction.
+    ///
+    /// The lengths are in the event position type. The delta is validated before any op
+    /// is applied, so an invalid delta leaves the text unchanged. A retain with attributes
+    /// may extend past the end of the text; the missing part is filled with `"\n"`, like
+    /// the implicit trailing newline of a Quill document.
     pub fn apply_delta_with_txn(
         &self,
         txn: &mut Transaction,
         delta: &[TextDelta],
+    ) -> LoroResult<()> {
+        self.check_delta_bounds(delta)?;
+        let idx = self.inner.try_attached_state()?.container_idx;
+        txn.atomic_edit(idx, |txn| self.apply_checked_delta_with_txn(txn, delta))
+    }
+
+    fn apply_checked_delta_with_txn(
+        &self,
+        txn: &mut Transaction,
+        delta: &[TextDelta],
     ) -> LoroResult<()> {
         let mut index = 0;
         let mut marks = Vec::new();
         for d in delta {
             match d {
                 TextDelta::Insert { insert, attributes } => {
-                    let end = index + event_len(insert.as_str());
+                    // The pre-commit hook may rewrite the inserted text
+                    let len = self.len_event();
                     let override_styles = self.insert_with_txn_and_attr(
                         txn,
                         index,
@@ -2113,6 +2286,11 @@ impl TextHandler {
                         Some(attributes.as_ref().unwrap_or(&Default::default())),
                         PosType::Event,
                     )?;
+                    let end = index + self.len_event() - len;
+                    if end == index {
+                        // The hook rewrote the text to be empty, there is nothing to mark
+                        continue;
+                    }
 
                     for (key, value) in override_styles {
                         marks.push((index, end, key, value));
@@ -2151,14 +2329,84 @@ impl TextHandler {
         Ok(())
     }
 
-    pub fn update(&self, text: &str) {
+    /// Check that the deletions and the retains without attributes are within the text
+    fn check_delta_bounds(&self, delta: &[TextDelta]) -> LoroResult<()> {
+        let mut len = self.len_event();
+        let mut index = 0;
+        for d in delta {
+            match d {
+                TextDelta::Insert { insert, .. } => {
+                    let insert_len = event_len(insert.as_str());
+                    index += insert_len;
+                    len += insert_len;
+                }
+                TextDelta::Delete { delete } => {
+                    if index + *delete > len {
+                        return Err(LoroError::OutOfBound {
+                            pos: index + *delete,
+                            len,
+                            info: format!("Position: {}:{}", file!(), line!()).into_boxed_str(),
+                        });
+                    }
+
+                    len -= *delete;
+                }
+                TextDelta::Retain { retain, attributes } => {
+                    index += *retain;
+                    let has_attributes = attributes.as_ref().is_some_and(|x| !x.is_empty());
+                    if !has_attributes && index > len {
+                        return Err(LoroError::OutOfBound {
+                            pos: index,
+                            len,
+                            info: format!("Position: {}:{}", file!(), line!()).into_boxed_str(),
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Update the text to `text` with the minimal edits, found by a diff of the two texts.
+    ///
+    /// The edits are applied in one transaction. If one of them fails, e.g. it's rejected by
+    /// the [PreCommitHook](crate::txn::PreCommitHook), the text is unchanged.
+    pub fn update(&self, text: &str) -> LoroResult<()> {
         let old_str = self.to_string();
         let new = text.chars().collect::<Vec<char>>();
-        myers_diff(
-            &mut OperateProxy::new(DiffHook::new(self, &new)),
-            &old_str.chars().collect::<Vec<char>>(),
-            &new,
-        );
+        let mut proxy = OperateProxy::new(DiffHook::new(&new));
+        myers_diff(&mut proxy, &old_str.chars().collect::<Vec<char>>(), &new);
+        let edits = proxy.into_handler().edits;
+        match &self.inner {
+            MaybeDetached::Detached(_) => {
+                for (pos, len, insert) in edits {
+                    if len > 0 {
+                        self.delete_unicode(pos, len)?;
+                    }
+                    if !insert.is_empty() {
+                        self.insert_unicode(pos, &insert)?;
+                    }
+                }
+                Ok(())
+            }
+            MaybeDetached::Attached(a) => a.with_txn(|txn| {
+                txn.atomic_edit(a.container_idx, |txn| {
+                    // The pre-commit hook may rewrite the inserted text, which shifts the
+                    // positions of the edits after it
+                    let mut shift = 0isize;
+                    for (pos, len, insert) in edits {
+                        let pos = (pos as isize + shift) as usize;
+                        self.delete_with_txn_inline(txn, pos, len, PosType::Unicode)?;
+                        let before = self.len_unicode();
+                        self.insert_with_txn_and_attr(txn, pos, &insert, None, PosType::Unicode)?;
+                        let inserted = self.len_unicode() - before;
+                        shift += inserted as isize - insert.chars().count() as isize;
+                    }
+                    Ok(())
+                })
+            }),
+        }
     }
 
     #[allow(clippy::inherent_to_string)]
@@ -2316,6 +2564,8 @@ impl ListHandler {
             ));
         }
 
+        check_finite(&v)?;
+
         txn.apply_local_op(
             inner.container_idx,
             crate::op::RawOpContent::List(crate::container::list::list_op::ListOp::Insert {
@@ -2409,6 +2659,80 @@ impl ListHandler {
         Ok(ans)
     }
 
+    /// Insert the containers at `pos` with a single op.
+    ///
+    /// A new container's id is the id of the op that creates it. Here the containers take
+    /// the consecutive ids of one op, so inserting many children, e.g. to instantiate a
+    /// template, is encoded as one op instead of one op per child. The content of the
+    /// detached children is inserted after the op.
+    pub fn insert_containers(
+        &self,
+        pos: usize,
+        children: Vec<Handler>,
+    ) -> LoroResult<Vec<Handler>> {
+        match &self.inner {
+            MaybeDetached::Detached(l) => {
+                let mut list = l.try_lock().unwrap();
+                for (i, child) in children.iter().enumerate() {
+                    list.value
+                        .insert(pos + i, ValueOrHandler::Handler(child.clone()));
+                }
+                Ok(children)
+            }
+            MaybeDetached::Attached(a) => {
+                a.with_txn(|txn| self.insert_containers_with_txn(txn, pos, &children))
+            }
+        }
+    }
+
+    pub fn insert_containers_with_txn(
+        &self,
+        txn: &mut Transaction,
+        pos: usize,
+        children: &[Handler],
+    ) -> LoroResult<Vec<Handler>> {
+        if pos > self.len() {
+            return Err(LoroError::OutOfBound {
+                pos,
+                info: format!("Position: {}:{}", file!(), line!()).into_boxed_str(),
+                len: self.len(),
+            });
+        }
+
+        if children.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let inner = self.inner.try_attached_state()?;
+        let id = txn.next_id();
+        let container_ids: Vec<ContainerID> = children
+            .iter()
+            .enumerate()
+            .map(|(i, child)| ContainerID::new_normal(id.inc(i as i32), child.kind()))
+            .collect();
+        let values = container_ids
+            .iter()
+            .map(|x| LoroValue::Container(x.clone()))
+            .collect();
+        txn.apply_local_op(
+            inner.container_idx,
+            crate::op::RawOpContent::List(crate::container::list::list_op::ListOp::Insert {
+                slice: ListSlice::RawData(Cow::Owned(values)),
+                pos,
+            }),
+            EventHint::InsertList {
+                len: children.len() as u32,
+                pos,
+            },
+            &inner.state,
+        )?;
+        children
+            .iter()
+            .zip(container_ids)
+            .map(|(child, id)| child.attach(txn, inner, id))
+            .collect()
+    }
+
     pub fn delete(&self, pos: usize, len: usize) -> LoroResult<()> {
         match &self.inner {
             MaybeDetached::Detached(l) => {
@@ -2719,6 +3043,7 @@ impl MovableListHandler {
             ));
         }
 
+        check_finite(&v)?;
         let op_index = self.with_state(|state| {
             let list = state.as_movable_list_state().unwrap();
             Ok(list
@@ -2964,6 +3289,7 @@ impl MovableListHandler {
             });
         }
 
+        check_finite(&value)?;
         let inner = self.inner.try_attached_state()?;
         let Some(elem_id) = self.with_state(|state| {
             let list = state.as_movable_list_state().unwrap();
@@ -3547,6 +3873,7 @@ impl MapHandler {
             ));
         }
 
+        check_finite(&value)?;
         if self.get(key).map(|x| x == value).unwrap_or(false) {
             // skip if the value is already set
             return Ok(());
@@ -3567,6 +3894,190 @@ impl MapHandler {
         )
     }
 
+    /// Insert a value that expires `ttl` after it's written.
+    ///
+    /// The value is stored as is. The TTL is stored in a hidden root map together with the
+    /// id of this write, so it's dropped once the key is overwritten. The entry expires once
+    /// the timestamp of the state's version reaches the timestamp of the change of this
+    /// write plus `ttl`. It doesn't depend on the local clock, so the peers with the same
+    /// version agree on whether an entry has expired.
+    ///
+    /// Expired entries are hidden from the reads of this handler, and they are deleted by
+    /// [MapHandler::gc_expired]. A detached map ignores the TTL.
+    ///
+    /// Return an error if the doc doesn't record the timestamps of the changes.
+    pub fn insert_with_ttl(
+        &self,
+        key: &str,
+        value: impl Into<LoroValue>,
+        ttl: Timestamp,
+    ) -> LoroResult<()> {
+        let value = value.into();
+        if value.is_container() {
+            return Err(LoroError::ArgErr(
+                INSERT_CONTAINER_VALUE_ARG_ERROR
+                    .to_string()
+                    .into_boxed_str(),
+            ));
+        }
+
+        check_finite(&value)?;
+
+        match &self.inner {
+            MaybeDetached::Detached(_) => self.insert(key, value),
+            MaybeDetached::Attached(a) => {
+                if !a.with_doc_state(|state| state.config.record_timestamp()) {
+                    return Err(LoroError::ArgErr(
+                        "insert_with_ttl needs the timestamps of the changes".into(),
+                    ));
+                }
+
+                let ttl_map = ttl_map(a);
+                a.with_txn(|txn| {
+                    self.insert_with_txn(txn, key, value)?;
+                    let written = a.with_state(|state| {
+                        let v = state.as_map_state().unwrap().get_entry(key).unwrap();
+                        IdLp::new(v.peer, v.lamp)
+                    });
+                    ttl_map.insert_with_txn(
+                        txn,
+                        &ttl_key(&a.id, key),
+                        LoroValue::List(Arc::new(vec![
+                            ttl.into(),
+                            (written.peer as i64).into(),
+                            (written.lamport as i64).into(),
+                        ])),
+                    )
+                })
+            }
+        }
+    }
+
+    /// Delete the entries that have expired. See [MapHandler::insert_with_ttl].
+    ///
+    /// The TTLs of the keys that have been overwritten are deleted too.
+    ///
+    /// Return the deleted keys.
+    pub fn gc_expired(&self) -> LoroResult<Vec<InternalString>> {
+        let MaybeDetached::Attached(a) = &self.inner else {
+            return Ok(Vec::new());
+        };
+
+        let ttls = self.ttl_entries(a);
+        if ttls.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let expired = self.expired_keys(a);
+        let stale = a.with_state(|state| {
+            let map = state.as_map_state().unwrap();
+            ttls.into_iter()
+                .filter(|(key, (_, written))| {
+                    map.get_entry(key)
+                        .is_none_or(|v| IdLp::new(v.peer, v.lamp) != *written)
+                })
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>()
+        });
+        if expired.is_empty() && stale.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let ttl_map = ttl_map(a);
+        a.with_txn(|txn| {
+            for key in expired.iter() {
+                self.delete_with_txn(txn, key)?;
+                ttl_map.delete_with_txn(txn, &ttl_key(&a.id, key))?;
+            }
+            for key in stale.iter() {
+                ttl_map.delete_with_txn(txn, &ttl_key(&a.id, key))?;
+            }
+            Ok(())
+        })?;
+        let mut expired = expired.into_iter().collect::<Vec<_>>();
+        expired.sort_unstable();
+        Ok(expired)
+    }
+
+    /// The TTLs of the keys of this map and the ids of the writes they belong to, see
+    /// [MapHandler::insert_with_ttl].
+    fn ttl_entries(&self, inner: &BasicHandler) -> FxHashMap<InternalString, (Timestamp, IdLp)> {
+        let ttl_id = system_root_id(TTL_MAP);
+        // Don't register the map when there is no TTL
+        let Some(ttl_idx) = inner.arena.id_to_idx(&ttl_id) else {
+            return FxHashMap::default();
+        };
+
+        let prefix = ttl_key(&inner.id, "");
+        inner.with_doc_state(|state| {
+            state.with_state_mut(ttl_idx, |state| {
+                state
+                    .as_map_state()
+                    .unwrap()
+                    .iter()
+                    .filter_map(|(k, v)| {
+                        let key = k.strip_prefix(prefix.as_str())?;
+                        let ttl = v.value.as_ref()?.as_list()?;
+                        let [LoroValue::I64(ttl), LoroValue::I64(peer), LoroValue::I64(lamport)] =
+                            ttl.as_slice()
+                        else {
+                            return None;
+                        };
+                        Some((
+                            key.into(),
+                            (*ttl, IdLp::new(*peer as PeerID, *lamport as Lamport)),
+                        ))
+                    })
+                    .collect()
+            })
+        })
+    }
+
+    /// The keys of this map that have expired, see [MapHandler::insert_with_ttl].
+    fn expired_keys(&self, inner: &BasicHandler) -> FxHashSet<InternalString> {
+        let ttls = self.ttl_entries(inner);
+        if ttls.is_empty() {
+            return FxHashSet::default();
+        }
+
+        let (frontiers, oplog) =
+            inner.with_doc_state(|state| (state.frontiers.clone(), state.oplog.clone()));
+        let Some(oplog) = oplog.upgrade() else {
+            return FxHashSet::default();
+        };
+        let expired = {
+            let oplog = oplog.lock().unwrap();
+            let clock = oplog.get_timestamp_of_version(&frontiers);
+            ttls.into_iter()
+                .filter(|(_, (ttl, written))| {
+                    // The change isn't in the oplog yet if the write is still in the txn
+                    oplog
+                        .get_change_with_lamport(written.peer, written.lamport)
+                        .is_some_and(|c| {
+                            c.lamport() <= written.lamport
+                                && written.lamport < c.lamport_end()
+                                && c.timestamp().saturating_add(*ttl) <= clock
+                        })
+                })
+                .collect::<Vec<_>>()
+        };
+        if expired.is_empty() {
+            return FxHashSet::default();
+        }
+
+        inner.with_state(|state| {
+            let map = state.as_map_state().unwrap();
+            expired
+                .into_iter()
+                .filter(|(key, (_, written))| {
+                    map.get_entry(key)
+                        .is_some_and(|v| v.value.is_some() && IdLp::new(v.peer, v.lamp) == *written)
+                })
+                .map(|(key, _)| key)
+                .collect()
+        })
+    }
+
     pub fn insert_container<T: HandlerTrait>(&self, key: &str, handler: T) -> LoroResult<T> {
         match &self.inner {
             MaybeDetached::Detached(m) => {
@@ -3646,6 +4157,7 @@ impl MapHandler {
                 }
             }
             MaybeDetached::Attached(inner) => {
+                let expired = self.expired_keys(inner);
                 inner.with_state(|state| {
                     let a = state.as_map_state().unwrap();
                     for (k, v) in a.iter() {
@@ -3654,7 +4166,11 @@ impl MapHandler {
                                 LoroValue::Container(c) => {
                                     f(k, ValueOrHandler::Handler(create_handler(inner, c.clone())))
                                 }
-                                value => f(k, ValueOrHandler::Value(value.clone())),
+                                value => {
+                                    if !expired.contains(k) {
+                                        f(k, ValueOrHandler::Value(value.clone()))
+                                    }
+                                }
                             },
                             None => {}
                         }
@@ -3711,11 +4227,46 @@ impl MapHandler {
                 m.value.get(key).map(|v| v.to_value())
             }
             MaybeDetached::Attached(inner) => {
+                if self
+                    .expired_keys(inner)
+                    .contains(&InternalString::from(key))
+                {
+                    return None;
+                }
+
                 inner.with_state(|state| state.as_map_state().unwrap().get(key).cloned())
             }
         }
     }
 
+    /// Call `f` with the value at `key`, borrowed from the state.
+    ///
+    /// The doc state is locked while `f` runs, so `f` must not access the doc.
+    pub fn get_ref<R>(&self, key: &str, f: impl FnOnce(Option<ValueRef<'_>>) -> R) -> R {
+        match &self.inner {
+            MaybeDetached::Detached(m) => {
+                let m = m.try_lock().unwrap();
+                match m.value.get(key) {
+                    Some(ValueOrHandler::Value(v)) => f(Some(v.into())),
+                    Some(ValueOrHandler::Handler(h)) => f(Some(ValueRef::Container(&h.id()))),
+                    None => f(None),
+                }
+            }
+            MaybeDetached::Attached(inner) => {
+                if self
+                    .expired_keys(inner)
+                    .contains(&InternalString::from(key))
+                {
+                    return f(None);
+                }
+
+                inner.with_state(|state| {
+                    f(state.as_map_state().unwrap().get(key).map(ValueRef::from))
+                })
+            }
+        }
+    }
+
     /// Get the value at given key, if value is a container, return a handler to the container
     pub fn get_(&self, key: &str) -> Option<ValueOrHandler> {
         match &self.inner {
@@ -3724,8 +4275,7 @@ impl MapHandler {
                 m.value.get(key).cloned()
             }
             MaybeDetached::Attached(inner) => {
-                let value =
-                    inner.with_state(|state| state.as_map_state().unwrap().get(key).cloned());
+                let value = self.get(key);
                 match value {
                     Some(LoroValue::Container(container_id)) => Some(ValueOrHandler::Handler(
                         create_handler(inner, container_id.clone()),
@@ -3766,7 +4316,8 @@ impl MapHandler {
         match &self.inner {
             MaybeDetached::Detached(m) => m.try_lock().unwrap().value.len(),
             MaybeDetached::Attached(a) => {
-                a.with_state(|state| state.as_map_state().unwrap().size())
+                let expired = self.expired_keys(a);
+                a.with_state(|state| state.as_map_state().unwrap().size()) - expired.len()
             }
         }
     }
@@ -3776,6 +4327,34 @@ impl MapHandler {
     }
 }
 
+/// The key of the TTL of `key` of the map `map` in [TTL_MAP]
+fn ttl_key(map: &ContainerID, key: &str) -> String {
+    format!("{}\u{0}{}", map, key)
+}
+
+/// The hidden root map that stores the TTLs of the map entries
+fn ttl_map(inner: &BasicHandler) -> MapHandler {
+    create_handler(inner, system_root_id(TTL_MAP))
+        .into_map()
+        .unwrap()
+}
+
+/// Remove `keys` from the value of a map
+fn remove_keys(value: LoroValue, keys: &FxHashSet<InternalString>) -> LoroValue {
+    if keys.is_empty() {
+        return value;
+    }
+
+    let LoroValue::Map(map) = value else {
+        return value;
+    };
+    let mut map = (*map).clone();
+    for key in keys {
+        map.remove(&**key);
+    }
+    LoroValue::Map(Arc::new(map))
+}
+
 #[inline(always)]
 fn with_txn<R>(
     txn: &Weak<Mutex<Option<Transaction>>>,
@@ -3784,7 +4363,16 @@ fn with_txn<R>(
     let mutex = &txn.upgrade().unwrap();
     let mut txn = mutex.try_lock().unwrap();
     match &mut *txn {
-        Some(t) => f(t),
+        Some(t) => {
+            let ans = f(t)?;
+            if t.reached_auto_commit_limit() {
+                let on_commit = t.commit_then_renew()?;
+                drop(txn);
+                on_commit();
+            }
+
+            Ok(ans)
+        }
         None => Err(LoroError::AutoCommitNotStarted),
     }
 }
@@ -3792,7 +4380,7 @@ fn with_txn<R>(
 #[cfg(feature = "counter")]
 pub mod counter {
 
-    use loro_common::LoroResult;
+    use loro_common::{LoroResult, LoroValue};
 
     use crate::{
         state::ContainerState,
@@ -3800,7 +4388,7 @@ pub mod counter {
         HandlerTrait,
     };
 
-    use super::{create_handler, Handler, MaybeDetached};
+    use super::{check_finite, create_handler, Handler, MaybeDetached};
 
     #[derive(Clone)]
     pub struct CounterHandler {
@@ -3836,7 +4424,8 @@ pub mod counter {
             }
         }
 
-        fn increment_with_txn(&self, txn: &mut Transaction, n: f64) -> LoroResult<()> {
+        pub fn increment_with_txn(&self, txn: &mut Transaction, n: f64) -> LoroResult<()> {
+            check_finite(&LoroValue::Double(n))?;
             let inner = self.inner.try_attached_state()?;
             txn.apply_local_op(
                 inner.container_idx,
@@ -3845,6 +4434,10 @@ pub mod counter {
                 &inner.state,
             )
         }
+
+        pub fn decrement_with_txn(&self, txn: &mut Transaction, n: f64) -> LoroResult<()> {
+            self.increment_with_txn(txn, -n)
+        }
     }
 
     impl std::fmt::Debug for CounterHandler {
@@ -772,6 +772,34 @@ impl TreeHandler {
         self.children(None).unwrap_or_default()
     }
 
+    /// Get the value of the tree as nested nodes, in the order of the siblings.
+    ///
+    /// Each node is a map of `id`, `meta` (the deep value of the meta map) and `children`.
+    pub fn get_nested_value(&self) -> LoroValue {
+        fn node_value(tree: &TreeHandler, node: TreeID) -> LoroValue {
+            let mut map = FxHashMap::default();
+            map.insert("id".to_string(), node.to_string().into());
+            let meta = tree
+                .get_meta(node)
+                .map(|m| m.get_deep_value())
+                .unwrap_or(LoroValue::Null);
+            map.insert("meta".to_string(), meta);
+            map.insert("children".to_string(), children_value(tree, Some(node)));
+            map.into()
+        }
+
+        fn children_value(tree: &TreeHandler, parent: Option<TreeID>) -> LoroValue {
+            tree.children(parent)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|child| node_value(tree, child))
+                .collect::<Vec<_>>()
+                .into()
+        }
+
+        children_value(self, None)
+    }
+
     #[allow(non_snake_case)]
     pub fn __internal__next_tree_id(&self) -> TreeID {
         match &self.inner {
@@ -1,4 +1,5 @@
 pub use loro_common::Counter;
+pub use loro_common::IdSpan;
 pub use loro_common::PeerID;
 pub use loro_common::TreeID;
 pub use loro_common::ID;
@@ -0,0 +1,430 @@
+//! Convert the events of a doc into [RFC 6902](https://www.rfc-editor.org/rfc/rfc6902) JSON
+//! Patches, and apply the JSON Patches of a client to a doc.
+//!
+//! A read-mostly web client can keep the JSON value of a doc, i.e.
+//! [LoroDoc::get_deep_value], and apply the patches streamed by the server, e.g. with
+//! Server-Sent Events (see [sse_message]), without loading the CRDT. See
+//! [LoroDoc::subscribe_json_patch] and [LoroDoc::apply_json_patch].
+//!
+//! JSON has no string edits, so a text edit replaces the whole string. A tree edit replaces
+//! the whole tree, because its nodes are addressed by their positions in the list of nodes.
+use std::sync::Arc;
+
+use fxhash::FxHashSet;
+use itertools::Itertools;
+use loro_common::{ContainerID, InternalString, LoroError, LoroResult, LoroValue};
+use loro_delta::DeltaItem;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+
+use crate::{
+    event::{Diff, DiffEvent, Index},
+    handler::{Handler, HandlerTrait, ValueOrHandler},
+    loro::is_system_root,
+    state::DocState,
+    value::{unresolved_to_collection, ToJson},
+    LoroDoc,
+};
+
+/// An operation of a JSON Patch. Only the operations needed to describe the changes of a
+/// doc are supported.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum JsonPatchOp {
+    Add { path: String, value: LoroValue },
+    Remove { path: String },
+    Replace { path: String, value: LoroValue },
+}
+
+impl JsonPatchOp {
+    /// The JSON Pointer of the target
+    pub fn path(&self) -> &str {
+        match self {
+            JsonPatchOp::Add { path, .. }
+            | JsonPatchOp::Remove { path }
+            | JsonPatchOp::Replace { path, .. } => path,
+        }
+    }
+}
+
+/// Receive the JSON Patch of each event, see [LoroDoc::subscribe_json_patch].
+pub type JsonPatchSubscriber = Arc<dyn Fn(&[JsonPatchOp]) + Send + Sync>;
+
+/// Format a patch as a Server-Sent Events message of the `patch` event type.
+pub fn sse_message(patch: &[JsonPatchOp]) -> String {
+    format!(
+        "event: patch\ndata: {}\n\n",
+        serde_json::to_string(patch).unwrap()
+    )
+}
+
+/// Convert the events of a doc into JSON Patches against the JSON value of the client.
+pub(crate) struct JsonPatchEncoder {
+    /// The roots in the JSON value of the client
+    roots: FxHashSet<InternalString>,
+}
+
+impl JsonPatchEncoder {
+    /// Create an encoder for a client that starts from the deep value `value`.
+    pub(crate) fn new(value: &LoroValue) -> Self {
+        let roots = match value {
+            LoroValue::Map(map) => map.keys().map(|x| x.as_str().into()).collect(),
+            _ => Default::default(),
+        };
+        Self { roots }
+    }
+
+    /// Convert an event of a root subscription. The state must be the one after the event,
+    /// because the texts, counters and trees are replaced by their current values.
+    pub(crate) fn encode(&mut self, event: &DiffEvent, state: &mut DocState) -> Vec<JsonPatchOp> {
+        let mut ans = Vec::new();
+        let mut replaced_trees: FxHashSet<&ContainerID> = FxHashSet::default();
+        for diff in event.events.iter() {
+            let Some((root_id, Index::Key(root))) = diff.path.first() else {
+                continue;
+            };
+            if is_system_root(root) || matches!(diff.diff, Diff::Unknown) {
+                continue;
+            }
+
+            // The diffs of a new container start from its empty value
+            if self.roots.insert(root.clone()) {
+                ans.push(JsonPatchOp::Add {
+                    path: pointer(&diff.path[..1]),
+                    value: root_id.container_type().default_value(),
+                });
+            }
+
+            let tree_end = match diff.diff {
+                Diff::Tree(_) => Some(diff.path.len()),
+                _ => diff
+                    .path
+                    .iter()
+                    .position(|(_, index)| matches!(index, Index::Node(_))),
+            };
+            if let Some(end) = tree_end {
+                if replaced_trees.insert(&diff.path[end - 1].0) {
+                    ans.push(replace_with_current(&diff.path[..end], state));
+                }
+                continue;
+            }
+
+            let path = pointer(&diff.path);
+            match &diff.diff {
+                Diff::List(list) => {
+                    let mut index = 0;
+                    for item in list.iter() {
+                        match item {
+                            DeltaItem::Retain { len, .. } => index += len,
+                            DeltaItem::Replace { value, delete, .. } => {
+                                for _ in 0..*delete {
+                                    ans.push(JsonPatchOp::Remove {
+                                        path: format!("{}/{}", path, index),
+                                    });
+                                }
+                                for v in value.iter() {
+                                    ans.push(JsonPatchOp::Add {
+                                        path: format!("{}/{}", path, index),
+                                        value: unresolved_to_collection(v),
+                                    });
+                                    index += 1;
+                                }
+                            }
+                        }
+                    }
+                }
+                Diff::Map(map) => {
+                    for (key, value) in map.updated.iter().sorted_by(|a, b| a.0.cmp(b.0)) {
+                        let path = format!("{}/{}", path, escape(key));
+                        ans.push(match &value.value {
+                            Some(v) => JsonPatchOp::Add {
+                                path,
+                                value: unresolved_to_collection(v),
+                            },
+                            None => JsonPatchOp::Remove { path },
+                        });
+                    }
+                }
+                Diff::Text(_) => ans.push(replace_with_current(&diff.path, state)),
+                #[cfg(feature = "counter")]
+                Diff::Counter(_) => ans.push(replace_with_current(&diff.path, state)),
+                Diff::Tree(_) | Diff::Unknown => unreachable!(),
+            }
+        }
+
+        ans
+    }
+}
+
+fn replace_with_current(path: &[(ContainerID, Index)], state: &mut DocState) -> JsonPatchOp {
+    let indexes: Vec<Index> = path.iter().map(|(_, index)| index.clone()).collect();
+    JsonPatchOp::Replace {
+        path: pointer(path),
+        value: state
+            .get_deep_value_by_path(&indexes)
+            .unwrap_or(LoroValue::Null),
+    }
+}
+
+fn pointer(path: &[(ContainerID, Index)]) -> String {
+    let mut ans = String::new();
+    for (_, index) in path {
+        ans.push('/');
+        match index {
+            Index::Key(key) => ans.push_str(&escape(key)),
+            Index::Seq(seq) => ans.push_str(&seq.to_string()),
+            Index::Node(node) => ans.push_str(&node.to_string()),
+        }
+    }
+    ans
+}
+
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn parse_pointer(path: &str) -> LoroResult<Vec<String>> {
+    let Some(path) = path.strip_prefix('/') else {
+        return Err(invalid_path(path));
+    };
+    Ok(path
+        .split('/')
+        .map(|x| x.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+fn invalid_path(path: &str) -> LoroError {
+    LoroError::ArgErr(format!("Invalid JSON Patch path: {:?}", path).into_boxed_str())
+}
+
+/// Apply `patch` to the doc as a single commit.
+///
+/// The whole patch is checked against the JSON value of the doc first, so an invalid
+/// patch changes nothing.
+pub(crate) fn apply(doc: &LoroDoc, patch: &[JsonPatchOp]) -> LoroResult<()> {
+    doc.commit_then_renew();
+    let mut value = Json::Object(Default::default());
+    if let LoroValue::Map(roots) = doc.get_deep_value() {
+        for key in roots.keys() {
+            if is_system_root(key) {
+                continue;
+            }
+            let root = doc.get_by_path(&[Index::Key(key.as_str().into())]);
+            value[key.as_str()] = root.map(|x| to_json(doc, x)).unwrap_or(Json::Null);
+        }
+    }
+
+    for op in patch {
+        let path = parse_pointer(op.path())?;
+        if path.len() == 1 {
+            check_root_op(&value, &path[0], op)?;
+        }
+        apply_to_json(&mut value, &path, op)?;
+    }
+
+    for op in patch {
+        apply_to_doc(doc, op)?;
+    }
+
+    doc.commit_then_renew();
+    Ok(())
+}
+
+/// The JSON value of a container, where the trees are `null` because they can't be
+/// patched.
+fn to_json(doc: &LoroDoc, value: ValueOrHandler) -> Json {
+    let value = match value {
+        ValueOrHandler::Value(v) => v,
+        ValueOrHandler::Handler(Handler::Tree(_)) => return Json::Null,
+        ValueOrHandler::Handler(h) => h.get_value(),
+    };
+    let child = |v: &LoroValue| match v {
+        LoroValue::Container(id) => {
+            to_json(doc, ValueOrHandler::Handler(doc.get_handler(id.clone())))
+        }
+        v => to_json(doc, ValueOrHandler::Value(v.clone())),
+    };
+    match value {
+        LoroValue::Map(map) => {
+            Json::Object(map.iter().map(|(k, v)| (k.clone(), child(v))).collect())
+        }
+        LoroValue::List(list) => Json::Array(list.iter().map(child).collect()),
+        v => v.to_json_value(),
+    }
+}
+
+/// A root container can't be added or removed, and only a text or a counter can be
+/// replaced as a whole.
+fn check_root_op(value: &Json, root: &str, op: &JsonPatchOp) -> LoroResult<()> {
+    let ok = match (value.get(root), op) {
+        (Some(Json::String(_)), JsonPatchOp::Add { value, .. })
+        | (Some(Json::String(_)), JsonPatchOp::Replace { value, .. }) => value.is_string(),
+        (Some(Json::Number(_)), JsonPatchOp::Add { value, .. })
+        | (Some(Json::Number(_)), JsonPatchOp::Replace { value, .. }) => {
+            matches!(value, LoroValue::Double(_) | LoroValue::I64(_))
+        }
+        _ => false,
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(LoroError::ArgErr(
+            format!("The root container {:?} can't be patched as a whole", root).into_boxed_str(),
+        ))
+    }
+}
+
+fn apply_to_json(target: &mut Json, path: &[String], op: &JsonPatchOp) -> LoroResult<()> {
+    let not_found =
+        || LoroError::NotFoundError(format!("JSON Patch path {:?}", op.path()).into_boxed_str());
+    let (last, parents) = path.split_last().ok_or_else(|| invalid_path(op.path()))?;
+    let mut target = target;
+    for key in parents {
+        target = match target {
+            Json::Object(map) => map.get_mut(key),
+            Json::Array(list) => key.parse::<usize>().ok().and_then(|i| list.get_mut(i)),
+            _ => None,
+        }
+        .ok_or_else(not_found)?;
+    }
+
+    match target {
+        Json::Object(map) => match op {
+            JsonPatchOp::Add { value, .. } => {
+                map.insert(last.clone(), value.to_json_value());
+            }
+            JsonPatchOp::Remove { .. } => {
+                map.remove(last).ok_or_else(not_found)?;
+            }
+            JsonPatchOp::Replace { value, .. } => {
+                *map.get_mut(last).ok_or_else(not_found)? = value.to_json_value();
+            }
+        },
+        Json::Array(list) => {
+            let index = match last.as_str() {
+                "-" => list.len(),
+                x => x.parse::<usize>().map_err(|_| invalid_path(op.path()))?,
+            };
+            match op {
+                JsonPatchOp::Add { value, .. } if index <= list.len() => {
+                    list.insert(index, value.to_json_value());
+                }
+                JsonPatchOp::Remove { .. } if index < list.len() => {
+                    list.remove(index);
+                }
+                JsonPatchOp::Replace { value, .. } if index < list.len() => {
+                    list[index] = value.to_json_value();
+                }
+                _ => return Err(not_found()),
+            }
+        }
+        _ => return Err(not_found()),
+    }
+
+    Ok(())
+}
+
+/// Apply an op that has been checked by [apply_to_json].
+fn apply_to_doc(doc: &LoroDoc, op: &JsonPatchOp) -> LoroResult<()> {
+    let path = parse_pointer(op.path())?;
+    let Some(ValueOrHandler::Handler(mut parent)) =
+        doc.get_by_path(&[Index::Key(path[0].as_str().into())])
+    else {
+        return Err(invalid_path(op.path()));
+    };
+    if path.len() == 1 {
+        return match op {
+            JsonPatchOp::Add { value, .. } | JsonPatchOp::Replace { value, .. } => {
+                set_whole(&parent, value).unwrap_or_else(|| Err(invalid_path(op.path())))
+            }
+            JsonPatchOp::Remove { .. } => Err(invalid_path(op.path())),
+        };
+    }
+
+    for (i, key) in path[1..path.len() - 1].iter().enumerate() {
+        match get_child(&parent, key)? {
+            ValueOrHandler::Handler(h) => parent = h,
+            ValueOrHandler::Value(v) => {
+                // Patch the plain value and write it back
+                let mut value = v.to_json_value();
+                apply_to_json(&mut value, &path[i + 2..], op)?;
+                let value: LoroValue = serde_json::from_value(value)
+                    .map_err(|e| LoroError::ArgErr(e.to_string().into_boxed_str()))?;
+                return set_child(&parent, key, value);
+            }
+        }
+    }
+
+    let key = path.last().unwrap();
+    match op {
+        JsonPatchOp::Add { value, .. } => match &parent {
+            Handler::Map(map) => map.insert(key, value.clone()),
+            Handler::List(list) => list.insert(seq_index(key, list.len()), value.clone()),
+            Handler::MovableList(list) => list.insert(seq_index(key, list.len()), value.clone()),
+            _ => Err(invalid_path(op.path())),
+        },
+        JsonPatchOp::Remove { .. } => match &parent {
+            Handler::Map(map) => map.delete(key),
+            Handler::List(list) => list.delete(seq_index(key, list.len()), 1),
+            Handler::MovableList(list) => list.delete(seq_index(key, list.len()), 1),
+            _ => Err(invalid_path(op.path())),
+        },
+        JsonPatchOp::Replace { value, .. } => {
+            // A text or a counter is edited instead of being overwritten
+            if let ValueOrHandler::Handler(h) = get_child(&parent, key)? {
+                if let Some(result) = set_whole(&h, value) {
+                    return result;
+                }
+            }
+            set_child(&parent, key, value.clone())
+        }
+    }
+}
+
+fn seq_index(key: &str, len: usize) -> usize {
+    match key {
+        "-" => len,
+        x => x.parse().unwrap(),
+    }
+}
+
+fn get_child(parent: &Handler, key: &str) -> LoroResult<ValueOrHandler> {
+    let child = match parent {
+        Handler::Map(map) => map.get_(key),
+        Handler::List(list) => key.parse().ok().and_then(|i| list.get_(i)),
+        Handler::MovableList(list) => key.parse().ok().and_then(|i| list.get_(i)),
+        _ => None,
+    };
+    child.ok_or_else(|| LoroError::NotFoundError(key.into()))
+}
+
+fn set_child(parent: &Handler, key: &str, value: LoroValue) -> LoroResult<()> {
+    match parent {
+        Handler::Map(map) => map.insert(key, value),
+        Handler::List(list) => {
+            let index = seq_index(key, list.len());
+            list.delete(index, 1)?;
+            list.insert(index, value)
+        }
+        Handler::MovableList(list) => list.set(seq_index(key, list.len()), value),
+        _ => Err(LoroError::NotFoundError(key.into())),
+    }
+}
+
+/// Replace the value of a text with a string, or the value of a counter with a number.
+/// Returns `None` if the value doesn't match the container.
+fn set_whole(handler: &Handler, value: &LoroValue) -> Option<LoroResult<()>> {
+    match (handler, value) {
+        (Handler::Text(text), LoroValue::String(s)) => Some(text.update(s)),
+        #[cfg(feature = "counter")]
+        (Handler::Counter(counter), LoroValue::Double(_) | LoroValue::I64(_)) => {
+            let current = counter.get_value().into_double().unwrap_or(0.);
+            let target = match value {
+                LoroValue::I64(x) => *x as f64,
+                x => *x.as_double().unwrap(),
+            };
+            Some(counter.increment(target - current))
+        }
+        _ => None,
+    }
+}
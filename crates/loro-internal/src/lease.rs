@@ -0,0 +1,204 @@
+//! Coordinate the peer ids of the processes of one user.
+//!
+//! Two processes, e.g. two tabs of a web app, must never edit with the same [PeerID] at the
+//! same time. Their ops would get the same ids, and the docs they're synced to would be
+//! corrupted. Apps that derive the peer id from the user, so that a user's edits share a
+//! few peers, can use [PeerLease] to give each process its own peer:
+//!
+//! - Every process creates a lease from the same base peer and broadcasts its
+//!   [PeerLease::heartbeat] to the others, e.g. with a `BroadcastChannel`, at intervals
+//!   shorter than the ttl of the lease.
+//! - The heartbeats of the others are passed to [PeerLease::apply]. If two processes claim
+//!   the same peer, the one that claimed it first keeps it, and the other one moves to
+//!   the next free sub-peer, see [sub_peer].
+//! - A process only edits the doc once [PeerLease::is_acquired] is true, with
+//!   [PeerLease::peer] as its peer id.
+//!
+//! The leases can't prevent a collision if the heartbeats are lost for longer than the
+//! ttl. And a process that reuses a sub-peer after another one released it must have
+//! imported all the ops of that peer first, so that the counters continue from there.
+use fxhash::FxHashMap;
+use loro_common::{LoroError, LoroResult, PeerID};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    change::get_sys_timestamp,
+    configure::{DefaultRandom, SecureRandomGenerator},
+};
+
+/// The peer at `slot` for the processes that share `base`.
+///
+/// The slot 0 is `base` itself, so a user with a single process always edits with the
+/// same peer. The other slots are derived from `base`, so the processes of a user reuse
+/// the same few peers across sessions.
+pub fn sub_peer(base: PeerID, slot: u32) -> PeerID {
+    if slot == 0 {
+        return base;
+    }
+
+    // splitmix64
+    let mut z = base.wrapping_add((slot as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE5_E9B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    if z == PeerID::MAX {
+        // It's reserved
+        z - 1
+    } else {
+        z
+    }
+}
+
+/// A claim on one of the sub-peers of a base peer. See the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct PeerLease {
+    base: PeerID,
+    /// The random id of this process
+    holder: u64,
+    ttl: i64,
+    slot: u32,
+    /// When the current slot was claimed
+    since: i64,
+    holders: FxHashMap<u64, LeaseInfo>,
+}
+
+/// The slot claimed by another process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeaseInfo {
+    pub slot: u32,
+    pub since: i64,
+    // This field is generated locally
+    pub timestamp: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Heartbeat {
+    base: PeerID,
+    holder: u64,
+    slot: u32,
+    since: i64,
+    released: bool,
+}
+
+impl PeerLease {
+    /// Create a lease that claims the first slot of `base`. The claims of the other
+    /// processes expire `ttl` milliseconds after their last heartbeat.
+    pub fn new(base: PeerID, ttl: i64) -> Self {
+        PeerLease {
+            base,
+            holder: DefaultRandom.next_u64(),
+            ttl,
+            slot: 0,
+            since: get_sys_timestamp(),
+            holders: FxHashMap::default(),
+        }
+    }
+
+    /// The peer id this process should edit with
+    pub fn peer(&self) -> PeerID {
+        sub_peer(self.base, self.slot)
+    }
+
+    pub fn slot(&self) -> u32 {
+        self.slot
+    }
+
+    pub fn holder(&self) -> u64 {
+        self.holder
+    }
+
+    /// Whether the slot has been claimed for a whole ttl without being contested.
+    ///
+    /// The other processes send a heartbeat within every ttl, so by then the claim would
+    /// have been contested by any process that claimed the slot earlier.
+    pub fn is_acquired(&self) -> bool {
+        get_sys_timestamp() - self.since >= self.ttl
+    }
+
+    /// The slots claimed by the other processes, by their holder ids
+    pub fn get_all_holders(&self) -> &FxHashMap<u64, LeaseInfo> {
+        &self.holders
+    }
+
+    /// Encode the claim of this process, to be sent to the other processes
+    pub fn heartbeat(&self) -> Vec<u8> {
+        self.encode(false)
+    }
+
+    /// Encode a message that gives up the claim, e.g. when the process exits. The
+    /// others can take the slot without waiting for the claim to expire.
+    pub fn release(&self) -> Vec<u8> {
+        self.encode(true)
+    }
+
+    fn encode(&self, released: bool) -> Vec<u8> {
+        postcard::to_allocvec(&Heartbeat {
+            base: self.base,
+            holder: self.holder,
+            slot: self.slot,
+            since: self.since,
+            released,
+        })
+        .unwrap()
+    }
+
+    /// Apply a heartbeat from another process.
+    ///
+    /// Returns whether [PeerLease::peer] has changed, because the other process claimed
+    /// the slot earlier. Then the lease has to be acquired again, and a heartbeat should
+    /// be sent right away.
+    pub fn apply(&mut self, heartbeat: &[u8]) -> LoroResult<bool> {
+        let heartbeat: Heartbeat = postcard::from_bytes(heartbeat)
+            .map_err(|_| LoroError::DecodeError("Invalid peer lease heartbeat".into()))?;
+        if heartbeat.base != self.base || heartbeat.holder == self.holder {
+            return Ok(false);
+        }
+
+        if heartbeat.released {
+            self.holders.remove(&heartbeat.holder);
+            return Ok(false);
+        }
+
+        let now = get_sys_timestamp();
+        self.holders.insert(
+            heartbeat.holder,
+            LeaseInfo {
+                slot: heartbeat.slot,
+                since: heartbeat.since,
+                timestamp: now,
+            },
+        );
+        // Ties are broken by the holder ids, so both processes agree on the winner
+        if heartbeat.slot != self.slot
+            || (heartbeat.since, heartbeat.holder) > (self.since, self.holder)
+        {
+            return Ok(false);
+        }
+
+        self.slot = (0..)
+            .find(|slot| {
+                !self
+                    .holders
+                    .values()
+                    .any(|x| x.slot == *slot && now - x.timestamp <= self.ttl)
+            })
+            .unwrap();
+        self.since = now;
+        Ok(true)
+    }
+
+    /// Remove the claims that haven't been renewed within the ttl. Returns their holders.
+    pub fn remove_outdated(&mut self) -> Vec<u64> {
+        let now = get_sys_timestamp();
+        let mut removed = Vec::new();
+        self.holders.retain(|holder, v| {
+            if now - v.timestamp > self.ttl {
+                removed.push(*holder);
+                false
+            } else {
+                true
+            }
+        });
+        removed
+    }
+}
@@ -24,9 +24,13 @@ pub use state::DocState;
 pub use undo::UndoManager;
 pub mod awareness;
 pub mod cursor;
+pub mod event_queue;
+pub mod json_patch;
+pub mod lease;
 pub mod loro;
 pub mod obs;
 pub mod oplog;
+pub mod outbound;
 pub mod txn;
 
 pub mod change;
@@ -52,7 +56,7 @@ pub mod delta;
 pub use loro_delta;
 pub mod event;
 
-pub use error::{LoroError, LoroResult};
+pub use error::{ErrorCategory, LoroError, LoroResult};
 pub(crate) mod group;
 pub(crate) mod macros;
 pub(crate) mod state;
@@ -65,7 +69,7 @@ pub(crate) use loro_common::InternalString;
 
 pub use container::ContainerType;
 pub use encoding::json_schema::op::*;
-pub use loro_common::{loro_value, to_value};
+pub use loro_common::{loro_value, to_value, ValueRef};
 #[cfg(feature = "wasm")]
 pub use value::wasm;
 pub use value::{ApplyDiff, LoroValue, ToJson};
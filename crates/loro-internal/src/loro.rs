@@ -11,15 +11,16 @@ use std::{
 };
 
 use either::Either;
-use fxhash::FxHashMap;
+use fxhash::{FxHashMap, FxHashSet};
 use itertools::Itertools;
 use loro_common::{ContainerID, ContainerType, HasIdSpan, IdSpan, LoroResult, LoroValue, ID};
+use loro_delta::DeltaItem;
 use rle::HasLength;
 use tracing::{info_span, instrument};
 
 use crate::{
     arena::SharedArena,
-    change::Timestamp,
+    change::{ChangeMeta, ChangesCursor, DepGraph, DepNode, Lamport, Timestamp},
     configure::Configure,
     container::{
         idx::ContainerIdx, list::list_op::InnerListOp, richtext::config::StyleConfigMap,
@@ -28,12 +29,15 @@ use crate::{
     cursor::{AbsolutePosition, CannotFindRelativePosition, Cursor, PosQueryResult},
     dag::DagUtils,
     encoding::{
-        decode_snapshot, export_snapshot, json_schema::op::JsonSchema, parse_header_and_body,
-        EncodeMode, ParsedHeaderAndBody,
+        check_decode_budget, decode_snapshot, encode_oplog_in_range, explain, export_for_backup,
+        export_preview, export_sectioned_snapshot, export_snapshot, json_schema::op::JsonSchema,
+        parse_header_and_body, state_section, EncodeMode, ImportBlobMetadata,
     },
-    event::{str_to_path, EventTriggerKind, Index},
-    handler::{Handler, MovableListHandler, TextHandler, TreeHandler, ValueOrHandler},
+    event::{str_to_path, Diff, EventTriggerKind, Index},
+    event_queue::EventQueue,
+    handler::{Handler, MovableListHandler, PosType, TextHandler, TreeHandler, ValueOrHandler},
     id::PeerID,
+    json_patch::{JsonPatchEncoder, JsonPatchOp, JsonPatchSubscriber},
     op::InnerContent,
     oplog::dag::FrontiersNotIncluded,
     undo::DiffBatch,
@@ -43,14 +47,58 @@ use crate::{
 
 use super::{
     diff_calc::DiffCalculator,
-    event::InternalDocDiff,
-    obs::{Observer, SubID, Subscriber},
+    event::{ContainerDiff, InternalDocDiff},
+    obs::{Observer, Projector, SelectSubscriber, SubID, Subscriber},
     oplog::OpLog,
     state::DocState,
-    txn::Transaction,
+    txn::{OnTxnStreamFn, PreCommitHook, Transaction, TxnHooks},
     ListHandler, MapHandler,
 };
 
+/// The names of the root containers used by Loro itself start with it, see
+/// [is_system_root]. Aliases can't use it.
+pub const RESERVED_ROOT_PREFIX: &str = "__loro_";
+/// The root map from the aliases of root containers to their names
+const ROOT_ALIAS_MAP: &str = "__loro_root_aliases";
+/// Aliases are resolved at most this many times, so that the aliases set concurrently
+/// by different peers can't loop forever
+const MAX_ALIAS_DEPTH: usize = 8;
+/// The root map of the metadata of the peers. The key of a field is `{peer}/{field}`,
+/// so that each field is last-write-wins on its own.
+const PEER_METADATA_MAP: &str = "__loro_peers";
+/// The field of the peer metadata that holds the display name of the peer
+pub const PEER_NAME_KEY: &str = "name";
+/// The root map of the app's own bookkeeping, see [LoroDoc::get_system_map]
+const SYSTEM_MAP: &str = "__loro_system";
+/// The root map of the TTLs of map entries, see [crate::handler::MapHandler::insert_with_ttl]
+pub(crate) const TTL_MAP: &str = "__loro_ttl";
+
+/// The root containers used by Loro itself
+const SYSTEM_ROOTS: [&str; 4] = [ROOT_ALIAS_MAP, PEER_METADATA_MAP, SYSTEM_MAP, TTL_MAP];
+
+/// Whether `name` is the name of a root container used by Loro itself. They're hidden from
+/// [LoroDoc::get_deep_value].
+///
+/// Only these roots are hidden, so a root of the app whose name happens to start with
+/// [RESERVED_ROOT_PREFIX] is still visible.
+pub(crate) fn is_system_root(name: &str) -> bool {
+    SYSTEM_ROOTS.contains(&name)
+}
+
+/// The id of the system root `name`. All the system roots are maps.
+pub(crate) fn system_root_id(name: &str) -> ContainerID {
+    debug_assert!(is_system_root(name));
+    ContainerID::new_root(name, ContainerType::Map)
+}
+
+/// Called once the doc includes the version passed to [LoroDoc::wait_for_version].
+pub type OnVersionReached = Box<dyn FnOnce() + Send + Sync>;
+/// Called with the name and the types of roots that got the same name on an import,
+/// see [LoroDoc::set_on_root_type_conflict].
+pub type OnRootTypeConflict = Arc<dyn Fn(&str, &[ContainerType]) + Send + Sync>;
+/// Called with the ops rejected by an import, see [LoroDoc::set_on_ops_rejected].
+pub type OnOpsRejected = Arc<dyn Fn(&[IdSpan]) + Send + Sync>;
+
 /// `LoroApp` serves as the library's primary entry point.
 /// It's constituted by an [OpLog] and an [AppState].
 ///
@@ -77,6 +125,11 @@ pub struct LoroDoc {
     diff_calculator: Arc<Mutex<DiffCalculator>>,
     // when dropping the doc, the txn will be committed
     txn: Arc<Mutex<Option<Transaction>>>,
+    txn_stream: Mutex<Option<OnTxnStreamFn>>,
+    pre_commit_hook: Mutex<Option<PreCommitHook>>,
+    version_waiters: Mutex<Vec<(Frontiers, OnVersionReached)>>,
+    on_root_type_conflict: Mutex<Option<OnRootTypeConflict>>,
+    on_ops_rejected: Mutex<Option<OnOpsRejected>>,
     auto_commit: AtomicBool,
     detached: AtomicBool,
 }
@@ -103,10 +156,16 @@ impl LoroDoc {
         let arena = oplog.arena.clone();
         let global_txn = Arc::new(Mutex::new(None));
         let config: Configure = oplog.configure.clone();
+        let oplog = Arc::new(Mutex::new(oplog));
         // share arena
-        let state = DocState::new_arc(arena.clone(), Arc::downgrade(&global_txn), config.clone());
+        let state = DocState::new_arc(
+            arena.clone(),
+            Arc::downgrade(&oplog),
+            Arc::downgrade(&global_txn),
+            config.clone(),
+        );
         Self {
-            oplog: Arc::new(Mutex::new(oplog)),
+            oplog,
             state,
             config,
             detached: AtomicBool::new(false),
@@ -114,6 +173,11 @@ impl LoroDoc {
             observer: Arc::new(Observer::new(arena.clone())),
             diff_calculator: Arc::new(Mutex::new(DiffCalculator::new())),
             txn: global_txn,
+            txn_stream: Mutex::new(None),
+            pre_commit_hook: Mutex::new(None),
+            version_waiters: Default::default(),
+            on_root_type_conflict: Mutex::new(None),
+            on_ops_rejected: Mutex::new(None),
             arena,
         }
     }
@@ -123,24 +187,31 @@ impl LoroDoc {
         let arena = self.arena.fork();
         let config = self.config.fork();
         let txn = Arc::new(Mutex::new(None));
-        let new_state =
-            self.state
+        let oplog = Arc::new(Mutex::new(
+            self.oplog()
                 .lock()
                 .unwrap()
-                .fork(arena.clone(), Arc::downgrade(&txn), config.clone());
+                .fork(arena.clone(), config.clone()),
+        ));
+        let new_state = self.state.lock().unwrap().fork(
+            arena.clone(),
+            Arc::downgrade(&oplog),
+            Arc::downgrade(&txn),
+            config.clone(),
+        );
         let doc = LoroDoc {
-            oplog: Arc::new(Mutex::new(
-                self.oplog()
-                    .lock()
-                    .unwrap()
-                    .fork(arena.clone(), config.clone()),
-            )),
+            oplog,
             state: new_state,
             arena,
             config,
             observer: Arc::new(Observer::new(self.arena.clone())),
             diff_calculator: Arc::new(Mutex::new(DiffCalculator::new())),
             txn,
+            txn_stream: Mutex::new(None),
+            pre_commit_hook: Mutex::new(None),
+            version_waiters: Default::default(),
+            on_root_type_conflict: Mutex::new(None),
+            on_ops_rejected: Mutex::new(None),
             auto_commit: AtomicBool::new(false),
             detached: AtomicBool::new(self.detached.load(std::sync::atomic::Ordering::Relaxed)),
         };
@@ -153,6 +224,52 @@ impl LoroDoc {
         doc
     }
 
+    /// Create a new doc with the history up to `frontiers`.
+    ///
+    /// The new doc has a new peer id and a copy of the config. Its new changes can be
+    /// merged back with [LoroDoc::export_from] and [LoroDoc::import].
+    pub fn fork_at(&self, frontiers: &Frontiers) -> LoroResult<Self> {
+        self.commit_then_stop();
+        let blob = {
+            let oplog = self.oplog.lock().unwrap();
+            for &i in frontiers.iter() {
+                if !oplog.dag.contains(i) {
+                    drop(oplog);
+                    self.renew_txn_if_auto_commit();
+                    return Err(LoroError::FrontiersNotFound(i));
+                }
+            }
+
+            let end = oplog.dag.frontiers_to_vv(frontiers).unwrap();
+            encode_oplog_in_range(&oplog, &Default::default(), &end)
+        };
+        self.renew_txn_if_auto_commit();
+
+        let doc = LoroDoc::new();
+        doc.config.update(&self.config.options())?;
+        *doc.config.text_style_config.write().unwrap() =
+            self.config.text_style_config.read().unwrap().clone();
+        let markers = self.oplog.lock().unwrap().rejection_markers();
+        doc.oplog.lock().unwrap().merge_rejection_markers(&markers);
+        doc.import(&blob)?;
+        if self.auto_commit.load(std::sync::atomic::Ordering::Relaxed) {
+            doc.start_auto_commit();
+        }
+
+        Ok(doc)
+    }
+
+    /// Create a read-only doc with the state at `frontiers`.
+    ///
+    /// Unlike [LoroDoc::checkout], it leaves this doc as it is, so several versions can be
+    /// viewed at the same time. The view has its own copy of the history and doesn't get
+    /// the later changes of this doc. Its containers can't be edited.
+    pub fn view_at(&self, frontiers: &Frontiers) -> LoroResult<Self> {
+        let doc = self.fork();
+        doc.checkout(frontiers)?;
+        Ok(doc)
+    }
+
     /// Set whether to record the timestamp of each change. Default is `false`.
     ///
     /// If enabled, the Unix timestamp will be recorded for each change automatically.
@@ -172,9 +289,192 @@ impl LoroDoc {
     ///
     /// If two continuous local changes are within the interval, they will be merged into one change.
     /// The default value is 1000 seconds.
+    ///
+    /// Return an error if `interval` is negative.
     #[inline]
-    pub fn set_change_merge_interval(&self, interval: i64) {
-        self.config.set_merge_interval(interval);
+    pub fn set_change_merge_interval(&self, interval: i64) -> LoroResult<()> {
+        self.config.set_merge_interval(interval)
+    }
+
+    /// Commit the auto-commit transaction once it has `limit` ops.
+    ///
+    /// `None` means the transaction is only committed explicitly.
+    ///
+    /// Return an error if `limit` is `Some(0)`.
+    #[inline]
+    pub fn set_auto_commit_ops_limit(&self, limit: Option<usize>) -> LoroResult<()> {
+        self.config.set_auto_commit_ops_limit(limit)
+    }
+
+    /// Set whether [`LoroDoc::export_snapshot`] should encode repeated text chunks as
+    /// back-references to their first occurrence. Default is `false`.
+    ///
+    /// It makes snapshots of repetitive text (templates, logs) smaller, but snapshots
+    /// exported with it enabled can't be imported by versions without the support.
+    #[inline]
+    pub fn set_snapshot_text_dedup(&self, dedup: bool) {
+        self.config.set_dedup_snapshot_text(dedup);
+    }
+
+    /// Set whether the exported updates and snapshots store the op counters implicitly.
+    /// Default is `false`.
+    ///
+    /// Each op then only stores the gap from the end of the previous op of the same peer,
+    /// which is 0 for a run of edits, so long typing sessions are a bit smaller. The blobs
+    /// exported with it enabled can't be imported by versions without the support, which
+    /// reject them as a future encoding.
+    #[inline]
+    pub fn set_implicit_op_counters(&self, implicit: bool) {
+        self.config.set_implicit_op_counters(implicit);
+    }
+
+    /// Compress the text inserted by a single op when it's exported, if it has at least
+    /// `threshold` bytes. Default is `None`, which disables the compression.
+    ///
+    /// It makes the updates and snapshots of large repetitive pastes, e.g. logs, much
+    /// smaller. The text is only compressed in the exported blobs, so it doesn't reduce the
+    /// memory of the oplog: in memory the inserted text is shared with the state of the
+    /// text, which needs it uncompressed.
+    ///
+    /// Blobs exported with it enabled can't be imported by versions without the support.
+    ///
+    /// Return an error if `threshold` is `Some(0)`.
+    #[inline]
+    pub fn set_op_compression_threshold(&self, threshold: Option<usize>) -> LoroResult<()> {
+        self.config.set_op_compression_threshold(threshold)
+    }
+
+    /// Stream the local ops of every transaction to `stream` as they are applied, before
+    /// the transaction is committed. See [`TxnStreamEvent`](crate::txn::TxnStreamEvent).
+    ///
+    /// It enables transports that send the edits without waiting for the commit.
+    pub fn set_txn_stream(&self, stream: Option<OnTxnStreamFn>) {
+        if let Some(txn) = self.txn.try_lock().unwrap().as_mut() {
+            txn.set_stream(stream.clone());
+        }
+
+        *self.txn_stream.lock().unwrap() = stream;
+    }
+
+    /// Inspect and rewrite every local op before it's applied, e.g. to strip the disallowed
+    /// characters of the inserted text or to clamp the values. See [`PreCommitHook`].
+    pub fn set_pre_commit_hook(&self, hook: Option<PreCommitHook>) {
+        if let Some(txn) = self.txn.try_lock().unwrap().as_mut() {
+            txn.set_pre_commit_hook(hook.clone());
+        }
+
+        *self.pre_commit_hook.lock().unwrap() = hook;
+    }
+
+    /// Set the callback that is invoked when an import or a local edit makes two non-empty
+    /// roots of different types share a name, e.g. if two peers created a root map and a
+    /// root list named "data" concurrently.
+    ///
+    /// Both roots are kept. [LoroDoc::get_deep_value] shows the one with precedence under
+    /// the name and [LoroDoc::get_shadowed_roots_value] shows the others, see
+    /// [DocState::root_type_conflicts]. For a local edit, it's called while the transaction
+    /// is locked, so it must not edit the doc.
+    pub fn set_on_root_type_conflict(&self, on_conflict: Option<OnRootTypeConflict>) {
+        if let Some(txn) = self.txn.try_lock().unwrap().as_mut() {
+            txn.set_on_root_type_conflict(on_conflict.clone());
+        }
+
+        *self.on_root_type_conflict.lock().unwrap() = on_conflict;
+    }
+
+    /// Reject the ops of each peer beyond `limit` on import, and the ops that depend on
+    /// them. `None` removes the limit.
+    ///
+    /// The rejection only depends on the ids and the deps of the ops, so all the replicas
+    /// with the same limit end up with the same ops. The local edits beyond the limit fail
+    /// with [LoroError::OpQuotaExceeded]. The rejected ops are reported to the callback set
+    /// by [LoroDoc::set_on_ops_rejected].
+    ///
+    /// A snapshot is imported like an update while the limit is set, since its state may
+    /// include the rejected ops. The first rejected op of each peer is saved in the
+    /// snapshots and copied by fork, so the later ops of the peer are still rejected.
+    ///
+    /// Return an error if `limit` is `Some(0)`, or if it's changed after the doc has ops.
+    #[inline]
+    pub fn set_max_ops_per_peer(&self, limit: Option<usize>) -> LoroResult<()> {
+        self.config.set_max_ops_per_peer(limit)
+    }
+
+    /// Reject the ops of each peer from the first one that takes the size of the content
+    /// inserted by the peer beyond `limit` on import, like [LoroDoc::set_max_ops_per_peer].
+    /// `None` removes the limit.
+    ///
+    /// The ops of a peer are counted one by one in the order of their counters. The size of
+    /// an op is the byte length of its text, map keys, values and tree positions, where
+    /// each number counts as 8 bytes and each of the other non-string values as 1 byte.
+    /// The deletions and the moves have no size. The local edits beyond the limit fail with
+    /// [LoroError::OpQuotaExceeded].
+    ///
+    /// Return an error if `limit` is `Some(0)`, or if it's changed after the doc has ops.
+    #[inline]
+    pub fn set_max_bytes_per_peer(&self, limit: Option<usize>) -> LoroResult<()> {
+        self.config.set_max_bytes_per_peer(limit)
+    }
+
+    /// Reject the ops of each peer from the first one that takes the number of the
+    /// containers created by the peer beyond `limit` on import, like
+    /// [LoroDoc::set_max_ops_per_peer]. `None` removes the limit.
+    ///
+    /// The metadata map of each created tree node counts as a container. The local edits
+    /// beyond the limit fail with [LoroError::OpQuotaExceeded].
+    ///
+    /// Return an error if `limit` is `Some(0)`, or if it's changed after the doc has ops.
+    #[inline]
+    pub fn set_max_containers_per_peer(&self, limit: Option<usize>) -> LoroResult<()> {
+        self.config.set_max_containers_per_peer(limit)
+    }
+
+    /// Set the callback called with the ops rejected by each import because of the
+    /// per-peer limits, e.g. [LoroDoc::set_max_ops_per_peer].
+    pub fn set_on_ops_rejected(&self, on_rejected: Option<OnOpsRejected>) {
+        *self.on_ops_rejected.lock().unwrap() = on_rejected;
+    }
+
+    fn report_rejected_ops(&self) {
+        let rejected = self.oplog.lock().unwrap().take_rejected_ops();
+        if rejected.is_empty() {
+            return;
+        }
+
+        let on_rejected = self.on_ops_rejected.lock().unwrap().clone();
+        if let Some(on_rejected) = on_rejected {
+            on_rejected(&rejected);
+        }
+    }
+
+    /// Run `import` and report the root type conflicts it introduces.
+    fn check_root_type_conflicts<R>(&self, import: impl FnOnce() -> R) -> R {
+        let on_conflict = self.on_root_type_conflict.lock().unwrap().clone();
+        let Some(on_conflict) = on_conflict else {
+            return import();
+        };
+        // The state is updated at the end of a batch
+        if self.oplog.lock().unwrap().batch_importing {
+            return import();
+        }
+
+        let before: FxHashSet<InternalString> = self
+            .state
+            .lock()
+            .unwrap()
+            .root_type_conflicts()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        let ans = import();
+        let conflicts = self.state.lock().unwrap().root_type_conflicts();
+        for (name, types) in conflicts {
+            if !before.contains(&name) {
+                on_conflict(&name, &types);
+            }
+        }
+
+        ans
     }
 
     /// Set the jitter of the tree position(Fractional Index).
@@ -202,9 +502,13 @@ impl LoroDoc {
 
     pub fn from_snapshot(bytes: &[u8]) -> LoroResult<Self> {
         let doc = Self::new();
-        let ParsedHeaderAndBody { mode, body, .. } = parse_header_and_body(bytes)?;
-        if mode.is_snapshot() {
-            decode_snapshot(&doc, mode, body)?;
+        let parsed = parse_header_and_body(bytes)?;
+        if parsed.mode == EncodeMode::Sectioned {
+            return Self::from_snapshot(state_section(&parsed)?);
+        }
+
+        if parsed.mode.is_snapshot() {
+            decode_snapshot(&doc, &parsed)?;
             Ok(doc)
         } else {
             Err(LoroError::DecodeError(
@@ -226,17 +530,25 @@ impl LoroDoc {
     }
 
     #[allow(unused)]
-    pub(super) fn from_existing(oplog: OpLog, state: DocState) -> Self {
+    pub(super) fn from_existing(oplog: OpLog, mut state: DocState) -> Self {
         let obs = Observer::new(oplog.arena.clone());
+        let arena = oplog.arena.clone();
+        let oplog = Arc::new(Mutex::new(oplog));
+        state.oplog = Arc::downgrade(&oplog);
         Self {
-            arena: oplog.arena.clone(),
+            arena,
             observer: Arc::new(obs),
             config: Default::default(),
             auto_commit: AtomicBool::new(false),
-            oplog: Arc::new(Mutex::new(oplog)),
+            oplog,
             state: Arc::new(Mutex::new(state)),
             diff_calculator: Arc::new(Mutex::new(DiffCalculator::new())),
             txn: Arc::new(Mutex::new(None)),
+            txn_stream: Mutex::new(None),
+            pre_commit_hook: Mutex::new(None),
+            version_waiters: Default::default(),
+            on_root_type_conflict: Mutex::new(None),
+            on_ops_rejected: Mutex::new(None),
             detached: AtomicBool::new(false),
         }
     }
@@ -341,6 +653,35 @@ impl LoroDoc {
         self.commit_with(CommitOptions::new().immediate_renew(true))
     }
 
+    /// Discard the ops of the cumulative auto commit transaction, see [Transaction::abort].
+    /// This method only has effect when `auto_commit` is true.
+    ///
+    /// The next transaction is started immediately.
+    pub fn abort_txn(&self) {
+        if !self.auto_commit.load(Acquire) {
+            return;
+        }
+
+        let mut txn_guard = self.txn.try_lock().unwrap();
+        let txn = txn_guard.take();
+        drop(txn_guard);
+        let Some(txn) = txn else {
+            return;
+        };
+
+        txn.abort();
+        self.renew_txn_if_auto_commit();
+    }
+
+    /// The changes made by the pending auto commit transaction, see
+    /// [Transaction::current_diff]. It's empty if auto commit is disabled.
+    pub fn current_diff(&self) -> Vec<ContainerDiff> {
+        match self.txn.try_lock().unwrap().as_ref() {
+            Some(txn) => txn.current_diff(),
+            None => Vec::new(),
+        }
+    }
+
     /// Commit the cumulative auto commit transaction.
     /// This method only has effect when `auto_commit` is true.
     /// If `immediate_renew` is true, a new transaction will be created after the old one is committed
@@ -368,6 +709,10 @@ impl LoroDoc {
             txn.set_timestamp(timestamp);
         }
 
+        if let Some(msg) = config.commit_msg {
+            txn.set_msg(Some(msg.into()));
+        }
+
         txn.commit().unwrap();
         if config.immediate_renew {
             let mut txn_guard = self.txn.try_lock().unwrap();
@@ -409,23 +754,18 @@ impl LoroDoc {
             ));
         }
 
-        let mut txn = Transaction::new_with_origin(
+        let txn = Transaction::new_with_hooks(
             self.state.clone(),
             self.oplog.clone(),
             origin.into(),
             self.get_global_txn(),
+            TxnHooks {
+                observer: Some(self.observer.clone()),
+                stream: self.txn_stream.lock().unwrap().clone(),
+                pre_commit_hook: self.pre_commit_hook.lock().unwrap().clone(),
+                on_root_type_conflict: self.on_root_type_conflict.lock().unwrap().clone(),
+            },
         );
-
-        let obs = self.observer.clone();
-        txn.set_on_commit(Box::new(move |state| {
-            let mut state = state.try_lock().unwrap();
-            let events = state.take_events();
-            drop(state);
-            for event in events {
-                obs.emit(event);
-            }
-        }));
-
         Ok(txn)
     }
 
@@ -451,6 +791,111 @@ impl LoroDoc {
         ans
     }
 
+    /// Export the updates from `vv` as a series of chunks, each with about `chunk_size` ops.
+    ///
+    /// Each chunk is an update blob prefixed by its byte length as a little-endian `u32`,
+    /// so the chunks can be written to a stream one after another and imported by
+    /// [LoroDoc::import_reader]. A chunk only depends on the chunks before it.
+    ///
+    /// The chunks are encoded lazily. A change is never split, so a chunk may contain more
+    /// than `chunk_size` ops.
+    pub fn export_chunks<'a>(
+        &'a self,
+        vv: &VersionVector,
+        chunk_size: usize,
+    ) -> impl Iterator<Item = Vec<u8>> + 'a {
+        self.commit_then_stop();
+        let versions = {
+            let oplog = self.oplog.lock().unwrap();
+            let start_vv = vv.trim(oplog.vv());
+            // Sorting by lamport gives a causal order, so every prefix is a valid version
+            let changes = oplog
+                .iter_changes_peer_by_peer(&start_vv, oplog.vv())
+                .map(|c| {
+                    let start = start_vv.get(&c.id.peer).copied().unwrap_or(0);
+                    let start = start.max(c.id.counter);
+                    let lamport = c.lamport + (start - c.id.counter) as Lamport;
+                    (lamport, c.id.peer, start, c.id_end().counter)
+                })
+                .sorted_unstable()
+                .collect_vec();
+
+            let mut versions = vec![start_vv.clone()];
+            let mut current = start_vv;
+            let mut len = 0;
+            for (_, peer, start, end) in changes {
+                current.insert(peer, end);
+                len += (end - start) as usize;
+                if len >= chunk_size {
+                    versions.push(current.clone());
+                    len = 0;
+                }
+            }
+
+            if len > 0 {
+                versions.push(current);
+            }
+
+            versions
+        };
+        self.renew_txn_if_auto_commit();
+
+        versions
+            .into_iter()
+            .tuple_windows()
+            .map(move |(start, end)| {
+                let blob = encode_oplog_in_range(&self.oplog.lock().unwrap(), &start, &end);
+                let mut chunk = Vec::with_capacity(blob.len() + 4);
+                chunk.extend_from_slice(&(blob.len() as u32).to_le_bytes());
+                chunk.extend_from_slice(&blob);
+                chunk
+            })
+    }
+
+    /// Import the chunks exported by [LoroDoc::export_chunks] from `reader`, one chunk at
+    /// a time, until the reader reaches the end.
+    pub fn import_reader(&self, mut reader: impl std::io::Read) -> LoroResult<()> {
+        use std::io::Read;
+
+        let to_err = |e: std::io::Error| {
+            LoroError::DecodeError(format!("Failed to read the chunk: {}", e).into_boxed_str())
+        };
+        loop {
+            let mut len_bytes = [0u8; 4];
+            let mut read = 0;
+            while read < len_bytes.len() {
+                match reader.read(&mut len_bytes[read..]) {
+                    Ok(0) if read == 0 => return Ok(()),
+                    Ok(0) => {
+                        return Err(LoroError::DecodeError(
+                            "Unexpected end of the chunk stream".into(),
+                        ))
+                    }
+                    Ok(n) => read += n,
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(to_err(e)),
+                }
+            }
+
+            // The length comes from the stream, so the buffer only grows with the bytes
+            // actually read instead of being allocated up front
+            let len = u32::from_le_bytes(len_bytes) as usize;
+            let mut chunk = Vec::new();
+            reader
+                .by_ref()
+                .take(len as u64)
+                .read_to_end(&mut chunk)
+                .map_err(to_err)?;
+            if chunk.len() < len {
+                return Err(LoroError::DecodeError(
+                    "Unexpected end of the chunk stream".into(),
+                ));
+            }
+
+            self.import(&chunk)?;
+        }
+    }
+
     #[inline(always)]
     #[instrument(skip_all)]
     pub fn import(&self, bytes: &[u8]) -> Result<(), LoroError> {
@@ -460,13 +905,39 @@ impl LoroDoc {
     #[inline]
     pub fn import_with(&self, bytes: &[u8], origin: InternalString) -> Result<(), LoroError> {
         self.commit_then_stop();
-        let ans = self._import_with(bytes, origin);
+        let ans = self.check_root_type_conflicts(|| self._import_with(bytes, origin));
         self.renew_txn_if_auto_commit();
+        self.report_rejected_ops();
+        // The batch notifies the waiters after the state is updated
+        if !self.oplog.lock().unwrap().batch_importing {
+            self.notify_version_waiters();
+        }
         ans
     }
 
+    /// Import like [LoroDoc::import], but return [LoroError::BudgetExceeded] without
+    /// decoding anything if decoding `bytes` is estimated to need more than `budget` bytes
+    /// of memory.
+    ///
+    /// The estimate is computed by scanning the columns of the blob without collecting
+    /// them, so a small blob that expands to a huge number of ops is rejected up front.
+    /// It's a heuristic that sums the rough sizes of the decoded ops, changes, strings and
+    /// container states of a snapshot; the decoding isn't metered, so the actual memory
+    /// may differ from it by a constant factor. It only covers the decoding; the memory
+    /// already used by the doc isn't counted.
+    pub fn import_with_budget(&self, bytes: &[u8], budget: usize) -> LoroResult<()> {
+        let parsed = parse_header_and_body(bytes)?;
+        check_decode_budget(&parsed, budget)?;
+        self.import(bytes)
+    }
+
     fn _import_with(&self, bytes: &[u8], origin: InternalString) -> Result<(), LoroError> {
         let parsed = parse_header_and_body(bytes)?;
+        if parsed.mode == EncodeMode::Sectioned && self.can_reset_with_snapshot() {
+            // The state section inits the empty doc without replaying the history section
+            return self._import_with(state_section(&parsed)?, origin);
+        }
+
         match parsed.mode.is_snapshot() {
             false => {
                 if self.state.lock().unwrap().is_in_txn() {
@@ -485,9 +956,10 @@ impl LoroDoc {
                 )?;
             }
             true => {
-                if self.can_reset_with_snapshot() {
+                let has_quota = self.config.has_peer_quota();
+                if self.can_reset_with_snapshot() && !has_quota {
                     tracing::info!("Init by snapshot {}", self.peer_id());
-                    decode_snapshot(self, parsed.mode, parsed.body)?;
+                    decode_snapshot(self, &parsed)?;
                 } else if parsed.mode == EncodeMode::Snapshot {
                     self.update_oplog_and_apply_delta_to_state_if_needed(
                         |oplog| oplog.decode(parsed),
@@ -496,8 +968,10 @@ impl LoroDoc {
                 } else {
                     tracing::info!("Import from new doc");
                     let app = LoroDoc::new();
-                    decode_snapshot(&app, parsed.mode, parsed.body)?;
-                    let oplog = self.oplog.lock().unwrap();
+                    decode_snapshot(&app, &parsed)?;
+                    let markers = app.oplog.lock().unwrap().rejection_markers();
+                    let mut oplog = self.oplog.lock().unwrap();
+                    oplog.merge_rejection_markers(&markers);
                     // TODO: PERF: the ser and de can be optimized out
                     let updates = app.export_from(oplog.vv());
                     drop(oplog);
@@ -550,10 +1024,11 @@ impl LoroDoc {
         let mut oplog = self.oplog.lock().unwrap();
         let old_vv = oplog.vv().clone();
         let old_frontiers = oplog.frontiers().clone();
-        let ans = oplog.decode(ParsedHeaderAndBody {
+        let ans = oplog.decode(crate::encoding::ParsedHeaderAndBody {
             checksum: [0; 16],
             checksum_body: body,
             mode: EncodeMode::Rle,
+            implicit_counters: false,
             body,
         });
         if ans.is_ok() && !self.detached.load(Acquire) {
@@ -582,7 +1057,16 @@ impl LoroDoc {
     #[cfg(feature = "test_utils")]
     pub fn import_snapshot_unchecked(&self, bytes: &[u8]) -> LoroResult<()> {
         self.commit_then_stop();
-        let ans = decode_snapshot(self, EncodeMode::Snapshot, bytes);
+        let ans = decode_snapshot(
+            self,
+            &crate::encoding::ParsedHeaderAndBody {
+                checksum: [0; 16],
+                checksum_body: bytes,
+                mode: EncodeMode::Snapshot,
+                implicit_counters: false,
+                body: bytes,
+            },
+        );
         self.renew_txn_if_auto_commit();
         ans
     }
@@ -606,6 +1090,61 @@ impl LoroDoc {
         ans
     }
 
+    /// Export the history in a layout for rsync and dedup-based backups.
+    ///
+    /// The ops of each peer are split into chunks at boundaries decided by the op ids, and
+    /// each chunk is encoded on its own. Exporting the doc again after it has grown only
+    /// changes the last chunk of each peer that has edited since, and adds new chunks, so
+    /// successive exports share most of their byte ranges.
+    ///
+    /// The blob is imported by [LoroDoc::import] like an update. It has no state, so
+    /// importing it replays the history, which is slower than importing a snapshot.
+    pub fn export_for_backup(&self) -> Vec<u8> {
+        self.commit_then_stop();
+        let ans = export_for_backup(&self.oplog.lock().unwrap());
+        self.renew_txn_if_auto_commit();
+        ans
+    }
+
+    /// Export the current state and the history as two sections with their offsets in
+    /// the header.
+    ///
+    /// The state section is a snapshot, which a viewer takes with [LoroDoc::state_section]
+    /// without reading the history bytes, and an archival tool takes the history with
+    /// [LoroDoc::history_section] without decoding the state.
+    ///
+    /// [LoroDoc::import] inits an empty doc by the state section like a snapshot, and
+    /// imports the history section into a doc that isn't empty like an update. The blob
+    /// is about twice as large as a snapshot, because the snapshot has the history too.
+    pub fn export_sectioned_snapshot(&self) -> Vec<u8> {
+        self.commit_then_stop();
+        let ans = export_sectioned_snapshot(self);
+        self.renew_txn_if_auto_commit();
+        ans
+    }
+
+    /// Export only the current deep value, for the services that render a read-only
+    /// preview of the doc, e.g. link previews and search indexing. The texts keep their
+    /// styles, see [LoroDoc::get_deep_value_with_richtext].
+    ///
+    /// It has no history, tombstones or op metadata, so it can't be imported. It's decoded
+    /// by [LoroDoc::decode_preview].
+    pub fn export_preview(&self) -> LoroResult<Vec<u8>> {
+        self.commit_then_stop();
+        let ans = export_preview(self);
+        self.renew_txn_if_auto_commit();
+        ans
+    }
+
+    /// The size of the exported history relative to the content of its ops, see
+    /// [crate::encoding::EncodingReport::metadata_overhead_ratio].
+    ///
+    /// It's below 2 for typical text editing traces like the automerge paper.
+    pub fn metadata_overhead_ratio(&self) -> LoroResult<f64> {
+        let updates = self.export_from(&Default::default());
+        Ok(explain(&updates)?.metadata_overhead_ratio())
+    }
+
     /// Import the json schema updates.
     ///
     /// only supports backward compatibility but not forward compatibility.
@@ -618,6 +1157,8 @@ impl LoroDoc {
         )?;
         self.emit_events();
         self.renew_txn_if_auto_commit();
+        self.notify_version_waiters();
+        self.report_rejected_ops();
         Ok(())
     }
 
@@ -634,12 +1175,180 @@ impl LoroDoc {
         json
     }
 
+    /// Whether the [OpLog] has changes that are not included in `vv`.
+    pub fn changes_since(&self, vv: &VersionVector) -> bool {
+        let oplog = self.oplog.lock().unwrap();
+        oplog
+            .vv()
+            .iter()
+            .any(|(peer, &counter)| counter > vv.get(peer).copied().unwrap_or(0))
+    }
+
+    /// Call `callback` once the [OpLog] includes `frontiers`.
+    ///
+    /// It's called immediately if the version is already included, otherwise after the
+    /// import that brings it in. The callback is kept until then, so it's never called if
+    /// the version never arrives.
+    pub fn wait_for_version(&self, frontiers: Frontiers, callback: OnVersionReached) {
+        self.version_waiters
+            .lock()
+            .unwrap()
+            .push((frontiers, callback));
+        self.notify_version_waiters();
+    }
+
+    fn notify_version_waiters(&self) {
+        let ready = {
+            let mut waiters = self.version_waiters.lock().unwrap();
+            if waiters.is_empty() {
+                return;
+            }
+
+            let oplog = self.oplog.lock().unwrap();
+            let (ready, pending): (Vec<_>, Vec<_>) = std::mem::take(&mut *waiters)
+                .into_iter()
+                .partition(|(f, _)| f.iter().all(|&id| oplog.dag.contains(id)));
+            *waiters = pending;
+            ready
+        };
+
+        // Call them without holding the locks, so that they can use the doc
+        for (_, callback) in ready {
+            callback();
+        }
+    }
+
     /// Get the version vector of the current OpLog
     #[inline]
     pub fn oplog_vv(&self) -> VersionVector {
         self.oplog.lock().unwrap().vv().clone()
     }
 
+    /// Whether the auto-commit transaction contains ops that are not committed yet.
+    #[inline]
+    pub fn has_uncommitted_txn(&self) -> bool {
+        self.uncommitted_ops_len() > 0
+    }
+
+    /// The number of atomic ops in the auto-commit transaction that are not committed yet.
+    pub fn uncommitted_ops_len(&self) -> usize {
+        self.txn
+            .try_lock()
+            .unwrap()
+            .as_ref()
+            .map_or(0, |txn| txn.len())
+    }
+
+    /// Get the committed ops that are not included in `vv`.
+    ///
+    /// If `vv` is the version the other side has acknowledged, the returned spans are the
+    /// changes that still need to be synced. The uncommitted ops are not included.
+    pub fn unsynced_since(&self, vv: &VersionVector) -> Vec<IdSpan> {
+        vv.get_missing_span(self.oplog.lock().unwrap().vv())
+            .into_iter()
+            .filter(|span| span.content_len() > 0)
+            .collect()
+    }
+
+    /// Get the frontiers of the ops on the container and the latest timestamp of their
+    /// changes, i.e. when the container was last modified in the [OpLog].
+    ///
+    /// It's maintained incrementally, so it doesn't scan the history. The ops of the pending
+    /// transaction are not included. Return `None` if the container has no ops.
+    pub fn container_last_modified(&self, id: &ContainerID) -> Option<(Frontiers, Timestamp)> {
+        let idx = self.arena.id_to_idx(id)?;
+        self.oplog.lock().unwrap().container_last_modified(idx)
+    }
+
+    /// Get the metadata of the change that contains the op with the given id.
+    ///
+    /// Local changes committed within the merge interval may be merged into one change,
+    /// unless they have commit messages.
+    pub fn get_change(&self, id: ID) -> Option<ChangeMeta> {
+        let mut change = {
+            let oplog = self.oplog.lock().unwrap();
+            oplog.get_change_at(id).map(ChangeMeta::from_change)?
+        };
+        change.author = self.peer_name(change.id.peer);
+        Some(change)
+    }
+
+    /// Iterate over the metadata of all the changes in the [OpLog], sorted by Lamport
+    /// timestamp, which is also a causal order.
+    pub fn change_iter(&self) -> impl Iterator<Item = ChangeMeta> {
+        let mut changes: Vec<ChangeMeta> = {
+            let oplog = self.oplog.lock().unwrap();
+            oplog
+                .iter_changes_peer_by_peer(&Default::default(), oplog.vv())
+                .map(ChangeMeta::from_change)
+                .collect()
+        };
+        changes.sort_by_key(|c| (c.lamport, c.id.peer));
+        self.fill_authors(&mut changes);
+        changes.into_iter()
+    }
+
+    /// Get a page of the metadata of the changes from the newest to the oldest, i.e. the
+    /// reverse order of [LoroDoc::change_iter], e.g. for a history view.
+    ///
+    /// Pass the returned cursor to get the next page, it's `None` after the oldest change.
+    /// Only the changes of the page are read, so paginating through a long history doesn't
+    /// load all the metadata. The changes imported between the pages that are older than
+    /// the cursor are on the following pages.
+    pub fn changes_page(
+        &self,
+        cursor: Option<&ChangesCursor>,
+        limit: usize,
+    ) -> (Vec<ChangeMeta>, Option<ChangesCursor>) {
+        let (mut changes, has_more) = {
+            let oplog = self.oplog.lock().unwrap();
+            let before = cursor.map(|c| (c.lamport, c.peer));
+            let mut changes = oplog.changes_before(before, limit.saturating_add(1));
+            let has_more = changes.len() > limit;
+            changes.truncate(limit);
+            let changes: Vec<ChangeMeta> =
+                changes.into_iter().map(ChangeMeta::from_change).collect();
+            (changes, has_more)
+        };
+        self.fill_authors(&mut changes);
+        let next = match changes.last() {
+            Some(last) if has_more => Some(ChangesCursor {
+                lamport: last.lamport,
+                peer: last.id.peer,
+            }),
+            _ if has_more => cursor.copied(),
+            _ => None,
+        };
+        (changes, next)
+    }
+
+    fn fill_authors(&self, changes: &mut [ChangeMeta]) {
+        if self.has_peer_metadata() {
+            let mut names: FxHashMap<PeerID, Option<Arc<str>>> = FxHashMap::default();
+            for c in changes.iter_mut() {
+                let peer = c.id.peer;
+                c.author = names
+                    .entry(peer)
+                    .or_insert_with(|| self.peer_name(peer))
+                    .clone();
+            }
+        }
+    }
+
+    /// Export the causal dependency graph of the changes, see [DepGraph].
+    pub fn export_dep_graph(&self) -> DepGraph {
+        let nodes = self
+            .change_iter()
+            .map(|c| DepNode {
+                id: c.id,
+                len: c.len,
+                lamport: c.lamport,
+                deps: c.deps,
+            })
+            .collect();
+        DepGraph { nodes }
+    }
+
     /// Get the version vector of the current [DocState]
     #[inline]
     pub fn state_vv(&self) -> VersionVector {
@@ -677,11 +1386,161 @@ impl LoroDoc {
         )
     }
 
+    /// Register `alias` as another name of the root container `name`.
+    ///
+    /// Getting a root container by `alias`, e.g. `get_text(alias)`, returns the container
+    /// named `name`, so a root container can be renamed without migrating its history.
+    /// Older clients keep using `name`. The alias is stored in the doc and synced like
+    /// other changes. It's ignored if the root container named `alias` has ops of its own,
+    /// so an alias never hides existing data.
+    pub fn alias_root(&self, name: &str, alias: &str) -> LoroResult<()> {
+        if name.starts_with(RESERVED_ROOT_PREFIX) || alias.starts_with(RESERVED_ROOT_PREFIX) {
+            return Err(LoroError::ArgErr(
+                format!(
+                    "Root names starting with {} are reserved",
+                    RESERVED_ROOT_PREFIX
+                )
+                .into_boxed_str(),
+            ));
+        }
+
+        if &*self.resolve_root_name(name) == alias {
+            return Err(LoroError::ArgErr(
+                format!("Aliasing {} to {} would create a cycle", alias, name).into_boxed_str(),
+            ));
+        }
+
+        self.root_alias_map().insert(alias, name)
+    }
+
+    /// Remove an alias registered by [LoroDoc::alias_root].
+    pub fn remove_root_alias(&self, alias: &str) -> LoroResult<()> {
+        self.root_alias_map().delete(alias)
+    }
+
+    fn root_alias_map(&self) -> MapHandler {
+        self.system_root(ROOT_ALIAS_MAP)
+    }
+
+    /// Get the system root `name`, see [is_system_root]
+    fn system_root(&self, name: &str) -> MapHandler {
+        self.get_handler(system_root_id(name)).into_map().unwrap()
+    }
+
+    /// Whether the system root `name` exists, so that reading it doesn't have to register it
+    fn has_system_root(&self, name: &str) -> bool {
+        self.arena.id_to_idx(&system_root_id(name)).is_some()
+    }
+
+    /// Follow the aliases registered by [LoroDoc::alias_root] from `name`.
+    fn resolve_root_name(&self, name: &str) -> InternalString {
+        let mut name: InternalString = name.into();
+        // Don't register the map when there is no alias
+        if !self.has_system_root(ROOT_ALIAS_MAP) {
+            return name;
+        }
+
+        let map = self.root_alias_map();
+        for _ in 0..MAX_ALIAS_DEPTH {
+            match map.get(&name) {
+                Some(LoroValue::String(target)) => name = target.as_str().into(),
+                _ => break,
+            }
+        }
+
+        name
+    }
+
+    fn resolve_root_alias(&self, id: ContainerID) -> ContainerID {
+        if self
+            .arena
+            .id_to_idx(&id)
+            .is_some_and(|idx| self.arena.is_written(idx))
+        {
+            return id;
+        }
+
+        match id {
+            ContainerID::Root {
+                name,
+                container_type,
+            } => ContainerID::Root {
+                name: self.resolve_root_name(&name),
+                container_type,
+            },
+            id => id,
+        }
+    }
+
+    /// Set a field of the metadata of this doc's peer, e.g. its display name with
+    /// [PEER_NAME_KEY] or the URL of its avatar.
+    ///
+    /// The metadata is stored in the doc and synced like other changes, so the edits of a
+    /// peer can be attributed to it without an external lookup, even long after the peer
+    /// is gone. Each field is last-write-wins.
+    pub fn set_peer_metadata(&self, field: &str, value: impl Into<LoroValue>) -> LoroResult<()> {
+        let key = format!("{}/{}", self.peer_id(), field);
+        self.peer_metadata_map().insert(&key, value)
+    }
+
+    /// Get the metadata of `peer` set by [LoroDoc::set_peer_metadata].
+    pub fn peer_metadata(&self, peer: PeerID) -> FxHashMap<String, LoroValue> {
+        let mut ans = FxHashMap::default();
+        if !self.has_peer_metadata() {
+            return ans;
+        }
+
+        let prefix = format!("{}/", peer);
+        self.peer_metadata_map().for_each(|key, value| {
+            let Some(field) = key.strip_prefix(&prefix) else {
+                return;
+            };
+            if let ValueOrHandler::Value(value) = value {
+                ans.insert(field.to_string(), value);
+            }
+        });
+        ans
+    }
+
+    /// Get the display name of `peer`, see [LoroDoc::set_peer_metadata].
+    pub fn peer_name(&self, peer: PeerID) -> Option<Arc<str>> {
+        if !self.has_peer_metadata() {
+            return None;
+        }
+
+        match self
+            .peer_metadata_map()
+            .get(&format!("{}/{}", peer, PEER_NAME_KEY))
+        {
+            Some(LoroValue::String(name)) => Some(name.as_str().into()),
+            _ => None,
+        }
+    }
+
+    fn peer_metadata_map(&self) -> MapHandler {
+        self.system_root(PEER_METADATA_MAP)
+    }
+
+    /// Don't register the map when no peer has metadata
+    fn has_peer_metadata(&self) -> bool {
+        self.has_system_root(PEER_METADATA_MAP)
+    }
+
+    /// Get the map for the bookkeeping of the app, e.g. its schema version, feature flags or
+    /// migration markers.
+    ///
+    /// It's a root map like the others, with the same merge semantics, but it's hidden from
+    /// [LoroDoc::get_deep_value], so the bookkeeping doesn't mix with the data of the users.
+    pub fn get_system_map(&self) -> MapHandler {
+        self.system_root(SYSTEM_MAP)
+    }
+
     /// id can be a str, ContainerID, or ContainerIdRaw.
     /// if it's str it will use Root container, which will not be None
     #[inline]
     pub fn get_text<I: IntoContainerId>(&self, id: I) -> TextHandler {
         let id = id.into_container_id(&self.arena, ContainerType::Text);
+        let id = self.resolve_root_alias(id);
         Handler::new_attached(
             id,
             self.arena.clone(),
@@ -697,6 +1556,7 @@ impl LoroDoc {
     #[inline]
     pub fn get_list<I: IntoContainerId>(&self, id: I) -> ListHandler {
         let id = id.into_container_id(&self.arena, ContainerType::List);
+        let id = self.resolve_root_alias(id);
         Handler::new_attached(
             id,
             self.arena.clone(),
@@ -712,6 +1572,7 @@ impl LoroDoc {
     #[inline]
     pub fn get_movable_list<I: IntoContainerId>(&self, id: I) -> MovableListHandler {
         let id = id.into_container_id(&self.arena, ContainerType::MovableList);
+        let id = self.resolve_root_alias(id);
         Handler::new_attached(
             id,
             self.arena.clone(),
@@ -727,6 +1588,7 @@ impl LoroDoc {
     #[inline]
     pub fn get_map<I: IntoContainerId>(&self, id: I) -> MapHandler {
         let id = id.into_container_id(&self.arena, ContainerType::Map);
+        let id = self.resolve_root_alias(id);
         Handler::new_attached(
             id,
             self.arena.clone(),
@@ -742,6 +1604,7 @@ impl LoroDoc {
     #[inline]
     pub fn get_tree<I: IntoContainerId>(&self, id: I) -> TreeHandler {
         let id = id.into_container_id(&self.arena, ContainerType::Tree);
+        let id = self.resolve_root_alias(id);
         Handler::new_attached(
             id,
             self.arena.clone(),
@@ -758,6 +1621,7 @@ impl LoroDoc {
         id: I,
     ) -> crate::handler::counter::CounterHandler {
         let id = id.into_container_id(&self.arena, ContainerType::Counter);
+        let id = self.resolve_root_alias(id);
         Handler::new_attached(
             id,
             self.arena.clone(),
@@ -960,6 +1824,58 @@ impl LoroDoc {
         self.oplog().lock().unwrap().cmp_with_frontiers(other)
     }
 
+    /// Get the direct dependencies of the op with the given id.
+    ///
+    /// Return `None` if the op is not included in the [OpLog].
+    #[inline]
+    pub fn deps_of(&self, id: ID) -> Option<Frontiers> {
+        self.oplog.lock().unwrap().get_deps_of(id)
+    }
+
+    /// Get the id spans that this doc must import before the blob described by `meta`
+    /// can be applied.
+    ///
+    /// Only the direct dependencies of the blob are returned. The missing spans may
+    /// themselves depend on other missing ops, which can be found by calling this method
+    /// again with the metadata of the fetched blobs.
+    pub fn missing_deps_for(&self, meta: &ImportBlobMetadata) -> Vec<IdSpan> {
+        let mut required = meta.partial_start_vv.clone();
+        for &id in meta.start_frontiers.iter() {
+            required.extend_to_include_last_id(id);
+        }
+
+        self.oplog_vv()
+            .get_missing_span(&required)
+            .into_iter()
+            .filter(|span| span.content_len() > 0)
+            .collect()
+    }
+
+    /// Compare the version of the [OpLog] with the version after importing `bytes`,
+    /// without importing it.
+    ///
+    /// - `Some(Ordering::Less)` means the blob contains ops that are strictly newer than this doc
+    /// - `Some(Ordering::Equal)` or `Some(Ordering::Greater)` means the blob is already included
+    /// - `None` means the blob is concurrent to this doc
+    pub fn vv_cmp_with_update_bytes(&self, bytes: &[u8]) -> LoroResult<Option<Ordering>> {
+        let meta = Self::decode_import_blob_meta(bytes)?;
+        let oplog = self.oplog.lock().unwrap();
+        let mut blob_vv = oplog
+            .dag
+            .frontiers_to_vv(&meta.start_frontiers)
+            .unwrap_or_else(|| {
+                // The deps are missing, so this doc cannot include the blob
+                let mut vv = VersionVector::new();
+                for &id in meta.start_frontiers.iter() {
+                    vv.extend_to_include_last_id(id);
+                }
+                vv
+            });
+        blob_vv.merge(&meta.partial_start_vv);
+        blob_vv.merge(&meta.partial_end_vv);
+        Ok(oplog.vv().partial_cmp(&blob_vv))
+    }
+
     /// Compare two [Frontiers] causally.
     ///
     /// If one of the [Frontiers] are not included, it will return [FrontiersNotIncluded].
@@ -990,13 +1906,115 @@ impl LoroDoc {
         self.observer.subscribe(container_id, callback)
     }
 
+    /// Push the events of the container, or of the whole doc if it's `None`, to `queue`.
+    ///
+    /// The events are popped by the consumer of the queue, see [EventQueue].
+    pub fn subscribe_queue(&self, container_id: Option<&ContainerID>, queue: &EventQueue) -> SubID {
+        match container_id {
+            Some(id) => self.subscribe(id, queue.subscriber()),
+            None => self.subscribe_root(queue.subscriber()),
+        }
+    }
+
+    /// Receive the changes of the doc as JSON Patches, e.g. to stream them to a web client.
+    ///
+    /// The patches apply to the JSON value of the doc at the time of subscribing, i.e.
+    /// [LoroDoc::get_deep_value] right after this call, and each patch brings it to the
+    /// value after the event. Hidden roots are skipped, and empty patches aren't sent.
+    pub fn subscribe_json_patch(&self, callback: JsonPatchSubscriber) -> SubID {
+        self.commit_then_renew();
+        let encoder = Mutex::new(JsonPatchEncoder::new(&self.get_deep_value()));
+        let state = Arc::downgrade(&self.state);
+        self.subscribe_root(Arc::new(move |event| {
+            let Some(state) = state.upgrade() else {
+                return;
+            };
+            let patch = {
+                let mut state = state.lock().unwrap();
+                encoder.lock().unwrap().encode(&event, &mut state)
+            };
+            if !patch.is_empty() {
+                callback(&patch);
+            }
+        }))
+    }
+
+    /// Apply a JSON Patch, e.g. one sent by a web client, as a single commit.
+    ///
+    /// The patch is checked before the doc is edited, so an invalid patch changes nothing.
+    /// A text or a counter is edited to reach the new value instead of being overwritten,
+    /// and trees can't be patched.
+    pub fn apply_json_patch(&self, patch: &[JsonPatchOp]) -> LoroResult<()> {
+        crate::json_patch::apply(self, patch)
+    }
+
+    /// Subscribe to a value projected from the given path.
+    ///
+    /// `projector` maps the deep value at `path` (`None` if the path doesn't exist) to the
+    /// selected value. `callback` receives the old and the new selected values, and it's only
+    /// invoked when the selected value changes.
+    ///
+    /// Events that only touch containers outside of `path`, or only touch other children of
+    /// its ancestors, are skipped without reading the state.
+    pub fn subscribe_select(
+        &self,
+        path: &[Index],
+        projector: Projector,
+        callback: SelectSubscriber,
+    ) -> SubID {
+        let path = path.to_vec();
+        let value = self.state.lock().unwrap().get_deep_value_by_path(&path);
+        let last = Mutex::new(projector(value.as_ref()));
+        let state = Arc::downgrade(&self.state);
+        self.subscribe_root(Arc::new(move |event| {
+            // A diff may change the selected value if its container is at or under the path,
+            // or if it's an ancestor that changes the next segment of the path
+            let touched = event.events.iter().any(|diff| {
+                diff.path.iter().zip(path.iter()).all(|((_, a), b)| a == b)
+                    && path
+                        .get(diff.path.len())
+                        .is_none_or(|next| diff_touches_child(&diff.diff, next))
+            });
+            if !touched {
+                return;
+            }
+
+            let Some(state) = state.upgrade() else {
+                return;
+            };
+            let value = state.lock().unwrap().get_deep_value_by_path(&path);
+            let new = projector(value.as_ref());
+            let old = {
+                let mut last = last.lock().unwrap();
+                if *last == new {
+                    return;
+                }
+                std::mem::replace(&mut *last, new.clone())
+            };
+            callback(&old, &new);
+        }))
+    }
+
     #[inline]
     pub fn unsubscribe(&self, id: SubID) {
         self.observer.unsubscribe(id);
     }
 
+    /// Import a batch of updates and snapshots in arbitrary order.
+    ///
+    /// A change whose deps are not imported yet is kept pending, and it's applied as soon
+    /// as its deps arrive from any blob of the batch. So the result doesn't depend on the
+    /// order of the blobs.
+    ///
+    /// The state is updated once after all the blobs are imported, which emits a single
+    /// import event. If any blob fails to import, the others are still imported and the
+    /// last error is returned.
     // PERF: opt
     pub fn import_batch(&self, bytes: &[Vec<u8>]) -> LoroResult<()> {
+        self.check_root_type_conflicts(|| self._import_batch(bytes))
+    }
+
+    fn _import_batch(&self, bytes: &[Vec<u8>]) -> LoroResult<()> {
         self.commit_then_stop();
         let is_detached = self.is_detached();
         self.detach();
@@ -1017,10 +2035,19 @@ impl LoroDoc {
         drop(oplog);
 
         if !is_detached {
-            self.checkout_to_latest();
+            let latest = self.oplog_frontiers();
+            self.checkout_without_emitting_by(
+                &latest,
+                Default::default(),
+                EventTriggerKind::Import,
+            )
+            .unwrap();
+            self.detached.store(false, Release);
+            self.emit_events();
         }
 
         self.renew_txn_if_auto_commit();
+        self.notify_version_waiters();
         if let Some(err) = err {
             return Err(err);
         }
@@ -1034,6 +2061,57 @@ impl LoroDoc {
         self.state.lock().unwrap().get_deep_value()
     }
 
+    /// Get deep value of the document, with each text as its rich text delta.
+    ///
+    /// See [DocState::get_deep_value_with_richtext].
+    #[inline]
+    pub fn get_deep_value_with_richtext(&self) -> LoroValue {
+        self.state.lock().unwrap().get_deep_value_with_richtext()
+    }
+
+    /// The values of the roots that share their name with a root of another type, see
+    /// [DocState::get_shadowed_roots_value].
+    #[inline]
+    pub fn get_shadowed_roots_value(&self) -> LoroValue {
+        self.state.lock().unwrap().get_shadowed_roots_value()
+    }
+
+    /// The hash of the current state, e.g. to check that the replicas have converged after a
+    /// sync round without comparing their deep values.
+    ///
+    /// It's updated with each op that is applied, so reading it is O(1). The replicas that
+    /// have imported the same ops have the same hash, whatever the order of the imports.
+    /// It covers the elements of every container, including the deleted ones.
+    #[inline]
+    pub fn state_hash(&self) -> u64 {
+        self.state.lock().unwrap().state_hash()
+    }
+
+    /// Get the deep values of `containers`, materializing them in parallel.
+    ///
+    /// It's read-only and returns the values in the order of `containers`. The value of a
+    /// container that the doc doesn't know is [LoroValue::Null].
+    #[cfg(feature = "parallel")]
+    pub fn par_get_values(&self, containers: &[ContainerID]) -> Vec<LoroValue> {
+        let idxs: Vec<Option<ContainerIdx>> = containers
+            .iter()
+            .map(|id| self.arena.id_to_idx(id))
+            .collect();
+        let known: Vec<ContainerIdx> = idxs.iter().flatten().copied().collect();
+        let mut values = self
+            .state
+            .lock()
+            .unwrap()
+            .par_get_container_deep_values(&known)
+            .into_iter();
+        idxs.iter()
+            .map(|idx| match idx {
+                Some(_) => values.next().unwrap(),
+                None => LoroValue::Null,
+            })
+            .collect()
+    }
+
     /// Get deep value of the document with container id
     #[inline]
     pub fn get_deep_value_with_id(&self) -> LoroValue {
@@ -1064,19 +2142,36 @@ impl LoroDoc {
         Ok(())
     }
 
-    #[instrument(level = "info", skip(self))]
+    #[inline]
     fn checkout_without_emitting(&self, frontiers: &Frontiers) -> Result<(), LoroError> {
+        self.checkout_without_emitting_by(frontiers, "checkout".into(), EventTriggerKind::Checkout)
+    }
+
+    /// Move the [DocState] to `frontiers` and record the events as triggered by `by`.
+    #[instrument(level = "info", skip(self))]
+    fn checkout_without_emitting_by(
+        &self,
+        frontiers: &Frontiers,
+        origin: InternalString,
+        by: EventTriggerKind,
+    ) -> Result<(), LoroError> {
+        // The target can be in the pending txn. It's renewed, and the target is validated
+        // before the detached flag is set, so that a failed checkout leaves the doc attached
+        self.commit_then_renew();
+        {
+            let oplog = self.oplog.lock().unwrap();
+            for &i in frontiers.iter() {
+                if !oplog.dag.contains(i) {
+                    return Err(LoroError::FrontiersNotFound(i));
+                }
+            }
+        }
+
         self.commit_then_stop();
         let oplog = self.oplog.lock().unwrap();
         let mut state = self.state.lock().unwrap();
         self.detached.store(true, Release);
         let mut calc = self.diff_calculator.lock().unwrap();
-        for &i in frontiers.iter() {
-            if !oplog.dag.contains(i) {
-                return Err(LoroError::FrontiersNotFound(i));
-            }
-        }
-
         let before = &oplog.dag.frontiers_to_vv(&state.frontiers).unwrap();
         let Some(after) = &oplog.dag.frontiers_to_vv(frontiers) else {
             return Err(LoroError::NotFoundError(
@@ -1092,9 +2187,9 @@ impl LoroDoc {
             None,
         );
         state.apply_diff(InternalDocDiff {
-            origin: "checkout".into(),
+            origin,
             diff: Cow::Owned(diff),
-            by: EventTriggerKind::Checkout,
+            by,
             new_version: Cow::Owned(frontiers.clone()),
         });
         Ok(())
@@ -1117,6 +2212,142 @@ impl LoroDoc {
         self.import(&other.export_from(&self.oplog_vv()))
     }
 
+    /// Import the current state of `other` under the map container `mount_at`.
+    ///
+    /// Every root container of `other` becomes a child container of `mount_at`, keyed by
+    /// the root name.
+    ///
+    /// Only the state is copied. The content is recreated by the ops of this doc's peer, so
+    /// the history of `other` is not carried over. Use [LoroDoc::import] to merge docs with
+    /// their history.
+    ///
+    /// It uses the same diff-and-remap machinery as undo, so the nested containers of `other`
+    /// are recreated with new [ContainerID]s in this doc.
+    pub fn absorb_state(&self, other: &Self, mount_at: &ContainerID) -> LoroResult<()> {
+        if self.is_detached() {
+            return Err(LoroError::EditWhenDetached);
+        }
+
+        if mount_at.container_type() != ContainerType::Map {
+            return Err(LoroError::ArgErr(
+                format!("Cannot mount the doc at a non-map container {:?}", mount_at)
+                    .into_boxed_str(),
+            ));
+        }
+
+        other.commit_then_stop();
+        let other_was_detached = other.is_detached();
+        let other_was_recording = other.state.lock().unwrap().is_recording();
+        let target = other.state_frontiers();
+        let diff = other.diff(&Frontiers::default(), &target);
+        if other_was_recording {
+            other.state.lock().unwrap().start_recording();
+        }
+        if !other_was_detached {
+            other.checkout_to_latest();
+        }
+
+        let mut diff = diff?;
+        // Sort container from the top to the bottom, so that we can have correct container remap
+        let containers = diff.0.keys().cloned().sorted_by_cached_key(|cid| {
+            let idx = other.arena.id_to_idx(cid).unwrap();
+            other.arena.get_depth(idx).unwrap().get()
+        });
+
+        let mount = self.get_map(mount_at);
+        let mut container_remap: FxHashMap<ContainerID, ContainerID> = Default::default();
+        for id in containers {
+            let container_diff = diff.0.remove(&id).unwrap();
+            let target = match &id {
+                ContainerID::Root {
+                    name,
+                    container_type,
+                } => {
+                    let child =
+                        mount.insert_container(name, Handler::new_unattached(*container_type))?;
+                    let new_id = child.id();
+                    container_remap.insert(id.clone(), new_id.clone());
+                    new_id
+                }
+                ContainerID::Normal { .. } => {
+                    let mut id = id;
+                    let mut remapped = false;
+                    while let Some(rid) = container_remap.get(&id) {
+                        remapped = true;
+                        id = rid.clone();
+                    }
+
+                    if !remapped {
+                        // It's not reachable from the roots of `other`
+                        continue;
+                    }
+
+                    id
+                }
+            };
+
+            self.get_handler(target)
+                .apply_diff(container_diff, &mut container_remap)?;
+        }
+
+        Ok(())
+    }
+
+    /// Create a new doc that only contains the subtree of `container`.
+    ///
+    /// The container becomes a root container of the new doc. It keeps its name if it's
+    /// already a root container, otherwise it's named `"root"`.
+    ///
+    /// The current state of the subtree is recreated in a single change by a new random
+    /// peer, so the new doc never reuses the ids of this doc's peers. The new doc has its
+    /// own history and should not be merged back into this doc.
+    pub fn extract(&self, container: &ContainerID) -> LoroResult<LoroDoc> {
+        let src = self.fork();
+        if src.arena.id_to_idx(container).is_none() {
+            return Err(LoroError::NotFoundError(
+                format!("Cannot find container {:?}", container).into_boxed_str(),
+            ));
+        }
+
+        let mut diff = src.diff(&Frontiers::default(), &src.oplog_frontiers())?;
+        // Sort container from the top to the bottom, so that we can have correct container remap
+        let containers = diff.0.keys().cloned().sorted_by_cached_key(|cid| {
+            let idx = src.arena.id_to_idx(cid).unwrap();
+            src.arena.get_depth(idx).unwrap().get()
+        });
+
+        let name = match container {
+            ContainerID::Root { name, .. } => name.clone(),
+            ContainerID::Normal { .. } => "root".into(),
+        };
+        let root = ContainerID::new_root(&name, container.container_type());
+        let doc = LoroDoc::new_auto_commit();
+        let mut container_remap: FxHashMap<ContainerID, ContainerID> = Default::default();
+        if root != *container {
+            container_remap.insert(container.clone(), root);
+        }
+
+        for mut id in containers {
+            let container_diff = diff.0.remove(&id).unwrap();
+            let mut remapped = id == *container;
+            while let Some(rid) = container_remap.get(&id) {
+                remapped = true;
+                id = rid.clone();
+            }
+
+            if !remapped {
+                // It's outside of the subtree
+                continue;
+            }
+
+            doc.get_handler(id)
+                .apply_diff(container_diff, &mut container_remap)?;
+        }
+
+        doc.commit_then_renew();
+        Ok(doc)
+    }
+
     #[cfg(feature = "test_utils")]
     #[allow(unused)]
     pub(crate) fn arena(&self) -> &SharedArena {
@@ -1185,6 +2416,14 @@ impl LoroDoc {
         pos: &Cursor,
         ret_event_index: bool,
     ) -> Result<PosQueryResult, CannotFindRelativePosition> {
+        // Only the sequence containers have positions
+        if !matches!(
+            pos.container.container_type(),
+            ContainerType::Text | ContainerType::List | ContainerType::MovableList
+        ) {
+            return Err(CannotFindRelativePosition::IdNotFound);
+        }
+
         let mut state = self.state.lock().unwrap();
         if let Some(ans) = state.get_relative_position(pos, ret_event_index) {
             Ok(PosQueryResult {
@@ -1193,6 +2432,7 @@ impl LoroDoc {
                     pos: ans,
                     side: pos.side,
                 },
+                approximate: false,
             })
         } else {
             // We need to trace back to the version where the relative position is valid.
@@ -1215,6 +2455,11 @@ impl LoroDoc {
                     .arena
                     .id_to_idx(&pos.container)
                     .ok_or(CannotFindRelativePosition::ContainerDeleted)?;
+                if !oplog.dag.vv.includes_id(id) {
+                    drop(oplog);
+                    return self.query_origin_pos(pos, ret_event_index);
+                }
+
                 // We know where the target id is when we trace back to the delete_op_id.
                 let delete_op_id = find_last_delete_op(&oplog, id, idx).unwrap();
                 let mut diff_calc = DiffCalculator::new();
@@ -1244,6 +2489,7 @@ impl LoroDoc {
                                 pos: current_pos,
                                 side: c.side,
                             },
+                            approximate: false,
                         })
                     }
                     crate::diff_calc::ContainerDiffCalculator::List(list) => {
@@ -1256,6 +2502,7 @@ impl LoroDoc {
                                 pos: new_pos,
                                 side: c.side,
                             },
+                            approximate: false,
                         })
                     }
                     crate::diff_calc::ContainerDiffCalculator::MovableList(list) => {
@@ -1269,6 +2516,7 @@ impl LoroDoc {
                                 pos: new_pos,
                                 side: c.side,
                             },
+                            approximate: false,
                         })
                     }
                     crate::diff_calc::ContainerDiffCalculator::Tree(_) => unreachable!(),
@@ -1292,6 +2540,7 @@ impl LoroDoc {
                                 pos: text.len_event(),
                                 side: pos.side,
                             },
+                            approximate: false,
                         })
                     }
                     ContainerType::List => {
@@ -1307,6 +2556,7 @@ impl LoroDoc {
                                 pos: list.len(),
                                 side: pos.side,
                             },
+                            approximate: false,
                         })
                     }
                     ContainerType::MovableList => {
@@ -1322,6 +2572,7 @@ impl LoroDoc {
                                 pos: list.len(),
                                 side: pos.side,
                             },
+                            approximate: false,
                         })
                     }
                     ContainerType::Map | ContainerType::Tree | ContainerType::Unknown(_) => {
@@ -1333,6 +2584,81 @@ impl LoroDoc {
             }
         }
     }
+
+    /// The fallback of [LoroDoc::query_pos] when the id of the cursor is not in the history,
+    /// e.g. the cursor was persisted before the history was trimmed. The position the cursor
+    /// was created at is clamped to the length of the container.
+    fn query_origin_pos(
+        &self,
+        pos: &Cursor,
+        ret_event_index: bool,
+    ) -> Result<PosQueryResult, CannotFindRelativePosition> {
+        let (current, update) = match pos.container.container_type() {
+            ContainerType::Text => {
+                let text = self.get_text(&pos.container);
+                let unicode_pos = pos.origin_pos.min(text.len_unicode());
+                let current = if ret_event_index {
+                    text.convert_pos(unicode_pos, PosType::Unicode, PosType::Event)
+                        .unwrap()
+                } else {
+                    unicode_pos
+                };
+                (
+                    current,
+                    text.get_cursor_internal(unicode_pos, pos.side, false),
+                )
+            }
+            ContainerType::List => {
+                let list = self.get_list(&pos.container);
+                let current = pos.origin_pos.min(list.len());
+                (current, list.get_cursor(current, pos.side))
+            }
+            ContainerType::MovableList => {
+                let list = self.get_movable_list(&pos.container);
+                let current = pos.origin_pos.min(list.len());
+                (current, list.get_cursor(current, pos.side))
+            }
+            ContainerType::Map | ContainerType::Tree | ContainerType::Unknown(_) => {
+                return Err(CannotFindRelativePosition::IdNotFound);
+            }
+            #[cfg(feature = "counter")]
+            ContainerType::Counter => return Err(CannotFindRelativePosition::IdNotFound),
+        };
+
+        Ok(PosQueryResult {
+            update,
+            current: AbsolutePosition {
+                pos: current,
+                side: pos.side,
+            },
+            approximate: true,
+        })
+    }
+}
+
+/// Whether `diff`, the diff of an ancestor of a path, may change its child at `index`,
+/// the next segment of the path.
+fn diff_touches_child(diff: &Diff, index: &Index) -> bool {
+    match (diff, index) {
+        (Diff::Map(map), Index::Key(key)) => map.updated.contains_key(key),
+        (Diff::List(list), Index::Seq(index)) => {
+            // An insertion or a deletion before the child shifts its index
+            let mut pos = 0;
+            for item in list.iter() {
+                if pos > *index {
+                    return false;
+                }
+
+                match item {
+                    DeltaItem::Retain { len, .. } => pos += len,
+                    DeltaItem::Replace { .. } => return true,
+                }
+            }
+
+            false
+        }
+        _ => true,
+    }
 }
 
 fn find_last_delete_op(oplog: &OpLog, id: ID, idx: ContainerIdx) -> Option<ID> {
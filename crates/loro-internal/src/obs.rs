@@ -5,7 +5,7 @@ use std::sync::{
 
 use fxhash::{FxHashMap, FxHashSet};
 use itertools::Itertools;
-use loro_common::ContainerID;
+use loro_common::{ContainerID, LoroValue};
 use smallvec::SmallVec;
 
 use crate::{container::idx::ContainerIdx, ContainerDiff};
@@ -16,6 +16,11 @@ use super::{
 };
 
 pub type Subscriber = Arc<dyn (for<'a> Fn(DiffEvent<'a>)) + Send + Sync>;
+/// Map the deep value at the selected path to the value a select subscriber cares about.
+/// It receives `None` if the path doesn't exist.
+pub type Projector = Arc<dyn Fn(Option<&LoroValue>) -> LoroValue + Send + Sync>;
+/// Receive the old and the new projected values of a select subscription.
+pub type SelectSubscriber = Arc<dyn Fn(&LoroValue, &LoroValue) + Send + Sync>;
 
 #[derive(Default)]
 struct ObserverInner {
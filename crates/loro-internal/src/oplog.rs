@@ -1,15 +1,19 @@
 pub(crate) mod dag;
 mod iter;
 mod pending_changes;
+pub(crate) mod quota;
 
 use std::borrow::Cow;
 use std::cell::RefCell;
 use std::cmp::Ordering;
+use std::collections::BinaryHeap;
 use std::mem::take;
 use std::rc::Rc;
+use std::sync::Arc;
 
 use crate::change::{get_sys_timestamp, Change, Lamport, Timestamp};
 use crate::configure::Configure;
+use crate::container::idx::ContainerIdx;
 use crate::container::list::list_op;
 use crate::dag::{Dag, DagUtils};
 use crate::encoding::ParsedHeaderAndBody;
@@ -20,15 +24,20 @@ use crate::op::{FutureInnerContent, ListSlice, Op, RawOpContent, RemoteOp, RichO
 use crate::span::{HasCounterSpan, HasIdSpan, HasLamportSpan};
 use crate::version::{Frontiers, ImVersionVector, VersionVector};
 use crate::LoroError;
-use fxhash::FxHashMap;
+use fxhash::{FxHashMap, FxHashSet};
 use loro_common::{HasCounter, HasId, IdLp, IdSpan};
 use rle::{HasLength, RleCollection, RlePush, RleVec, Sliceable};
 use smallvec::SmallVec;
 
-type ClientChanges = FxHashMap<PeerID, Vec<Change>>;
+/// The changes of each peer.
+///
+/// They're shared by the forks of a doc, and a fork only copies the changes of a peer
+/// when it modifies them, so N forks of a doc don't take N times the memory of its history.
+type ClientChanges = FxHashMap<PeerID, Arc<Vec<Change>>>;
 pub use self::dag::FrontiersNotIncluded;
 use self::iter::MergedChangeIter;
 use self::pending_changes::PendingChanges;
+use self::quota::Rejections;
 
 use super::arena::SharedArena;
 
@@ -55,6 +64,13 @@ pub struct OpLog {
     /// If so the Dag's frontiers won't be updated until the batch is finished.
     pub(crate) batch_importing: bool,
     pub(crate) configure: Configure,
+    /// The frontiers of the ops on each container and the latest timestamp of their changes
+    container_last_modified: FxHashMap<ContainerIdx, (Frontiers, Timestamp)>,
+    /// The last ids of the shared changes that got dependents. They're kept here instead of
+    /// in [Change::has_dependents], so that the changes aren't copied for it.
+    shared_dependents: FxHashSet<ID>,
+    /// The ops rejected by the per-peer limits of [Configure], see [quota]
+    rejections: Rejections,
 }
 
 /// [AppDag] maintains the causal graph of the app.
@@ -91,6 +107,9 @@ impl OpLog {
             pending_changes: Default::default(),
             batch_importing: false,
             configure,
+            container_last_modified: self.container_last_modified.clone(),
+            shared_dependents: self.shared_dependents.clone(),
+            rejections: self.rejections.fork(),
         }
     }
 }
@@ -182,6 +201,9 @@ impl OpLog {
             pending_changes: Default::default(),
             batch_importing: false,
             configure: Configure::default(),
+            container_last_modified: Default::default(),
+            shared_dependents: Default::default(),
+            rejections: Default::default(),
         }
     }
 
@@ -242,9 +264,11 @@ impl OpLog {
 
     /// This is the **only** place to update the `OpLog.changes`
     pub(crate) fn insert_new_change(&mut self, mut change: Change, _: EnsureChangeDepsAreAtTheEnd) {
+        self.configure.mark_has_ops();
         self.op_groups.insert_by_change(&change);
         self.register_container_and_parent_link(&change);
-        let entry = self.changes.entry(change.id.peer).or_default();
+        self.update_container_last_modified(&change);
+        let entry = Arc::make_mut(self.changes.entry(change.id.peer).or_default());
         match entry.last_mut() {
             Some(last) => {
                 assert_eq!(
@@ -254,9 +278,14 @@ impl OpLog {
                 );
                 let timestamp_change = change.timestamp - last.timestamp;
                 // TODO: make this a config
+                // Changes with commit messages are kept apart, so each message
+                // stays attached to its own ops
                 if !last.has_dependents
+                    && !self.shared_dependents.contains(&last.id_last())
                     && change.deps_on_self()
                     && timestamp_change < self.configure.merge_interval()
+                    && last.commit_msg.is_none()
+                    && change.commit_msg.is_none()
                 {
                     for op in take(change.ops.vec_mut()) {
                         last.ops.push(op);
@@ -272,6 +301,33 @@ impl OpLog {
         }
     }
 
+    /// The dag must be updated with the change before calling this
+    fn update_container_last_modified(&mut self, change: &Change) {
+        let vv = self.dag.get_vv(change.id).unwrap();
+        for op in change.ops.iter() {
+            let last_id = ID::new(change.id.peer, op.counter + op.atom_len() as Counter - 1);
+            let (frontiers, timestamp) = self
+                .container_last_modified
+                .entry(op.container)
+                .or_default();
+            // The ids from the same peer or in the history of the change are overridden
+            frontiers.retain(|id| {
+                id.peer != change.id.peer && vv.get(&id.peer).is_none_or(|end| *end <= id.counter)
+            });
+            frontiers.push(last_id);
+            *timestamp = (*timestamp).max(change.timestamp);
+        }
+    }
+
+    /// Get the frontiers of the ops on the given container and the latest timestamp of
+    /// their changes, i.e. when the container was last modified.
+    ///
+    /// It's maintained on every new change, so it doesn't scan the history. Return `None`
+    /// if the container has no ops.
+    pub fn container_last_modified(&self, idx: ContainerIdx) -> Option<(Frontiers, Timestamp)> {
+        self.container_last_modified.get(&idx).cloned()
+    }
+
     /// Import a change.
     ///
     /// Pending changes that haven't been applied to the dag.
@@ -372,11 +428,18 @@ impl OpLog {
         let changes = self.changes.get_mut(&dep.peer).unwrap();
         match changes.binary_search_by(|c| c.ctr_last().cmp(&dep.counter)) {
             Ok(index) => {
-                if src != dep.peer {
-                    changes[index].has_dependents = true;
+                if src != dep.peer && !changes[index].has_dependents {
+                    let id = changes[index].id_last();
+                    match Arc::get_mut(changes) {
+                        Some(changes) => changes[index].has_dependents = true,
+                        None => {
+                            self.shared_dependents.insert(id);
+                        }
+                    }
                 }
             }
             Err(index) => {
+                let changes = Arc::make_mut(changes);
                 // This operation is slow in some rare cases, but I guess it's fine for now.
                 //
                 // It's only slow when you import an old concurrent change.
@@ -442,7 +505,7 @@ impl OpLog {
     }
 
     pub fn get_peer_changes(&self, peer: PeerID) -> Option<&Vec<Change>> {
-        self.changes.get(&peer)
+        self.changes.get(&peer).map(Arc::as_ref)
     }
 
     pub(crate) fn vv(&self) -> &VersionVector {
@@ -557,6 +620,7 @@ impl OpLog {
             deps: change.deps.clone(),
             lamport: change.lamport,
             timestamp: change.timestamp,
+            commit_msg: change.commit_msg.clone(),
             has_dependents: false,
         }
     }
@@ -774,7 +838,7 @@ impl OpLog {
         let from_frontiers = match from_frontiers {
             Some(f) => f,
             None => {
-                from_frontiers_inner = Some(from.to_frontiers(&self.dag));
+                from_frontiers_inner = Some(self.dag.vv_to_frontiers(from));
                 from_frontiers_inner.as_ref().unwrap()
             }
         };
@@ -782,7 +846,7 @@ impl OpLog {
         let to_frontiers = match to_frontiers {
             Some(t) => t,
             None => {
-                to_frontiers_inner = Some(to.to_frontiers(&self.dag));
+                to_frontiers_inner = Some(self.dag.vv_to_frontiers(to));
                 to_frontiers_inner.as_ref().unwrap()
             }
         };
@@ -889,6 +953,42 @@ impl OpLog {
         })
     }
 
+    /// At most `limit` changes in the descending order of `(lamport, peer)`, starting from
+    /// the first one before `before` if it's given.
+    ///
+    /// The changes of a peer are sorted by Lamport timestamp, so it merges the peers with a
+    /// heap instead of sorting all the changes.
+    pub(crate) fn changes_before(
+        &self,
+        before: Option<(Lamport, PeerID)>,
+        limit: usize,
+    ) -> Vec<&Change> {
+        let mut heads = BinaryHeap::new();
+        for (&peer, changes) in self.changes.iter() {
+            let end = match before {
+                Some(before) => changes.partition_point(|c| (c.lamport, peer) < before),
+                None => changes.len(),
+            };
+            if end > 0 {
+                heads.push((changes[end - 1].lamport, peer, end - 1));
+            }
+        }
+
+        let mut ans = Vec::new();
+        while ans.len() < limit {
+            let Some((_, peer, index)) = heads.pop() else {
+                break;
+            };
+            let changes = &self.changes[&peer];
+            ans.push(&changes[index]);
+            if index > 0 {
+                heads.push((changes[index - 1].lamport, peer, index - 1));
+            }
+        }
+
+        ans
+    }
+
     pub(crate) fn iter_changes_causally_rev<'a>(
         &'a self,
         from: &VersionVector,
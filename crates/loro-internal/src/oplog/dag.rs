@@ -5,7 +5,7 @@ use crate::change::Lamport;
 use crate::dag::{Dag, DagNode};
 use crate::id::{Counter, ID};
 use crate::span::{HasId, HasLamport};
-use crate::version::{Frontiers, ImVersionVector, VersionVector};
+use crate::version::{shrink_frontiers, Frontiers, ImVersionVector, VersionVector};
 use loro_common::HasIdSpan;
 use rle::{HasIndex, HasLength, Mergable, RleCollection, Sliceable};
 
@@ -207,9 +207,15 @@ impl AppDag {
         vv
     }
 
+    /// Convert a version vector to the minimal [Frontiers] that represents it.
+    ///
+    /// # Panic
+    ///
+    /// When vv is greater than self.vv
     #[inline(always)]
     pub fn vv_to_frontiers(&self, vv: &VersionVector) -> Frontiers {
-        vv.to_frontiers(self)
+        let last_ids = vv.get_frontiers().to_vec();
+        shrink_frontiers(last_ids, self)
     }
 
     pub(crate) fn frontiers_to_next_lamport(&self, frontiers: &Frontiers) -> Lamport {
@@ -34,6 +34,35 @@ impl PendingChanges {
     pub fn is_empty(&self) -> bool {
         self.changes.is_empty()
     }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Change> {
+        self.changes
+            .values()
+            .flat_map(|x| x.values())
+            .flat_map(|x| x.iter())
+            .map(|x| x.deref())
+    }
+
+    /// Replace each change with the result of `f`, or remove it if `f` returns `None`.
+    ///
+    /// `f` must keep the start of the change, so that it's still waiting for the same dep.
+    pub(crate) fn filter_map(&mut self, mut f: impl FnMut(Change) -> Option<Change>) {
+        for tree in self.changes.values_mut() {
+            for changes in tree.values_mut() {
+                *changes = std::mem::take(changes)
+                    .into_iter()
+                    .filter_map(|change| match change {
+                        PendingChange::Unknown(c) => f(c).map(PendingChange::Unknown),
+                        PendingChange::Known(c) => f(c).map(PendingChange::Known),
+                    })
+                    .collect();
+            }
+
+            tree.retain(|_, changes| !changes.is_empty());
+        }
+
+        self.changes.retain(|_, tree| !tree.is_empty());
+    }
 }
 
 impl OpLog {
@@ -257,16 +286,17 @@ mod test {
             .unwrap();
         let update_a2 = a.export_from(&version_a1b1);
         c.import(&update_a2).unwrap();
-        assert_eq!(c.get_deep_value().to_json(), "{\"text\":\"\"}");
+        // The pending ops haven't written to the text yet
+        assert_eq!(c.get_deep_value().to_json(), "{}");
         c.import(&update_a1).unwrap();
         assert_eq!(c.get_deep_value().to_json(), "{\"text\":\"a\"}");
         c.import(&update_b1).unwrap();
         assert_eq!(a.get_deep_value(), c.get_deep_value());
 
         d.import(&update_a2).unwrap();
-        assert_eq!(d.get_deep_value().to_json(), "{\"text\":\"\"}");
+        assert_eq!(d.get_deep_value().to_json(), "{}");
         d.import(&update_b1).unwrap();
-        assert_eq!(d.get_deep_value().to_json(), "{\"text\":\"\"}");
+        assert_eq!(d.get_deep_value().to_json(), "{}");
         d.import(&update_a1).unwrap();
         assert_eq!(a.get_deep_value(), d.get_deep_value());
     }
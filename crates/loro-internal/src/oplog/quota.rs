@@ -0,0 +1,424 @@
+//! The rejection of the remote ops beyond the per-peer limits of [Configure].
+//!
+//! Whether an op is rejected only depends on its id, the earlier ops of its peer and its
+//! causal history, so all the replicas with the same limits reject the same ops, whatever
+//! order they receive them in:
+//!
+//! - The ops of a peer with a counter at or above [Configure::max_ops_per_peer] are rejected.
+//! - The ops of a peer are counted one atom at a time in the order of their counters, and
+//!   the first atom that takes the size of the peer's content beyond
+//!   [Configure::max_bytes_per_peer], or the number of the containers it created beyond
+//!   [Configure::max_containers_per_peer], is rejected with all the later ops of the peer.
+//!   See [visit_atom_usage] for how they're measured.
+//! - The ops that depend on a rejected op are rejected, along with all the later ops of
+//!   their peer, since those depend on them too.
+//!
+//! The first rejected counter of each peer is recorded in the [OpLog] as the rejection
+//! marker, so that the later changes of the peer are rejected as soon as they arrive
+//! instead of waiting for their deps forever. The markers are copied by fork and saved in
+//! the snapshots. The pending changes are checked again on each import, because a marker
+//! that rejects them may be set after they arrive.
+//!
+//! The usage of each peer is not saved, it's counted from the oplog again when it's needed.
+//! The limits should be the same on all the replicas and should never be lowered, otherwise
+//! they may not agree.
+//!
+//! [Configure]: crate::configure::Configure
+//! [Configure::max_ops_per_peer]: crate::configure::Configure::max_ops_per_peer
+//! [Configure::max_bytes_per_peer]: crate::configure::Configure::max_bytes_per_peer
+//! [Configure::max_containers_per_peer]: crate::configure::Configure::max_containers_per_peer
+use std::mem::take;
+
+use fxhash::FxHashMap;
+use loro_common::{Counter, HasCounterSpan, IdSpan, LoroError, LoroResult, LoroValue, PeerID, ID};
+use rle::{HasLength, Sliceable};
+
+use super::OpLog;
+use crate::{
+    arena::SharedArena,
+    change::Change,
+    configure::Configure,
+    container::{list::list_op::InnerListOp, tree::tree_op::TreeOp},
+    op::{InnerContent, Op},
+};
+
+#[derive(Debug, Default)]
+pub(crate) struct Rejections {
+    /// The first rejected counter of each peer
+    first_rejected: FxHashMap<PeerID, Counter>,
+    /// The counted usage of each peer
+    usage: FxHashMap<PeerID, Usage>,
+    /// The rejected ops that haven't been reported yet
+    unreported: Vec<IdSpan>,
+}
+
+impl Rejections {
+    /// Copy the markers and the usage for a forked oplog. The unreported ops are left to
+    /// this one.
+    pub(crate) fn fork(&self) -> Self {
+        Self {
+            first_rejected: self.first_rejected.clone(),
+            usage: self.usage.clone(),
+            unreported: Vec::new(),
+        }
+    }
+}
+
+/// The size and the number of the created containers of the ops of a peer
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Usage {
+    /// The ops of the peer before this counter have been counted
+    end: Counter,
+    bytes: usize,
+    containers: usize,
+}
+
+/// The limits of [Usage], `usize::MAX` if there is no limit
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct UsageLimits {
+    bytes: usize,
+    containers: usize,
+}
+
+impl UsageLimits {
+    /// `None` if neither the size nor the number of the containers is limited
+    pub(crate) fn new(configure: &Configure) -> Option<Self> {
+        let bytes = configure.max_bytes_per_peer();
+        let containers = configure.max_containers_per_peer();
+        if bytes.is_none() && containers.is_none() {
+            return None;
+        }
+
+        Some(Self {
+            bytes: bytes.unwrap_or(usize::MAX),
+            containers: containers.unwrap_or(usize::MAX),
+        })
+    }
+}
+
+impl Usage {
+    /// Count the atoms of `ops` that haven't been counted in the order of their counters.
+    ///
+    /// Stop at the first atom beyond `limits` and return its counter. The counting also
+    /// stops at a gap in the counters.
+    pub(crate) fn count<'a>(
+        &mut self,
+        ops: impl IntoIterator<Item = &'a Op>,
+        arena: &SharedArena,
+        limits: &UsageLimits,
+    ) -> Option<Counter> {
+        self.count_with_unit(ops, arena, limits).map(|x| x.0)
+    }
+
+    /// Like [Usage::count], and return what the exceeded limit counts too
+    fn count_with_unit<'a>(
+        &mut self,
+        ops: impl IntoIterator<Item = &'a Op>,
+        arena: &SharedArena,
+        limits: &UsageLimits,
+    ) -> Option<(Counter, &'static str)> {
+        for op in ops {
+            let end = op.counter + op.atom_len() as Counter;
+            if end <= self.end {
+                continue;
+            }
+
+            if op.counter > self.end {
+                return None;
+            }
+
+            let from = (self.end - op.counter) as usize;
+            let mut index = from;
+            let mut exceeded = None;
+            visit_atom_usage(&op.content, arena, from, &mut |bytes, containers| {
+                let bytes = self.bytes.saturating_add(bytes);
+                let containers = self.containers + containers;
+                if containers > limits.containers {
+                    exceeded = Some("containers");
+                } else if bytes > limits.bytes {
+                    exceeded = Some("bytes");
+                } else {
+                    self.bytes = bytes;
+                    self.containers = containers;
+                    index += 1;
+                }
+
+                exceeded.is_none()
+            });
+
+            if let Some(unit) = exceeded {
+                self.end = op.counter + index as Counter;
+                return Some((self.end, unit));
+            }
+
+            self.end = end;
+        }
+
+        None
+    }
+
+    /// The error if the local `op` would take the usage of `peer` beyond `limits`. The
+    /// usage is not changed.
+    pub(crate) fn check_local_op(
+        &self,
+        op: &Op,
+        arena: &SharedArena,
+        limits: &UsageLimits,
+        peer: PeerID,
+    ) -> LoroResult<()> {
+        let mut after = Usage {
+            end: op.counter,
+            ..*self
+        };
+        match after.count_with_unit([op], arena, limits) {
+            Some((_, unit)) => Err(LoroError::OpQuotaExceeded {
+                peer,
+                limit: if unit == "bytes" {
+                    limits.bytes
+                } else {
+                    limits.containers
+                },
+                unit,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Add the usage of the local `op` that has been checked by [Usage::check_local_op]
+    pub(crate) fn add_local_op(&mut self, op: &Op, arena: &SharedArena, limits: &UsageLimits) {
+        self.end = op.counter;
+        self.count([op], arena, limits);
+    }
+}
+
+/// Visit the size and the number of the created containers of each atom of `content` from
+/// the `from`-th one, until `f` returns false.
+///
+/// The size of an atom is the byte length of its text, keys, values and tree position.
+/// The size of a value is the byte length of its strings and binaries, plus 8 bytes for
+/// each number and 1 byte for each of the other values. The deletions, the moves and the
+/// ends of the styles have no size. The containers are the child containers in the
+/// inserted values and the metadata maps of the created tree nodes.
+fn visit_atom_usage(
+    content: &InnerContent,
+    arena: &SharedArena,
+    from: usize,
+    f: &mut dyn FnMut(usize, usize) -> bool,
+) {
+    let single = match content {
+        InnerContent::List(InnerListOp::InsertText { slice, .. }) => {
+            let text = std::str::from_utf8(slice).unwrap_or_default();
+            for c in text.chars().skip(from) {
+                if !f(c.len_utf8(), 0) {
+                    return;
+                }
+            }
+            return;
+        }
+        InnerContent::List(InnerListOp::Insert { slice, .. }) => {
+            for value in arena.iter_value_slice(slice.to_range()).skip(from) {
+                if !f(value_size(&value), value.is_container() as usize) {
+                    return;
+                }
+            }
+            return;
+        }
+        InnerContent::List(InnerListOp::Set { value, .. }) => {
+            (value_size(value), value.is_container() as usize)
+        }
+        InnerContent::List(InnerListOp::StyleStart { key, value, .. }) => {
+            (key.len() + value_size(value), 0)
+        }
+        InnerContent::Map(set) => (
+            set.key.len() + set.value.as_ref().map_or(0, value_size),
+            set.value.as_ref().is_some_and(|x| x.is_container()) as usize,
+        ),
+        InnerContent::Tree(TreeOp::Create { position, .. }) => (position.as_bytes().len(), 1),
+        InnerContent::Tree(TreeOp::Move { position, .. }) => (position.as_bytes().len(), 0),
+        #[cfg(feature = "counter")]
+        InnerContent::Future(crate::op::FutureInnerContent::Counter(_)) => (8, 0),
+        _ => return,
+    };
+
+    if from == 0 {
+        f(single.0, single.1);
+    }
+}
+
+fn value_size(value: &LoroValue) -> usize {
+    match value {
+        LoroValue::I64(_) | LoroValue::Double(_) => 8,
+        LoroValue::String(s) => s.len(),
+        LoroValue::Binary(b) => b.len(),
+        LoroValue::List(list) => list.iter().map(value_size).sum(),
+        LoroValue::Map(map) => map.iter().map(|(k, v)| k.len() + value_size(v)).sum(),
+        LoroValue::Null | LoroValue::Bool(_) | LoroValue::Container(_) => 1,
+    }
+}
+
+impl OpLog {
+    fn first_rejected_counter(&self, peer: PeerID) -> Counter {
+        let limit = match self.configure.max_ops_per_peer() {
+            Some(limit) => limit.min(Counter::MAX as usize) as Counter,
+            None => Counter::MAX,
+        };
+        match self.rejections.first_rejected.get(&peer) {
+            Some(&first) => first.min(limit),
+            None => limit,
+        }
+    }
+
+    fn is_rejected(&self, id: ID) -> bool {
+        id.counter >= self.first_rejected_counter(id.peer)
+    }
+
+    fn set_first_rejected(&mut self, peer: PeerID, counter: Counter) {
+        let first = self
+            .rejections
+            .first_rejected
+            .entry(peer)
+            .or_insert(counter);
+        *first = (*first).min(counter);
+    }
+
+    /// The rejection markers sorted by peer, to be saved in the snapshot
+    pub(crate) fn rejection_markers(&self) -> Vec<(PeerID, Counter)> {
+        let mut ans: Vec<_> = self
+            .rejections
+            .first_rejected
+            .iter()
+            .map(|(&peer, &counter)| (peer, counter))
+            .collect();
+        ans.sort_unstable();
+        ans
+    }
+
+    /// Merge the rejection markers loaded from a snapshot
+    pub(crate) fn merge_rejection_markers(&mut self, markers: &[(PeerID, Counter)]) {
+        for &(peer, counter) in markers {
+            self.set_first_rejected(peer, counter);
+        }
+    }
+
+    /// The usage of `peer` with all its ops in the oplog counted
+    pub(crate) fn peer_usage(&mut self, peer: PeerID, limits: &UsageLimits) -> Usage {
+        let mut usage = self
+            .rejections
+            .usage
+            .get(&peer)
+            .copied()
+            .unwrap_or_default();
+        let end = self.vv().get(&peer).copied().unwrap_or(0);
+        if usage.end >= end {
+            return usage;
+        }
+
+        let mut exceeded = None;
+        if let Some(changes) = self.changes.get(&peer) {
+            let start = changes.partition_point(|c| c.ctr_end() <= usage.end);
+            for change in &changes[start..] {
+                exceeded = usage.count(change.ops.iter(), &self.arena, limits);
+                if exceeded.is_some() {
+                    break;
+                }
+            }
+        }
+
+        if let Some(counter) = exceeded {
+            self.set_first_rejected(peer, counter);
+        }
+        self.rejections.usage.insert(peer, usage);
+        usage
+    }
+
+    /// Count the usage of the ops in `changes` and set the markers of the peers that go
+    /// beyond the limits
+    fn count_usage(&mut self, changes: &[&Change], limits: &UsageLimits) {
+        let mut by_peer: FxHashMap<PeerID, Vec<&Change>> = FxHashMap::default();
+        for &change in changes {
+            by_peer.entry(change.id.peer).or_default().push(change);
+        }
+
+        for (peer, mut changes) in by_peer {
+            changes.sort_by_key(|c| c.id.counter);
+            let mut usage = self.peer_usage(peer, limits);
+            for change in changes {
+                if change.id.counter > usage.end || usage.end >= self.first_rejected_counter(peer) {
+                    break;
+                }
+
+                if let Some(counter) = usage.count(change.ops.iter(), &self.arena, limits) {
+                    self.set_first_rejected(peer, counter);
+                    break;
+                }
+            }
+
+            self.rejections.usage.insert(peer, usage);
+        }
+    }
+
+    /// Remove the rejected ops from the imported `changes` and from the pending changes,
+    /// and record them to be reported by [OpLog::take_rejected_ops].
+    pub(crate) fn remove_rejected_ops(&mut self, changes: Vec<Change>) -> Vec<Change> {
+        if !self.configure.has_peer_quota() && self.rejections.first_rejected.is_empty() {
+            return changes;
+        }
+
+        let mut pending = take(&mut self.pending_changes);
+        let all: Vec<&Change> = changes.iter().chain(pending.iter()).collect();
+        if let Some(limits) = UsageLimits::new(&self.configure) {
+            self.count_usage(&all, &limits);
+        }
+
+        // The changes are not in causal order, so a change may come before the rejected
+        // change it depends on
+        loop {
+            let mut updated = false;
+            for change in all.iter() {
+                let rejected_dep = change.deps.iter().any(|&x| self.is_rejected(x));
+                if rejected_dep && !self.is_rejected(change.id) {
+                    self.rejections
+                        .first_rejected
+                        .insert(change.id.peer, change.id.counter);
+                    updated = true;
+                }
+            }
+
+            if !updated {
+                break;
+            }
+        }
+
+        pending.filter_map(|change| self.remove_rejected_part(change));
+        self.pending_changes = pending;
+        changes
+            .into_iter()
+            .filter_map(|change| self.remove_rejected_part(change))
+            .collect()
+    }
+
+    fn remove_rejected_part(&mut self, change: Change) -> Option<Change> {
+        let peer = change.id.peer;
+        let first = self.first_rejected_counter(peer);
+        if change.ctr_end() <= first {
+            return Some(change);
+        }
+
+        self.rejections.first_rejected.insert(peer, first);
+        self.rejections.unreported.push(IdSpan::new(
+            peer,
+            change.id.counter.max(first),
+            change.ctr_end(),
+        ));
+        if change.id.counter < first {
+            Some(change.slice(0, (first - change.id.counter) as usize))
+        } else {
+            None
+        }
+    }
+
+    /// Take the ops rejected since the last call
+    pub(crate) fn take_rejected_ops(&mut self) -> Vec<IdSpan> {
+        take(&mut self.rejections.unreported)
+    }
+}
@@ -0,0 +1,143 @@
+//! A queue of the local updates that haven't been acknowledged by the server yet.
+//!
+//! An offline-first app exports its updates while it's disconnected, sends them when it
+//! can, and has to resend whatever the server didn't receive. [OutboundQueue] keeps the
+//! bookkeeping in terms of versions instead of messages, so it stays correct when the acks
+//! are lost, arrive out of order or overlap:
+//!
+//! - [OutboundQueue::enqueue] exports the ops that are neither acknowledged nor queued.
+//! - [OutboundQueue::ack] records a version the server has, and drops the updates it
+//!   covers.
+//! - [OutboundQueue::reconnect] replaces the queue with a single update from the version
+//!   the server reports, which is the minimal set of ops to resubmit.
+use std::collections::VecDeque;
+
+use loro_common::{LoroError, LoroResult};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    version::{Frontiers, VersionVector},
+    LoroDoc,
+};
+
+/// The queued updates and the version acknowledged by the server.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OutboundQueue {
+    /// A version the server is known to have
+    acked: VersionVector,
+    updates: VecDeque<QueuedUpdate>,
+}
+
+/// An update exported by [OutboundQueue::enqueue].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueuedUpdate {
+    /// The version the update starts from. The server can only apply the update if it has
+    /// this version, otherwise the ops are pending until the missing ones arrive.
+    pub from: VersionVector,
+    /// The frontiers of `from`
+    pub deps: Frontiers,
+    /// The version of the doc after the update
+    pub to: VersionVector,
+    /// The update, as exported by [LoroDoc::export_from]
+    pub bytes: Vec<u8>,
+}
+
+impl OutboundQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create an empty queue for a doc that the server already has up to `acked`.
+    pub fn with_acked(acked: VersionVector) -> Self {
+        Self {
+            acked,
+            updates: VecDeque::new(),
+        }
+    }
+
+    /// The version the server is known to have
+    pub fn acked(&self) -> &VersionVector {
+        &self.acked
+    }
+
+    /// The updates to send or resend, in the order they were exported
+    pub fn pending(&self) -> impl Iterator<Item = &QueuedUpdate> {
+        self.updates.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.updates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.updates.is_empty()
+    }
+
+    /// The version covered by the acks and the queued updates
+    fn covered(&self) -> VersionVector {
+        let mut ans = self.acked.clone();
+        if let Some(last) = self.updates.back() {
+            ans.merge(&last.to);
+        }
+        ans
+    }
+
+    /// Export the ops of `doc` that are neither acknowledged nor queued, and queue them.
+    ///
+    /// The pending transaction is committed first. Returns `None` if there is nothing new.
+    pub fn enqueue(&mut self, doc: &LoroDoc) -> Option<&QueuedUpdate> {
+        doc.commit_then_renew();
+        let covered = self.covered();
+        let to = doc.oplog_vv();
+        if covered.includes_vv(&to) {
+            return None;
+        }
+
+        // `covered` may include ops the doc hasn't imported, they're not deps of the update
+        let from = covered.intersection(&to);
+        let deps = doc.vv_to_frontiers(&from);
+        let bytes = doc.export_from(&covered);
+        self.updates.push_back(QueuedUpdate {
+            from,
+            deps,
+            to,
+            bytes,
+        });
+        self.updates.back()
+    }
+
+    /// Record that the server has `version`, and drop the updates it covers.
+    ///
+    /// The acks are merged, so they can be applied in any order and can overlap. An update
+    /// that is only partly covered stays in the queue.
+    pub fn ack(&mut self, version: &VersionVector) {
+        self.acked.merge(version);
+        let acked = &self.acked;
+        self.updates.retain(|u| !acked.includes_vv(&u.to));
+    }
+
+    /// Replace the queue with the minimal update for a server that reports `server_version`
+    /// after a reconnect, and return it.
+    ///
+    /// The reported version replaces the acknowledged one, even if it's older, because the
+    /// server may have lost the ops it acknowledged before, e.g. if it was restored from a
+    /// backup. Returns `None` if the server has all the ops of `doc`.
+    pub fn reconnect(
+        &mut self,
+        doc: &LoroDoc,
+        server_version: &VersionVector,
+    ) -> Option<&QueuedUpdate> {
+        self.acked = server_version.clone();
+        self.updates.clear();
+        self.enqueue(doc)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        postcard::to_allocvec(self).unwrap()
+    }
+
+    pub fn decode(bytes: &[u8]) -> LoroResult<Self> {
+        postcard::from_bytes(bytes)
+            .map_err(|_| LoroError::DecodeError("Invalid outbound queue".into()))
+    }
+}
@@ -21,6 +21,7 @@ impl OpLog {
     pub(super) fn register_container_and_parent_link(&self, change: &Change) {
         let arena = &self.arena;
         for op in change.ops.iter() {
+            arena.mark_written(op.container);
             op.content.visit_created_children(arena, &mut |c| {
                 let idx = arena.register_container(c);
                 arena.set_parent(idx, Some(op.container));
@@ -33,6 +33,7 @@ mod list_state;
 mod map_state;
 mod movable_list_state;
 mod richtext_state;
+pub(crate) mod state_hash;
 mod tree_state;
 mod unknown_state;
 
@@ -44,7 +45,7 @@ pub(crate) use tree_state::{
     get_meta_value, FractionalIndexGenResult, NodePosition, TreeParentId, TreeState,
 };
 
-use self::unknown_state::UnknownState;
+use self::{state_hash::StateHashes, unknown_state::UnknownState};
 
 use super::{arena::SharedArena, event::InternalDocDiff};
 
@@ -68,10 +69,14 @@ pub struct DocState {
     pub(crate) config: Configure,
     // resolve event stuff
     weak_state: Weak<Mutex<DocState>>,
+    /// The oplog of the doc, for reading the timestamps of the changes.
+    /// Never lock it while the state is locked.
+    pub(super) oplog: Weak<Mutex<OpLog>>,
     global_txn: Weak<Mutex<Option<Transaction>>>,
     // txn related stuff
     in_txn: bool,
     changed_idx_in_txn: FxHashSet<ContainerIdx>,
+    hashes: StateHashes,
 
     // diff related stuff
     event_recorder: EventRecorder,
@@ -263,6 +268,7 @@ impl DocState {
     #[inline]
     pub fn new_arc(
         arena: SharedArena,
+        oplog: Weak<Mutex<OpLog>>,
         global_txn: Weak<Mutex<Option<Transaction>>>,
         config: Configure,
     ) -> Arc<Mutex<Self>> {
@@ -275,10 +281,12 @@ impl DocState {
                 frontiers: Frontiers::default(),
                 states: FxHashMap::default(),
                 weak_state: weak.clone(),
+                oplog,
                 config,
                 global_txn,
                 in_txn: false,
                 changed_idx_in_txn: FxHashSet::default(),
+                hashes: Default::default(),
                 event_recorder: Default::default(),
             })
         })
@@ -287,6 +295,7 @@ impl DocState {
     pub fn fork(
         &self,
         arena: SharedArena,
+        oplog: Weak<Mutex<OpLog>>,
         global_txn: Weak<Mutex<Option<Transaction>>>,
         config: Configure,
     ) -> Arc<Mutex<Self>> {
@@ -298,9 +307,11 @@ impl DocState {
                 arena,
                 config,
                 weak_state: weak.clone(),
+                oplog,
                 global_txn,
                 in_txn: false,
                 changed_idx_in_txn: FxHashSet::default(),
+                hashes: self.hashes.clone(),
                 event_recorder: Default::default(),
             })
         })
@@ -549,6 +560,7 @@ impl DocState {
                             &self.weak_state,
                         );
                     }
+                    self.update_hash(idx);
                 }
                 crate::event::DiffVariant::External(_) => unreachable!(),
             }
@@ -610,7 +622,12 @@ impl DocState {
         if self.in_txn {
             self.changed_idx_in_txn.insert(op.container);
         }
-        state.apply_local_op(raw_op, op)
+        let ans = state.apply_local_op(raw_op, op);
+        self.update_hash(op.container);
+        ans?;
+        // only a container with an applied op is written, a failed op leaves it as it was
+        self.arena.mark_written(op.container);
+        Ok(())
     }
 
     pub(crate) fn start_txn(&mut self, origin: InternalString, trigger: EventTriggerKind) {
@@ -622,6 +639,47 @@ impl DocState {
         self.in_txn = false;
     }
 
+    /// Roll back the local ops of the txn in `containers`, by replacing their states with
+    /// `diff`, the diff from the empty version to the current version. No event is recorded.
+    pub(crate) fn rollback_txn(
+        &mut self,
+        containers: &FxHashSet<ContainerIdx>,
+        diff: Vec<InternalContainerDiff>,
+    ) {
+        self.in_txn = false;
+        for &idx in containers {
+            self.states.remove(&idx);
+            self.update_hash(idx);
+        }
+
+        let recording = std::mem::replace(&mut self.event_recorder.recording_diff, false);
+        let frontiers = self.frontiers.clone();
+        self.apply_diff(InternalDocDiff {
+            origin: Default::default(),
+            by: EventTriggerKind::Local,
+            diff: Cow::Owned(diff),
+            new_version: Cow::Owned(frontiers),
+        });
+        self.event_recorder.recording_diff = recording;
+    }
+
+    /// Put back the state of `idx` from before a failed edit, see
+    /// [Transaction::atomic_edit](crate::txn::Transaction::atomic_edit).
+    pub(crate) fn restore_state(&mut self, idx: ContainerIdx, state: Option<State>, written: bool) {
+        match state {
+            Some(state) => {
+                self.states.insert(idx, state);
+            }
+            None => {
+                self.states.remove(&idx);
+            }
+        }
+        if !written {
+            self.arena.unmark_written(idx);
+        }
+        self.update_hash(idx);
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = &State> {
         self.states.values()
     }
@@ -637,7 +695,9 @@ impl DocState {
     ) -> LoroResult<()> {
         let idx = self.arena.register_container(&cid);
         let state = get_or_create!(self, idx);
-        state.import_from_snapshot_ops(decode_ctx)
+        let ans = state.import_from_snapshot_ops(decode_ctx);
+        self.update_hash(idx);
+        ans
     }
 
     pub(crate) fn init_unknown_container(&mut self, cid: ContainerID) {
@@ -688,6 +748,10 @@ impl DocState {
         assert!(self.states.is_empty(), "overriding states");
         self.pre_txn(Default::default(), EventTriggerKind::Import);
         self.states = states;
+        let idxs: Vec<ContainerIdx> = self.states.keys().copied().collect();
+        for idx in idxs {
+            self.update_hash(idx);
+        }
         for (idx, state) in self.states.iter() {
             for child_id in state.get_child_containers() {
                 let child_idx = self.arena.register_container(&child_id);
@@ -810,44 +874,146 @@ impl DocState {
     }
 
     pub fn get_deep_value(&mut self) -> LoroValue {
-        let roots = self.arena.root_containers();
+        let roots = self.root_keys();
         let mut ans = FxHashMap::with_capacity_and_hasher(roots.len(), Default::default());
-        for root_idx in roots {
-            let id = self.arena.idx_to_id(root_idx).unwrap();
-            match id {
-                loro_common::ContainerID::Root { name, .. } => {
-                    ans.insert(name.to_string(), self.get_container_deep_value(root_idx));
-                }
-                loro_common::ContainerID::Normal { .. } => {
-                    unreachable!()
-                }
-            }
+        for (key, root_idx, _) in roots {
+            ans.insert(key, self.get_container_deep_value(root_idx));
+        }
+
+        LoroValue::Map(Arc::new(ans))
+    }
+
+    /// Get the deep value like [DocState::get_deep_value], but with each text as its rich
+    /// text delta, i.e. a list of `{"insert": .., "attributes": ..}`, so the styles are kept.
+    pub fn get_deep_value_with_richtext(&mut self) -> LoroValue {
+        let roots = self.root_keys();
+        let mut ans = FxHashMap::with_capacity_and_hasher(roots.len(), Default::default());
+        for (key, root_idx, _) in roots {
+            ans.insert(key, self.get_container_deep_value_as(root_idx, true));
         }
 
         LoroValue::Map(Arc::new(ans))
     }
 
     pub fn get_deep_value_with_id(&mut self) -> LoroValue {
-        let roots = self.arena.root_containers();
+        let roots = self.root_keys();
         let mut ans = FxHashMap::with_capacity_and_hasher(roots.len(), Default::default());
-        for root_idx in roots {
-            let id = self.arena.idx_to_id(root_idx).unwrap();
-            match id.clone() {
-                loro_common::ContainerID::Root { name, .. } => {
-                    ans.insert(
-                        name.to_string(),
-                        self.get_container_deep_value_with_id(root_idx, Some(id)),
+        for (key, root_idx, id) in roots {
+            ans.insert(
+                key,
+                self.get_container_deep_value_with_id(root_idx, Some(id)),
+            );
+        }
+
+        LoroValue::Map(Arc::new(ans))
+    }
+
+    /// The keys of the roots in [DocState::get_deep_value].
+    ///
+    /// Roots of different types can have the same name, e.g. if two peers create them
+    /// concurrently. Then the non-empty root with the smallest type id, see
+    /// [ContainerType::to_u8], gets the name. The other non-empty ones are in
+    /// [DocState::get_shadowed_roots_value] instead, so that they can't collide with the
+    /// name of another root. It only depends on the state, so all the peers at the same
+    /// version agree on it.
+    fn root_keys(&self) -> Vec<(String, ContainerIdx, ContainerID)> {
+        self.roots_by_name()
+            .into_iter()
+            .map(|(name, mut roots)| {
+                let (_, idx, id) = roots.swap_remove(0);
+                (name.to_string(), idx, id)
+            })
+            .collect()
+    }
+
+    /// The deep values of the non-empty roots that share their name with a root of another
+    /// type that has precedence, see [DocState::get_deep_value]. It's a map from the name to
+    /// a map from the type to the value, e.g. `{"data": {"List": [1]}}`.
+    pub fn get_shadowed_roots_value(&mut self) -> LoroValue {
+        let mut ans = FxHashMap::default();
+        for (name, roots) in self.roots_by_name() {
+            let mut shadowed = FxHashMap::default();
+            for (is_empty, idx, id) in roots.into_iter().skip(1) {
+                if !is_empty {
+                    shadowed.insert(
+                        id.container_type().to_string(),
+                        self.get_container_deep_value(idx),
                     );
                 }
-                loro_common::ContainerID::Normal { .. } => {
-                    unreachable!()
-                }
+            }
+
+            if !shadowed.is_empty() {
+                ans.insert(name.to_string(), LoroValue::Map(Arc::new(shadowed)));
             }
         }
 
         LoroValue::Map(Arc::new(ans))
     }
 
+    /// The non-empty types of the root `idx` and the roots with its name, if there are
+    /// several, see [DocState::root_type_conflicts].
+    pub(crate) fn root_type_conflict(
+        &self,
+        idx: ContainerIdx,
+    ) -> Option<(InternalString, Vec<ContainerType>)> {
+        let ContainerID::Root { name, .. } = self.arena.idx_to_id(idx)? else {
+            return None;
+        };
+        self.root_type_conflicts()
+            .into_iter()
+            .find(|(x, _)| *x == name)
+    }
+
+    /// The names shared by non-empty roots of different types, with the types in the order
+    /// of precedence. See [DocState::get_deep_value].
+    pub fn root_type_conflicts(&self) -> Vec<(InternalString, Vec<ContainerType>)> {
+        self.roots_by_name()
+            .into_iter()
+            .filter_map(|(name, roots)| {
+                let types: Vec<ContainerType> = roots
+                    .iter()
+                    .filter(|(is_empty, ..)| !is_empty)
+                    .map(|(_, _, id)| id.container_type())
+                    .collect();
+                (types.len() > 1).then_some((name, types))
+            })
+            .collect()
+    }
+
+    /// The visible roots grouped by name, in the order of precedence: the non-empty ones
+    /// first, then by type id. A root is visible once it's written or has a non-empty state.
+    fn roots_by_name(&self) -> FxHashMap<InternalString, Vec<(bool, ContainerIdx, ContainerID)>> {
+        let mut ans: FxHashMap<InternalString, Vec<_>> = FxHashMap::default();
+        for idx in self.arena.root_containers() {
+            let id = self.arena.idx_to_id(idx).unwrap();
+            let ContainerID::Root { name, .. } = &id else {
+                unreachable!()
+            };
+            if crate::loro::is_system_root(name) {
+                continue;
+            }
+
+            let is_empty = match self.states.get(&idx) {
+                Some(state) => state.is_state_empty(),
+                None => true,
+            };
+            // Getting a root doesn't create it, e.g. in a doc opened read-only
+            if is_empty && !self.arena.is_written(idx) {
+                continue;
+            }
+
+            ans.entry(name.clone())
+                .or_default()
+                .push((is_empty, idx, id));
+        }
+
+        for roots in ans.values_mut() {
+            roots.sort_by_key(|(is_empty, _, id)| (*is_empty, id.container_type().to_u8()));
+        }
+
+        ans
+    }
+
     pub(crate) fn get_container_deep_value_with_id(
         &mut self,
         container: ContainerIdx,
@@ -915,10 +1081,63 @@ impl DocState {
     }
 
     pub fn get_container_deep_value(&mut self, container: ContainerIdx) -> LoroValue {
+        self.get_container_deep_value_as(container, false)
+    }
+
+    /// Get the deep value of `container`, with the texts as rich text deltas if `richtext`
+    pub(crate) fn get_container_deep_value_as(
+        &mut self,
+        container: ContainerIdx,
+        richtext: bool,
+    ) -> LoroValue {
         let Some(state) = self.states.get_mut(&container) else {
+            if richtext && container.get_type() == ContainerType::Text {
+                return LoroValue::List(Default::default());
+            }
+
             return container.get_type().default_value();
         };
-        let value = state.get_value();
+        let value = match state {
+            State::RichtextState(s) if richtext => s.get_richtext_value(),
+            state => state.get_value(),
+        };
+        self.resolve_deep_value(container, value, richtext)
+    }
+
+    /// Get the deep values of `containers`, materializing their states in parallel.
+    ///
+    /// Each state is only borrowed by one thread. The child containers are resolved on the
+    /// current thread afterwards, so it pays off when there are many large containers.
+    #[cfg(feature = "parallel")]
+    pub fn par_get_container_deep_values(&mut self, containers: &[ContainerIdx]) -> Vec<LoroValue> {
+        use rayon::prelude::*;
+        let wanted: FxHashSet<ContainerIdx> = containers.iter().copied().collect();
+        let states: Vec<(&ContainerIdx, &mut State)> = self
+            .states
+            .iter_mut()
+            .filter(|(idx, _)| wanted.contains(idx))
+            .collect();
+        let mut values: FxHashMap<ContainerIdx, LoroValue> = states
+            .into_par_iter()
+            .map(|(idx, state)| (*idx, state.get_value()))
+            .collect();
+        containers
+            .iter()
+            .map(|&idx| match values.remove(&idx) {
+                Some(value) => self.resolve_deep_value(idx, value, false),
+                // The container is requested twice or has no state
+                None => self.get_container_deep_value(idx),
+            })
+            .collect()
+    }
+
+    /// Replace the child containers in the shallow `value` of `container` with their deep values
+    fn resolve_deep_value(
+        &mut self,
+        container: ContainerIdx,
+        value: LoroValue,
+        richtext: bool,
+    ) -> LoroValue {
         match value {
             LoroValue::Container(_) => unreachable!(),
             LoroValue::List(mut list) => {
@@ -927,7 +1146,7 @@ impl DocState {
                     // the metadata of this node. When the user get the deep value,
                     // we need to add a field named `meta` to the tree node,
                     // whose value is deep value of map container.
-                    get_meta_value(Arc::make_mut(&mut list), self);
+                    get_meta_value(Arc::make_mut(&mut list), self, richtext);
                 } else {
                     if list.iter().all(|x| !x.is_container()) {
                         return LoroValue::List(list);
@@ -938,7 +1157,7 @@ impl DocState {
                         if item.is_container() {
                             let container = item.as_container().unwrap();
                             let container_idx = self.arena.register_container(container);
-                            let value = self.get_container_deep_value(container_idx);
+                            let value = self.get_container_deep_value_as(container_idx, richtext);
                             *item = value;
                         }
                     }
@@ -955,7 +1174,7 @@ impl DocState {
                     if value.is_container() {
                         let container = value.as_container().unwrap();
                         let container_idx = self.arena.register_container(container);
-                        let new_value = self.get_container_deep_value(container_idx);
+                        let new_value = self.get_container_deep_value_as(container_idx, richtext);
                         *value = new_value;
                     }
                 }
@@ -1060,7 +1279,7 @@ impl DocState {
     }
 
     // the container may be override, so it may return None
-    fn get_path(&self, idx: ContainerIdx) -> Option<Vec<(ContainerID, Index)>> {
+    pub(crate) fn get_path(&self, idx: ContainerIdx) -> Option<Vec<(ContainerID, Index)>> {
         let mut ans = Vec::new();
         let mut idx = idx;
         loop {
@@ -1330,6 +1549,17 @@ impl DocState {
 
         Some(value)
     }
+
+    /// Get the value by the path. If it's a container, return its deep value.
+    pub fn get_deep_value_by_path(&mut self, path: &[Index]) -> Option<LoroValue> {
+        match self.get_value_by_path(path)? {
+            LoroValue::Container(c) => {
+                let idx = self.arena.register_container(&c);
+                Some(self.get_container_deep_value(idx))
+            }
+            value => Some(value),
+        }
+    }
 }
 
 fn trigger_on_new_container(
@@ -12,7 +12,7 @@ use crate::{
     DocState,
 };
 
-use super::ContainerState;
+use super::{state_hash::elem_hash, ContainerState};
 
 #[derive(Debug, Clone)]
 pub struct CounterState {
@@ -24,6 +24,14 @@ impl CounterState {
     pub(crate) fn new(idx: ContainerIdx) -> Self {
         Self { idx, value: 0. }
     }
+
+    pub(crate) fn state_hash(&self) -> u64 {
+        if self.value == 0. {
+            return 0;
+        }
+
+        elem_hash(|ctx| ctx.consume(self.value.to_bits().to_le_bytes()))
+    }
 }
 
 impl ContainerState for CounterState {
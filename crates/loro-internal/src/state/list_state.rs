@@ -3,7 +3,10 @@ use std::{
     sync::{Arc, Mutex, Weak},
 };
 
-use super::ContainerState;
+use super::{
+    state_hash::{id_span_hash, SetHash},
+    ContainerState,
+};
 use crate::{
     arena::SharedArena,
     container::{idx::ContainerIdx, list::list_op::ListOp, ContainerID},
@@ -29,6 +32,8 @@ pub struct ListState {
     idx: ContainerIdx,
     list: BTree<ListImpl>,
     child_container_to_leaf: FxHashMap<ContainerID, LeafIndex>,
+    /// The hash of the ids of the elements
+    hash: SetHash,
 }
 
 impl Clone for ListState {
@@ -37,6 +42,7 @@ impl Clone for ListState {
             idx: self.idx,
             list: self.list.clone(),
             child_container_to_leaf: Default::default(),
+            hash: self.hash,
         }
     }
 }
@@ -151,6 +157,7 @@ impl ListState {
             idx,
             list: tree,
             child_container_to_leaf: Default::default(),
+            hash: SetHash::default(),
         }
     }
 
@@ -185,6 +192,7 @@ impl ListState {
             panic!("Index {index} out of range. The length is {}", self.len());
         }
 
+        self.hash.insert(id_span_hash(id.idlp().into()));
         if self.list.is_empty() {
             let idx = self.list.push(Elem {
                 v: value.clone(),
@@ -223,6 +231,7 @@ impl ListState {
     pub fn delete(&mut self, index: usize) {
         let leaf = self.list.query::<LengthFinder>(&index);
         let leaf = self.list.remove_leaf(leaf.unwrap().cursor).unwrap();
+        self.hash.remove(id_span_hash(leaf.id.idlp().into()));
         if leaf.v.is_container() {
             self.child_container_to_leaf
                 .remove(leaf.v.as_container().unwrap());
@@ -250,6 +259,7 @@ impl ListState {
         let start1 = list.query::<LengthFinder>(&q.start);
         let end1 = list.query::<LengthFinder>(&q.end);
         for v in iter::Drain::new(list, start1, end1) {
+            self.hash.remove(id_span_hash(v.id.idlp().into()));
             if v.v.is_container() {
                 self.child_container_to_leaf
                     .remove(v.v.as_container().unwrap());
@@ -280,6 +290,10 @@ impl ListState {
         *self.list.root_cache() as usize
     }
 
+    pub(crate) fn state_hash(&self) -> u64 {
+        self.hash.get()
+    }
+
     fn to_vec(&self) -> Vec<LoroValue> {
         let mut ans = Vec::with_capacity(self.len());
         for value in self.list.iter() {
@@ -19,13 +19,18 @@ use crate::{
     DocState, InternalString, LoroValue,
 };
 
-use super::ContainerState;
+use super::{
+    state_hash::{elem_hash, write_bytes, write_value, SetHash},
+    ContainerState,
+};
 
 #[derive(Debug, Clone)]
 pub struct MapState {
     idx: ContainerIdx,
     map: FxHashMap<InternalString, MapValue>,
     size: usize,
+    /// The hash of the entries that have a value
+    hash: SetHash,
 }
 
 impl ContainerState for MapState {
@@ -203,12 +208,19 @@ impl MapState {
             idx,
             map: FxHashMap::default(),
             size: 0,
+            hash: SetHash::default(),
         }
     }
 
     pub fn insert(&mut self, key: InternalString, value: MapValue) {
         let value_yes = value.value.is_some();
+        if let Some(v) = &value.value {
+            self.hash.insert(entry_hash(&key, v));
+        }
         let result = self.map.insert(key.clone(), value);
+        if let Some(v) = result.as_ref().and_then(|x| x.value.as_ref()) {
+            self.hash.remove(entry_hash(&key, v));
+        }
         match (result, value_yes) {
             (Some(x), true) => {
                 if let None = x.value {
@@ -235,6 +247,10 @@ impl MapState {
         self.size
     }
 
+    pub(crate) fn state_hash(&self) -> u64 {
+        self.hash.get()
+    }
+
     fn to_map(
         &self,
     ) -> std::collections::HashMap<String, LoroValue, std::hash::BuildHasherDefault<fxhash::FxHasher>>
@@ -250,6 +266,11 @@ impl MapState {
         ans
     }
 
+    /// Get the entry at `k`, including the id of its last write. It may be a deleted entry.
+    pub fn get_entry(&self, k: &str) -> Option<&MapValue> {
+        self.map.get(&k.into())
+    }
+
     pub fn get(&self, k: &str) -> Option<&LoroValue> {
         match self.map.get(&k.into()) {
             Some(value) => match &value.value {
@@ -260,3 +281,10 @@ impl MapState {
         }
     }
 }
+
+fn entry_hash(key: &str, value: &LoroValue) -> u64 {
+    elem_hash(|ctx| {
+        write_bytes(ctx, 5, key.as_bytes());
+        write_value(ctx, value);
+    })
+}
@@ -286,6 +286,7 @@ mod list_item_tree {
 /// - list items' `pointed_by` must be consistent with the `elements`' `pos`.
 /// - `id_to_list_leaf` must be consistent with the list.
 /// - `child_container_to_elem` must be consistent with the element.
+/// - `hash` must cover exactly the elements pointed by the list items.
 mod inner {
     use fxhash::{FxHashMap, FxHashSet};
     use generic_btree::{BTree, Cursor, LeafIndex, Query};
@@ -296,6 +297,7 @@ mod inner {
         list_item_tree::{MovableListTreeTrait, OpLenQuery, UserLenQuery},
         Element, IndexType, ListItem,
     };
+    use crate::state::state_hash::{elem_hash, write_id, SetHash};
 
     #[derive(Debug, Clone)]
     pub(super) struct InnerState {
@@ -306,6 +308,19 @@ mod inner {
         /// But it's guaranteed that if there is a ContainerID in the actual list,
         /// it will be mapped correctly.
         child_container_to_elem: FxHashMap<ContainerID, CompactIdLp>,
+        /// The hash of the visible elements, see [visible_elem_hash]
+        hash: SetHash,
+    }
+
+    /// The hash of the element `elem_id` with the value set by `value_id`, which is shown at
+    /// the list item `pos`
+    fn visible_elem_hash(pos: IdLp, elem_id: CompactIdLp, value_id: IdLp) -> u64 {
+        let elem_id = elem_id.to_id();
+        elem_hash(|ctx| {
+            write_id(ctx, pos.peer, pos.lamport);
+            write_id(ctx, elem_id.peer, elem_id.lamport);
+            write_id(ctx, value_id.peer, value_id.lamport);
+        })
     }
 
     fn eq<T: PartialEq>(a: T, b: T) -> Result<(), ()> {
@@ -323,9 +338,24 @@ mod inner {
                 id_to_list_leaf: FxHashMap::default(),
                 elements: FxHashMap::default(),
                 child_container_to_elem: FxHashMap::default(),
+                hash: SetHash::default(),
             }
         }
 
+        #[inline]
+        pub fn hash(&self) -> u64 {
+            self.hash.get()
+        }
+
+        /// The hash of the element if a list item points to it
+        fn visible_hash(&self, elem_id: CompactIdLp) -> Option<u64> {
+            let elem = self.elements.get(&elem_id)?;
+            let leaf = self.id_to_list_leaf.get(&elem.pos)?;
+            let item = self.list.get_elem(*leaf)?;
+            (item.pointed_by == Some(elem_id))
+                .then(|| visible_elem_hash(elem.pos, elem_id, elem.value_id))
+        }
+
         #[inline]
         pub fn child_container_to_elem(&self) -> &FxHashMap<ContainerID, CompactIdLp> {
             &self.child_container_to_elem
@@ -342,6 +372,9 @@ mod inner {
         }
 
         pub fn remove_elem_by_id(&mut self, elem_id: &CompactIdLp) {
+            if let Some(hash) = self.visible_hash(*elem_id) {
+                self.hash.remove(hash);
+            }
             self.elements.remove(elem_id);
         }
 
@@ -511,6 +544,8 @@ mod inner {
                 self.id_to_list_leaf.remove(&item.id.idlp());
                 if let Some(elem_id) = &item.pointed_by {
                     let elem = self.elements.get(elem_id).unwrap();
+                    self.hash
+                        .remove(visible_elem_hash(item.id.idlp(), *elem_id, elem.value_id));
                     on_elem_id(*elem_id, elem);
                     self.elements.remove(elem_id);
                 }
@@ -561,6 +596,7 @@ mod inner {
                 );
             }
 
+            let value_id = self.elements.get(&elem_id).unwrap().value_id;
             let mut ans = UpdateResultFromPosChange {
                 activate_new_list_item: true,
                 new_list_item_leaf: None,
@@ -568,12 +604,22 @@ mod inner {
             };
             if let Some(leaf) = self.id_to_list_leaf.get(&new_pos) {
                 ans.new_list_item_leaf = Some(*leaf);
+                let mut replaced = None;
                 self.list.update_leaf(*leaf, |elem| {
                     ans.activate_new_list_item = elem.pointed_by.is_none();
                     debug_assert!(ans.activate_new_list_item);
-                    elem.pointed_by = Some(elem_id);
+                    replaced = elem.pointed_by.replace(elem_id);
                     (true, None, None)
                 });
+                if replaced != Some(elem_id) {
+                    if let Some(replaced) = replaced {
+                        let replaced_value_id = self.elements.get(&replaced).unwrap().value_id;
+                        self.hash
+                            .remove(visible_elem_hash(new_pos, replaced, replaced_value_id));
+                    }
+                    self.hash
+                        .insert(visible_elem_hash(new_pos, elem_id, value_id));
+                }
             } else {
                 ans.activate_new_list_item = false;
             }
@@ -587,14 +633,21 @@ mod inner {
                     let leaf = self.id_to_list_leaf.remove(&old).unwrap();
                     let elem = self.list.remove_leaf(Cursor { leaf, offset: 0 }).unwrap();
                     assert_eq!(elem.pointed_by, Some(elem_id));
+                    self.hash.remove(visible_elem_hash(old, elem_id, value_id));
                 } else if let Some(leaf) = self.id_to_list_leaf.get(&old) {
                     ans.removed_old_list_item_leaf = Some(*leaf);
+                    let mut unpointed = None;
                     let (still_valid, split) = self.list.update_leaf(*leaf, |item| {
-                        item.pointed_by = None;
+                        unpointed = item.pointed_by.take();
                         (true, None, None)
                     });
                     assert!(still_valid);
                     assert!(split.arr.is_empty());
+                    if let Some(unpointed) = unpointed {
+                        let value_id = self.elements.get(&unpointed).unwrap().value_id;
+                        self.hash
+                            .remove(visible_elem_hash(old, unpointed, value_id));
+                    }
                 }
             }
 
@@ -649,6 +702,12 @@ mod inner {
                 self.child_container_to_elem.insert(c.clone(), elem_id);
             }
 
+            if let Some(hash) = self.visible_hash(elem_id) {
+                let pos = self.elements.get(&elem_id).unwrap().pos;
+                self.hash.remove(hash);
+                self.hash.insert(visible_elem_hash(pos, elem_id, value_id));
+            }
+
             if let Some(element) = self.elements.get_mut(&elem_id) {
                 if let LoroValue::Container(c) = &element.value {
                     if element.value != new_value {
@@ -677,6 +736,11 @@ mod inner {
                     self.child_container_to_elem.insert(c.clone(), elem.elem_id);
                 }
                 debug_assert!(!elem.last_set_id.is_none());
+                self.hash.insert(visible_elem_hash(
+                    list_item_id.idlp(),
+                    elem.elem_id,
+                    elem.last_set_id,
+                ));
                 self.elements.insert(
                     elem.elem_id,
                     Element {
@@ -731,6 +795,10 @@ impl MovableListState {
         self.inner.list()
     }
 
+    pub(crate) fn state_hash(&self) -> u64 {
+        self.inner.hash()
+    }
+
     #[inline]
     fn elements(&self) -> &FxHashMap<CompactIdLp, Element> {
         self.inner.elements()
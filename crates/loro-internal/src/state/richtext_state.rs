@@ -5,7 +5,7 @@ use std::{
 
 use fxhash::{FxHashMap, FxHashSet};
 use generic_btree::{rle::HasLength, Cursor};
-use loro_common::{ContainerID, InternalString, LoroError, LoroResult, LoroValue, ID};
+use loro_common::{ContainerID, IdSpan, InternalString, LoroError, LoroResult, LoroValue, ID};
 use loro_delta::DeltaRopeBuilder;
 
 use crate::{
@@ -18,7 +18,7 @@ use crate::{
             richtext_state::{
                 DrainInfo, EntityRangeInfo, IterRangeItem, PosType, RichtextStateChunk,
             },
-            AnchorType, RichtextState as InnerState, StyleOp, Styles,
+            AnchorType, RichtextState as InnerState, StyleOp, Styles, TextSpan,
         },
     },
     delta::{StyleMeta, StyleMetaItem},
@@ -26,6 +26,7 @@ use crate::{
     event::{Diff, Index, InternalDiff, TextDiff},
     handler::TextDelta,
     op::{Op, RawOp},
+    state::state_hash::{id_span_hash, SetHash},
     txn::Transaction,
     utils::{lazy::LazyLoad, string_slice::StringSlice},
     DocState,
@@ -116,6 +117,10 @@ impl RichtextState {
         }
     }
 
+    pub(crate) fn chunks(&mut self) -> impl Iterator<Item = &str> + '_ {
+        self.state.get_mut().iter_str()
+    }
+
     fn get_style_start(
         &mut self,
         style_starts: &mut FxHashMap<Arc<StyleOp>, Pos>,
@@ -735,6 +740,14 @@ impl RichtextState {
         }
     }
 
+    #[inline]
+    pub(crate) fn state_hash(&self) -> u64 {
+        match &self.state {
+            LazyLoad::Src(s) => s.hash.get(),
+            LazyLoad::Dst(d) => d.state_hash(),
+        }
+    }
+
     pub fn len_event(&mut self) -> usize {
         if cfg!(feature = "wasm") {
             self.len_utf16()
@@ -842,6 +855,27 @@ impl RichtextState {
             .get_mut()
             .event_index_to_unicode_index(event_index)
     }
+
+    pub(crate) fn convert_pos(
+        &mut self,
+        index: usize,
+        from: PosType,
+        to: PosType,
+    ) -> Option<usize> {
+        self.state.get_mut().convert_pos(index, from, to)
+    }
+
+    pub(crate) fn get_attribution(&mut self, range: Range<usize>) -> Vec<IdSpan> {
+        self.state.get_mut().get_attribution(range)
+    }
+
+    pub(crate) fn get_spans_by_event_index(
+        &mut self,
+        pos: usize,
+        len: usize,
+    ) -> LoroResult<Vec<TextSpan>> {
+        self.state.get_mut().get_spans_by_event_index(pos, len)
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -850,6 +884,7 @@ pub(crate) struct RichtextStateLoader {
     elements: Vec<RichtextStateChunk>,
     style_ranges: Vec<(Arc<StyleOp>, Range<usize>)>,
     entity_index: usize,
+    hash: SetHash,
 }
 
 impl From<RichtextStateLoader> for InnerState {
@@ -877,6 +912,7 @@ impl RichtextStateLoader {
         }
 
         self.entity_index += elem.rle_len();
+        self.hash.insert(id_span_hash(elem.get_id_lp_span()));
         self.elements.push(elem);
     }
 
@@ -0,0 +1,219 @@
+//! The incremental hash of the state, see [DocState::state_hash].
+//!
+//! Each container state keeps a [SetHash] of its elements, which is updated when an element
+//! is inserted or removed by a local op or a diff:
+//!
+//! - The entries of a map, by their key and value.
+//! - The elements of a list and the chars and style anchors of a text, by their ids. The
+//!   content of an element never changes once it's inserted, and its position is decided by
+//!   its id, so the id covers both. The ids of a run of elements are hashed in O(log n)
+//!   together, see [id_span_hash].
+//! - The visible elements of a movable list, by their position, their id and the id of their
+//!   value.
+//! - The nodes of a tree, by their parent, position and last move.
+//! - The value of a counter.
+//!
+//! The hash of the doc is the wrapping sum of the hashes of the containers, each mixed with
+//! its container id, and it's updated with the hash of a container whenever the container is
+//! changed. So it's read in O(1), it doesn't depend on the order the ops are applied in, and
+//! it's the same on any platform. The replicas that have converged have the same hash.
+//!
+//! The containers that were deleted from their parents are still covered, because the
+//! converged replicas have the same states for them too. An empty container doesn't change
+//! the hash.
+use fxhash::FxHashMap;
+use itertools::Itertools;
+use loro_common::{ContainerID, IdLpSpan, LoroValue, PeerID};
+
+use super::{DocState, State};
+use crate::container::idx::ContainerIdx;
+
+/// The hashes of the elements are summed modulo this Mersenne prime, 2^61 - 1
+const P: u64 = (1 << 61) - 1;
+/// The base of [id_span_hash]
+const G: u64 = 37;
+/// The inverse of `G - 1` modulo [P], to sum a geometric series in O(1)
+const INV_G_MINUS_1: u64 = pow_mod(G - 1, P - 2);
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct StateHashes {
+    /// The hash of each non-empty container mixed with its id, see [mix]
+    containers: FxHashMap<ContainerIdx, u64>,
+    /// The wrapping sum of `containers`
+    doc: u64,
+}
+
+/// The hash of a set of elements, which is the sum of the hashes of the elements modulo
+/// [P]. It's updated in O(1) when an element is inserted or removed, and it doesn't
+/// depend on the order of the updates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct SetHash(u64);
+
+impl SetHash {
+    /// Add an element hash, which must come from [elem_hash] or [id_span_hash]
+    #[inline]
+    pub(crate) fn insert(&mut self, elem: u64) {
+        self.0 = (self.0 + elem) % P;
+    }
+
+    /// Remove an element hash that was inserted before
+    #[inline]
+    pub(crate) fn remove(&mut self, elem: u64) {
+        self.0 = (self.0 + P - elem) % P;
+    }
+
+    #[inline]
+    pub(crate) fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl DocState {
+    /// The hash of the current state.
+    ///
+    /// It's maintained incrementally when the ops are applied, so it's O(1). See the
+    /// [module docs](self) for what it covers.
+    #[inline]
+    pub fn state_hash(&self) -> u64 {
+        self.hashes.doc
+    }
+
+    /// Update the hash of the doc after the state of `idx` has changed.
+    pub(crate) fn update_hash(&mut self, idx: ContainerIdx) {
+        let hash = self.states.get(&idx).map_or(0, |s| s.content_hash());
+        let new = if hash == 0 {
+            0
+        } else {
+            mix(&self.arena.idx_to_id(idx).unwrap(), hash)
+        };
+        let old = if new == 0 {
+            self.hashes.containers.remove(&idx)
+        } else {
+            self.hashes.containers.insert(idx, new)
+        };
+        self.hashes.doc = self
+            .hashes
+            .doc
+            .wrapping_sub(old.unwrap_or(0))
+            .wrapping_add(new);
+    }
+}
+
+impl State {
+    /// The [SetHash] of the elements of the state, 0 if it's empty
+    fn content_hash(&self) -> u64 {
+        match self {
+            State::ListState(s) => s.state_hash(),
+            State::MovableListState(s) => s.state_hash(),
+            State::MapState(s) => s.state_hash(),
+            State::RichtextState(s) => s.state_hash(),
+            State::TreeState(s) => s.state_hash(),
+            #[cfg(feature = "counter")]
+            State::CounterState(s) => s.state_hash(),
+            State::UnknownState(_) => 0,
+        }
+    }
+}
+
+/// The hash of an element that is written by `f`, e.g. the key and the value of a map entry
+pub(crate) fn elem_hash(f: impl FnOnce(&mut md5::Context)) -> u64 {
+    let mut ctx = md5::Context::new();
+    f(&mut ctx);
+    digest_to_u64(ctx.compute()) % P
+}
+
+/// The sum of the hashes of the elements with the ids in `span`.
+///
+/// The hash of the element `lamport` of `peer` is `M(peer) * G^lamport`, so the hash of a
+/// span is a geometric series, and a chunk of text is hashed without visiting its chars.
+pub(crate) fn id_span_hash(span: IdLpSpan) -> u64 {
+    let len = span.lamport.end.saturating_sub(span.lamport.start) as u64;
+    if len == 0 {
+        return 0;
+    }
+
+    let m = splitmix64(span.peer) % (P - 1) + 1;
+    let series = mul_mod((pow_mod(G, len) + P - 1) % P, INV_G_MINUS_1);
+    mul_mod(mul_mod(m, pow_mod(G, span.lamport.start as u64)), series)
+}
+
+fn mix(id: &ContainerID, hash: u64) -> u64 {
+    let mut ctx = md5::Context::new();
+    write_bytes(&mut ctx, 1, id.to_string().as_bytes());
+    ctx.consume(hash.to_le_bytes());
+    digest_to_u64(ctx.compute())
+}
+
+const fn mul_mod(a: u64, b: u64) -> u64 {
+    ((a as u128 * b as u128) % P as u128) as u64
+}
+
+const fn pow_mod(mut base: u64, mut exp: u64) -> u64 {
+    let mut ans = 1;
+    base %= P;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            ans = mul_mod(ans, base);
+        }
+        base = mul_mod(base, base);
+        exp >>= 1;
+    }
+    ans
+}
+
+fn splitmix64(x: PeerID) -> u64 {
+    let mut x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+fn digest_to_u64(digest: md5::Digest) -> u64 {
+    u64::from_le_bytes(digest.0[..8].try_into().unwrap())
+}
+
+pub(crate) fn write_bytes(ctx: &mut md5::Context, tag: u8, bytes: &[u8]) {
+    ctx.consume([tag]);
+    ctx.consume((bytes.len() as u64).to_le_bytes());
+    ctx.consume(bytes);
+}
+
+/// Write the value in a canonical form, where the entries of a map are sorted by key
+pub(crate) fn write_value(ctx: &mut md5::Context, value: &LoroValue) {
+    match value {
+        LoroValue::Null => ctx.consume([0]),
+        LoroValue::Bool(x) => ctx.consume([1, *x as u8]),
+        LoroValue::Double(x) => {
+            ctx.consume([2]);
+            ctx.consume(x.to_bits().to_le_bytes());
+        }
+        LoroValue::I64(x) => {
+            ctx.consume([3]);
+            ctx.consume(x.to_le_bytes());
+        }
+        LoroValue::Binary(x) => write_bytes(ctx, 4, x),
+        LoroValue::String(x) => write_bytes(ctx, 5, x.as_bytes()),
+        LoroValue::List(list) => {
+            ctx.consume([6]);
+            ctx.consume((list.len() as u64).to_le_bytes());
+            for x in list.iter() {
+                write_value(ctx, x);
+            }
+        }
+        LoroValue::Map(map) => {
+            ctx.consume([7]);
+            ctx.consume((map.len() as u64).to_le_bytes());
+            for (key, x) in map.iter().sorted_by(|a, b| a.0.cmp(b.0)) {
+                write_bytes(ctx, 5, key.as_bytes());
+                write_value(ctx, x);
+            }
+        }
+        LoroValue::Container(id) => write_bytes(ctx, 8, id.to_string().as_bytes()),
+    }
+}
+
+/// Write the id of an element, e.g. the position of a movable list element
+pub(crate) fn write_id(ctx: &mut md5::Context, peer: PeerID, lamport: u32) {
+    ctx.consume(peer.to_le_bytes());
+    ctx.consume(lamport.to_le_bytes());
+}
@@ -30,7 +30,10 @@ use crate::{
     op::RawOp,
 };
 
-use super::ContainerState;
+use super::{
+    state_hash::{elem_hash, write_bytes, write_id, SetHash},
+    ContainerState,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumAsInner, Serialize)]
 pub enum TreeParentId {
@@ -560,6 +563,8 @@ pub struct TreeState {
     children: TreeChildrenCache,
     rng: Option<rand::rngs::StdRng>,
     jitter: u8,
+    /// The hash of the nodes, including the deleted ones
+    hash: SetHash,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -595,6 +600,7 @@ impl TreeState {
             children: Default::default(),
             rng: use_jitter.then_some(rand::rngs::StdRng::seed_from_u64(peer_id)),
             jitter,
+            hash: SetHash::default(),
         }
     }
 
@@ -626,18 +632,23 @@ impl TreeState {
         debug_assert!(!entry.has_child(&node_position));
         entry.insert_child(node_position, target);
 
-        self.trees.insert(
-            target,
-            TreeStateNode {
-                parent,
-                position,
-                last_move_op: id,
-            },
-        );
+        let node = TreeStateNode {
+            parent,
+            position,
+            last_move_op: id,
+        };
+        self.hash.insert(node_hash(&target, &node));
+        if let Some(old) = self.trees.insert(target, node) {
+            self.hash.remove(node_hash(&target, &old));
+        }
 
         Ok(())
     }
 
+    pub(crate) fn state_hash(&self) -> u64 {
+        self.hash.get()
+    }
+
     #[inline(never)]
     fn is_ancestor_of(&self, maybe_ancestor: &TreeID, node_id: &TreeParentId) -> bool {
         if !self.trees.contains_key(maybe_ancestor) {
@@ -909,6 +920,7 @@ impl ContainerState for TreeState {
                         // delete it from state
                         let parent = self.trees.remove(&target);
                         if let Some(parent) = parent {
+                            self.hash.remove(node_hash(&target, &parent));
                             if !parent.parent.is_deleted() {
                                 self.children
                                     .get_mut(&parent.parent)
@@ -958,6 +970,7 @@ impl ContainerState for TreeState {
                         // delete it from state
                         let parent = self.trees.remove(&target);
                         if let Some(parent) = parent {
+                            self.hash.remove(node_hash(&target, &parent));
                             if !parent.parent.is_deleted() {
                                 self.children
                                     .get_mut(&parent.parent)
@@ -1124,14 +1137,34 @@ impl ContainerState for TreeState {
     }
 }
 
+fn node_hash(target: &TreeID, node: &TreeStateNode) -> u64 {
+    elem_hash(|ctx| {
+        write_id(ctx, target.peer, target.counter as u32);
+        match node.parent {
+            TreeParentId::Node(parent) => {
+                ctx.consume([0]);
+                write_id(ctx, parent.peer, parent.counter as u32);
+            }
+            TreeParentId::Unexist => ctx.consume([1]),
+            TreeParentId::Deleted => ctx.consume([2]),
+            TreeParentId::Root => ctx.consume([3]),
+        }
+        if let Some(position) = &node.position {
+            write_bytes(ctx, 4, position.as_bytes());
+        }
+        write_id(ctx, node.last_move_op.peer, node.last_move_op.lamport);
+    })
+}
+
 // convert map container to LoroValue
 #[allow(clippy::ptr_arg)]
-pub(crate) fn get_meta_value(nodes: &mut Vec<LoroValue>, state: &mut DocState) {
+pub(crate) fn get_meta_value(nodes: &mut Vec<LoroValue>, state: &mut DocState, richtext: bool) {
     for node in nodes.iter_mut() {
         let map = Arc::make_mut(node.as_map_mut().unwrap());
         let meta = map.get_mut("meta").unwrap();
         let id = meta.as_container().unwrap();
-        *meta = state.get_container_deep_value(state.arena.register_container(id));
+        let idx = state.arena.register_container(id);
+        *meta = state.get_container_deep_value_as(idx, richtext);
     }
 }
 
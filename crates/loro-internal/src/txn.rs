@@ -6,26 +6,32 @@ use std::{
 };
 
 use enum_as_inner::EnumAsInner;
+use fxhash::FxHashSet;
 use generic_btree::rle::{HasLength as RleHasLength, Mergeable as GBSliceable};
-use loro_common::{ContainerType, IdLp, LoroResult};
+use loro_common::{ContainerID, ContainerType, IdLp, IdSpan, LoroResult};
 use loro_delta::{array_vec::ArrayVec, DeltaRopeBuilder};
 use rle::{HasLength, Mergable, RleVec};
 use smallvec::{smallvec, SmallVec};
 
 use crate::{
     change::{Change, Lamport, Timestamp},
+    configure::Configure,
     container::{
         idx::ContainerIdx,
-        list::list_op::{DeleteSpan, InnerListOp},
+        list::list_op::{DeleteSpan, InnerListOp, ListOp},
+        map::MapSet,
         richtext::Style,
         IntoContainerId,
     },
     delta::{ResolvedMapDelta, ResolvedMapValue, StyleMeta, StyleMetaItem, TreeDiff, TreeDiffItem},
-    event::{Diff, ListDeltaMeta, TextDiff},
+    diff_calc::DiffCalculator,
+    event::{ContainerDiff, Diff, ListDeltaMeta, TextDiff},
     handler::{Handler, ValueOrHandler},
     id::{Counter, PeerID, ID},
-    op::{Op, RawOp, RawOpContent},
+    loro::OnRootTypeConflict,
+    op::{ListSlice, Op, RawOp, RawOpContent},
     span::HasIdSpan,
+    utils::utf16::count_utf16_len,
     version::Frontiers,
     InternalString, LoroError, LoroValue,
 };
@@ -34,12 +40,92 @@ use super::{
     arena::SharedArena,
     event::{InternalContainerDiff, InternalDocDiff},
     handler::{ListHandler, MapHandler, TextHandler, TreeHandler},
-    oplog::OpLog,
-    state::{DocState, State},
+    obs::Observer,
+    oplog::{
+        quota::{Usage, UsageLimits},
+        OpLog,
+    },
+    state::{ContainerState, DocState, State},
 };
 
 pub type OnCommitFn = Box<dyn FnOnce(&Arc<Mutex<DocState>>) + Sync + Send>;
 
+/// The ops of a [Transaction], streamed as they are applied instead of at commit.
+#[derive(Debug)]
+pub enum TxnStreamEvent<'a> {
+    /// A local op has been applied to the state.
+    ///
+    /// It's provisional until the transaction is committed, because the change that
+    /// contains it doesn't exist yet, so it can't be exported or imported by other peers.
+    Provisional {
+        id: ID,
+        container: &'a ContainerID,
+        content: &'a RawOpContent<'a>,
+    },
+    /// The transaction is committed. The ops in `span` are final and can be exported.
+    Committed { span: IdSpan },
+    /// The transaction is aborted by [Transaction::abort], or an edit of several ops failed
+    /// halfway, e.g. a [TextHandler::apply_delta] whose op was rejected by the
+    /// [PreCommitHook]. The ops in `span` are rolled back, and their ids are reused by the
+    /// next ops of the peer.
+    ///
+    /// Every provisional op is followed by the `Committed` or the `Aborted` event of its
+    /// transaction. An op rejected by the [PreCommitHook] isn't applied, so it's never
+    /// streamed.
+    Aborted { span: IdSpan },
+}
+
+/// It's called while the transaction is locked, so it must not edit the doc.
+pub type OnTxnStreamFn = Arc<dyn for<'a> Fn(TxnStreamEvent<'a>) + Sync + Send>;
+
+/// A local op passed to the [PreCommitHook] before it's applied.
+///
+/// The inserted text and the values can be rewritten in place. The other ops can only be
+/// inspected.
+#[derive(Debug)]
+pub enum StagedOp<'a> {
+    /// The text inserted into a text container. Nothing is inserted if it's rewritten to an
+    /// empty string.
+    InsertText {
+        container: &'a ContainerID,
+        text: &'a mut String,
+    },
+    /// A value inserted into a list, or set in a map or a movable list. It can't be
+    /// rewritten to a container. The ops that create child containers are passed as
+    /// [StagedOp::Other].
+    Value {
+        container: &'a ContainerID,
+        value: &'a mut LoroValue,
+    },
+    /// Any other op, e.g. a deletion, a move, a style or a tree op
+    Other {
+        container: &'a ContainerID,
+        content: &'a RawOpContent<'a>,
+    },
+}
+
+/// Inspect and rewrite each local op before it's applied. Returning an error rejects the
+/// op, then the edit fails with [LoroError::OpRejected] and the doc is unchanged. An edit of
+/// several ops, e.g. [TextHandler::apply_delta] or [TextHandler::update], is all or
+/// nothing: the ops it applied before the rejected one are rolled back.
+///
+/// The rewritten ops are what the other peers receive, so the hook enforces the invariants
+/// at the CRDT boundary. It should only depend on the op, so that the same edit is always
+/// rewritten the same way. It's called while the transaction is locked, so it must not
+/// edit the doc.
+pub type PreCommitHook = Arc<dyn for<'a> Fn(StagedOp<'a>) -> Result<(), String> + Sync + Send>;
+
+/// The callbacks of the transactions of a [LoroDoc](crate::LoroDoc).
+///
+/// A transaction renewed by [Transaction::commit_then_renew] keeps them.
+#[derive(Clone, Default)]
+pub(crate) struct TxnHooks {
+    pub(crate) observer: Option<Arc<Observer>>,
+    pub(crate) stream: Option<OnTxnStreamFn>,
+    pub(crate) pre_commit_hook: Option<PreCommitHook>,
+    pub(crate) on_root_type_conflict: Option<OnRootTypeConflict>,
+}
+
 pub struct Transaction {
     global_txn: Weak<Mutex<Option<Transaction>>>,
     peer: PeerID,
@@ -56,7 +142,13 @@ pub struct Transaction {
     pub(super) arena: SharedArena,
     finished: bool,
     on_commit: Option<OnCommitFn>,
+    hooks: TxnHooks,
     timestamp: Option<Timestamp>,
+    msg: Option<Arc<str>>,
+    config: Configure,
+    /// The usage of the peer with the ops of the transaction, counted lazily when the size
+    /// or the number of the containers of each peer is limited
+    usage: Option<Usage>,
 }
 
 impl std::fmt::Debug for Transaction {
@@ -250,6 +342,7 @@ impl Transaction {
         let peer = state_lock.peer;
         let next_counter = oplog_lock.next_id(peer).counter;
         let next_lamport = oplog_lock.dag.frontiers_to_next_lamport(&frontiers);
+        let config = state_lock.config.clone();
         drop(state_lock);
         drop(oplog_lock);
         Self {
@@ -259,6 +352,7 @@ impl Transaction {
             oplog,
             frontiers,
             timestamp: None,
+            msg: None,
             global_txn,
             next_counter,
             next_lamport,
@@ -269,9 +363,38 @@ impl Transaction {
             local_ops: RleVec::new(),
             finished: false,
             on_commit: None,
+            hooks: Default::default(),
+            config,
+            usage: None,
         }
     }
 
+    /// Create a transaction of a [LoroDoc](crate::LoroDoc) with its callbacks.
+    ///
+    /// If `hooks` has an observer, the events of the transaction are emitted to it on commit.
+    pub(crate) fn new_with_hooks(
+        state: Arc<Mutex<DocState>>,
+        oplog: Arc<Mutex<OpLog>>,
+        origin: InternalString,
+        global_txn: Weak<Mutex<Option<Transaction>>>,
+        hooks: TxnHooks,
+    ) -> Self {
+        let mut txn = Self::new_with_origin(state, oplog, origin, global_txn);
+        if let Some(obs) = hooks.observer.clone() {
+            txn.set_on_commit(Box::new(move |state| {
+                let mut state = state.try_lock().unwrap();
+                let events = state.take_events();
+                drop(state);
+                for event in events {
+                    obs.emit(event);
+                }
+            }));
+        }
+
+        txn.hooks = hooks;
+        txn
+    }
+
     pub fn set_origin(&mut self, origin: InternalString) {
         self.origin = origin;
     }
@@ -280,10 +403,109 @@ impl Transaction {
         self._commit()
     }
 
+    /// Discard the ops of this transaction instead of committing them.
+    ///
+    /// The containers edited by it are rebuilt from the oplog, so it costs as much as
+    /// checking them out from the empty version. No events are emitted, because the
+    /// events of a transaction are only emitted when it's committed.
+    pub fn abort(mut self) {
+        self._abort()
+    }
+
+    pub(crate) fn _abort(&mut self) {
+        if self.finished {
+            return;
+        }
+
+        self.finished = true;
+        self.event_hints.clear();
+        let ops = take(&mut self.local_ops);
+        let mut state = self.state.lock().unwrap();
+        if ops.is_empty() {
+            state.abort_txn();
+            return;
+        }
+
+        let containers: FxHashSet<ContainerIdx> = ops.iter().map(|op| op.container).collect();
+        let oplog = self.oplog.lock().unwrap();
+        let diff = DiffCalculator::new().calc_diff_internal(
+            &oplog,
+            &Default::default(),
+            None,
+            oplog.vv(),
+            Some(oplog.frontiers()),
+            Some(&|idx| containers.contains(&idx)),
+        );
+        state.rollback_txn(&containers, diff);
+        drop(state);
+        drop(oplog);
+        if let Some(stream) = self.hooks.stream.as_ref() {
+            stream(TxnStreamEvent::Aborted {
+                span: IdSpan::new(self.peer, self.start_counter, self.next_counter),
+            });
+        }
+    }
+
+    /// Run `f`, an edit of several ops on `container`, and roll back the ops it applied if
+    /// it fails, so that a failed edit leaves the doc unchanged.
+    ///
+    /// The state of `container` is cloned before the edit, so it's only worth it for the
+    /// edits that may fail halfway.
+    pub(crate) fn atomic_edit<R>(
+        &mut self,
+        container: ContainerIdx,
+        f: impl FnOnce(&mut Self) -> LoroResult<R>,
+    ) -> LoroResult<R> {
+        let next_counter = self.next_counter;
+        let next_lamport = self.next_lamport;
+        // The first op of the edit may be merged into the last op of the txn
+        let ops_len = self.local_ops.merged_len();
+        let last_op = self.local_ops.vec().last().cloned();
+        let hints_len = self.event_hints.len();
+        let last_hint = self.event_hints.last().cloned();
+        let was_written = self.arena.is_written(container);
+        let state = self.state.lock().unwrap().get_state(container).cloned();
+        let err = match f(self) {
+            Ok(ans) => return Ok(ans),
+            Err(err) => err,
+        };
+
+        if self.next_counter == next_counter {
+            return Err(err);
+        }
+
+        let ops = self.local_ops.vec_mut();
+        ops.truncate(ops_len);
+        if let Some(last_op) = last_op {
+            *ops.last_mut().unwrap() = last_op;
+        }
+        self.event_hints.truncate(hints_len);
+        if let Some(last_hint) = last_hint {
+            *self.event_hints.last_mut().unwrap() = last_hint;
+        }
+        let span = IdSpan::new(self.peer, next_counter, self.next_counter);
+        self.next_counter = next_counter;
+        self.next_lamport = next_lamport;
+        self.state
+            .lock()
+            .unwrap()
+            .restore_state(container, state, was_written);
+        if let Some(stream) = self.hooks.stream.as_ref() {
+            stream(TxnStreamEvent::Aborted { span });
+        }
+
+        Err(err)
+    }
+
     pub fn set_timestamp(&mut self, time: Timestamp) {
         self.timestamp = Some(time);
     }
 
+    /// Set the commit message of the change of this transaction.
+    pub fn set_msg(&mut self, msg: Option<Arc<str>>) {
+        self.msg = msg;
+    }
+
     pub(crate) fn set_on_commit(&mut self, f: OnCommitFn) {
         self.on_commit = Some(f);
     }
@@ -292,6 +514,49 @@ impl Transaction {
         self.on_commit.take()
     }
 
+    /// Stream the ops of this transaction to `stream` as they are applied.
+    pub fn set_stream(&mut self, stream: Option<OnTxnStreamFn>) {
+        self.hooks.stream = stream;
+    }
+
+    /// Run `hook` on the ops of this transaction before they are applied.
+    pub fn set_pre_commit_hook(&mut self, hook: Option<PreCommitHook>) {
+        self.hooks.pre_commit_hook = hook;
+    }
+
+    pub(crate) fn set_on_root_type_conflict(&mut self, on_conflict: Option<OnRootTypeConflict>) {
+        self.hooks.on_root_type_conflict = on_conflict;
+    }
+
+    /// Whether this transaction has reached [Configure::auto_commit_ops_limit].
+    pub(crate) fn reached_auto_commit_limit(&self) -> bool {
+        self.config
+            .auto_commit_ops_limit()
+            .is_some_and(|limit| self.len() >= limit)
+    }
+
+    /// Commit this transaction and start the next one in place with the same [TxnHooks].
+    ///
+    /// The on-commit callback of this commit is returned instead of being called, so that
+    /// the caller can call it after releasing the lock of the global transaction.
+    pub(crate) fn commit_then_renew(&mut self) -> Result<impl FnOnce(), LoroError> {
+        let on_commit = self.take_on_commit();
+        self._commit()?;
+        *self = Transaction::new_with_hooks(
+            self.state.clone(),
+            self.oplog.clone(),
+            "".into(),
+            self.global_txn.clone(),
+            self.hooks.clone(),
+        );
+        let state = self.state.clone();
+        Ok(move || {
+            if let Some(on_commit) = on_commit {
+                on_commit(&state);
+            }
+        })
+    }
+
     fn _commit(&mut self) -> Result<(), LoroError> {
         if self.finished {
             return Ok(());
@@ -316,6 +581,7 @@ impl Transaction {
                 self.timestamp
                     .unwrap_or_else(|| oplog.get_timestamp_for_next_txn()),
             ),
+            commit_msg: take(&mut self.msg),
             has_dependents: false,
         };
 
@@ -332,6 +598,7 @@ impl Transaction {
         };
 
         let last_id = change.id_last();
+        let span = change.id_span();
         if let Err(err) = oplog.import_local_change(change) {
             drop(state);
             drop(oplog);
@@ -358,6 +625,10 @@ impl Transaction {
         );
         drop(state);
         drop(oplog);
+        if let Some(stream) = self.hooks.stream.as_ref() {
+            stream(TxnStreamEvent::Committed { span });
+        }
+
         if let Some(on_commit) = self.on_commit.take() {
             on_commit(&self.state);
         }
@@ -379,7 +650,30 @@ impl Transaction {
             });
         }
 
+        let (content, event) = match &self.hooks.pre_commit_hook {
+            Some(hook) => {
+                let cid = self.arena.idx_to_id(container).unwrap();
+                match run_pre_commit_hook(hook, &cid, content, event)? {
+                    Some(x) => x,
+                    // The inserted text is rewritten to be empty
+                    None => return Ok(()),
+                }
+            }
+            None => (content, event),
+        };
+
         let len = content.content_len();
+        // The other peers would reject the ops beyond the limit
+        if let Some(limit) = self.config.max_ops_per_peer() {
+            if self.next_counter as usize + len > limit {
+                return Err(LoroError::OpQuotaExceeded {
+                    peer: self.peer,
+                    limit,
+                    unit: "ops",
+                });
+            }
+        }
+
         let raw_op = RawOp {
             id: ID {
                 peer: self.peer,
@@ -390,10 +684,47 @@ impl Transaction {
             content,
         };
 
-        let mut state = self.state.lock().unwrap();
         let op = self.arena.convert_raw_op(&raw_op);
+        let limits = UsageLimits::new(&self.config);
+        if let Some(limits) = limits.as_ref() {
+            let peer = self.peer;
+            let oplog = &self.oplog;
+            let usage = self
+                .usage
+                .get_or_insert_with(|| oplog.lock().unwrap().peer_usage(peer, limits));
+            usage.check_local_op(&op, &self.arena, limits, peer)?;
+        }
+
+        let mut state = self.state.lock().unwrap();
+        // A root that gets its first value may now share its name with a root of another type
+        let is_empty_root = self.hooks.on_root_type_conflict.is_some()
+            && self.arena.get_parent(container).is_none()
+            && state
+                .get_state(container)
+                .is_none_or(|x| x.is_state_empty());
         state.apply_local_op(&raw_op, &op)?;
+        if let (Some(limits), Some(usage)) = (limits.as_ref(), self.usage.as_mut()) {
+            usage.add_local_op(&op, &self.arena, limits);
+        }
+        let conflict = if is_empty_root {
+            state.root_type_conflict(container)
+        } else {
+            None
+        };
         drop(state);
+        if let (Some(on_conflict), Some((name, types))) =
+            (self.hooks.on_root_type_conflict.as_ref(), conflict)
+        {
+            on_conflict(&name, &types);
+        }
+        if let Some(stream) = self.hooks.stream.as_ref() {
+            let cid = self.arena.idx_to_id(container).unwrap();
+            stream(TxnStreamEvent::Provisional {
+                id: raw_op.id,
+                container: &cid,
+                content: &raw_op.content,
+            });
+        }
 
         debug_assert_eq!(
             event.rle_len(),
@@ -412,6 +743,8 @@ impl Transaction {
             }
         }
         self.local_ops.push(op);
+        // The limits are checked against the pending ops too, so they're frozen from now on
+        self.config.mark_has_ops();
         self.next_counter += len as Counter;
         self.next_lamport += len as Lamport;
         Ok(())
@@ -503,6 +836,64 @@ impl Transaction {
     pub fn is_empty(&self) -> bool {
         self.local_ops.is_empty()
     }
+
+    /// The number of atomic ops in this transaction
+    pub fn len(&self) -> usize {
+        (self.next_counter - self.start_counter) as usize
+    }
+
+    /// The changes made by this transaction so far, in the form of the events it will
+    /// emit when it's committed.
+    ///
+    /// It lets apps preview or validate the staged edits before committing them. The
+    /// diffs of a container are composed into one, and they're sorted by the length of
+    /// their paths like the events.
+    pub fn current_diff(&self) -> Vec<ContainerDiff> {
+        if self.local_ops.is_empty() {
+            return Vec::new();
+        }
+
+        let change = Change {
+            lamport: self.start_lamport,
+            ops: self.local_ops.clone(),
+            deps: self.frontiers.clone(),
+            id: ID::new(self.peer, self.start_counter),
+            timestamp: 0,
+            commit_msg: None,
+            has_dependents: false,
+        };
+        let diffs = change_to_diff(
+            &change,
+            &self.arena,
+            &self.global_txn,
+            &Arc::downgrade(&self.state),
+            self.event_hints.clone(),
+        );
+        let mut composed: Vec<TxnContainerDiff> = Vec::new();
+        for d in diffs {
+            match composed.iter_mut().find(|x| x.idx == d.idx) {
+                Some(x) => x.diff.compose_ref(&d.diff),
+                None => composed.push(d),
+            }
+        }
+
+        let state = self.state.lock().unwrap();
+        let mut ans: Vec<ContainerDiff> = composed
+            .into_iter()
+            .map(|x| {
+                let id = self.arena.idx_to_id(x.idx).unwrap();
+                ContainerDiff {
+                    is_unknown: id.is_unknown(),
+                    path: state.get_path(x.idx).unwrap_or_default(),
+                    id,
+                    idx: x.idx,
+                    diff: x.diff,
+                }
+            })
+            .collect();
+        ans.sort_by_key(|x| x.path.len());
+        ans
+    }
 }
 
 impl Drop for Transaction {
@@ -515,6 +906,101 @@ impl Drop for Transaction {
     }
 }
 
+/// Run the hook on a local op, and update the event hint to the rewritten op. Returns
+/// `None` if there is nothing left to apply.
+fn run_pre_commit_hook<'a>(
+    hook: &PreCommitHook,
+    container: &ContainerID,
+    mut content: RawOpContent<'a>,
+    mut event: EventHint,
+) -> LoroResult<Option<(RawOpContent<'a>, EventHint)>> {
+    let reject = |reason: String| LoroError::OpRejected(reason.into_boxed_str());
+    match (&mut content, &mut event) {
+        (
+            RawOpContent::List(ListOp::Insert {
+                slice: ListSlice::RawStr { str, unicode_len },
+                ..
+            }),
+            EventHint::InsertText {
+                event_len,
+                unicode_len: hint_unicode_len,
+                ..
+            },
+        ) => {
+            let mut text = str.to_string();
+            hook(StagedOp::InsertText {
+                container,
+                text: &mut text,
+            })
+            .map_err(reject)?;
+            if text.is_empty() {
+                return Ok(None);
+            }
+
+            if text != *str {
+                *unicode_len = text.chars().count();
+                *hint_unicode_len = *unicode_len as u32;
+                *event_len = if cfg!(feature = "wasm") {
+                    count_utf16_len(text.as_bytes()) as u32
+                } else {
+                    *unicode_len as u32
+                };
+                *str = Cow::Owned(text);
+            }
+        }
+        (
+            RawOpContent::List(ListOp::Insert {
+                slice: ListSlice::RawData(values),
+                ..
+            }),
+            _,
+        ) if values.iter().all(|x| !x.is_container()) => {
+            for value in values.to_mut().iter_mut() {
+                rewrite_value(hook, container, value)?;
+            }
+        }
+        (
+            RawOpContent::List(ListOp::Set { value, .. }),
+            EventHint::SetList {
+                value: hint_value, ..
+            },
+        ) if !value.is_container() => {
+            rewrite_value(hook, container, value)?;
+            *hint_value = value.clone();
+        }
+        (
+            RawOpContent::Map(MapSet {
+                value: Some(value), ..
+            }),
+            EventHint::Map {
+                value: hint_value, ..
+            },
+        ) if !value.is_container() => {
+            rewrite_value(hook, container, value)?;
+            *hint_value = Some(value.clone());
+        }
+        (content, _) => hook(StagedOp::Other { container, content }).map_err(reject)?,
+    }
+
+    Ok(Some((content, event)))
+}
+
+fn rewrite_value(
+    hook: &PreCommitHook,
+    container: &ContainerID,
+    value: &mut LoroValue,
+) -> LoroResult<()> {
+    hook(StagedOp::Value { container, value })
+        .map_err(|reason| LoroError::OpRejected(reason.into_boxed_str()))?;
+    if value.is_container() {
+        return Err(LoroError::OpRejected(
+            "A value can't be rewritten to a container".into(),
+        ));
+    }
+
+    Ok(())
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct TxnContainerDiff {
     pub(crate) idx: ContainerIdx,
@@ -6,8 +6,8 @@ use std::{
 use either::Either;
 use fxhash::FxHashMap;
 use loro_common::{
-    ContainerID, Counter, CounterSpan, HasCounterSpan, HasIdSpan, IdSpan, LoroError, LoroResult,
-    LoroValue, PeerID,
+    ContainerID, Counter, CounterSpan, HasCounterSpan, HasIdSpan, IdSpan, InternalString,
+    LoroError, LoroResult, LoroValue, PeerID,
 };
 use tracing::{debug_span, info_span, instrument};
 
@@ -154,11 +154,23 @@ struct UndoManagerInner {
     processing_undo: bool,
     last_undo_time: i64,
     merge_interval: i64,
+    /// Whether the consecutive local changes with the same non-empty origin are merged
+    merge_by_origin: bool,
+    /// The origin of the last item pushed to the undo stack
+    last_origin: InternalString,
     max_stack_size: usize,
     exclude_origin_prefixes: Vec<Box<str>>,
     last_popped_selection: Option<Vec<CursorWithPos>>,
     on_push: Option<OnPush>,
     on_pop: Option<OnPop>,
+    group: Option<UndoGroup>,
+}
+
+/// The state of the undo group opened by [UndoManager::group_start].
+#[derive(Debug, Default, Clone, Copy)]
+struct UndoGroup {
+    /// Whether an item has been pushed to the undo stack since the group started.
+    has_item: bool,
 }
 
 impl std::fmt::Debug for UndoManagerInner {
@@ -170,8 +182,11 @@ impl std::fmt::Debug for UndoManagerInner {
             .field("processing_undo", &self.processing_undo)
             .field("last_undo_time", &self.last_undo_time)
             .field("merge_interval", &self.merge_interval)
+            .field("merge_by_origin", &self.merge_by_origin)
+            .field("last_origin", &self.last_origin)
             .field("max_stack_size", &self.max_stack_size)
             .field("exclude_origin_prefixes", &self.exclude_origin_prefixes)
+            .field("group", &self.group)
             .finish()
     }
 }
@@ -267,6 +282,11 @@ impl Stack {
         // Cursor position transformation relies on the remote diff in the same row.
     }
 
+    /// The item that [Stack::pop] would return
+    fn top(&self) -> Option<&StackItem> {
+        self.stack.iter().rev().find_map(|(items, _)| items.back())
+    }
+
     pub fn push(&mut self, span: CounterSpan, meta: UndoItemMeta) {
         self.push_with_merge(span, meta, false)
     }
@@ -366,16 +386,19 @@ impl UndoManagerInner {
             redo_stack: Default::default(),
             processing_undo: false,
             merge_interval: 0,
+            merge_by_origin: false,
+            last_origin: Default::default(),
             last_undo_time: 0,
             max_stack_size: usize::MAX,
             exclude_origin_prefixes: vec![],
             last_popped_selection: None,
             on_pop: None,
             on_push: None,
+            group: None,
         }
     }
 
-    fn record_checkpoint(&mut self, latest_counter: Counter) {
+    fn record_checkpoint(&mut self, latest_counter: Counter, origin: &InternalString) {
         if Some(latest_counter) == self.latest_counter {
             return;
         }
@@ -394,13 +417,22 @@ impl UndoManagerInner {
             .map(|x| x(UndoOrRedo::Undo, span))
             .unwrap_or_default();
 
-        if !self.undo_stack.is_empty() && now - self.last_undo_time < self.merge_interval {
+        let in_group = self.group.is_some_and(|g| g.has_item);
+        // The system clock may go backwards, then the change starts a new step
+        let in_interval = (0..self.merge_interval).contains(&(now - self.last_undo_time));
+        let same_origin = self.merge_by_origin && !origin.is_empty() && *origin == self.last_origin;
+        if !self.undo_stack.is_empty() && (in_group || in_interval || same_origin) {
             self.undo_stack.push_with_merge(span, meta, true);
         } else {
             self.last_undo_time = now;
             self.undo_stack.push(span, meta);
         }
 
+        if let Some(group) = self.group.as_mut() {
+            group.has_item = true;
+        }
+
+        self.last_origin = origin.clone();
         self.latest_counter = Some(latest_counter);
         self.redo_stack.clear();
         while self.undo_stack.len() > self.max_stack_size {
@@ -448,7 +480,7 @@ impl UndoManager {
                         inner.redo_stack.compose_remote_event(event.events);
                         inner.latest_counter = Some(id.counter + 1);
                     } else {
-                        inner.record_checkpoint(id.counter + 1);
+                        inner.record_checkpoint(id.counter + 1, &event.event_meta.origin);
                     }
                 }
             }
@@ -480,6 +512,12 @@ impl UndoManager {
         self.inner.try_lock().unwrap().merge_interval = interval;
     }
 
+    /// Merge the consecutive local changes that have the same non-empty origin into one
+    /// undo step, whatever the time between them.
+    pub fn set_merge_by_origin(&mut self, merge: bool) {
+        self.inner.try_lock().unwrap().merge_by_origin = merge;
+    }
+
     pub fn set_max_undo_steps(&mut self, size: usize) {
         self.inner.try_lock().unwrap().max_stack_size = size;
     }
@@ -502,7 +540,35 @@ impl UndoManager {
 
         doc.commit_then_renew();
         let counter = get_counter_end(doc, self.peer);
-        self.inner.try_lock().unwrap().record_checkpoint(counter);
+        self.inner
+            .try_lock()
+            .unwrap()
+            .record_checkpoint(counter, &InternalString::default());
+        Ok(())
+    }
+
+    /// Start a group of edits. All the local changes committed before [UndoManager::group_end]
+    /// are merged into a single undo step.
+    ///
+    /// The pending changes of the doc are committed first, so they are not part of the group.
+    /// Calling [UndoManager::undo] or [UndoManager::redo] ends the group.
+    pub fn group_start(&mut self, doc: &LoroDoc) -> LoroResult<()> {
+        self.record_new_checkpoint(doc)?;
+        let mut inner = self.inner.try_lock().unwrap();
+        if inner.group.is_some() {
+            return Err(LoroError::ArgErr(
+                "The undo group has already started".into(),
+            ));
+        }
+
+        inner.group = Some(UndoGroup::default());
+        Ok(())
+    }
+
+    /// End the group started by [UndoManager::group_start].
+    pub fn group_end(&mut self, doc: &LoroDoc) -> LoroResult<()> {
+        self.record_new_checkpoint(doc)?;
+        self.inner.try_lock().unwrap().group = None;
         Ok(())
     }
 
@@ -591,6 +657,7 @@ impl UndoManager {
         let end_counter = get_counter_end(doc, self.peer);
         let mut top = {
             let mut inner = self.inner.try_lock().unwrap();
+            inner.group = None;
             inner.processing_undo = true;
             get_stack(&mut inner).pop()
         };
@@ -683,6 +750,22 @@ impl UndoManager {
     pub fn set_on_pop(&self, on_pop: Option<OnPop>) {
         self.inner.try_lock().unwrap().on_pop = on_pop;
     }
+
+    /// The metadata of the item that the next [UndoManager::undo] will pop, as returned by
+    /// the on_push listener.
+    ///
+    /// The cursors haven't been transformed by the remote changes yet, they're transformed
+    /// when the item is popped.
+    pub fn top_undo_meta(&self) -> Option<UndoItemMeta> {
+        let inner = self.inner.try_lock().unwrap();
+        inner.undo_stack.top().map(|x| x.meta.clone())
+    }
+
+    /// The metadata of the item that the next [UndoManager::redo] will pop.
+    pub fn top_redo_meta(&self) -> Option<UndoItemMeta> {
+        let inner = self.inner.try_lock().unwrap();
+        inner.redo_stack.top().map(|x| x.meta.clone())
+    }
 }
 
 /// Undo the given spans of operations.
@@ -1,13 +1,21 @@
-use std::sync::{atomic::AtomicBool, Arc, Mutex};
+use std::sync::{
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+    Arc, Mutex,
+};
 
 use fxhash::FxHashMap;
-use loro_common::{ContainerID, ContainerType, LoroResult, LoroValue, ID};
+use loro_common::{ContainerID, ContainerType, IdSpan, LoroError, LoroResult, LoroValue, ID};
 use loro_internal::{
+    configure::ConfigOptions,
+    cursor::{CannotFindRelativePosition, Cursor, Side},
     delta::ResolvedMapValue,
-    event::{Diff, EventTriggerKind},
-    handler::{Handler, TextDelta, ValueOrHandler},
+    event::{str_to_path, Diff, EventTriggerKind},
+    handler::{Handler, PosType, TextDelta, ValueOrHandler},
+    loro::{CommitOptions, PEER_NAME_KEY},
+    txn::{StagedOp, TxnStreamEvent},
     version::Frontiers,
     ApplyDiff, HandlerTrait, ListHandler, LoroDoc, MapHandler, TextHandler, ToJson, TreeHandler,
+    ValueRef,
 };
 use serde_json::json;
 
@@ -48,6 +56,50 @@ fn issue_225() -> LoroResult<()> {
     Ok(())
 }
 
+#[test]
+fn text_apply_delta_overrun_leaves_text_unchanged() -> LoroResult<()> {
+    let doc = LoroDoc::new_auto_commit();
+    let text = doc.get_text("text");
+    text.insert(0, "Hello")?;
+    doc.commit_then_renew();
+    let vv = doc.oplog_vv();
+    let result = text.apply_delta(&[
+        TextDelta::Insert {
+            insert: "Oh, ".into(),
+            attributes: None,
+        },
+        TextDelta::Retain {
+            retain: 2,
+            attributes: None,
+        },
+        TextDelta::Delete { delete: 4 },
+    ]);
+    assert!(matches!(result, Err(LoroError::OutOfBound { .. })));
+    assert!(text
+        .apply_delta(&[TextDelta::Retain {
+            retain: 6,
+            attributes: None,
+        }])
+        .is_err());
+    doc.commit_then_renew();
+    assert_eq!(text.to_string(), "Hello");
+    assert_eq!(doc.oplog_vv(), vv);
+
+    text.apply_delta(&[
+        TextDelta::Insert {
+            insert: "Oh, ".into(),
+            attributes: None,
+        },
+        TextDelta::Retain {
+            retain: 2,
+            attributes: None,
+        },
+        TextDelta::Delete { delete: 3 },
+    ])?;
+    assert_eq!(text.to_string(), "Oh, He");
+    Ok(())
+}
+
 #[test]
 fn issue_211() -> LoroResult<()> {
     let doc1 = LoroDoc::new_auto_commit();
@@ -115,6 +167,72 @@ fn event_from_checkout() {
     assert!(ran_cloned.load(std::sync::atomic::Ordering::Relaxed));
 }
 
+#[test]
+fn subscribe_select_only_fires_on_changed_projection() {
+    let doc = LoroDoc::new_auto_commit();
+    let state = doc.get_map("state");
+    let todos = state
+        .insert_container("todos", ListHandler::new_detached())
+        .unwrap();
+    doc.commit_then_renew();
+
+    let projected = Arc::new(AtomicUsize::new(0));
+    let projected_cp = projected.clone();
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let changes_cp = changes.clone();
+    doc.subscribe_select(
+        &str_to_path("state/todos").unwrap(),
+        Arc::new(move |v| {
+            projected_cp.fetch_add(1, Ordering::SeqCst);
+            let len = v.and_then(|v| v.as_list().map(|l| l.len())).unwrap_or(0);
+            LoroValue::I64(len as i64)
+        }),
+        Arc::new(move |old, new| changes_cp.lock().unwrap().push((old.clone(), new.clone()))),
+    );
+    assert_eq!(projected.load(Ordering::SeqCst), 1);
+
+    // unrelated containers are filtered out by the path
+    doc.get_text("text").insert(0, "hello").unwrap();
+    doc.commit_then_renew();
+    state.insert("title", "todo").unwrap();
+    doc.commit_then_renew();
+    assert_eq!(projected.load(Ordering::SeqCst), 1);
+
+    todos.push("a".into()).unwrap();
+    doc.commit_then_renew();
+    todos.push("b".into()).unwrap();
+    doc.commit_then_renew();
+    assert_eq!(projected.load(Ordering::SeqCst), 3);
+
+    // the projection doesn't change, so the callback is skipped
+    todos.delete(0, 1).unwrap();
+    todos.insert(0, "c").unwrap();
+    doc.commit_then_renew();
+    assert_eq!(projected.load(Ordering::SeqCst), 4);
+
+    let remote = LoroDoc::new_auto_commit();
+    remote.import(&doc.export_snapshot()).unwrap();
+    remote
+        .get_map("state")
+        .get_child_handler("todos")
+        .unwrap()
+        .as_list()
+        .unwrap()
+        .push("d".into())
+        .unwrap();
+    remote.commit_then_renew();
+    doc.import(&remote.export_from(&doc.oplog_vv())).unwrap();
+
+    assert_eq!(
+        *changes.lock().unwrap(),
+        vec![
+            (LoroValue::I64(0), LoroValue::I64(1)),
+            (LoroValue::I64(1), LoroValue::I64(2)),
+            (LoroValue::I64(2), LoroValue::I64(3)),
+        ]
+    );
+}
+
 #[test]
 fn handler_in_event() {
     let doc = LoroDoc::new_auto_commit();
@@ -473,6 +591,35 @@ fn test_checkout() {
     );
 }
 
+#[test]
+fn checkout_keeps_history() -> LoroResult<()> {
+    let doc = LoroDoc::new_auto_commit();
+    doc.set_peer_id(1)?;
+    let text = doc.get_text("text");
+    text.insert(0, "Hello")?;
+    doc.commit_then_renew();
+    let v1 = doc.oplog_frontiers();
+    text.insert(5, " World")?;
+    doc.commit_then_renew();
+    let len_ops = doc.len_ops();
+
+    doc.checkout(&v1)?;
+    assert!(doc.is_detached());
+    assert_eq!(text.to_string(), "Hello");
+    assert_eq!(doc.len_ops(), len_ops);
+    doc.checkout(&Frontiers::default())?;
+    assert_eq!(text.to_string(), "");
+
+    // A failed checkout leaves the doc untouched
+    doc.checkout_to_latest();
+    assert!(doc.checkout(&Frontiers::from(vec![ID::new(2, 0)])).is_err());
+    assert!(!doc.is_detached());
+    text.insert(11, "!")?;
+    doc.commit_then_renew();
+    assert_eq!(text.to_string(), "Hello World!");
+    Ok(())
+}
+
 #[test]
 fn import() {
     let doc = LoroDoc::new();
@@ -775,6 +922,49 @@ fn issue_batch_import_snapshot() {
     doc3.import_batch(&[data1, data2]).unwrap();
 }
 
+#[test]
+fn import_batch_out_of_order() -> LoroResult<()> {
+    let a = LoroDoc::new_auto_commit();
+    a.set_peer_id(1)?;
+    let b = LoroDoc::new_auto_commit();
+    b.set_peer_id(2)?;
+    let c = LoroDoc::new_auto_commit();
+    c.set_peer_id(3)?;
+    a.get_list("list").insert(0, 1)?;
+    a.commit_then_renew();
+    let update_a = a.export_from(&Default::default());
+    b.import(&update_a)?;
+    b.get_list("list").insert(1, 2)?;
+    b.commit_then_renew();
+    let update_b = b.export_from(&a.oplog_vv());
+    c.import(&b.export_snapshot())?;
+    c.get_list("list").insert(2, 3)?;
+    c.commit_then_renew();
+    let update_c = c.export_from(&b.oplog_vv());
+
+    // Each update depends on the previous one
+    let doc = LoroDoc::new_auto_commit();
+    doc.import(&update_c)?;
+    assert_eq!(doc.get_deep_value().to_json_value(), json!({}));
+
+    let doc = LoroDoc::new_auto_commit();
+    let count = Arc::new(Mutex::new(0));
+    let count_cloned = count.clone();
+    doc.subscribe_root(Arc::new(move |event| {
+        assert_eq!(event.event_meta.by, EventTriggerKind::Import);
+        *count_cloned.lock().unwrap() += 1;
+    }));
+    doc.import_batch(&[update_c, update_b, update_a])?;
+    assert_eq!(*count.lock().unwrap(), 1);
+    assert!(!doc.is_detached());
+    assert_eq!(doc.get_deep_value(), c.get_deep_value());
+    assert_eq!(
+        doc.get_deep_value().to_json_value(),
+        json!({"list": [1, 2, 3]})
+    );
+    Ok(())
+}
+
 #[test]
 fn state_may_deadlock_when_import() {
     // helper function ref: https://github.com/rust-lang/rfcs/issues/2798#issuecomment-552949300
@@ -961,6 +1151,41 @@ fn counter() {
     doc2.import_json_updates(json).unwrap();
 }
 
+#[test]
+#[cfg(feature = "counter")]
+fn counter_sums_concurrent_increments() -> LoroResult<()> {
+    let doc_a = LoroDoc::new();
+    doc_a.set_peer_id(1)?;
+    let counter_a = doc_a.get_counter("counter");
+    let mut txn = doc_a.txn()?;
+    counter_a.increment_with_txn(&mut txn, 1.5)?;
+    counter_a.increment_with_txn(&mut txn, 2.)?;
+    txn.commit()?;
+
+    let doc_b = LoroDoc::new();
+    doc_b.set_peer_id(2)?;
+    let counter_b = doc_b.get_counter("counter");
+    let mut txn = doc_b.txn()?;
+    counter_b.decrement_with_txn(&mut txn, 0.5)?;
+    txn.commit()?;
+
+    doc_a.import(&doc_b.export_from(&Default::default()))?;
+    doc_b.import(&doc_a.export_from(&Default::default()))?;
+    assert_eq!(counter_a.get_value(), LoroValue::Double(3.));
+    assert_eq!(counter_b.get_value(), LoroValue::Double(3.));
+
+    let doc_c = LoroDoc::new();
+    doc_c.import(&doc_a.export_snapshot())?;
+    assert_eq!(doc_c.get_deep_value(), doc_a.get_deep_value());
+    let doc_d = LoroDoc::new();
+    doc_d.import(&doc_b.export_from(&Default::default()))?;
+    assert_eq!(
+        doc_d.get_counter("counter").get_value(),
+        LoroValue::Double(3.)
+    );
+    Ok(())
+}
+
 #[test]
 fn test_insert_utf8() {
     let doc = LoroDoc::new_auto_commit();
@@ -997,6 +1222,47 @@ fn test_insert_utf8_cross_unicode_2() {
     )
 }
 
+#[test]
+fn text_utf16_and_unicode_index() {
+    let doc = LoroDoc::new_auto_commit();
+    let text = doc.get_text("text");
+    text.insert_unicode(0, "a😀b").unwrap();
+    assert_eq!(
+        (text.len_unicode(), text.len_utf16(), text.len_utf8()),
+        (3, 4, 6)
+    );
+    text.insert_utf16(3, "c").unwrap();
+    assert!(text.insert_utf16(2, "x").is_err());
+    assert!(text.insert_utf16(6, "x").is_err());
+    assert_eq!(text.to_string(), "a😀cb");
+    assert!(text.delete_utf16(2, 1).is_err());
+    text.delete_utf16(1, 2).unwrap();
+    text.delete_unicode(2, 1).unwrap();
+    assert!(text.delete_unicode(2, 1).is_err());
+    assert_eq!(text.to_string(), "ac");
+
+    text.insert_utf8(1, "你").unwrap();
+    assert_eq!(
+        text.convert_pos(4, PosType::Bytes, PosType::Unicode),
+        Some(2)
+    );
+    assert_eq!(text.convert_pos(2, PosType::Bytes, PosType::Unicode), None);
+    assert_eq!(
+        text.convert_pos(2, PosType::Unicode, PosType::Utf16),
+        Some(2)
+    );
+    assert_eq!(
+        text.convert_pos(3, PosType::Unicode, PosType::Bytes),
+        Some(5)
+    );
+    assert_eq!(text.convert_pos(4, PosType::Unicode, PosType::Bytes), None);
+
+    let detached = TextHandler::new_detached();
+    detached.insert_utf16(0, "😀😀").unwrap();
+    detached.delete_utf16(2, 2).unwrap();
+    assert_eq!(detached.to_string(), "😀");
+}
+
 #[test]
 fn test_insert_utf8_detached() {
     let text = TextHandler::new_detached();
@@ -1247,7 +1513,7 @@ fn test_text_update() {
     let doc = LoroDoc::new_auto_commit();
     let text = doc.get_text("text");
     text.insert(0, "Hello 😊Bro").unwrap();
-    text.update("Hello World Bro😊");
+    text.update("Hello World Bro😊").unwrap();
     assert_eq!(text.to_string(), "Hello World Bro😊");
 }
 
@@ -1255,9 +1521,1753 @@ fn test_text_update() {
 fn test_map_contains_key() {
     let doc = LoroDoc::new_auto_commit();
     let map = doc.get_map("m");
-    assert_eq!(map.contains_key(    "bro"), false);
+    assert_eq!(map.contains_key("bro"), false);
     map.insert("bro", 114514).unwrap();
     assert_eq!(map.contains_key("bro"), true);
     map.delete("bro").unwrap();
     assert_eq!(map.contains_key("bro"), false);
 }
+
+#[test]
+fn explain_encoding() {
+    let doc = LoroDoc::new_auto_commit();
+    doc.get_text("text").insert(0, "hello world").unwrap();
+    doc.get_map("map").insert("key", "value").unwrap();
+    let snapshot = doc.export_snapshot();
+    let report = loro_internal::encoding::explain(&snapshot).unwrap();
+    assert!(report.is_snapshot);
+    assert_eq!(report.total, snapshot.len());
+    assert_eq!(report.header, 22);
+    assert_eq!(report.peer_num, 1);
+    assert_eq!(report.change_num, 1);
+    assert_eq!(report.container_states.len(), 2);
+    assert!(report.state_blob > 0 || report.container_states.iter().any(|x| x.state_op_len > 0));
+
+    let updates = doc.export_from(&Default::default());
+    let report = loro_internal::encoding::explain(&updates).unwrap();
+    assert!(!report.is_snapshot);
+    assert_eq!(report.state_blob, 0);
+    assert!(report.raw_values > 0);
+    assert!(report.container_states.iter().all(|x| x.state_bytes == 0));
+    assert!(loro_internal::encoding::explain(&updates[..10]).is_err());
+}
+
+#[test]
+fn size_breakdown() -> LoroResult<()> {
+    use loro_internal::encoding::{size_breakdown_by, Dimension, SizeKey};
+    let a = LoroDoc::new_auto_commit();
+    a.set_peer_id(1)?;
+    a.get_text("text").insert(0, &"bloat".repeat(100))?;
+    let b = LoroDoc::new_auto_commit();
+    b.set_peer_id(2)?;
+    b.get_map("map").insert("key", "value")?;
+    b.get_text("text").insert(0, "hi")?;
+    a.import(&b.export_from(&Default::default()))?;
+
+    let updates = a.export_from(&Default::default());
+    let by_container = size_breakdown_by(&updates, Dimension::Container)?;
+    let by_peer = size_breakdown_by(&updates, Dimension::Peer)?;
+    let by_type = size_breakdown_by(&updates, Dimension::OpType)?;
+    assert_eq!(
+        by_container[0].key,
+        SizeKey::Container(ContainerID::new_root("text", ContainerType::Text))
+    );
+    assert_eq!(by_peer[0].key, SizeKey::Peer(1));
+    assert_eq!(by_peer[0].op_num, 1);
+    assert_eq!(by_type[0].key, SizeKey::OpType("text_insert"));
+    assert!(by_type.iter().any(|x| x.key == SizeKey::OpType("map_set")));
+
+    // Every dimension attributes the same bytes
+    let total = |entries: &[loro_internal::encoding::SizeEntry]| -> usize {
+        entries.iter().map(|x| x.bytes).sum()
+    };
+    assert_eq!(total(&by_container), total(&by_peer));
+    assert_eq!(total(&by_peer), total(&by_type));
+    assert!(total(&by_type) < updates.len());
+    Ok(())
+}
+
+#[test]
+fn change_commit_msg_and_timestamp() -> LoroResult<()> {
+    let doc = LoroDoc::new_auto_commit();
+    doc.set_peer_id(1)?;
+    let text = doc.get_text("text");
+    text.insert(0, "hello")?;
+    doc.commit_with(CommitOptions::new().commit_msg("init").timestamp(100));
+    text.insert(5, " world")?;
+    doc.commit_with(CommitOptions::new().commit_msg("append").timestamp(200));
+    text.insert(0, "> ")?;
+    doc.commit_with(CommitOptions::new().timestamp(300));
+
+    let changes: Vec<_> = doc.change_iter().collect();
+    assert_eq!(changes.len(), 3);
+    assert_eq!(changes[0].message.as_deref(), Some("init"));
+    assert_eq!(changes[1].message.as_deref(), Some("append"));
+    assert_eq!(changes[1].timestamp, 200);
+    assert_eq!(changes[2].message, None);
+    assert_eq!(doc.get_change(ID::new(1, 7)).unwrap(), changes[1]);
+
+    for bytes in [doc.export_snapshot(), doc.export_from(&Default::default())] {
+        let new_doc = LoroDoc::new_auto_commit();
+        new_doc.import(&bytes)?;
+        assert_eq!(new_doc.change_iter().collect::<Vec<_>>(), changes);
+    }
+
+    let new_doc = LoroDoc::new_auto_commit();
+    new_doc.import_json_updates(doc.export_json_updates(&Default::default(), &doc.oplog_vv()))?;
+    assert_eq!(new_doc.change_iter().collect::<Vec<_>>(), changes);
+    Ok(())
+}
+
+#[test]
+fn changes_page() -> LoroResult<()> {
+    let doc = LoroDoc::new_auto_commit();
+    doc.set_peer_id(1)?;
+    let text = doc.get_text("text");
+    for i in 0..5 {
+        text.insert(0, "a")?;
+        doc.commit_with(CommitOptions::new().commit_msg(&i.to_string()));
+    }
+
+    let (page, cursor) = doc.changes_page(None, 2);
+    let messages: Vec<_> = page.iter().map(|c| c.message.as_deref().unwrap()).collect();
+    assert_eq!(messages, ["4", "3"]);
+
+    // A concurrent change older than the cursor is on the next pages
+    let other = LoroDoc::new_auto_commit();
+    other.set_peer_id(2)?;
+    other.get_text("text").insert(0, "b")?;
+    other.commit_with(CommitOptions::new().commit_msg("other"));
+    doc.import(&other.export_from(&Default::default()))?;
+
+    let mut cursor = cursor;
+    let mut rest = Vec::new();
+    while let Some(c) = cursor {
+        let (page, next) = doc.changes_page(Some(&c), 2);
+        rest.extend(page);
+        cursor = next;
+    }
+    let mut expected: Vec<_> = doc.change_iter().collect();
+    expected.reverse();
+    assert_eq!(rest, expected[2..]);
+    assert_eq!(rest.last().unwrap().message.as_deref(), Some("0"));
+    assert_eq!(doc.changes_page(None, 10).1, None);
+    Ok(())
+}
+
+#[test]
+fn txn_stream_reports_ops_before_commit() -> LoroResult<()> {
+    let doc = LoroDoc::new_auto_commit();
+    doc.set_peer_id(1)?;
+    let text_id = ContainerID::new_root("text", ContainerType::Text);
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let events_clone = events.clone();
+    doc.set_txn_stream(Some(Arc::new(move |e: TxnStreamEvent| {
+        let e = match e {
+            TxnStreamEvent::Provisional { id, container, .. } => {
+                assert_eq!(container, &text_id);
+                format!("op {}", id.counter)
+            }
+            TxnStreamEvent::Committed { span } => {
+                format!("commit {}..{}", span.counter.start, span.counter.end)
+            }
+            TxnStreamEvent::Aborted { span } => {
+                format!("abort {}..{}", span.counter.start, span.counter.end)
+            }
+        };
+        events_clone.lock().unwrap().push(e);
+    })));
+
+    let text = doc.get_text("text");
+    text.insert(0, "ab")?;
+    text.insert(2, "c")?;
+    assert_eq!(*events.lock().unwrap(), vec!["op 0", "op 2"]);
+    // The provisional ops can't be exported before the commit
+    assert!(doc.oplog_vv().is_empty());
+
+    doc.commit_then_renew();
+    assert_eq!(*events.lock().unwrap(), vec!["op 0", "op 2", "commit 0..3"]);
+    text.insert(3, "d")?;
+    assert_eq!(events.lock().unwrap().last().unwrap(), "op 3");
+
+    // The aborted ops are rolled back and their ids are reused
+    doc.abort_txn();
+    assert_eq!(events.lock().unwrap().last().unwrap(), "abort 3..4");
+    assert_eq!(text.to_string(), "abc");
+    text.insert(0, "e")?;
+    doc.commit_then_renew();
+    assert_eq!(
+        events.lock().unwrap()[5..],
+        ["op 3".to_string(), "commit 3..4".to_string()]
+    );
+    assert_eq!(text.to_string(), "eabc");
+    Ok(())
+}
+
+#[test]
+fn text_attribution() -> LoroResult<()> {
+    let doc_a = LoroDoc::new_auto_commit();
+    doc_a.set_peer_id(1)?;
+    doc_a.get_text("text").insert(0, "hello world")?;
+    doc_a.commit_then_renew();
+    let doc_b = LoroDoc::new_auto_commit();
+    doc_b.set_peer_id(2)?;
+    doc_b.import(&doc_a.export_snapshot())?;
+    let text_b = doc_b.get_text("text");
+    text_b.delete(0, 6)?;
+    text_b.insert(0, "hi ")?;
+    doc_b.commit_then_renew();
+    doc_a.import(&doc_b.export_from(&doc_a.oplog_vv()))?;
+
+    let text_a = doc_a.get_text("text");
+    assert_eq!(text_a.to_string(), "hi world");
+    assert_eq!(
+        text_a.get_attribution(0..text_a.len_unicode())?,
+        vec![IdSpan::new(2, 6, 9), IdSpan::new(1, 6, 11)]
+    );
+    assert_eq!(
+        text_a.get_attribution(2..4)?,
+        vec![IdSpan::new(2, 8, 9), IdSpan::new(1, 6, 7)]
+    );
+    assert!(text_a.get_attribution(0..9).is_err());
+    Ok(())
+}
+
+#[test]
+fn snapshot_text_dedup() {
+    let doc = LoroDoc::new_auto_commit();
+    let text = doc.get_text("text");
+    // Prepend the lines, so that the inserts are not merged into one op
+    for _ in 0..10 {
+        text.insert(0, "[INFO] request handled\n").unwrap();
+    }
+
+    let plain = doc.export_snapshot();
+    let report = loro_internal::encoding::explain(&plain).unwrap();
+    assert_eq!(report.deduped_text_bytes, 0);
+    assert!(report.text_bytes > 0);
+
+    doc.set_snapshot_text_dedup(true);
+    let deduped = doc.export_snapshot();
+    let dedup_report = loro_internal::encoding::explain(&deduped).unwrap();
+    assert!(deduped.len() < plain.len());
+    assert_eq!(dedup_report.text_bytes, report.text_bytes);
+    assert!(dedup_report.deduped_text_bytes > 0);
+
+    let new_doc = LoroDoc::new_auto_commit();
+    new_doc.import(&deduped).unwrap();
+    assert_eq!(new_doc.get_deep_value(), doc.get_deep_value());
+}
+
+#[test]
+fn op_compression_threshold() {
+    let doc = LoroDoc::new_auto_commit();
+    let text = doc.get_text("text");
+    let paste = "2024-01-01 [INFO] request handled in 3ms\n".repeat(25000);
+    assert!(paste.len() > 1_000_000);
+    text.insert(0, &paste).unwrap();
+    text.insert(0, "short").unwrap();
+
+    let plain = doc.export_from(&Default::default());
+    doc.set_op_compression_threshold(Some(1024)).unwrap();
+    let compressed = doc.export_from(&Default::default());
+    assert!(compressed.len() * 100 < plain.len());
+    let report = loro_internal::encoding::explain(&compressed).unwrap();
+    assert_eq!(report.compressed_text_bytes, paste.len());
+    assert_eq!(report.text_bytes, paste.len() + 5);
+
+    let snapshot = doc.export_snapshot();
+    assert!(snapshot.len() * 100 < plain.len());
+    for bytes in [&compressed, &snapshot] {
+        let new_doc = LoroDoc::new_auto_commit();
+        new_doc.import(bytes).unwrap();
+        assert_eq!(new_doc.get_deep_value(), doc.get_deep_value());
+    }
+}
+
+#[test]
+fn config_update_and_notify() {
+    let doc = LoroDoc::new_auto_commit();
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let changes_clone = changes.clone();
+    doc.config()
+        .set_on_change(Some(Arc::new(move |changed: &ConfigOptions| {
+            changes_clone.lock().unwrap().push(changed.clone());
+        })));
+
+    doc.config()
+        .update(&ConfigOptions {
+            record_timestamp: Some(true),
+            merge_interval: Some(doc.config().merge_interval()),
+            ..Default::default()
+        })
+        .unwrap();
+    doc.set_change_merge_interval(10).unwrap();
+    assert_eq!(
+        *changes.lock().unwrap(),
+        vec![
+            ConfigOptions {
+                record_timestamp: Some(true),
+                ..Default::default()
+            },
+            ConfigOptions {
+                merge_interval: Some(10),
+                ..Default::default()
+            },
+        ]
+    );
+
+    // An invalid update changes nothing
+    assert!(doc
+        .config()
+        .update(&ConfigOptions {
+            record_timestamp: Some(false),
+            auto_commit_ops_limit: Some(Some(0)),
+            ..Default::default()
+        })
+        .is_err());
+    assert!(doc.config().record_timestamp());
+    assert_eq!(changes.lock().unwrap().len(), 2);
+
+    let other = LoroDoc::new_auto_commit();
+    other.config().update(&doc.config().options()).unwrap();
+    assert_eq!(other.config().options(), doc.config().options());
+}
+
+#[test]
+fn json_updates_round_trip_with_binary() -> LoroResult<()> {
+    let doc = LoroDoc::new_auto_commit();
+    doc.set_peer_id(1)?;
+    doc.get_text("text").insert(0, "Hello")?;
+    doc.get_map("map").insert("key", 1)?;
+    doc.commit_with(CommitOptions::new().commit_msg("first"));
+    let v1 = doc.oplog_vv();
+    doc.get_list("list").insert(0, "a")?;
+    doc.get_text("text").delete(0, 1)?;
+    doc.commit_then_renew();
+
+    let json = doc.export_json_updates(&Default::default(), &doc.oplog_vv());
+    let json = serde_json::to_string(&json).unwrap();
+    let from_json = LoroDoc::new_auto_commit();
+    from_json.import_json_updates(json.as_str())?;
+    assert_eq!(from_json.get_deep_value(), doc.get_deep_value());
+    assert_eq!(
+        from_json
+            .get_change(ID::new(1, 0))
+            .unwrap()
+            .message
+            .as_deref(),
+        Some("first")
+    );
+    assert_eq!(from_json.oplog_frontiers(), doc.oplog_frontiers());
+
+    // The json and binary updates of adjacent ranges can be mixed
+    let mixed = LoroDoc::new_auto_commit();
+    let json = doc.export_json_updates(&Default::default(), &v1);
+    mixed.import_json_updates(serde_json::to_string(&json).unwrap())?;
+    mixed.import(&doc.export_from(&v1))?;
+    assert_eq!(mixed.get_deep_value(), doc.get_deep_value());
+    let binary = LoroDoc::new_auto_commit();
+    binary.import(&from_json.export_from(&Default::default()))?;
+    assert_eq!(binary.get_deep_value(), doc.get_deep_value());
+
+    let mut future = doc.export_json_updates(&Default::default(), &doc.oplog_vv());
+    future.schema_version += 1;
+    assert!(LoroDoc::new_auto_commit()
+        .import_json_updates(future)
+        .is_err());
+    Ok(())
+}
+
+#[test]
+fn fork_at_version() -> LoroResult<()> {
+    let doc = LoroDoc::new_auto_commit();
+    doc.set_peer_id(1)?;
+    doc.set_record_timestamp(true);
+    doc.get_map("map").insert("a", 1)?;
+    doc.commit_then_renew();
+    let v1 = doc.oplog_frontiers();
+    doc.get_map("map").insert("b", 2)?;
+    doc.commit_then_renew();
+
+    let branch = doc.fork_at(&v1)?;
+    assert_ne!(branch.peer_id(), doc.peer_id());
+    assert!(branch.config().record_timestamp());
+    assert_eq!(branch.oplog_frontiers(), v1);
+    assert_eq!(
+        branch.get_deep_value().to_json_value(),
+        json!({"map": {"a": 1}})
+    );
+    branch.get_map("map").insert("c", 3)?;
+    branch.commit_then_renew();
+
+    doc.import(&branch.export_from(&doc.oplog_vv()))?;
+    assert_eq!(
+        doc.get_deep_value().to_json_value(),
+        json!({"map": {"a": 1, "b": 2, "c": 3}})
+    );
+    assert!(doc.fork_at(&ID::new(2, 0).into()).is_err());
+    Ok(())
+}
+
+#[test]
+fn import_with_budget() -> LoroResult<()> {
+    let doc = LoroDoc::new_auto_commit();
+    let list = doc.get_list("list");
+    for i in 0..1000 {
+        list.insert(0, i)?;
+    }
+    doc.commit_then_renew();
+    let updates = doc.export_from(&Default::default());
+
+    let new_doc = LoroDoc::new_auto_commit();
+    let err = new_doc.import_with_budget(&updates, 10_000).unwrap_err();
+    match err {
+        LoroError::BudgetExceeded { needed, budget } => {
+            assert_eq!(budget, 10_000);
+            assert!(needed > budget);
+        }
+        _ => panic!("unexpected error {:?}", err),
+    }
+    assert!(new_doc.oplog_vv().is_empty());
+
+    new_doc.import_with_budget(&updates, 10_000_000)?;
+    assert_eq!(new_doc.get_deep_value(), doc.get_deep_value());
+    Ok(())
+}
+
+#[test]
+fn dep_graph() -> LoroResult<()> {
+    let a = LoroDoc::new_auto_commit();
+    a.set_peer_id(1)?;
+    let b = LoroDoc::new_auto_commit();
+    b.set_peer_id(2)?;
+    a.get_text("text").insert(0, "ab")?;
+    a.commit_then_renew();
+    b.import(&a.export_from(&Default::default()))?;
+    b.get_text("text").insert(2, "c")?;
+    b.commit_then_renew();
+    a.import(&b.export_from(&a.oplog_vv()))?;
+    a.get_text("text").insert(0, "d")?;
+    a.commit_then_renew();
+
+    let graph = a.export_dep_graph();
+    assert_eq!(graph.nodes.len(), 3);
+    assert_eq!(graph.nodes[0].id, ID::new(1, 0));
+    assert_eq!(graph.nodes[0].len, 2);
+    assert_eq!(graph.nodes[1].deps, ID::new(1, 1).into());
+    assert_eq!(graph.nodes[2].deps, ID::new(2, 0).into());
+    assert_eq!(graph.check_causal_order(), Ok(()));
+
+    let mut broken = graph.clone();
+    broken.nodes.swap(0, 2);
+    assert!(broken.check_causal_order().is_err());
+    let mut broken = graph.clone();
+    broken.nodes[2].lamport = 0;
+    assert!(broken.check_causal_order().is_err());
+    Ok(())
+}
+
+#[cfg(feature = "parallel")]
+#[test]
+fn par_get_values() -> LoroResult<()> {
+    let doc = LoroDoc::new_auto_commit();
+    let mut ids = Vec::new();
+    for i in 0..16 {
+        let text = doc.get_text(format!("text{}", i).as_str());
+        text.insert(0, &"x".repeat(i))?;
+        ids.push(text.id());
+    }
+    let map = doc.get_map("map");
+    map.insert_container("list", ListHandler::new_detached())?
+        .insert(0, 1)?;
+    ids.push(map.id());
+    ids.push(ContainerID::new_root("empty", ContainerType::List));
+    doc.commit_then_renew();
+
+    let values = doc.par_get_values(&ids);
+    assert_eq!(values.len(), ids.len());
+    for (i, value) in values[..16].iter().enumerate() {
+        assert_eq!(value, &LoroValue::String(Arc::new("x".repeat(i))));
+    }
+    assert_eq!(values[16].to_json_value(), json!({"list": [1]}));
+    // The unknown containers are not registered by the read
+    assert_eq!(values[17], LoroValue::Null);
+    assert_eq!(doc.par_get_values(&ids[17..]), vec![LoroValue::Null]);
+    Ok(())
+}
+
+#[test]
+fn root_alias() -> LoroResult<()> {
+    let old = LoroDoc::new_auto_commit();
+    old.get_map("settings").insert("theme", "dark")?;
+    let new = LoroDoc::new_auto_commit();
+    new.import(&old.export_snapshot())?;
+    new.alias_root("settings", "preferences")?;
+    new.alias_root("preferences", "prefs")?;
+    new.get_map("prefs").insert("font", "mono")?;
+    assert_eq!(
+        new.get_deep_value().to_json_value(),
+        json!({"settings": {"theme": "dark", "font": "mono"}})
+    );
+
+    assert!(new.alias_root("settings", "prefs").is_ok());
+    assert!(new.alias_root("prefs", "settings").is_err());
+    assert!(new.alias_root("settings", "__loro_x").is_err());
+
+    // The old client doesn't know the alias but sees the same container
+    old.import(&new.export_from(&old.oplog_vv()))?;
+    assert_eq!(
+        old.get_map("settings").get("font"),
+        Some(LoroValue::String(Arc::new("mono".into())))
+    );
+
+    new.remove_root_alias("prefs")?;
+    assert!(new.get_map("prefs").is_empty());
+
+    // An alias doesn't hide a root with data, even with the reserved prefix
+    new.get_text("notes").insert(0, "mine")?;
+    new.get_text("__loro_notes").insert(0, "legacy")?;
+    new.alias_root("settings", "notes")?;
+    assert_eq!(new.get_text("notes").to_string(), "mine");
+    let value = new.get_deep_value().to_json_value();
+    assert_eq!(value["notes"], json!("mine"));
+    assert_eq!(value["__loro_notes"], json!("legacy"));
+    assert!(value.get("__loro_root_aliases").is_none());
+    Ok(())
+}
+
+#[test]
+fn wait_for_version() -> LoroResult<()> {
+    let a = LoroDoc::new_auto_commit();
+    let b = LoroDoc::new_auto_commit();
+    a.get_text("text").insert(0, "a")?;
+    a.commit_then_renew();
+    let v1 = a.oplog_frontiers();
+    let updates_1 = a.export_from(&Default::default());
+    a.get_text("text").insert(1, "b")?;
+    a.commit_then_renew();
+    let v2 = a.oplog_frontiers();
+    let updates_2 = a.export_from(&b.oplog_vv());
+
+    let reached = Arc::new(Mutex::new(Vec::new()));
+    for (name, version) in [("v1", v1.clone()), ("v2", v2)] {
+        let reached = reached.clone();
+        b.wait_for_version(
+            version,
+            Box::new(move || reached.lock().unwrap().push(name)),
+        );
+    }
+    assert!(reached.lock().unwrap().is_empty());
+    b.import(&updates_1)?;
+    assert_eq!(*reached.lock().unwrap(), vec!["v1"]);
+    b.import_batch(&[updates_2])?;
+    assert_eq!(*reached.lock().unwrap(), vec!["v1", "v2"]);
+    assert!(!a.changes_since(&b.oplog_vv()));
+
+    // An included version is reported immediately
+    let reached_clone = reached.clone();
+    b.wait_for_version(
+        v1,
+        Box::new(move || reached_clone.lock().unwrap().push("again")),
+    );
+    assert_eq!(reached.lock().unwrap().len(), 3);
+    Ok(())
+}
+
+#[test]
+fn borrowed_value_views() -> LoroResult<()> {
+    let doc = LoroDoc::new_auto_commit();
+    let map = doc.get_map("map");
+    map.insert("name", "Alice")?;
+    let child = map.insert_container("child", MapHandler::new_detached())?;
+    assert_eq!(
+        map.get_ref("name", |v| v.map(|v| v.to_value())),
+        map.get("name")
+    );
+    assert_eq!(
+        map.get_ref("name", |v| v.unwrap().as_string().map(|s| s.to_string())),
+        Some("Alice".to_string())
+    );
+    assert!(
+        map.get_ref("child", |v| **v.unwrap().as_container().unwrap()
+            == child.id())
+    );
+    assert!(map.get_ref("missing", |v| v.is_none()));
+
+    let detached = MapHandler::new_detached();
+    detached.insert("n", 1)?;
+    assert!(detached.get_ref("n", |v| v == Some(ValueRef::I64(1))));
+
+    let text = doc.get_text("text");
+    text.insert(0, "Hello")?;
+    text.mark(0, 2, "bold", true.into())?;
+    text.insert(5, " world")?;
+    let chunks = text.as_chunks(|chunks| chunks.collect::<String>());
+    assert_eq!(chunks, "Hello world");
+    let detached = TextHandler::new_detached();
+    detached.insert(0, "abc")?;
+    assert_eq!(
+        detached.as_chunks(|chunks| chunks.collect::<String>()),
+        "abc"
+    );
+    Ok(())
+}
+
+#[test]
+fn peer_metadata() -> LoroResult<()> {
+    let a = LoroDoc::new_auto_commit();
+    a.set_peer_id(1)?;
+    a.get_text("text").insert(0, "a")?;
+    a.commit_then_renew();
+    assert_eq!(a.peer_name(1), None);
+    assert_eq!(a.get_change(ID::new(1, 0)).unwrap().author, None);
+    a.set_peer_metadata(PEER_NAME_KEY, "Alice")?;
+    a.set_peer_metadata("avatar", "https://example.com/a.png")?;
+    a.commit_then_renew();
+
+    let b = LoroDoc::new_auto_commit();
+    b.set_peer_id(2)?;
+    b.import(&a.export_snapshot())?;
+    b.set_peer_metadata(PEER_NAME_KEY, "Bob")?;
+    b.get_text("text").insert(1, "b")?;
+    b.commit_then_renew();
+    // Hidden from the value of the doc
+    assert_eq!(b.get_deep_value().to_json_value(), json!({"text": "ab"}));
+    assert_eq!(b.peer_metadata(1).len(), 2);
+    assert_eq!(b.peer_metadata(2).len(), 1);
+    let authors: Vec<_> = b.change_iter().map(|c| c.author).collect();
+    assert!(authors.contains(&Some("Alice".into())));
+    assert!(authors.contains(&Some("Bob".into())));
+
+    // The latest name wins
+    a.set_peer_metadata(PEER_NAME_KEY, "Alice Liddell")?;
+    a.commit_then_renew();
+    b.import(&a.export_from(&b.oplog_vv()))?;
+    assert_eq!(b.peer_name(1).as_deref(), Some("Alice Liddell"));
+    assert_eq!(
+        b.get_change(ID::new(1, 0)).unwrap().author.as_deref(),
+        Some("Alice Liddell")
+    );
+    Ok(())
+}
+
+#[test]
+fn export_for_backup() -> LoroResult<()> {
+    let a = LoroDoc::new_auto_commit();
+    a.set_peer_id(1)?;
+    let b = LoroDoc::new_auto_commit();
+    b.set_peer_id(2)?;
+    for i in 0..10_000 {
+        a.get_text("a").insert(0, &(i % 10).to_string())?;
+        b.get_text("b").insert(0, &(i % 10).to_string())?;
+    }
+    a.commit_then_renew();
+    b.commit_then_renew();
+    a.import(&b.export_snapshot())?;
+    a.get_map("map").insert("key", "value")?;
+    a.commit_then_renew();
+
+    let backup = a.export_for_backup();
+    let doc = LoroDoc::new_auto_commit();
+    doc.import(&backup)?;
+    assert_eq!(doc.get_deep_value(), a.get_deep_value());
+    assert_eq!(&doc.oplog_vv(), &a.oplog_vv());
+    let meta = LoroDoc::decode_import_blob_meta(&backup)?;
+    assert!(!meta.is_snapshot);
+    assert_eq!(meta.partial_end_vv, a.oplog_vv());
+    assert!(matches!(
+        doc.import_with_budget(&backup, 1000),
+        Err(LoroError::BudgetExceeded { .. })
+    ));
+
+    // Only the end of the ops of peer 1 changes, the chunks of peer 2 are kept
+    a.get_text("a").insert(0, "new")?;
+    a.commit_then_renew();
+    let new_backup = a.export_for_backup();
+    let header_len = 22;
+    let shared = backup[header_len..]
+        .iter()
+        .zip(&new_backup[header_len..])
+        .take_while(|(x, y)| x == y)
+        .count();
+    assert!(shared > (backup.len() - header_len) / 4);
+    let shared_suffix = backup
+        .iter()
+        .rev()
+        .zip(new_backup.iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    assert!(shared_suffix > backup.len() / 4);
+    doc.import(&new_backup)?;
+    assert_eq!(doc.get_deep_value(), a.get_deep_value());
+    Ok(())
+}
+
+#[test]
+fn export_sectioned_snapshot() -> LoroResult<()> {
+    let doc = LoroDoc::new_auto_commit();
+    doc.set_peer_id(1)?;
+    doc.get_text("text").insert(0, "hello")?;
+    doc.get_map("map").insert("key", 1)?;
+    doc.commit_then_renew();
+    let blob = doc.export_sectioned_snapshot();
+
+    assert_eq!(LoroDoc::decode_state_section(&blob)?, doc.get_deep_value());
+    // The history bytes are not read when decoding the state
+    let mut corrupted = blob.clone();
+    *corrupted.last_mut().unwrap() ^= 1;
+    assert_eq!(
+        LoroDoc::decode_state_section(&corrupted)?,
+        doc.get_deep_value()
+    );
+    assert!(LoroDoc::new().import(&corrupted).is_err());
+
+    let history = LoroDoc::history_section(&blob)?;
+    assert_eq!(history, doc.export_from(&Default::default()));
+    let archive = LoroDoc::new_auto_commit();
+    archive.import(history)?;
+    assert_eq!(archive.get_deep_value(), doc.get_deep_value());
+
+    // The state section is a snapshot
+    let viewer = LoroDoc::from_snapshot(LoroDoc::state_section(&blob)?)?;
+    assert_eq!(viewer.get_deep_value(), doc.get_deep_value());
+    assert_eq!(viewer.state_hash(), doc.state_hash());
+
+    let imported = LoroDoc::new_auto_commit();
+    imported.import(&blob)?;
+    assert_eq!(imported.get_deep_value(), doc.get_deep_value());
+    assert_eq!(imported.oplog_vv(), doc.oplog_vv());
+    assert!(LoroDoc::decode_import_blob_meta(&blob)?.is_snapshot);
+    assert!(LoroDoc::decode_state_section(history).is_err());
+
+    // A doc that isn't empty imports the history section
+    let other = LoroDoc::new_auto_commit();
+    other.set_peer_id(2)?;
+    other.get_text("text").insert(0, "world")?;
+    other.import(&blob)?;
+    doc.import(&other.export_from(&Default::default()))?;
+    assert_eq!(other.get_deep_value(), doc.get_deep_value());
+    Ok(())
+}
+
+#[test]
+fn export_preview() -> LoroResult<()> {
+    let doc = LoroDoc::new_auto_commit();
+    let text = doc.get_text("text");
+    text.insert(0, &"deleted ".repeat(100))?;
+    text.delete(0, 800)?;
+    text.insert(0, "visible")?;
+    let list = doc
+        .get_map("map")
+        .insert_container("list", ListHandler::new_detached())?;
+    list.insert(0, 1)?;
+    text.mark(0, 3, "bold", true.into())?;
+    let preview = doc.export_preview()?;
+    assert!(preview.len() < 200);
+    let value = LoroDoc::decode_preview(&preview)?;
+    assert_eq!(value, doc.get_deep_value_with_richtext());
+    assert_eq!(
+        value.as_map().unwrap().get("text").unwrap(),
+        &text.get_richtext_value()
+    );
+    let sectioned = doc.export_sectioned_snapshot();
+    assert_eq!(LoroDoc::decode_preview(&sectioned)?, value);
+    let report = loro_internal::encoding::explain(&preview)?;
+    assert_eq!(report.header + report.state_blob, preview.len());
+
+    // It has no history to import
+    let other = LoroDoc::new_auto_commit();
+    assert!(other.import(&preview).is_err());
+    assert!(LoroDoc::decode_import_blob_meta(&preview).is_err());
+    assert!(LoroDoc::decode_preview(&doc.export_snapshot()).is_err());
+    let mut corrupted = preview.clone();
+    *corrupted.last_mut().unwrap() ^= 1;
+    assert!(LoroDoc::decode_preview(&corrupted).is_err());
+    Ok(())
+}
+
+#[test]
+fn max_ops_per_peer() -> LoroResult<()> {
+    let a = LoroDoc::new_auto_commit();
+    a.set_peer_id(1)?;
+    a.get_text("text").insert(0, "0123456789")?;
+    a.commit_then_renew();
+    // c depends on the ops of a beyond the limit
+    let c = LoroDoc::new_auto_commit();
+    c.set_peer_id(2)?;
+    c.import(&a.export_snapshot())?;
+    c.get_text("text").insert(10, "c")?;
+    c.get_map("map").insert("key", 1)?;
+    c.commit_then_renew();
+    // d only depends on the ops of a within the limit
+    let d = a.fork_at(&ID::new(1, 4).into())?;
+    d.set_peer_id(3)?;
+    d.get_text("text").insert(0, "d")?;
+    d.commit_then_renew();
+
+    let updates = [
+        a.export_from(&Default::default()),
+        c.export_from(&a.oplog_vv()),
+        d.export_from(&Default::default()),
+    ];
+    let mut versions = Vec::new();
+    for order in [[0, 1, 2], [1, 2, 0], [2, 1, 0]] {
+        let doc = LoroDoc::new_auto_commit();
+        doc.set_max_ops_per_peer(Some(5))?;
+        let rejected = Arc::new(Mutex::new(Vec::new()));
+        let rejected_clone = rejected.clone();
+        doc.set_on_ops_rejected(Some(Arc::new(move |spans| {
+            rejected_clone.lock().unwrap().extend_from_slice(spans);
+        })));
+        for i in order {
+            doc.import(&updates[i])?;
+        }
+
+        let mut rejected = rejected.lock().unwrap().clone();
+        rejected.sort_by_key(|x| x.peer);
+        assert_eq!(rejected, vec![IdSpan::new(1, 5, 10), IdSpan::new(2, 0, 2)]);
+        assert_eq!(doc.get_text("text").to_string(), "d01234");
+        versions.push(doc.oplog_vv());
+    }
+
+    assert!(versions.iter().all(|x| x == &versions[0]));
+    let doc = LoroDoc::new_auto_commit();
+    doc.set_max_ops_per_peer(Some(3))?;
+    doc.get_text("text").insert(0, "012")?;
+    assert!(matches!(
+        doc.get_text("text").insert(0, "3"),
+        Err(LoroError::OpQuotaExceeded { limit: 3, .. })
+    ));
+    // the limit is fixed once the doc has ops
+    assert!(doc.set_max_ops_per_peer(Some(4)).is_err());
+    assert!(doc.set_max_ops_per_peer(Some(3)).is_ok());
+    assert!(doc.set_change_merge_interval(-1).is_err());
+    assert!(doc.set_auto_commit_ops_limit(Some(0)).is_err());
+    Ok(())
+}
+
+#[test]
+fn max_bytes_and_containers_per_peer() -> LoroResult<()> {
+    let a = LoroDoc::new_auto_commit();
+    a.set_peer_id(1)?;
+    a.get_text("text").insert(0, "012")?;
+    a.commit_then_renew();
+    let a_first = a.export_from(&Default::default());
+    let a_v1 = a.oplog_vv();
+    a.get_text("text").insert(3, "3456789")?;
+    a.commit_then_renew();
+    let a_later = a.export_from(&a_v1);
+    let b = LoroDoc::new_auto_commit();
+    b.set_peer_id(2)?;
+    for key in ["a", "b", "c"] {
+        b.get_map("map")
+            .insert_container(key, MapHandler::new_detached())?;
+    }
+    b.commit_then_renew();
+    // c depends on the rejected ops of a
+    let c = LoroDoc::new_auto_commit();
+    c.set_peer_id(3)?;
+    c.import(&a.export_snapshot())?;
+    c.get_text("text").insert(10, "c")?;
+    c.commit_then_renew();
+    let c_first = c.export_from(&a.oplog_vv());
+    let c_v1 = c.oplog_vv();
+    c.get_text("text").insert(0, "c")?;
+    c.commit_then_renew();
+    let c_later = c.export_from(&c_v1);
+
+    let updates = [
+        a_first,
+        a_later,
+        b.export_from(&Default::default()),
+        c_first,
+    ];
+    let watch = |doc: &LoroDoc| {
+        let rejected = Arc::new(Mutex::new(Vec::new()));
+        let rejected_clone = rejected.clone();
+        doc.set_on_ops_rejected(Some(Arc::new(move |spans| {
+            rejected_clone.lock().unwrap().extend_from_slice(spans);
+        })));
+        rejected
+    };
+    let new_doc = || -> LoroResult<LoroDoc> {
+        let doc = LoroDoc::new_auto_commit();
+        doc.set_max_bytes_per_peer(Some(5))?;
+        doc.set_max_containers_per_peer(Some(2))?;
+        Ok(doc)
+    };
+
+    let mut docs = Vec::new();
+    // The later changes of a wait for the earlier ones in the pending changes
+    for order in [[0, 1, 2, 3], [1, 3, 2, 0], [3, 1, 0, 2]] {
+        let doc = new_doc()?;
+        let rejected = watch(&doc);
+        for i in order {
+            doc.import(&updates[i])?;
+        }
+
+        let mut rejected = rejected.lock().unwrap().clone();
+        rejected.sort_by_key(|x| x.peer);
+        assert_eq!(
+            rejected,
+            vec![
+                IdSpan::new(1, 5, 10),
+                IdSpan::new(2, 2, 3),
+                IdSpan::new(3, 0, 1)
+            ]
+        );
+        assert_eq!(
+            doc.get_deep_value().to_json_value(),
+            json!({"text": "01234", "map": {"a": {}, "b": {}}})
+        );
+        docs.push(doc);
+    }
+
+    // The rejection of c is kept by the forks and the snapshots, so its later ops are
+    // rejected instead of waiting for the rejected ones
+    let doc = docs.pop().unwrap();
+    let restored = new_doc()?;
+    restored.import(&doc.export_snapshot())?;
+    let fork = doc.fork();
+    for doc in [restored, fork] {
+        let rejected = watch(&doc);
+        doc.import(&c_later)?;
+        assert_eq!(*rejected.lock().unwrap(), vec![IdSpan::new(3, 1, 2)]);
+    }
+
+    let doc = new_doc()?;
+    doc.get_text("text").insert(0, "01234")?;
+    assert!(matches!(
+        doc.get_text("text").insert(0, "5"),
+        Err(LoroError::OpQuotaExceeded {
+            limit: 5,
+            unit: "bytes",
+            ..
+        })
+    ));
+    let doc = new_doc()?;
+    let map = doc.get_map("map");
+    map.insert_container("a", MapHandler::new_detached())?;
+    map.insert_container("b", MapHandler::new_detached())?;
+    assert!(matches!(
+        map.insert_container("c", MapHandler::new_detached()),
+        Err(LoroError::OpQuotaExceeded {
+            limit: 2,
+            unit: "containers",
+            ..
+        })
+    ));
+    Ok(())
+}
+
+#[test]
+fn cursor_encoding() {
+    let doc = LoroDoc::new_auto_commit();
+    doc.set_peer_id(1).unwrap();
+    let text = doc.get_text("text");
+    text.insert(0, "hello world").unwrap();
+    let cursor = text.get_cursor(6, Side::Middle).unwrap();
+    assert_eq!(Cursor::decode(&cursor.encode()).unwrap(), cursor);
+    assert_eq!(cursor.to_string(), "c1:M:6:6@1:cid:root-text:Text");
+    assert_eq!(
+        Cursor::try_from(cursor.to_string().as_str()).unwrap(),
+        cursor
+    );
+    // The cursors encoded by the older versions
+    let legacy = postcard::to_allocvec(&cursor).unwrap();
+    assert_eq!(Cursor::decode(&legacy).unwrap(), cursor);
+
+    let bytes = cursor.encode();
+    assert!(Cursor::decode(&bytes[..bytes.len() - 1]).is_err());
+    assert!(Cursor::decode(&[bytes[0], 2]).is_err());
+    assert!(Cursor::try_from("c2:M:6:6@1:cid:root-text:Text").is_err());
+
+    let list = doc.get_list("list");
+    list.insert(0, 1).unwrap();
+    let cursor = list.get_cursor(1, Side::Right).unwrap();
+    assert_eq!(Cursor::decode(&cursor.encode()).unwrap(), cursor);
+    assert_eq!(
+        Cursor::try_from(cursor.to_string().as_str()).unwrap(),
+        cursor
+    );
+}
+
+#[test]
+fn cursor_falls_back_to_origin_pos() {
+    let doc = LoroDoc::new_auto_commit();
+    doc.set_peer_id(1).unwrap();
+    let text = doc.get_text("text");
+    text.insert(0, "hello world").unwrap();
+    let cursor = text.get_cursor(6, Side::Middle).unwrap();
+    assert!(!doc.query_pos(&cursor).unwrap().approximate);
+
+    // The history that has the id of the cursor is missing
+    let other = LoroDoc::new_auto_commit();
+    other.set_peer_id(2).unwrap();
+    other.get_text("text").insert(0, "hello the world").unwrap();
+    let ans = other.query_pos(&cursor).unwrap();
+    assert!(ans.approximate);
+    assert_eq!(ans.current.pos, 6);
+    other.get_text("text").delete(3, 12).unwrap();
+    let ans = other.query_pos(&cursor).unwrap();
+    assert!(ans.approximate);
+    assert_eq!(ans.current.pos, 3);
+    assert_eq!(ans.update.unwrap().id, None);
+}
+
+#[test]
+fn cursor_of_map_is_rejected() {
+    let doc = LoroDoc::new_auto_commit();
+    doc.set_peer_id(1).unwrap();
+    let map = doc.get_map("map");
+    map.insert("key", 1).unwrap();
+    let known = Cursor::new(Some(ID::new(1, 0)), map.id(), Side::Middle, 0);
+    let unknown = Cursor::new(Some(ID::new(2, 0)), map.id(), Side::Middle, 0);
+    for cursor in [known, unknown] {
+        assert!(matches!(
+            doc.query_pos(&cursor),
+            Err(CannotFindRelativePosition::IdNotFound)
+        ));
+        assert!(Cursor::decode(&cursor.encode()).is_err());
+        assert!(Cursor::try_from(cursor.to_string().as_str()).is_err());
+    }
+}
+
+#[test]
+fn pre_commit_hook() {
+    let doc = LoroDoc::new_auto_commit();
+    let others = Arc::new(AtomicUsize::new(0));
+    let others_clone = others.clone();
+    doc.set_pre_commit_hook(Some(Arc::new(move |op: StagedOp| -> Result<(), String> {
+        match op {
+            StagedOp::InsertText { text, .. } => {
+                if text.contains('!') {
+                    return Err("no exclamation".into());
+                }
+                *text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            }
+            StagedOp::Value { container, value } => {
+                if matches!(value, LoroValue::String(x) if x.is_empty()) {
+                    return Err(format!("empty string in {container}"));
+                }
+                if let LoroValue::I64(x) = value {
+                    *x = (*x).min(10);
+                }
+            }
+            StagedOp::Other { .. } => {
+                others_clone.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    })));
+
+    let text = doc.get_text("text");
+    text.insert(0, "a  b\t\tc").unwrap();
+    text.insert(0, "   ").unwrap();
+    assert_eq!(text.to_string(), "a b c");
+    let mut bold = FxHashMap::default();
+    bold.insert("bold".to_string(), true.into());
+    text.apply_delta(&[TextDelta::Insert {
+        insert: "x  y".into(),
+        attributes: Some(bold.clone()),
+    }])
+    .unwrap();
+    assert_eq!(
+        text.get_richtext_value().to_json_value(),
+        json!([{ "insert": "x y", "attributes": { "bold": true } }, { "insert": "a b c" }])
+    );
+
+    // The emptied insert isn't marked, and the ops after a shortened insert are shifted
+    text.apply_delta(&[
+        TextDelta::Insert {
+            insert: "  ".into(),
+            attributes: Some(bold),
+        },
+        TextDelta::Retain {
+            retain: 1,
+            attributes: None,
+        },
+        TextDelta::Insert {
+            insert: "-  -".into(),
+            attributes: None,
+        },
+        TextDelta::Delete { delete: 1 },
+    ])
+    .unwrap();
+    assert_eq!(text.to_string(), "x- -ya b c");
+    text.update("Aq   rx- -ya b cZ").unwrap();
+    assert_eq!(text.to_string(), "Aq rx- -ya b cZ");
+
+    // An edit of several ops is rolled back if one of them is rejected
+    let value = text.get_richtext_value();
+    assert!(matches!(
+        text.update("zAq rx- -ya b cZ!"),
+        Err(LoroError::OpRejected(_))
+    ));
+    assert!(matches!(
+        text.apply_delta(&[
+            TextDelta::Insert {
+                insert: "z".into(),
+                attributes: None,
+            },
+            TextDelta::Retain {
+                retain: 2,
+                attributes: None,
+            },
+            TextDelta::Insert {
+                insert: "!".into(),
+                attributes: None,
+            },
+        ]),
+        Err(LoroError::OpRejected(_))
+    ));
+    assert_eq!(text.get_richtext_value(), value);
+
+    let map = doc.get_map("map");
+    map.insert("n", 100).unwrap();
+    assert!(matches!(map.insert("s", ""), Err(LoroError::OpRejected(_))));
+    map.insert_container("child", MapHandler::new_detached())
+        .unwrap();
+    assert_eq!(map.get_value().as_map().unwrap().len(), 2);
+    assert_eq!(map.get("n"), Some(LoroValue::I64(10)));
+    doc.get_list("list").insert(0, 20).unwrap();
+    assert_eq!(doc.get_list("list").get(0), Some(LoroValue::I64(10)));
+
+    // The peers receive the rewritten ops
+    doc.commit_then_renew();
+    let other = LoroDoc::new_auto_commit();
+    other.import(&doc.export_snapshot()).unwrap();
+    assert_eq!(other.get_deep_value(), doc.get_deep_value());
+    assert!(others.load(Ordering::Relaxed) > 0);
+}
+
+#[test]
+fn state_hash() -> LoroResult<()> {
+    let doc = LoroDoc::new_auto_commit();
+    doc.set_peer_id(1)?;
+    let empty = doc.state_hash();
+    let text = doc.get_text("text");
+    text.insert(0, "Hello world")?;
+    let map = doc.get_map("map");
+    map.insert("a", 1)?;
+    let list = map.insert_container("list", ListHandler::new_detached())?;
+    list.insert(0, "x")?;
+    let after_edits = doc.state_hash();
+    assert_ne!(after_edits, empty);
+    assert_eq!(doc.state_hash(), after_edits);
+
+    // The incremental hash is the same as the one computed from scratch
+    let check = |doc: &LoroDoc| -> LoroResult<u64> {
+        let fresh = LoroDoc::new_auto_commit();
+        fresh.import(&doc.export_snapshot())?;
+        assert_eq!(fresh.state_hash(), doc.state_hash());
+        Ok(doc.state_hash())
+    };
+    check(&doc)?;
+    text.mark(0, 5, "bold", true.into())?;
+    let marked = check(&doc)?;
+    assert_ne!(marked, after_edits);
+    list.insert(1, 2)?;
+    check(&doc)?;
+    map.delete("list")?;
+    check(&doc)?;
+
+    // Replicas converge to the same hash, whatever the order of the imports
+    let other = LoroDoc::new_auto_commit();
+    other.set_peer_id(2)?;
+    other.get_map("map").insert("b", 2)?;
+    let other_hash = other.state_hash();
+    other.import(&doc.export_from(&Default::default()))?;
+    assert_ne!(other.state_hash(), other_hash);
+    doc.import(&other.export_from(&Default::default()))?;
+    assert_eq!(doc.get_deep_value(), other.get_deep_value());
+    assert_eq!(doc.state_hash(), other.state_hash());
+    check(&other)?;
+    Ok(())
+}
+
+/// Edit a map and a text in each transaction. The invariant is that the map has `k{i}`
+/// iff the text has `{i},`.
+fn txn_cross_container_doc(peer: u64, n: usize) -> LoroResult<LoroDoc> {
+    let doc = LoroDoc::new_auto_commit();
+    doc.set_peer_id(peer)?;
+    // Keep the transactions in separate changes
+    doc.set_change_merge_interval(0)?;
+    for i in 0..n {
+        doc.get_map("map")
+            .insert(&format!("{}-{}", peer, i), i as i64)?;
+        let text = doc.get_text("text");
+        text.insert(text.len_unicode(), &format!("{}-{},", peer, i))?;
+        doc.commit_then_renew();
+    }
+
+    Ok(doc)
+}
+
+fn check_txn_invariant(doc: &LoroDoc) {
+    let map = doc.get_map("map").get_value();
+    let map = map.as_map().unwrap();
+    let text = doc.get_text("text").to_string();
+    let items: Vec<&str> = text.split(',').filter(|x| !x.is_empty()).collect();
+    assert_eq!(items.len(), map.len(), "{:?} {:?}", items, map);
+    for item in items {
+        assert!(map.contains_key(item), "{} is missing from {:?}", item, map);
+    }
+}
+
+#[test]
+fn txn_is_observed_as_a_whole_in_any_import_order() -> LoroResult<()> {
+    let a = txn_cross_container_doc(1, 10)?;
+    let b = txn_cross_container_doc(2, 10)?;
+    a.import(&b.export_snapshot())?;
+    let chunks: Vec<Vec<u8>> = a
+        .export_chunks(&Default::default(), 1)
+        .map(|chunk| chunk[4..].to_vec())
+        .collect();
+    assert!(chunks.len() >= 20);
+
+    let forward: Vec<usize> = (0..chunks.len()).collect();
+    let reverse: Vec<usize> = forward.iter().rev().copied().collect();
+    let odd_first: Vec<usize> = forward
+        .iter()
+        .filter(|i| *i % 2 == 1)
+        .chain(forward.iter().filter(|i| *i % 2 == 0))
+        .copied()
+        .collect();
+    for order in [forward, reverse, odd_first] {
+        let doc = LoroDoc::new_auto_commit();
+        for i in order {
+            doc.import(&chunks[i])?;
+            check_txn_invariant(&doc);
+        }
+        assert_eq!(doc.get_deep_value(), a.get_deep_value());
+    }
+
+    // The same holds for updates exported from the versions of a partial replica
+    let doc = LoroDoc::new_auto_commit();
+    doc.import(&chunks[3])?;
+    doc.import(&chunks[0])?;
+    check_txn_invariant(&doc);
+    doc.import(&a.export_from(&doc.oplog_vv()))?;
+    check_txn_invariant(&doc);
+    assert_eq!(doc.get_deep_value(), a.get_deep_value());
+    Ok(())
+}
+
+#[test]
+fn txn_is_observed_as_a_whole_through_backup_and_events() -> LoroResult<()> {
+    let a = txn_cross_container_doc(1, 3000)?;
+    let doc = LoroDoc::new_auto_commit();
+    doc.import(&a.export_for_backup())?;
+    check_txn_invariant(&doc);
+
+    let a = txn_cross_container_doc(1, 1)?;
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let events_clone = events.clone();
+    let doc = LoroDoc::new_auto_commit();
+    doc.subscribe_root(Arc::new(move |e| {
+        events_clone.lock().unwrap().push(e.events.len());
+    }));
+    doc.import(&a.export_from(&Default::default()))?;
+    // One event with the diffs of both containers
+    assert_eq!(*events.lock().unwrap(), vec![2]);
+    Ok(())
+}
+
+#[test]
+fn txn_current_diff() -> LoroResult<()> {
+    let doc = LoroDoc::new_auto_commit();
+    assert!(doc.current_diff().is_empty());
+    let text = doc.get_text("text");
+    text.insert(0, "Hello")?;
+    text.insert(5, " world")?;
+    text.delete(0, 1)?;
+    let map = doc.get_map("map");
+    let list = map.insert_container("list", ListHandler::new_detached())?;
+    list.insert(0, 1)?;
+
+    let diffs = doc.current_diff();
+    assert_eq!(diffs.len(), 3);
+    let text_diff = diffs.iter().find(|d| d.id == text.id()).unwrap();
+    assert_eq!(
+        text_diff.diff.as_text().unwrap().to_json_value(),
+        json!([{"insert": "ello world"}])
+    );
+    let list_diff = diffs.iter().find(|d| d.id == list.id()).unwrap();
+    assert_eq!(list_diff.path.len(), 2);
+    assert!(diffs.last().unwrap().id == list.id());
+
+    // It doesn't change the state or commit the txn
+    assert_eq!(text.get_value().as_string().unwrap().as_str(), "ello world");
+    assert!(doc.oplog_vv().is_empty());
+    doc.commit_then_renew();
+    assert!(doc.current_diff().is_empty());
+    assert!(!doc.oplog_vv().is_empty());
+    Ok(())
+}
+
+#[test]
+fn metadata_overhead_of_automerge_paper() {
+    let doc = LoroDoc::new_auto_commit();
+    let text = doc.get_text("text");
+    for bench_utils::TextAction { pos, ins, del } in bench_utils::get_automerge_actions() {
+        text.delete(pos, del).unwrap();
+        text.insert(pos, &ins).unwrap();
+    }
+    let ratio = doc.metadata_overhead_ratio().unwrap();
+    assert!(ratio < 2.0, "ratio: {}", ratio);
+
+    // Map values count as content too
+    doc.get_map("map").insert("key", "a value").unwrap();
+    doc.commit_then_renew();
+    let report = loro_internal::encoding::explain(&doc.export_from(&Default::default())).unwrap();
+    assert!(report.value_bytes >= "a value".len());
+    assert!(report.content_bytes() > report.text_bytes);
+}
+
+/// The doc of the blobs in `tests/fixtures`, which were exported before op counters could
+/// be implicit
+fn legacy_counters_doc() -> LoroDoc {
+    let a = LoroDoc::new_auto_commit();
+    a.set_peer_id(1).unwrap();
+    let text = a.get_text("text");
+    text.insert(0, "Hello world").unwrap();
+    text.delete(5, 6).unwrap();
+    text.insert(5, ", loro").unwrap();
+    let map = a.get_map("map");
+    map.insert("a", 1).unwrap();
+    map.insert("b", "str").unwrap();
+    a.commit_then_renew();
+
+    let b = LoroDoc::new_auto_commit();
+    b.set_peer_id(2).unwrap();
+    b.import(&a.export_snapshot()).unwrap();
+    let list = b.get_list("list");
+    list.insert(0, 1).unwrap();
+    list.insert(1, "two").unwrap();
+    list.delete(0, 1).unwrap();
+    b.get_text("text").insert(0, "> ").unwrap();
+    b.get_map("map").delete("a").unwrap();
+    b.commit_then_renew();
+    b
+}
+
+#[test]
+fn explicit_counters_are_the_default_layout() {
+    const UPDATES: &[u8] = include_bytes!("fixtures/legacy_updates.blob");
+    const SNAPSHOT: &[u8] = include_bytes!("fixtures/legacy_snapshot.blob");
+    let expected = json!({"text": "> Hello, loro", "list": ["two"], "map": {"b": "str"}});
+    for blob in [UPDATES, SNAPSHOT] {
+        let doc = LoroDoc::new_auto_commit();
+        doc.import(blob).unwrap();
+        assert_eq!(doc.get_deep_value().to_json_value(), expected);
+    }
+
+    // Without the option, the blobs are the same as before
+    let doc = legacy_counters_doc();
+    assert_eq!(doc.export_from(&Default::default()), UPDATES);
+    assert_eq!(doc.export_snapshot(), SNAPSHOT);
+
+    // The blobs with implicit counters have a flag in the mode of the header
+    doc.set_implicit_op_counters(true);
+    let updates = doc.export_from(&Default::default());
+    let snapshot = doc.export_snapshot();
+    assert_ne!(updates[20..22], UPDATES[20..22]);
+    assert_ne!(snapshot[20..22], SNAPSHOT[20..22]);
+    for blob in [updates, snapshot] {
+        let doc = LoroDoc::new_auto_commit();
+        doc.import(&blob).unwrap();
+        assert_eq!(doc.get_deep_value().to_json_value(), expected);
+    }
+}
+
+#[test]
+fn root_type_conflicts_are_resolved_deterministically() -> LoroResult<()> {
+    fn fill(doc: &LoroDoc, ty: ContainerType) -> LoroResult<()> {
+        match ty {
+            ContainerType::Map => doc.get_map("data").insert("key", 1)?,
+            ContainerType::List => doc.get_list("data").push(1.into())?,
+            ContainerType::MovableList => doc.get_movable_list("data").push(1.into())?,
+            ContainerType::Text => doc.get_text("data").insert(0, "text")?,
+            ContainerType::Tree => {
+                doc.get_tree("data").create(None)?;
+            }
+            #[cfg(feature = "counter")]
+            ContainerType::Counter => doc.get_counter("data").increment(1.)?,
+            ContainerType::Unknown(_) => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[allow(unused_mut)]
+    let mut types = vec![
+        ContainerType::Map,
+        ContainerType::List,
+        ContainerType::MovableList,
+        ContainerType::Text,
+        ContainerType::Tree,
+    ];
+    #[cfg(feature = "counter")]
+    types.push(ContainerType::Counter);
+    for &a_type in types.iter() {
+        for &b_type in types.iter() {
+            if a_type == b_type {
+                continue;
+            }
+
+            let a = LoroDoc::new_auto_commit();
+            a.set_peer_id(1)?;
+            fill(&a, a_type)?;
+            a.commit_then_renew();
+            let b = LoroDoc::new_auto_commit();
+            b.set_peer_id(2)?;
+            fill(&b, b_type)?;
+            b.commit_then_renew();
+            let conflicts = Arc::new(Mutex::new(Vec::new()));
+            let conflicts_clone = conflicts.clone();
+            a.set_on_root_type_conflict(Some(Arc::new(move |name, types| {
+                conflicts_clone
+                    .lock()
+                    .unwrap()
+                    .push((name.to_string(), types.to_vec()));
+            })));
+            a.import(&b.export_snapshot())?;
+            b.import(&a.export_snapshot())?;
+            assert_eq!(a.get_deep_value(), b.get_deep_value());
+
+            let (winner, loser) = if a_type.to_u8() < b_type.to_u8() {
+                (a_type, b_type)
+            } else {
+                (b_type, a_type)
+            };
+            assert_eq!(
+                *conflicts.lock().unwrap(),
+                vec![("data".to_string(), vec![winner, loser])]
+            );
+            let value = a.get_deep_value();
+            let map = value.as_map().unwrap();
+            assert_eq!(map.len(), 1, "{:?}", map);
+            let shadowed = a.get_shadowed_roots_value();
+            assert_eq!(shadowed, b.get_shadowed_roots_value());
+            let shadowed = shadowed.as_map().unwrap().get("data").unwrap().clone();
+            assert_eq!(
+                shadowed.as_map().unwrap().keys().collect::<Vec<_>>(),
+                vec![&loser.to_string()]
+            );
+        }
+    }
+
+    // A local edit that makes a root share the name is reported too
+    let a = LoroDoc::new_auto_commit();
+    let conflicts = Arc::new(Mutex::new(Vec::new()));
+    let conflicts_clone = conflicts.clone();
+    a.set_on_root_type_conflict(Some(Arc::new(move |name, types| {
+        conflicts_clone
+            .lock()
+            .unwrap()
+            .push((name.to_string(), types.to_vec()));
+    })));
+    fill(&a, ContainerType::List)?;
+    fill(&a, ContainerType::List)?;
+    assert!(conflicts.lock().unwrap().is_empty());
+    fill(&a, ContainerType::Map)?;
+    fill(&a, ContainerType::Map)?;
+    assert_eq!(
+        *conflicts.lock().unwrap(),
+        vec![(
+            "data".to_string(),
+            vec![ContainerType::Map, ContainerType::List]
+        )]
+    );
+
+    // The empty roots don't take the name
+    let a = LoroDoc::new_auto_commit();
+    a.get_map("data").get_value();
+    a.get_list("data").push(1.into())?;
+    a.get_text("data");
+    a.commit_then_renew();
+    assert_eq!(a.get_deep_value().to_json_value(), json!({"data": [1]}));
+    Ok(())
+}
+
+#[test]
+fn forks_share_the_history() -> LoroResult<()> {
+    let doc = LoroDoc::new_auto_commit();
+    doc.set_peer_id(1)?;
+    doc.get_text("text").insert(0, "Hello")?;
+    doc.commit_then_renew();
+    let shared = |a: &LoroDoc, b: &LoroDoc, peer: u64| {
+        let a = a.oplog().lock().unwrap();
+        let b = b.oplog().lock().unwrap();
+        Arc::ptr_eq(&a.changes()[&peer], &b.changes()[&peer])
+    };
+
+    let fork = doc.fork();
+    fork.set_peer_id(2)?;
+    assert!(shared(&doc, &fork, 1));
+    // A change that depends on the shared changes doesn't copy them
+    fork.get_text("text").insert(5, " world")?;
+    fork.commit_then_renew();
+    assert!(shared(&doc, &fork, 1));
+
+    // Appending to them does
+    doc.get_text("text").insert(0, "> ")?;
+    doc.commit_then_renew();
+    assert!(!shared(&doc, &fork, 1));
+    assert_eq!(fork.get_text("text").to_string(), "Hello world");
+
+    doc.import(&fork.export_from(&doc.oplog_vv()))?;
+    fork.import(&doc.export_from(&fork.oplog_vv()))?;
+    assert_eq!(doc.get_deep_value(), fork.get_deep_value());
+    assert_eq!(doc.get_text("text").to_string(), "> Hello world");
+    Ok(())
+}
+
+#[test]
+fn non_finite_numbers() {
+    let doc = LoroDoc::new_auto_commit();
+    let map = doc.get_map("map");
+    let list = doc.get_list("list");
+    let nested = LoroValue::from(vec![LoroValue::from(1.5), LoroValue::from(f64::NAN)]);
+    assert!(matches!(
+        map.insert("a", f64::NAN),
+        Err(LoroError::ArgErr(_))
+    ));
+    assert!(map.insert("a", f64::INFINITY).is_err());
+    assert!(map.insert("a", nested.clone()).is_err());
+    assert!(list.insert(0, f64::NEG_INFINITY).is_err());
+    assert!(list.push(nested).is_err());
+    #[cfg(feature = "counter")]
+    assert!(doc.get_counter("counter").increment(f64::NAN).is_err());
+    map.insert("a", -0.0).unwrap();
+    assert_eq!(map.get_deep_value().to_json_value(), json!({"a": -0.0}));
+
+    // Equal values have the same hash
+    let hash = |v: &LoroValue| {
+        use std::hash::{Hash, Hasher};
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        v.hash(&mut hasher);
+        hasher.finish()
+    };
+    assert_eq!(hash(&LoroValue::from(0.0)), hash(&LoroValue::from(-0.0)));
+    let other_nan = f64::from_bits(f64::NAN.to_bits() | 1);
+    assert_eq!(
+        hash(&LoroValue::from(f64::NAN)),
+        hash(&LoroValue::from(other_nan))
+    );
+    assert_eq!(LoroValue::from(f64::INFINITY).to_json_value(), json!(null));
+}
+
+#[test]
+fn unwritten_roots_are_not_created() {
+    let doc = LoroDoc::new_auto_commit();
+    let text = doc.get_text("text");
+    let map = doc.get_map("map");
+    assert_eq!(text.to_string(), "");
+    assert!(map.get("key").is_none());
+    assert_eq!(doc.get_deep_value().to_json_value(), json!({}));
+    let remote = LoroDoc::new_auto_commit();
+    remote.import(&doc.export_snapshot()).unwrap();
+    assert_eq!(remote.get_deep_value().to_json_value(), json!({}));
+
+    // A root stays once it's written, even if it becomes empty
+    text.insert(0, "a").unwrap();
+    assert_eq!(doc.get_deep_value().to_json_value(), json!({"text": "a"}));
+    text.delete(0, 1).unwrap();
+    doc.commit_then_renew();
+    assert_eq!(doc.get_deep_value().to_json_value(), json!({"text": ""}));
+    let remote = LoroDoc::new_auto_commit();
+    remote.import(&doc.export_snapshot()).unwrap();
+    assert_eq!(remote.get_deep_value().to_json_value(), json!({"text": ""}));
+}
+
+#[test]
+fn absorb_state_of_other_docs() -> LoroResult<()> {
+    let doc = LoroDoc::new_auto_commit();
+    doc.set_peer_id(1)?;
+    doc.get_text("title").insert(0, "Combined")?;
+
+    let a = LoroDoc::new_auto_commit();
+    a.get_text("text").insert(0, "Hello")?;
+    let list = a
+        .get_map("map")
+        .insert_container("list", ListHandler::new_detached())?;
+    list.insert(0, 1)?;
+    list.insert(1, "two")?;
+
+    let b = LoroDoc::new_auto_commit();
+    b.get_list("list").insert(0, 3)?;
+
+    let notes = doc.get_map("notes").id();
+    doc.absorb_state(&a, &notes)?;
+    doc.absorb_state(&b, &doc.get_map("others").id())?;
+    doc.commit_then_renew();
+    assert_eq!(
+        doc.get_deep_value().to_json_value(),
+        json!({
+            "title": "Combined",
+            "notes": {
+                "text": "Hello",
+                "map": { "list": [1, "two"] }
+            },
+            "others": { "list": [3] }
+        })
+    );
+
+    // The absorbed content is authored by this doc only
+    assert_eq!(doc.oplog_vv().len(), 1);
+    // The source docs stay editable
+    a.get_text("text").insert(5, "!")?;
+    assert!(!a.is_detached());
+    assert!(doc.absorb_state(&a, &doc.get_text("title").id()).is_err());
+    Ok(())
+}
+
+#[test]
+fn extract_subtree() -> LoroResult<()> {
+    let doc = LoroDoc::new_auto_commit();
+    doc.set_peer_id(1)?;
+    let pages = doc.get_map("pages");
+    let page = pages.insert_container("a", MapHandler::new_detached())?;
+    let text = page.insert_container("body", TextHandler::new_detached())?;
+    text.insert(0, "Hello")?;
+    doc.get_text("title").insert(0, "Index")?;
+    doc.commit_with(CommitOptions::new().timestamp(100));
+
+    let doc_b = doc.fork();
+    doc_b.set_peer_id(2)?;
+    let text_b = doc_b.get_text(text.id());
+    text_b.insert(5, " World")?;
+    doc_b.commit_with(CommitOptions::new().timestamp(200));
+    doc.import(&doc_b.export_from(&doc.oplog_vv()))?;
+
+    let extracted = doc.extract(&page.id())?;
+    assert_eq!(
+        extracted.get_deep_value().to_json_value(),
+        json!({"root": {"body": "Hello World"}})
+    );
+    // It's recreated by a new peer in a single change
+    let peers = extracted.oplog_vv().keys().copied().collect::<Vec<_>>();
+    assert_eq!(peers.len(), 1);
+    assert!(peers[0] != 1 && peers[0] != 2);
+    assert_eq!(extracted.len_changes(), 1);
+
+    let extracted = doc.extract(&doc.get_text("title").id())?;
+    assert_eq!(
+        extracted.get_deep_value().to_json_value(),
+        json!({"title": "Index"})
+    );
+    Ok(())
+}
+
+#[test]
+fn large_paste_is_a_single_op() -> LoroResult<()> {
+    let doc = LoroDoc::new_auto_commit();
+    doc.set_peer_id(1)?;
+    let text = doc.get_text("text");
+    let content: String = "Hello 你好 🦜\n".chars().cycle().take(1_000_000).collect();
+    text.insert(0, &content)?;
+    doc.commit_then_renew();
+    {
+        let oplog = doc.oplog().lock().unwrap();
+        let change = oplog.get_change_at(ID::new(1, 0)).unwrap();
+        assert_eq!(change.ops().len(), 1);
+    }
+    assert_eq!(doc.len_changes(), 1);
+    assert_eq!(doc.len_ops(), 1_000_000);
+
+    let snapshot = doc.export_snapshot();
+    let new_doc = LoroDoc::from_snapshot(&snapshot)?;
+    assert_eq!(new_doc.get_text("text").len_unicode(), 1_000_000);
+    assert_eq!(new_doc.get_text("text").to_string(), content);
+    Ok(())
+}
+
+#[test]
+fn export_chunks_and_import_reader() -> LoroResult<()> {
+    let doc_a = LoroDoc::new_auto_commit();
+    doc_a.set_peer_id(1)?;
+    let doc_b = LoroDoc::new_auto_commit();
+    doc_b.set_peer_id(2)?;
+    for i in 0..20 {
+        doc_a.get_text("text").insert(0, &i.to_string())?;
+        doc_a.commit_then_renew();
+        doc_b.get_list("list").insert(0, i)?;
+        doc_b.commit_then_renew();
+        if i % 5 == 0 {
+            doc_a.import(&doc_b.export_from(&doc_a.oplog_vv()))?;
+            doc_b.import(&doc_a.export_from(&doc_b.oplog_vv()))?;
+        }
+    }
+    doc_a.import(&doc_b.export_from(&doc_a.oplog_vv()))?;
+
+    let chunks: Vec<Vec<u8>> = doc_a.export_chunks(&Default::default(), 10).collect();
+    assert!(chunks.len() > 1);
+    let stream = chunks.concat();
+    let new_doc = LoroDoc::new_auto_commit();
+    new_doc.import_reader(stream.as_slice())?;
+    assert_eq!(new_doc.get_deep_value(), doc_a.get_deep_value());
+    assert_eq!(new_doc.oplog_vv(), doc_a.oplog_vv());
+
+    // Import the chunks after a known version
+    let vv = doc_b.oplog_vv();
+    let chunks: Vec<Vec<u8>> = doc_a.export_chunks(&vv, 3).collect();
+    let stream = chunks.concat();
+    doc_b.import_reader(stream.as_slice())?;
+    assert_eq!(doc_b.get_deep_value(), doc_a.get_deep_value());
+
+    // A truncated stream is an error
+    let stream = chunks.concat();
+    let new_doc = LoroDoc::new_auto_commit();
+    assert!(new_doc.import_reader(&stream[..stream.len() - 1]).is_err());
+    // A huge length prefix doesn't allocate the claimed size
+    let mut stream = u32::MAX.to_le_bytes().to_vec();
+    stream.extend_from_slice(&[0; 8]);
+    assert!(new_doc.import_reader(stream.as_slice()).is_err());
+    Ok(())
+}
+
+#[test]
+fn backspace_run_is_a_single_op() -> LoroResult<()> {
+    let doc = LoroDoc::new_auto_commit();
+    doc.set_peer_id(1)?;
+    let text = doc.get_text("text");
+    for i in 0..500 {
+        text.insert(i, "a")?;
+        doc.commit_then_renew();
+    }
+    for i in (0..500).rev() {
+        text.delete(i, 1)?;
+        doc.commit_then_renew();
+    }
+
+    let json = doc.export_json_updates(&Default::default(), &doc.oplog_vv());
+    let json = serde_json::to_value(&json).unwrap();
+    let changes = json["changes"].as_array().unwrap();
+    assert_eq!(changes.len(), 1);
+    // The inserts are split where the string arena grows, but the deletions are merged
+    let ops = changes[0]["ops"].as_array().unwrap();
+    let deletes: Vec<_> = ops
+        .iter()
+        .filter(|x| x["content"]["type"] == "delete")
+        .collect();
+    assert_eq!(deletes.len(), 1);
+    assert_eq!(deletes[0]["content"]["pos"], 499);
+    assert_eq!(deletes[0]["content"]["len"], -500);
+
+    // The reversed delete span survives the encoding
+    for bytes in [doc.export_from(&Default::default()), doc.export_snapshot()] {
+        let new_doc = LoroDoc::new_auto_commit();
+        new_doc.import(&bytes)?;
+        assert_eq!(new_doc.get_text("text").to_string(), "");
+        let new_json = new_doc.export_json_updates(&Default::default(), &new_doc.oplog_vv());
+        assert_eq!(serde_json::to_value(&new_json).unwrap(), json);
+    }
+
+    let new_doc = LoroDoc::new_auto_commit();
+    new_doc.import_json_updates(serde_json::to_string(&json).unwrap())?;
+    assert_eq!(new_doc.len_ops(), 1000);
+    assert_eq!(new_doc.get_text("text").to_string(), "");
+    Ok(())
+}
@@ -158,7 +158,9 @@ extern "C" {
     pub type JsImportBlobMetadata;
     #[wasm_bindgen(typescript_type = "Side")]
     pub type JsSide;
-    #[wasm_bindgen(typescript_type = "{ update?: Cursor, offset: number, side: Side }")]
+    #[wasm_bindgen(
+        typescript_type = "{ update?: Cursor, offset: number, side: Side, approximate: boolean }"
+    )]
     pub type JsCursorQueryAns;
     #[wasm_bindgen(typescript_type = "UndoConfig")]
     pub type JsUndoConfig;
@@ -327,8 +329,9 @@ impl Loro {
     ///
     /// The default value is 1_000_000, the default unit is milliseconds.
     #[wasm_bindgen(js_name = "setChangeMergeInterval")]
-    pub fn set_change_merge_interval(&self, interval: f64) {
-        self.0.set_change_merge_interval(interval as i64);
+    pub fn set_change_merge_interval(&self, interval: f64) -> JsResult<()> {
+        self.0.set_change_merge_interval(interval as i64)?;
+        Ok(())
     }
 
     /// Set the jitter of the tree position(Fractional Index).
@@ -1337,6 +1340,11 @@ impl Loro {
             &JsValue::from_str("side"),
             &JsValue::from(ans.current.side.to_i32()),
         )?;
+        Reflect::set(
+            &obj,
+            &JsValue::from_str("approximate"),
+            &JsValue::from(ans.approximate),
+        )?;
         Ok(JsValue::from(obj).into())
     }
 }
@@ -1533,8 +1541,9 @@ impl LoroText {
     /// text.insert(0, "Hello");
     /// text.update("Hello World");
     /// ```
-    pub fn update(&self, text: &str) -> () {
-        self.handler.update(text);
+    pub fn update(&self, text: &str) -> JsResult<()> {
+        self.handler.update(text)?;
+        Ok(())
     }
 
     /// Insert some string at index.
@@ -3611,6 +3620,7 @@ impl UndoManager {
     /// ## Config
     ///
     /// - `mergeInterval`: Optional. The interval in milliseconds within which undo steps can be merged. Default is 1000 ms.
+    /// - `mergeByOrigin`: Optional. Whether the consecutive commits with the same non-empty origin are merged into one undo step. Default is false.
     /// - `maxUndoSteps`: Optional. The maximum number of undo steps to retain. Default is 100.
     /// - `excludeOriginPrefixes`: Optional. An array of string prefixes. Events with origins matching these prefixes will be excluded from undo steps.
     /// - `onPush`: Optional. A callback function that is called when an undo/redo step is pushed.
@@ -3628,6 +3638,10 @@ impl UndoManager {
             .unwrap_or(JsValue::from_f64(1000.0))
             .as_f64()
             .unwrap_or(1000.0) as i64;
+        let merge_by_origin = Reflect::get(&config, &JsValue::from_str("mergeByOrigin"))
+            .ok()
+            .and_then(|x| x.as_bool())
+            .unwrap_or(false);
         let exclude_origin_prefixes =
             Reflect::get(&config, &JsValue::from_str("excludeOriginPrefixes"))
                 .ok()
@@ -3644,6 +3658,7 @@ impl UndoManager {
         let mut undo = InnerUndoManager::new(&doc.0);
         undo.set_max_undo_steps(max_undo_steps);
         undo.set_merge_interval(merge_interval);
+        undo.set_merge_by_origin(merge_by_origin);
         for prefix in exclude_origin_prefixes {
             undo.add_exclude_origin_prefix(&prefix);
         }
@@ -3697,12 +3712,34 @@ impl UndoManager {
         self.undo.set_merge_interval(interval as i64);
     }
 
+    /// Merge the consecutive commits with the same non-empty origin into one undo step,
+    /// whatever the time between them.
+    pub fn setMergeByOrigin(&mut self, merge: bool) {
+        self.undo.set_merge_by_origin(merge);
+    }
+
     /// If a local event's origin matches the given prefix, it will not be recorded in the
     /// undo stack.
     pub fn addExcludeOriginPrefix(&mut self, prefix: String) {
         self.undo.add_exclude_origin_prefix(&prefix)
     }
 
+    /// The value attached by `onPush` to the step that the next undo will revert.
+    pub fn topUndoValue(&self) -> JsValue {
+        match self.undo.top_undo_meta() {
+            Some(meta) => meta.value.into(),
+            None => JsValue::UNDEFINED,
+        }
+    }
+
+    /// The value attached by `onPush` to the step that the next redo will reapply.
+    pub fn topRedoValue(&self) -> JsValue {
+        match self.undo.top_redo_meta() {
+            Some(meta) => meta.value.into(),
+            None => JsValue::UNDEFINED,
+        }
+    }
+
     /// Check if the undo manager is bound to the given document.
     pub fn checkBinding(&self, doc: &Loro) -> bool {
         Arc::ptr_eq(&self.doc, &doc.0)
@@ -4132,6 +4169,7 @@ export type Value =
 
 export type UndoConfig = {
     mergeInterval?: number,
+    mergeByOrigin?: boolean,
     maxUndoSteps?: number,
     excludeOriginPrefixes?: string[],
     onPush?: (isUndo: boolean, counterRange: { start: number, end: number }) => { value: Value, cursors: Cursor[] },
@@ -0,0 +1,324 @@
+//! A collaborative text editor in the terminal.
+//!
+//! Two peers, alice and bob, edit the same text in one process. Their docs only talk to
+//! each other through channels of update blobs, like they would over a network, so the
+//! edits are only exchanged when you sync them. Each peer keeps its caret as a cursor, has
+//! its own undo stack and logs its events.
+//!
+//! ```text
+//! cargo run --example tui_editor
+//! ```
+//!
+//! Keys:
+//!
+//! ```text
+//! Tab             switch between alice and bob
+//! chars           insert at the caret
+//! Backspace       delete the char before the caret
+//! Left/Right      move the caret
+//! Ctrl-Z/Ctrl-Y   undo or redo the local edits of the peer
+//! Ctrl-S          exchange the updates between the peers
+//! Up/Down         check out alice's doc at an older or newer version
+//! Esc             go back to the latest version
+//! Ctrl-C          quit
+//! ```
+//!
+//! The scripted sessions in `tests/editor_session.rs` drive the same flows.
+use std::{
+    io::{stdout, Write},
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+};
+
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{read, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    execute, queue,
+    style::{Attribute, Print, SetAttribute},
+    terminal::{
+        disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
+};
+use loro::{
+    cursor::{Cursor, Side},
+    event::DiffEvent,
+    Frontiers, LoroDoc, LoroResult, UndoManager, VersionVector, ID,
+};
+
+/// The number of event log lines that are shown
+const LOG_LINES: usize = 8;
+
+struct Peer {
+    name: &'static str,
+    doc: LoroDoc,
+    undo: UndoManager,
+    /// The caret is a cursor, so it stays at the same place when remote edits arrive
+    caret: Option<Cursor>,
+    outbox: Sender<Vec<u8>>,
+    inbox: Receiver<Vec<u8>>,
+    /// The version sent to the other peer last time
+    sent: VersionVector,
+}
+
+impl Peer {
+    fn new(
+        name: &'static str,
+        peer_id: u64,
+        outbox: Sender<Vec<u8>>,
+        inbox: Receiver<Vec<u8>>,
+        log: Arc<Mutex<Vec<String>>>,
+    ) -> LoroResult<Self> {
+        let doc = LoroDoc::new();
+        doc.set_peer_id(peer_id)?;
+        // The subscription lives as long as the doc
+        let _ = doc.subscribe_root(Arc::new(move |e: DiffEvent| {
+            log.lock().unwrap().push(format!(
+                "{}: {} event on {} container(s)",
+                name,
+                e.triggered_by,
+                e.events.len()
+            ));
+        }));
+        Ok(Self {
+            name,
+            undo: UndoManager::new(&doc),
+            doc,
+            caret: None,
+            outbox,
+            inbox,
+            sent: Default::default(),
+        })
+    }
+
+    fn caret_pos(&self) -> usize {
+        let len = self.doc.get_text("text").len_unicode();
+        self.caret
+            .as_ref()
+            .and_then(|c| self.doc.get_cursor_pos(c).ok())
+            .map_or(len, |x| x.current.pos)
+            .min(len)
+    }
+
+    fn set_caret(&mut self, pos: usize) {
+        self.caret = self.doc.get_text("text").get_cursor(pos, Side::Middle);
+    }
+
+    fn insert(&mut self, s: &str) -> LoroResult<()> {
+        let pos = self.caret_pos();
+        self.doc.get_text("text").insert(pos, s)?;
+        self.doc.commit();
+        self.set_caret(pos + s.chars().count());
+        Ok(())
+    }
+
+    fn backspace(&mut self) -> LoroResult<()> {
+        let pos = self.caret_pos();
+        if pos > 0 {
+            self.doc.get_text("text").delete(pos - 1, 1)?;
+            self.doc.commit();
+            self.set_caret(pos - 1);
+        }
+
+        Ok(())
+    }
+
+    fn move_caret(&mut self, right: bool) {
+        let pos = self.caret_pos();
+        let len = self.doc.get_text("text").len_unicode();
+        self.set_caret(if right {
+            (pos + 1).min(len)
+        } else {
+            pos.saturating_sub(1)
+        });
+    }
+
+    fn send(&mut self) {
+        let vv = self.doc.oplog_vv();
+        if vv != self.sent {
+            let _ = self.outbox.send(self.doc.export_from(&self.sent));
+            self.sent = vv;
+        }
+    }
+
+    fn receive(&mut self) -> LoroResult<()> {
+        while let Ok(update) = self.inbox.try_recv() {
+            self.doc.import(&update)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The versions of the doc, one for the end of each change in causal order
+fn versions(doc: &LoroDoc) -> Vec<Frontiers> {
+    let mut vv = VersionVector::default();
+    doc.change_iter()
+        .map(|c| {
+            vv.extend_to_include_last_id(ID::new(c.id.peer, c.id.counter + c.len as i32 - 1));
+            doc.vv_to_frontiers(&vv)
+        })
+        .collect()
+}
+
+struct Editor {
+    peers: [Peer; 2],
+    /// The index of the focused peer
+    focus: usize,
+    /// The version alice's doc is checked out at, `None` if it's at the latest version
+    version: Option<usize>,
+    log: Arc<Mutex<Vec<String>>>,
+    /// The last error, it's shown until the next key
+    error: Option<String>,
+}
+
+impl Editor {
+    fn new() -> LoroResult<Self> {
+        let (to_bob, from_alice) = channel();
+        let (to_alice, from_bob) = channel();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        Ok(Self {
+            peers: [
+                Peer::new("alice", 1, to_bob, from_bob, log.clone())?,
+                Peer::new("bob", 2, to_alice, from_alice, log.clone())?,
+            ],
+            focus: 0,
+            version: None,
+            log,
+            error: None,
+        })
+    }
+
+    fn sync(&mut self) -> LoroResult<()> {
+        for peer in self.peers.iter_mut() {
+            peer.send();
+        }
+        for peer in self.peers.iter_mut() {
+            peer.receive()?;
+        }
+
+        Ok(())
+    }
+
+    /// Check out alice's doc `delta` versions away from the current one
+    fn travel(&mut self, delta: isize) -> LoroResult<()> {
+        let alice = &self.peers[0].doc;
+        let versions = versions(alice);
+        if versions.is_empty() {
+            return Ok(());
+        }
+
+        let current = self.version.unwrap_or(versions.len() - 1) as isize;
+        let target = (current + delta).clamp(0, versions.len() as isize - 1) as usize;
+        alice.checkout(&versions[target])?;
+        self.version = Some(target);
+        Ok(())
+    }
+
+    /// Handle a key, return false if the editor should quit
+    fn on_key(&mut self, key: KeyEvent) -> bool {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        let peer = &mut self.peers[self.focus];
+        let result = match key.code {
+            KeyCode::Char('c') if ctrl => return false,
+            KeyCode::Char('s') if ctrl => self.sync(),
+            KeyCode::Char('z') if ctrl => peer.undo.undo(&peer.doc).map(|_| ()),
+            KeyCode::Char('y') if ctrl => peer.undo.redo(&peer.doc).map(|_| ()),
+            KeyCode::Char(c) if !ctrl => peer.insert(c.encode_utf8(&mut [0; 4])),
+            KeyCode::Enter => peer.insert("\n"),
+            KeyCode::Backspace => peer.backspace(),
+            KeyCode::Left | KeyCode::Right => {
+                peer.move_caret(key.code == KeyCode::Right);
+                Ok(())
+            }
+            KeyCode::Tab => {
+                self.focus = 1 - self.focus;
+                Ok(())
+            }
+            KeyCode::Up => self.travel(-1),
+            KeyCode::Down => self.travel(1),
+            KeyCode::Esc => {
+                self.peers[0].doc.checkout_to_latest();
+                self.version = None;
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        self.error = result.err().map(|e| e.to_string());
+        true
+    }
+
+    fn render(&self, out: &mut impl Write) -> std::io::Result<()> {
+        queue!(out, Clear(ClearType::All), MoveTo(0, 0))?;
+        queue!(
+            out,
+            Print("Tab switch peer | Ctrl-S sync | Ctrl-Z/Y undo/redo | Up/Down/Esc history | Ctrl-C quit"),
+        )?;
+        let mut row = 2;
+        for (i, peer) in self.peers.iter().enumerate() {
+            let mut status = String::new();
+            if peer.doc.is_detached() {
+                let n = versions(&peer.doc).len();
+                status = format!(
+                    " (version {}/{}, read only)",
+                    self.version.unwrap_or(0) + 1,
+                    n
+                );
+            }
+            let attr = if i == self.focus {
+                Attribute::Bold
+            } else {
+                Attribute::Dim
+            };
+            queue!(
+                out,
+                MoveTo(0, row),
+                SetAttribute(attr),
+                Print(format!("{}{}", peer.name, status)),
+                SetAttribute(Attribute::Reset),
+            )?;
+            row += 1;
+            let mut text: Vec<char> = peer.doc.get_text("text").to_string().chars().collect();
+            text.insert(peer.caret_pos(), '|');
+            for line in text.into_iter().collect::<String>().split('\n') {
+                queue!(out, MoveTo(2, row), Print(line))?;
+                row += 1;
+            }
+            row += 1;
+        }
+
+        if let Some(e) = &self.error {
+            queue!(out, MoveTo(0, row), Print(format!("error: {}", e)))?;
+            row += 1;
+        }
+
+        queue!(out, MoveTo(0, row + 1), Print("events:"))?;
+        let log = self.log.lock().unwrap();
+        for (i, entry) in log.iter().rev().take(LOG_LINES).enumerate() {
+            queue!(out, MoveTo(2, row + 2 + i as u16), Print(entry))?;
+        }
+
+        out.flush()
+    }
+}
+
+fn main() -> std::io::Result<()> {
+    let mut editor = Editor::new().unwrap();
+    let mut out = stdout();
+    enable_raw_mode()?;
+    execute!(out, EnterAlternateScreen, Hide)?;
+    let result = (|| loop {
+        editor.render(&mut out)?;
+        if let Event::Key(key) = read()? {
+            // Windows also reports the key releases
+            if key.kind == KeyEventKind::Press && !editor.on_key(key) {
+                return Ok(());
+            }
+        }
+    })();
+    execute!(out, Show, LeaveAlternateScreen)?;
+    disable_raw_mode()?;
+    result
+}
@@ -0,0 +1,140 @@
+use loro_internal::{id::PeerID, LoroResult};
+
+use crate::{LoroDoc, LoroList, LoroMap, LoroMovableList, LoroText, LoroTree};
+
+type Edit = Box<dyn FnOnce(&LoroDoc) -> LoroResult<()>>;
+
+/// Build a document with a few root containers in one expression, e.g. to set up a test.
+///
+/// The edits are applied in order when [DocBuilder::build] is called, and committed as
+/// one change.
+///
+/// # Example
+///
+/// ```
+/// # use loro::{DocBuilder, ToJson};
+/// # use serde_json::json;
+/// let doc = DocBuilder::new()
+///     .peer_id(1)
+///     .text("body", "hello")
+///     .map("meta", |m| m.insert("title", "x"))
+///     .list("tags", |l| l.push("draft"))
+///     .build();
+/// assert_eq!(
+///     doc.get_deep_value().to_json_value(),
+///     json!({"body": "hello", "meta": {"title": "x"}, "tags": ["draft"]})
+/// );
+/// assert_eq!(doc.len_changes(), 1);
+/// ```
+#[derive(Default)]
+pub struct DocBuilder {
+    peer_id: Option<PeerID>,
+    edits: Vec<Edit>,
+}
+
+impl std::fmt::Debug for DocBuilder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DocBuilder")
+            .field("peer_id", &self.peer_id)
+            .field("edits", &self.edits.len())
+            .finish()
+    }
+}
+
+impl DocBuilder {
+    /// Create a builder of an empty document.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the peer id of the document.
+    pub fn peer_id(mut self, peer: PeerID) -> Self {
+        self.peer_id = Some(peer);
+        self
+    }
+
+    /// Append `content` to the root text `name`.
+    pub fn text(self, name: &str, content: &str) -> Self {
+        let name = name.to_string();
+        let content = content.to_string();
+        self.edit(move |doc| {
+            let text = doc.get_text(name.as_str());
+            text.insert(text.len_unicode(), &content)
+        })
+    }
+
+    /// Edit the root text `name`.
+    pub fn text_with<R>(
+        self,
+        name: &str,
+        f: impl FnOnce(&LoroText) -> LoroResult<R> + 'static,
+    ) -> Self {
+        let name = name.to_string();
+        self.edit(move |doc| f(&doc.get_text(name.as_str())).map(|_| ()))
+    }
+
+    /// Edit the root map `name`.
+    pub fn map<R>(self, name: &str, f: impl FnOnce(&LoroMap) -> LoroResult<R> + 'static) -> Self {
+        let name = name.to_string();
+        self.edit(move |doc| f(&doc.get_map(name.as_str())).map(|_| ()))
+    }
+
+    /// Edit the root list `name`.
+    pub fn list<R>(self, name: &str, f: impl FnOnce(&LoroList) -> LoroResult<R> + 'static) -> Self {
+        let name = name.to_string();
+        self.edit(move |doc| f(&doc.get_list(name.as_str())).map(|_| ()))
+    }
+
+    /// Edit the root movable list `name`.
+    pub fn movable_list<R>(
+        self,
+        name: &str,
+        f: impl FnOnce(&LoroMovableList) -> LoroResult<R> + 'static,
+    ) -> Self {
+        let name = name.to_string();
+        self.edit(move |doc| f(&doc.get_movable_list(name.as_str())).map(|_| ()))
+    }
+
+    /// Edit the root tree `name`.
+    pub fn tree<R>(self, name: &str, f: impl FnOnce(&LoroTree) -> LoroResult<R> + 'static) -> Self {
+        let name = name.to_string();
+        self.edit(move |doc| f(&doc.get_tree(name.as_str())).map(|_| ()))
+    }
+
+    /// Increment the root counter `name` by `value`.
+    #[cfg(feature = "counter")]
+    pub fn counter(self, name: &str, value: f64) -> Self {
+        let name = name.to_string();
+        self.edit(move |doc| doc.get_counter(name.as_str()).increment(value))
+    }
+
+    /// Apply any other edit to the document.
+    pub fn edit(mut self, f: impl FnOnce(&LoroDoc) -> LoroResult<()> + 'static) -> Self {
+        self.edits.push(Box::new(f));
+        self
+    }
+
+    /// Apply the edits and commit them.
+    ///
+    /// # Panics
+    ///
+    /// If an edit fails, see [DocBuilder::try_build] for a fallible version.
+    pub fn build(self) -> LoroDoc {
+        self.try_build().unwrap()
+    }
+
+    /// Apply the edits and commit them. Returns the error of the first edit that fails.
+    pub fn try_build(self) -> LoroResult<LoroDoc> {
+        let doc = LoroDoc::new();
+        if let Some(peer) = self.peer_id {
+            doc.set_peer_id(peer)?;
+        }
+
+        for edit in self.edits {
+            edit(&doc)?;
+        }
+
+        doc.commit();
+        Ok(doc)
+    }
+}
@@ -0,0 +1,334 @@
+//! Test vectors for checking that other implementations are compatible with this one.
+//!
+//! A [TestVector] is a set of updates exported by concurrent peers, the deep value of the
+//! doc after merging them, and the snapshot of the merged doc. [canonical_vectors] builds
+//! the vectors covering each container type, and [TestVector::to_value] turns them into a
+//! [LoroValue] that can be published as JSON, with the binary fields encoded in hex.
+//!
+//! An implementation is compatible if, for every vector,
+//!
+//! - importing the updates in any order results in the expected value, and
+//! - importing the snapshot results in the expected value, and
+//! - the updates and snapshots it exports can be imported by this implementation.
+//!
+//! [TestVector::verify] runs the first two checks against this implementation, plus a
+//! round trip of its own exports. The bytes exported by two implementations are not
+//! required to be identical.
+//!
+//! The canonical vectors are published in `crates/loro/tests/fixtures/conformance_vectors.json`.
+//! The tests check that the published blobs can still be imported, and that this
+//! implementation still exports the same bytes, so the file has to be regenerated on
+//! purpose when the encoding changes.
+use std::{collections::HashMap, fmt::Display, sync::Arc};
+
+use crate::{LoroDoc, LoroError, LoroResult, LoroValue};
+
+/// A set of concurrent updates and the expected result of merging them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestVector {
+    /// The name of the scenario, e.g. `text_concurrent_insert`
+    pub name: String,
+    /// The updates exported by each peer
+    pub updates: Vec<Vec<u8>>,
+    /// The deep value of the doc after importing all the updates
+    pub expected_value: LoroValue,
+    /// The snapshot of the doc after importing all the updates
+    pub expected_snapshot: Vec<u8>,
+}
+
+/// The check of [TestVector::verify] that failed.
+#[derive(Debug)]
+pub enum ConformanceError {
+    /// A blob could not be imported
+    Import {
+        /// The name of the vector
+        vector: String,
+        /// The blobs that were being imported
+        step: &'static str,
+        /// The error of the import
+        error: LoroError,
+    },
+    /// The value of the doc is not the expected one
+    ValueMismatch {
+        /// The name of the vector
+        vector: String,
+        /// The blobs that were imported
+        step: &'static str,
+        /// The expected value of the vector
+        expected: LoroValue,
+        /// The value of the doc
+        actual: LoroValue,
+    },
+    /// The value passed to [TestVector::from_value] is not a valid test vector
+    InvalidVector(String),
+}
+
+impl Display for ConformanceError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConformanceError::Import {
+                vector,
+                step,
+                error,
+            } => write!(f, "{}: failed to import {}: {}", vector, step, error),
+            ConformanceError::ValueMismatch {
+                vector,
+                step,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{}: unexpected value after importing {}, expected {:?}, got {:?}",
+                vector, step, expected, actual
+            ),
+            ConformanceError::InvalidVector(msg) => write!(f, "invalid test vector: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ConformanceError {}
+
+impl TestVector {
+    /// Build a vector from the docs of concurrent peers.
+    ///
+    /// The updates are the whole history of each doc, and the expected results are taken
+    /// from a doc that imports all of them.
+    pub fn from_docs(name: &str, docs: &[&LoroDoc]) -> LoroResult<Self> {
+        let updates: Vec<Vec<u8>> = docs
+            .iter()
+            .map(|doc| doc.export_from(&Default::default()))
+            .collect();
+        let merged = LoroDoc::new();
+        for update in updates.iter() {
+            merged.import(update)?;
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            updates,
+            expected_value: merged.get_deep_value(),
+            expected_snapshot: merged.export_snapshot(),
+        })
+    }
+
+    /// Check this implementation against the vector.
+    pub fn verify(&self) -> Result<(), ConformanceError> {
+        let doc = self.import_all("updates", self.updates.iter())?;
+        self.check_value("updates", &doc)?;
+        let doc = self.import_all("updates in reverse order", self.updates.iter().rev())?;
+        self.check_value("updates in reverse order", &doc)?;
+        let doc = self.import_all("snapshot", [&self.expected_snapshot])?;
+        self.check_value("snapshot", &doc)?;
+
+        let exported = doc.export_from(&Default::default());
+        let doc = self.import_all("exported updates", [&exported])?;
+        self.check_value("exported updates", &doc)?;
+        let exported = doc.export_snapshot();
+        let doc = self.import_all("exported snapshot", [&exported])?;
+        self.check_value("exported snapshot", &doc)
+    }
+
+    fn import_all<'a>(
+        &self,
+        step: &'static str,
+        blobs: impl IntoIterator<Item = &'a Vec<u8>>,
+    ) -> Result<LoroDoc, ConformanceError> {
+        let doc = LoroDoc::new();
+        for blob in blobs {
+            doc.import(blob).map_err(|error| ConformanceError::Import {
+                vector: self.name.clone(),
+                step,
+                error,
+            })?;
+        }
+
+        Ok(doc)
+    }
+
+    fn check_value(&self, step: &'static str, doc: &LoroDoc) -> Result<(), ConformanceError> {
+        let actual = doc.get_deep_value();
+        if actual != self.expected_value {
+            return Err(ConformanceError::ValueMismatch {
+                vector: self.name.clone(),
+                step,
+                expected: self.expected_value.clone(),
+                actual,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Convert the vector to a [LoroValue] map, with the binary fields encoded in hex,
+    /// so that it can be published as JSON.
+    pub fn to_value(&self) -> LoroValue {
+        let mut map: HashMap<String, LoroValue> = HashMap::new();
+        map.insert("name".into(), self.name.clone().into());
+        map.insert(
+            "updates".into(),
+            LoroValue::List(Arc::new(
+                self.updates.iter().map(|x| to_hex(x).into()).collect(),
+            )),
+        );
+        map.insert("expected_value".into(), self.expected_value.clone());
+        map.insert(
+            "expected_snapshot".into(),
+            to_hex(&self.expected_snapshot).into(),
+        );
+        LoroValue::Map(Arc::new(map.into_iter().collect()))
+    }
+
+    /// Parse a vector from the value returned by [TestVector::to_value].
+    pub fn from_value(value: &LoroValue) -> Result<Self, ConformanceError> {
+        let invalid = |msg: &str| ConformanceError::InvalidVector(msg.to_string());
+        let LoroValue::Map(map) = value else {
+            return Err(invalid("expected a map"));
+        };
+        let get_str = |key: &str| match map.get(key) {
+            Some(LoroValue::String(s)) => Ok(s.to_string()),
+            _ => Err(invalid(&format!("`{}` should be a string", key))),
+        };
+        let Some(LoroValue::List(updates)) = map.get("updates") else {
+            return Err(invalid("`updates` should be a list"));
+        };
+        let updates = updates
+            .iter()
+            .map(|x| match x {
+                LoroValue::String(s) => from_hex(s).ok_or_else(|| invalid("invalid hex")),
+                _ => Err(invalid("`updates` should be a list of strings")),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let expected_value = map
+            .get("expected_value")
+            .cloned()
+            .ok_or_else(|| invalid("missing `expected_value`"))?;
+        let expected_snapshot =
+            from_hex(&get_str("expected_snapshot")?).ok_or_else(|| invalid("invalid hex"))?;
+        Ok(Self {
+            name: get_str("name")?,
+            updates,
+            expected_value,
+            expected_snapshot,
+        })
+    }
+}
+
+/// Build the canonical test vectors.
+///
+/// They only depend on the peer ids and the edits, so the same vectors are built on
+/// every run.
+pub fn canonical_vectors() -> LoroResult<Vec<TestVector>> {
+    Ok(vec![
+        text_concurrent_insert()?,
+        map_concurrent_set()?,
+        list_concurrent_insert_delete()?,
+        movable_list_concurrent_move()?,
+        tree_concurrent_move()?,
+    ])
+}
+
+/// Verify all the vectors, stopping at the first failure.
+pub fn verify_all(vectors: &[TestVector]) -> Result<(), ConformanceError> {
+    vectors.iter().try_for_each(|x| x.verify())
+}
+
+fn new_peer(peer: u64) -> LoroResult<LoroDoc> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(peer)?;
+    Ok(doc)
+}
+
+fn sync(a: &LoroDoc, b: &LoroDoc) -> LoroResult<()> {
+    a.import(&b.export_from(&a.oplog_vv()))?;
+    b.import(&a.export_from(&b.oplog_vv()))
+}
+
+fn text_concurrent_insert() -> LoroResult<TestVector> {
+    let a = new_peer(1)?;
+    let b = new_peer(2)?;
+    a.get_text("text").insert(0, "Hello")?;
+    sync(&a, &b)?;
+    a.get_text("text").insert(5, " world")?;
+    b.get_text("text").insert(5, " there")?;
+    b.get_text("text").delete(0, 1)?;
+    a.commit();
+    b.commit();
+    TestVector::from_docs("text_concurrent_insert", &[&a, &b])
+}
+
+fn map_concurrent_set() -> LoroResult<TestVector> {
+    let a = new_peer(1)?;
+    let b = new_peer(2)?;
+    a.get_map("map").insert("key", "a")?;
+    a.get_map("map").insert("removed", 1)?;
+    sync(&a, &b)?;
+    a.get_map("map").insert("key", "from a")?;
+    b.get_map("map").insert("key", "from b")?;
+    b.get_map("map").delete("removed")?;
+    a.commit();
+    b.commit();
+    TestVector::from_docs("map_concurrent_set", &[&a, &b])
+}
+
+fn list_concurrent_insert_delete() -> LoroResult<TestVector> {
+    let a = new_peer(1)?;
+    let b = new_peer(2)?;
+    let list = a.get_list("list");
+    for i in 0..4 {
+        list.push(i)?;
+    }
+    sync(&a, &b)?;
+    a.get_list("list").insert(2, "a")?;
+    b.get_list("list").delete(1, 2)?;
+    b.get_list("list").push(true)?;
+    a.commit();
+    b.commit();
+    TestVector::from_docs("list_concurrent_insert_delete", &[&a, &b])
+}
+
+fn movable_list_concurrent_move() -> LoroResult<TestVector> {
+    let a = new_peer(1)?;
+    let b = new_peer(2)?;
+    let list = a.get_movable_list("list");
+    for i in 0..4 {
+        list.push(i)?;
+    }
+    sync(&a, &b)?;
+    a.get_movable_list("list").mov(0, 3)?;
+    b.get_movable_list("list").mov(0, 1)?;
+    b.get_movable_list("list").set(2, "b")?;
+    a.commit();
+    b.commit();
+    TestVector::from_docs("movable_list_concurrent_move", &[&a, &b])
+}
+
+fn tree_concurrent_move() -> LoroResult<TestVector> {
+    let a = new_peer(1)?;
+    let b = new_peer(2)?;
+    let tree = a.get_tree("tree");
+    let root = tree.create(None)?;
+    let x = tree.create(root)?;
+    let y = tree.create(root)?;
+    sync(&a, &b)?;
+    // The concurrent moves would create a cycle, only one of them takes effect
+    a.get_tree("tree").mov(x, y)?;
+    b.get_tree("tree").mov(y, x)?;
+    a.commit();
+    b.commit();
+    TestVector::from_docs("tree_concurrent_move", &[&a, &b])
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -3,19 +3,26 @@ use enum_as_inner::EnumAsInner;
 use loro_internal::container::ContainerID;
 use loro_internal::delta::TreeDiff;
 use loro_internal::event::EventTriggerKind;
+use loro_internal::event_queue::{EventQueue as InnerEventQueue, QueuedEvent};
 use loro_internal::handler::{TextDelta, ValueOrHandler};
 use loro_internal::FxHashMap;
 use loro_internal::{
     event::{Diff as DiffInner, Index},
     ContainerDiff as ContainerDiffInner, DiffEvent as DiffEventInner,
 };
-use std::sync::Arc;
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
 
 use crate::ValueOrContainer;
 
+pub use loro_internal::event_queue::OverflowPolicy;
+
 /// A subscriber to the event.
 pub type Subscriber = Arc<dyn (for<'a> Fn(DiffEvent<'a>)) + Send + Sync>;
 
+/// A subscriber to the edits in a [VisibleRange], see [crate::LoroDoc::subscribe_text_range].
+pub type RangeSubscriber = Arc<dyn Fn(&[TextDelta]) + Send + Sync>;
+
 /// An event that is triggered by a change in the state of a [super::LoroDoc].
 #[derive(Debug)]
 pub struct DiffEvent<'a> {
@@ -186,3 +193,391 @@ impl From<ValueOrHandler> for ValueOrContainer {
         }
     }
 }
+
+/// A position in a text, as a row and a byte offset in the row. It matches tree-sitter's
+/// `Point`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Point {
+    /// The number of line breaks before the position.
+    pub row: usize,
+    /// The byte offset from the start of the row.
+    pub column: usize,
+}
+
+/// A text edit in bytes, with the fields of tree-sitter's `InputEdit`.
+///
+/// The edits of a delta are applied one after another, so the positions of an edit are
+/// in the text after the previous edits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEdit {
+    /// The byte offset where the edit starts.
+    pub start_byte: usize,
+    /// The end of the replaced text, in the text before the edit.
+    pub old_end_byte: usize,
+    /// The end of the new text, in the text after the edit.
+    pub new_end_byte: usize,
+    /// The position of `start_byte`.
+    pub start_position: Point,
+    /// The position of `old_end_byte`, in the text before the edit.
+    pub old_end_position: Point,
+    /// The position of `new_end_byte`, in the text after the edit.
+    pub new_end_position: Point,
+}
+
+/// Convert the text events of a [crate::LoroText] to [InputEdit]s, so that a code editor
+/// can keep its syntax tree in sync with the text.
+///
+/// The deltas count unicode chars, and the deleted text isn't in the events, so the
+/// tracker keeps a copy of the text to find the byte offsets.
+///
+/// # Example
+///
+/// ```
+/// # use loro::{LoroDoc, event::{InputEdit, Point, TextEditTracker}};
+/// let doc = LoroDoc::new();
+/// let text = doc.get_text("text");
+/// text.insert(0, "fn main() {}").unwrap();
+/// doc.commit();
+/// let mut tracker = TextEditTracker::new(&text);
+///
+/// let deltas = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+/// let deltas_clone = deltas.clone();
+/// let _sub = doc.subscribe(
+///     &text.id(),
+///     std::sync::Arc::new(move |e| {
+///         for d in e.events {
+///             deltas_clone.lock().unwrap().push(d.diff.as_text().unwrap().clone());
+///         }
+///     }),
+/// );
+/// text.insert(11, "\n    ").unwrap();
+/// doc.commit();
+///
+/// let edits = tracker.apply(&deltas.lock().unwrap()[0]);
+/// assert_eq!(
+///     edits,
+///     vec![InputEdit {
+///         start_byte: 11,
+///         old_end_byte: 11,
+///         new_end_byte: 16,
+///         start_position: Point { row: 0, column: 11 },
+///         old_end_position: Point { row: 0, column: 11 },
+///         new_end_position: Point { row: 1, column: 4 },
+///     }]
+/// );
+/// assert_eq!(tracker.text(), text.to_string());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct TextEditTracker {
+    text: String,
+}
+
+impl TextEditTracker {
+    /// Track `text` from its current value. The deltas passed to [TextEditTracker::apply]
+    /// must be the ones emitted after this point.
+    pub fn new(text: &crate::LoroText) -> Self {
+        Self {
+            text: text.to_string(),
+        }
+    }
+
+    /// The tracked copy of the text.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Apply the delta of a text event and return the edits it makes.
+    ///
+    /// Retains that only change the styles don't produce edits.
+    pub fn apply(&mut self, delta: &[TextDelta]) -> Vec<InputEdit> {
+        let mut edits = Vec::new();
+        let mut byte = 0;
+        for item in delta {
+            match item {
+                TextDelta::Retain { retain, .. } => {
+                    byte = advance_chars(&self.text, byte, *retain);
+                }
+                TextDelta::Insert { insert, .. } => {
+                    let start_position = point_at(&self.text, byte);
+                    self.text.insert_str(byte, insert);
+                    let new_end_byte = byte + insert.len();
+                    edits.push(InputEdit {
+                        start_byte: byte,
+                        old_end_byte: byte,
+                        new_end_byte,
+                        start_position,
+                        old_end_position: start_position,
+                        new_end_position: point_at(&self.text, new_end_byte),
+                    });
+                    byte = new_end_byte;
+                }
+                TextDelta::Delete { delete } => {
+                    let old_end_byte = advance_chars(&self.text, byte, *delete);
+                    let start_position = point_at(&self.text, byte);
+                    let old_end_position = point_at(&self.text, old_end_byte);
+                    self.text.drain(byte..old_end_byte);
+                    edits.push(InputEdit {
+                        start_byte: byte,
+                        old_end_byte,
+                        new_end_byte: byte,
+                        start_position,
+                        old_end_position,
+                        new_end_position: start_position,
+                    });
+                }
+            }
+        }
+
+        edits
+    }
+}
+
+impl From<&str> for TextEditTracker {
+    /// Track a text from `value`, e.g. the content of an editor buffer
+    fn from(value: &str) -> Self {
+        Self {
+            text: value.to_string(),
+        }
+    }
+}
+
+/// The byte offset `chars` unicode chars after `byte`
+fn advance_chars(text: &str, byte: usize, chars: usize) -> usize {
+    text[byte..]
+        .char_indices()
+        .nth(chars)
+        .map_or(text.len(), |(i, _)| byte + i)
+}
+
+fn point_at(text: &str, byte: usize) -> Point {
+    let before = &text[..byte];
+    match before.rfind('\n') {
+        Some(i) => Point {
+            row: before.matches('\n').count(),
+            column: byte - i - 1,
+        },
+        None => Point {
+            row: 0,
+            column: byte,
+        },
+    }
+}
+
+/// A range of a text that follows its edits, e.g. the part of a long text drawn by a
+/// virtualized renderer.
+///
+/// The range is shared by its clones, so the renderer can keep a clone to update it when
+/// it scrolls, while [crate::LoroDoc::subscribe_text_range] moves it with the edits.
+///
+/// # Example
+///
+/// ```
+/// # use loro::{event::VisibleRange, TextDelta};
+/// let range = VisibleRange::new(10..20);
+/// // Insert 2 chars at 5, and delete 3 chars from 12
+/// let delta = vec![
+///     TextDelta::Retain { retain: 5, attributes: None },
+///     TextDelta::Insert { insert: "ab".into(), attributes: None },
+///     TextDelta::Retain { retain: 7, attributes: None },
+///     TextDelta::Delete { delete: 3 },
+/// ];
+/// assert_eq!(range.apply(&delta), vec![
+///     TextDelta::Retain { retain: 2, attributes: None },
+///     TextDelta::Delete { delete: 3 },
+/// ]);
+/// assert_eq!(range.get(), 12..19);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct VisibleRange(Arc<Mutex<Range<usize>>>);
+
+impl VisibleRange {
+    /// Track `range`, in the same index as the text events.
+    pub fn new(range: Range<usize>) -> Self {
+        Self(Arc::new(Mutex::new(range)))
+    }
+
+    /// The current range.
+    pub fn get(&self) -> Range<usize> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Replace the range, e.g. when the renderer scrolls.
+    pub fn set(&self, range: Range<usize>) {
+        *self.0.lock().unwrap() = range;
+    }
+
+    /// Move the range by the delta of a text event, and return the part of the delta in
+    /// the range, with the positions relative to its start.
+    ///
+    /// The text inserted at the start of the range is in the range, and the text inserted
+    /// at its end isn't. The returned delta is empty if the edit doesn't touch the range.
+    pub fn apply(&self, delta: &[TextDelta]) -> Vec<TextDelta> {
+        let mut range = self.0.lock().unwrap();
+        let mut ans = Vec::new();
+        // The length of the text `ans` covers, after the edit
+        let mut written = 0;
+        // The position in the text, with the items before it applied
+        let mut pos = 0;
+        for item in delta {
+            match item {
+                TextDelta::Retain { retain, attributes } => {
+                    let start = pos.max(range.start);
+                    let end = (pos + retain).min(range.end);
+                    if attributes.is_some() && start < end {
+                        push_local(
+                            &mut ans,
+                            &mut written,
+                            start - range.start,
+                            TextDelta::Retain {
+                                retain: end - start,
+                                attributes: attributes.clone(),
+                            },
+                        );
+                    }
+                    pos += retain;
+                }
+                TextDelta::Insert { insert, .. } => {
+                    let len = insert.chars().count();
+                    if pos < range.start {
+                        range.start += len;
+                        range.end += len;
+                    } else if pos < range.end {
+                        push_local(&mut ans, &mut written, pos - range.start, item.clone());
+                        range.end += len;
+                    }
+                    pos += len;
+                }
+                TextDelta::Delete { delete } => {
+                    let start = pos.max(range.start);
+                    let end = (pos + delete).min(range.end);
+                    if start < end {
+                        push_local(
+                            &mut ans,
+                            &mut written,
+                            start - range.start,
+                            TextDelta::Delete {
+                                delete: end - start,
+                            },
+                        );
+                    }
+                    let before = (pos + delete).min(range.start).saturating_sub(pos);
+                    range.start -= before;
+                    range.end -= before + end.saturating_sub(start);
+                }
+            }
+        }
+
+        ans
+    }
+}
+
+/// Push an item at the local position `at`, after the items already in `ans`
+fn push_local(ans: &mut Vec<TextDelta>, written: &mut usize, at: usize, item: TextDelta) {
+    if at > *written {
+        ans.push(TextDelta::Retain {
+            retain: at - *written,
+            attributes: None,
+        });
+    }
+
+    *written = match &item {
+        TextDelta::Retain { retain, .. } => at + retain,
+        TextDelta::Insert { insert, .. } => at + insert.chars().count(),
+        TextDelta::Delete { .. } => at,
+    };
+    ans.push(item);
+}
+
+/// A bounded queue of the events of a subscription, for a consumer that is slower than the
+/// doc, e.g. one on another thread. See [crate::LoroDoc::subscribe_queue].
+///
+/// It keeps at most `limit` events, and the [OverflowPolicy] decides what happens to a new
+/// event when it's full. The queue is shared by its clones.
+///
+/// # Example
+///
+/// ```
+/// # use loro::{event::{EventQueue, OverflowPolicy}, LoroDoc};
+/// let doc = LoroDoc::new();
+/// let queue = EventQueue::new(2, OverflowPolicy::DropOldest);
+/// let _sub = doc.subscribe_queue(None, &queue);
+/// let text = doc.get_text("text");
+/// for i in 0..3 {
+///     text.insert(0, &i.to_string()).unwrap();
+///     doc.commit();
+/// }
+///
+/// assert_eq!(queue.len(), 2);
+/// // The first event is dropped, so the consumer has to read the whole state again
+/// assert!(queue.take_needs_resync());
+/// let mut n = 0;
+/// queue.drain_with(|event| n += event.events.len());
+/// assert_eq!(n, 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct EventQueue(pub(crate) InnerEventQueue);
+
+impl EventQueue {
+    /// Create a queue that keeps at most `limit` events.
+    ///
+    /// # Panics
+    ///
+    /// If `limit` is 0.
+    pub fn new(limit: usize, policy: OverflowPolicy) -> Self {
+        Self(InnerEventQueue::new(limit, policy))
+    }
+
+    /// The max number of queued events.
+    pub fn limit(&self) -> usize {
+        self.0.limit()
+    }
+
+    /// What happens to a new event when the queue is full.
+    pub fn policy(&self) -> OverflowPolicy {
+        self.0.policy()
+    }
+
+    /// The number of queued events.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether there is no queued event.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The max number of events that have been queued at the same time, for monitoring.
+    pub fn peak_len(&self) -> usize {
+        self.0.peak_len()
+    }
+
+    /// Whether events have been lost since the last call. Then the consumer has to read the
+    /// state of the doc again instead of applying the diffs.
+    pub fn take_needs_resync(&self) -> bool {
+        self.0.take_needs_resync()
+    }
+
+    /// Pop the oldest event and pass it to `f`. Returns `None` if the queue is empty.
+    pub fn pop_with<R>(&self, f: impl FnOnce(DiffEvent) -> R) -> Option<R> {
+        self.0.pop().map(|event| f((&event).into()))
+    }
+
+    /// Pop all the queued events and pass them to `f`, oldest first.
+    pub fn drain_with(&self, mut f: impl FnMut(DiffEvent)) {
+        for event in self.0.drain() {
+            f((&event).into());
+        }
+    }
+}
+
+impl<'a> From<&'a QueuedEvent> for DiffEvent<'a> {
+    fn from(value: &'a QueuedEvent) -> Self {
+        DiffEvent {
+            triggered_by: value.diff.by,
+            origin: &value.diff.origin,
+            current_target: value.current_target.clone(),
+            events: value.diff.diff.iter().map(|diff| diff.into()).collect(),
+        }
+    }
+}
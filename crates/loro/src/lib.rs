@@ -11,7 +11,7 @@ use loro_internal::cursor::Side;
 use loro_internal::encoding::ImportBlobMetadata;
 use loro_internal::handler::HandlerTrait;
 use loro_internal::handler::ValueOrHandler;
-use loro_internal::undo::{OnPop, OnPush};
+use loro_internal::undo::{OnPop, OnPush, UndoItemMeta};
 use loro_internal::LoroDoc as InnerLoroDoc;
 use loro_internal::OpLog;
 
@@ -22,6 +22,7 @@ use loro_internal::{
     UnknownHandler as InnerUnknownHandler,
 };
 use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::ops::Range;
 use std::sync::Arc;
 
@@ -29,30 +30,49 @@ use tracing::info;
 
 pub mod event;
 pub use loro_internal::awareness;
-pub use loro_internal::configure::Configure;
+pub use loro_internal::change::{ChangeMeta, ChangesCursor, DepGraph, DepNode};
 pub use loro_internal::configure::StyleConfigMap;
-pub use loro_internal::container::richtext::ExpandType;
+pub use loro_internal::configure::{ConfigOptions, Configure, OnConfigChange};
+pub use loro_internal::container::richtext::{ExpandType, TextSpan};
 pub use loro_internal::container::{ContainerID, ContainerType};
 pub use loro_internal::cursor;
 pub use loro_internal::delta::{TreeDeltaItem, TreeDiff, TreeExternalDiff};
+pub use loro_internal::encoding::{
+    ContainerEncodingReport, Dimension, EncodingReport, SizeEntry, SizeKey,
+};
 pub use loro_internal::event::Index;
 pub use loro_internal::handler::TextDelta;
-pub use loro_internal::id::{PeerID, TreeID, ID};
-pub use loro_internal::loro::CommitOptions;
-pub use loro_internal::obs::SubID;
+pub use loro_internal::id::{IdSpan, PeerID, TreeID, ID};
+pub use loro_internal::json_patch;
+pub use loro_internal::lease;
+pub use loro_internal::loro::{
+    CommitOptions, OnOpsRejected, OnRootTypeConflict, OnVersionReached, PEER_NAME_KEY,
+};
+pub use loro_internal::obs::{Projector, SelectSubscriber, SubID};
 pub use loro_internal::oplog::FrontiersNotIncluded;
+pub use loro_internal::outbound::OutboundQueue as InnerOutboundQueue;
+pub use loro_internal::outbound::QueuedUpdate;
+pub use loro_internal::txn::{OnTxnStreamFn, PreCommitHook, StagedOp, TxnStreamEvent};
 pub use loro_internal::undo;
 pub use loro_internal::version::{Frontiers, VersionVector};
 pub use loro_internal::ApplyDiff;
 pub use loro_internal::JsonSchema;
 pub use loro_internal::UndoManager as InnerUndoManager;
-pub use loro_internal::{loro_value, to_value};
-pub use loro_internal::{LoroError, LoroResult, LoroValue, ToJson};
+pub use loro_internal::{loro_value, to_value, ValueRef};
+pub use loro_internal::{ErrorCategory, LoroError, LoroResult, LoroValue, ToJson};
 
+mod builder;
+pub use builder::DocBuilder;
 #[cfg(feature = "counter")]
 mod counter;
 #[cfg(feature = "counter")]
 pub use counter::LoroCounter;
+#[cfg(feature = "conformance")]
+pub mod conformance;
+#[cfg(feature = "server")]
+pub mod server;
+#[cfg(feature = "test-utils")]
+pub mod test_utils;
 
 /// `LoroDoc` is the entry for the whole document.
 /// When it's dropped, all the associated [`Handler`]s will be invalidated.
@@ -89,12 +109,77 @@ impl LoroDoc {
 
     /// Duplicate the document with a different PeerID
     ///
-    /// The time complexity and space complexity of this operation are both O(n),
+    /// The decoded history is shared with this document instead of being copied. The
+    /// changes of a peer are only copied when one of the documents modifies them, e.g. when
+    /// it imports new changes of the peer. So an app that opens many forks of a large
+    /// document, like a review tool, should decode it once and fork it. The state is still
+    /// copied, which takes O(n) time and space.
     pub fn fork(&self) -> Self {
         let doc = self.doc.fork();
         LoroDoc::_new(doc)
     }
 
+    /// Create a new document with the history up to `frontiers`, with a different PeerID.
+    ///
+    /// It can be used to branch a document at an old version. The changes made on the branch
+    /// can be merged back with [LoroDoc::export_from] and [LoroDoc::import].
+    ///
+    /// # Example
+    /// ```
+    /// use loro::LoroDoc;
+    ///
+    /// let doc = LoroDoc::new();
+    /// doc.get_text("text").insert(0, "Hello").unwrap();
+    /// doc.commit();
+    /// let v1 = doc.oplog_frontiers();
+    /// doc.get_text("text").insert(5, " world").unwrap();
+    /// doc.commit();
+    ///
+    /// let branch = doc.fork_at(&v1).unwrap();
+    /// assert_eq!(branch.get_text("text").to_string(), "Hello");
+    /// branch.get_text("text").insert(0, "Oh, ").unwrap();
+    /// branch.commit();
+    /// doc.import(&branch.export_from(&doc.oplog_vv())).unwrap();
+    /// assert_eq!(doc.get_text("text").to_string(), "Oh, Hello world");
+    /// ```
+    pub fn fork_at(&self, frontiers: &Frontiers) -> LoroResult<Self> {
+        let doc = self.doc.fork_at(frontiers)?;
+        Ok(LoroDoc::_new(doc))
+    }
+
+    /// Create a read-only view of the document at `frontiers`.
+    ///
+    /// Unlike [LoroDoc::checkout], it leaves this document as it is, so several versions
+    /// can be viewed at the same time, e.g. to compare them side by side. The views share
+    /// the history of this document, see [LoroDoc::fork], and don't get its later changes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::LoroDoc;
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "Hello").unwrap();
+    /// doc.commit();
+    /// let v1 = doc.oplog_frontiers();
+    /// text.insert(5, " world").unwrap();
+    /// doc.commit();
+    /// let v2 = doc.oplog_frontiers();
+    ///
+    /// let old = doc.view_at(&v1).unwrap();
+    /// let new = doc.view_at(&v2).unwrap();
+    /// assert_eq!(old.get_text("text").to_string(), "Hello");
+    /// assert_eq!(new.get_text("text").to_string(), "Hello world");
+    /// assert!(old.get_text("text").insert(0, "!").is_err());
+    /// assert!(!doc.is_detached());
+    /// ```
+    pub fn view_at(&self, frontiers: &Frontiers) -> LoroResult<DocView> {
+        let doc = self.doc.view_at(frontiers)?;
+        Ok(DocView {
+            doc: LoroDoc::_new(doc),
+        })
+    }
+
     /// Get the configurations of the document.
     pub fn config(&self) -> &Configure {
         self.doc.config()
@@ -105,6 +190,73 @@ impl LoroDoc {
         InnerLoroDoc::decode_import_blob_meta(bytes)
     }
 
+    /// Decode the state section of a blob exported by [`LoroDoc::export_sectioned_snapshot`]
+    /// into the deep value of the document, without reading the history section.
+    pub fn decode_state_section(bytes: &[u8]) -> LoroResult<LoroValue> {
+        InnerLoroDoc::decode_state_section(bytes)
+    }
+
+    /// Get the state section of a blob exported by
+    /// [`LoroDoc::export_sectioned_snapshot`], without reading the history section.
+    ///
+    /// It's a snapshot that can be imported into an empty document on its own.
+    pub fn state_section(bytes: &[u8]) -> LoroResult<&[u8]> {
+        InnerLoroDoc::state_section(bytes)
+    }
+
+    /// Get the history section of a blob exported by
+    /// [`LoroDoc::export_sectioned_snapshot`], without decoding the state section.
+    ///
+    /// It's an update blob from the empty version that can be imported on its own.
+    pub fn history_section(bytes: &[u8]) -> LoroResult<&[u8]> {
+        InnerLoroDoc::history_section(bytes)
+    }
+
+    /// Decode the deep value of the document, with each text as its rich text delta, from a
+    /// blob exported by [`LoroDoc::export_preview`] or [`LoroDoc::export_sectioned_snapshot`].
+    pub fn decode_preview(bytes: &[u8]) -> LoroResult<LoroValue> {
+        InnerLoroDoc::decode_preview(bytes)
+    }
+
+    /// Break down an exported snapshot or updates blob by section.
+    ///
+    /// It reports the size of the header, peers, change columns, state columns and
+    /// the bytes attributed to each container.
+    pub fn explain_encoding(bytes: &[u8]) -> LoroResult<EncodingReport> {
+        loro_internal::encoding::explain(bytes)
+    }
+
+    /// The size of the exported history relative to the content of its ops, i.e. the text
+    /// they insert and the values they set. See [EncodingReport::metadata_overhead_ratio].
+    ///
+    /// It's below 2 for typical text editing traces like the automerge paper.
+    pub fn metadata_overhead_ratio(&self) -> LoroResult<f64> {
+        self.doc.metadata_overhead_ratio()
+    }
+
+    /// Attribute the encoded size of the ops in an exported blob to containers, peers or
+    /// op types, to find out what makes a document large.
+    ///
+    /// # Example
+    /// ```
+    /// use loro::{Dimension, LoroDoc, SizeKey};
+    ///
+    /// let doc = LoroDoc::new();
+    /// doc.set_peer_id(1).unwrap();
+    /// doc.get_text("text").insert(0, "a long paragraph of text").unwrap();
+    /// doc.get_map("map").insert("key", 1).unwrap();
+    /// doc.commit();
+    /// let bytes = doc.export_snapshot();
+    /// let by_type = LoroDoc::size_breakdown_by(&bytes, Dimension::OpType).unwrap();
+    /// assert_eq!(by_type[0].key, SizeKey::OpType("text_insert"));
+    /// let by_peer = LoroDoc::size_breakdown_by(&bytes, Dimension::Peer).unwrap();
+    /// assert_eq!(by_peer.len(), 1);
+    /// assert_eq!(by_peer[0].op_num, 2);
+    /// ```
+    pub fn size_breakdown_by(bytes: &[u8], dimension: Dimension) -> LoroResult<Vec<SizeEntry>> {
+        loro_internal::encoding::size_breakdown_by(bytes, dimension)
+    }
+
     /// Set whether to record the timestamp of each change. Default is `false`.
     ///
     /// If enabled, the Unix timestamp will be recorded for each change automatically.
@@ -123,9 +275,235 @@ impl LoroDoc {
     ///
     /// If two continuous local changes are within the interval, they will be merged into one change.
     /// The default value is 1000 seconds.
+    ///
+    /// Return an error if `interval` is negative.
+    #[inline]
+    pub fn set_change_merge_interval(&self, interval: i64) -> LoroResult<()> {
+        self.doc.set_change_merge_interval(interval)
+    }
+
+    /// Commit the pending local ops automatically once there are `limit` of them.
+    ///
+    /// By default (`None`), the local ops are only committed when [`LoroDoc::commit`] is
+    /// called, or before importing, exporting and checking out. `Some(1)` commits after
+    /// every edit, so each edit emits its own event.
+    ///
+    /// The limit is checked after each edit, so a single edit with more ops than
+    /// `limit` is committed as a whole.
+    ///
+    /// Return an error if `limit` is `Some(0)`.
+    #[inline]
+    pub fn set_auto_commit_ops_limit(&self, limit: Option<usize>) -> LoroResult<()> {
+        self.doc.set_auto_commit_ops_limit(limit)
+    }
+
+    /// Set whether [`LoroDoc::export_snapshot`] should encode repeated text chunks as
+    /// back-references to their first occurrence. Default is `false`.
+    ///
+    /// It makes snapshots of repetitive text (templates, logs) smaller. The saved bytes
+    /// are reported in [`EncodingReport::deduped_text_bytes`]. Snapshots exported with it
+    /// enabled can't be imported by versions without the support.
+    #[inline]
+    pub fn set_snapshot_text_dedup(&self, dedup: bool) {
+        self.doc.set_snapshot_text_dedup(dedup);
+    }
+
+    /// Set whether the exported updates and snapshots store the op counters implicitly.
+    /// Default is `false`.
+    ///
+    /// Each op then only stores the gap from the end of the previous op of the same peer,
+    /// which is 0 for a run of edits, so long typing sessions are a bit smaller. The blobs
+    /// exported with it enabled can't be imported by versions without the support, which
+    /// reject them as a future encoding.
+    #[inline]
+    pub fn set_implicit_op_counters(&self, implicit: bool) {
+        self.doc.set_implicit_op_counters(implicit);
+    }
+
+    /// Compress the text inserted by a single op when it's exported, if it has at least
+    /// `threshold` bytes. Default is `None`, which disables the compression.
+    ///
+    /// It makes the updates and snapshots of large repetitive pastes, e.g. logs, much
+    /// smaller. The text is only compressed in the exported blobs, so it doesn't reduce the
+    /// memory of the oplog: in memory the inserted text is shared with the state of the
+    /// text, which needs it uncompressed. The compressed text is reported in
+    /// [`EncodingReport::compressed_text_bytes`].
+    ///
+    /// Blobs exported with it enabled can't be imported by versions without the support.
+    ///
+    /// Return an error if `threshold` is `Some(0)`.
+    #[inline]
+    pub fn set_op_compression_threshold(&self, threshold: Option<usize>) -> LoroResult<()> {
+        self.doc.set_op_compression_threshold(threshold)
+    }
+
+    /// Stream the local ops of every transaction to `stream` as they are applied, before
+    /// the transaction is committed.
+    ///
+    /// Each op is reported as [`TxnStreamEvent::Provisional`], and the end of its
+    /// transaction as [`TxnStreamEvent::Committed`] or, if it's discarded by
+    /// [`LoroDoc::abort_txn`], [`TxnStreamEvent::Aborted`]. Only committed ops can be exported,
+    /// so a live typing transport can send the provisional ops for a preview and the
+    /// exported updates once they are committed. The callback must not edit the doc.
+    #[inline]
+    pub fn set_txn_stream(&self, stream: Option<OnTxnStreamFn>) {
+        self.doc.set_txn_stream(stream);
+    }
+
+    /// Set the hook that inspects and rewrites every local op before it's applied.
+    ///
+    /// The inserted text and the values can be rewritten, and an error rejects the edit
+    /// with [`LoroError::OpRejected`]. The peers receive the rewritten ops, so the hook can
+    /// enforce the invariants of the app at the CRDT boundary. It must not edit the doc.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use std::sync::Arc;
+    /// # use loro::{LoroDoc, LoroValue, StagedOp};
+    /// let doc = LoroDoc::new();
+    /// doc.set_pre_commit_hook(Some(Arc::new(|op: StagedOp| -> Result<(), String> {
+    ///     match op {
+    ///         StagedOp::InsertText { text, .. } => text.retain(|c| !c.is_control()),
+    ///         StagedOp::Value { value, .. } => {
+    ///             if let LoroValue::I64(x) = value {
+    ///                 *x = (*x).clamp(0, 100);
+    ///             }
+    ///         }
+    ///         StagedOp::Other { .. } => {}
+    ///     }
+    ///     Ok(())
+    /// })));
+    /// doc.get_text("text").insert(0, "a\u{7}b").unwrap();
+    /// doc.get_map("map").insert("volume", 200).unwrap();
+    /// assert_eq!(doc.get_text("text").to_string(), "ab");
+    /// assert_eq!(doc.get_map("map").get_deep_value(), loro::loro_value!({"volume": 100}));
+    /// ```
+    #[inline]
+    pub fn set_pre_commit_hook(&self, hook: Option<PreCommitHook>) {
+        self.doc.set_pre_commit_hook(hook);
+    }
+
+    /// Set the callback that is invoked when an import or a local edit makes two non-empty
+    /// roots of different types share a name, e.g. if two peers created a root map and a
+    /// root list named "data" concurrently.
+    ///
+    /// Both roots are kept and can be accessed with their types. [LoroDoc::get_deep_value]
+    /// shows the non-empty one with the smallest type id under the name, so that all the
+    /// peers show the same value. The others are shown by
+    /// [LoroDoc::get_shadowed_roots_value]. For a local edit, the callback is called while
+    /// the transaction is locked, so it must not edit the doc.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{LoroDoc, ToJson};
+    /// # use serde_json::json;
+    /// # use std::sync::{Arc, Mutex};
+    /// let a = LoroDoc::new();
+    /// a.get_map("data").insert("key", 1).unwrap();
+    /// let b = LoroDoc::new();
+    /// b.get_list("data").push(1).unwrap();
+    ///
+    /// let conflicts = Arc::new(Mutex::new(Vec::new()));
+    /// let conflicts_clone = conflicts.clone();
+    /// a.set_on_root_type_conflict(Some(Arc::new(move |name, types| {
+    ///     conflicts_clone.lock().unwrap().push((name.to_string(), types.to_vec()));
+    /// })));
+    /// a.import(&b.export_snapshot()).unwrap();
+    /// assert_eq!(conflicts.lock().unwrap().len(), 1);
+    /// assert_eq!(a.get_deep_value().to_json_value(), json!({"data": {"key": 1}}));
+    /// assert_eq!(
+    ///     a.get_shadowed_roots_value().to_json_value(),
+    ///     json!({"data": {"List": [1]}})
+    /// );
+    /// ```
+    pub fn set_on_root_type_conflict(&self, on_conflict: Option<OnRootTypeConflict>) {
+        self.doc.set_on_root_type_conflict(on_conflict);
+    }
+
+    /// Reject the ops of each peer beyond `limit` on import, and the ops that depend on
+    /// them. `None` removes the limit.
+    ///
+    /// Unlike [`LoroDoc::import_with_budget`], the rest of the imported ops are kept. The
+    /// rejection only depends on the ids and the deps of the ops, so all the replicas with
+    /// the same limit end up with the same ops whatever order they receive them in. The
+    /// local edits beyond the limit fail with [`LoroError::OpQuotaExceeded`].
+    ///
+    /// Return an error if `limit` is `Some(0)`, or if it's changed after the doc has ops.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{IdSpan, LoroDoc};
+    /// # use std::sync::{Arc, Mutex};
+    /// let a = LoroDoc::new();
+    /// a.set_peer_id(1).unwrap();
+    /// a.get_text("text").insert(0, "0123456789").unwrap();
+    ///
+    /// let b = LoroDoc::new();
+    /// b.set_max_ops_per_peer(Some(4)).unwrap();
+    /// let rejected = Arc::new(Mutex::new(Vec::new()));
+    /// let rejected_clone = rejected.clone();
+    /// b.set_on_ops_rejected(Some(Arc::new(move |spans| {
+    ///     rejected_clone.lock().unwrap().extend_from_slice(spans);
+    /// })));
+    /// b.import(&a.export_snapshot()).unwrap();
+    /// assert_eq!(b.get_text("text").to_string(), "0123");
+    /// assert_eq!(*rejected.lock().unwrap(), vec![IdSpan::new(1, 4, 10)]);
+    /// ```
+    #[inline]
+    pub fn set_max_ops_per_peer(&self, limit: Option<usize>) -> LoroResult<()> {
+        self.doc.set_max_ops_per_peer(limit)
+    }
+
+    /// Reject the ops of each peer from the first one that takes the size of the content
+    /// inserted by the peer beyond `limit` on import, like
+    /// [`LoroDoc::set_max_ops_per_peer`]. `None` removes the limit.
+    ///
+    /// The ops of a peer are counted one by one in the order of their counters. The size of
+    /// an op is the byte length of its text, map keys, values and tree positions, where
+    /// each number counts as 8 bytes and each of the other non-string values as 1 byte.
+    /// The deletions and the moves have no size. The local edits beyond the limit fail with
+    /// [`LoroError::OpQuotaExceeded`].
+    ///
+    /// Return an error if `limit` is `Some(0)`, or if it's changed after the doc has ops.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::LoroDoc;
+    /// let a = LoroDoc::new();
+    /// a.set_peer_id(1).unwrap();
+    /// a.get_text("text").insert(0, "0123456789").unwrap();
+    ///
+    /// let b = LoroDoc::new();
+    /// b.set_max_bytes_per_peer(Some(4)).unwrap();
+    /// b.import(&a.export_snapshot()).unwrap();
+    /// assert_eq!(b.get_text("text").to_string(), "0123");
+    /// ```
+    #[inline]
+    pub fn set_max_bytes_per_peer(&self, limit: Option<usize>) -> LoroResult<()> {
+        self.doc.set_max_bytes_per_peer(limit)
+    }
+
+    /// Reject the ops of each peer from the first one that takes the number of the
+    /// containers created by the peer beyond `limit` on import, like
+    /// [`LoroDoc::set_max_ops_per_peer`]. `None` removes the limit.
+    ///
+    /// The metadata map of each created tree node counts as a container. The local edits
+    /// beyond the limit fail with [`LoroError::OpQuotaExceeded`].
+    ///
+    /// Return an error if `limit` is `Some(0)`, or if it's changed after the doc has ops.
     #[inline]
-    pub fn set_change_merge_interval(&self, interval: i64) {
-        self.doc.set_change_merge_interval(interval);
+    pub fn set_max_containers_per_peer(&self, limit: Option<usize>) -> LoroResult<()> {
+        self.doc.set_max_containers_per_peer(limit)
+    }
+
+    /// Set the callback called with the ops rejected by each import because of the
+    /// per-peer limits, e.g. [`LoroDoc::set_max_ops_per_peer`].
+    pub fn set_on_ops_rejected(&self, on_rejected: Option<OnOpsRejected>) {
+        self.doc.set_on_ops_rejected(on_rejected);
     }
 
     /// Set the jitter of the tree position(Fractional Index).
@@ -217,6 +595,48 @@ impl LoroDoc {
         self.doc.import_batch(bytes)
     }
 
+    /// Import the current state of `other` into this doc under the map container `mount_at`.
+    ///
+    /// Each root container of `other` becomes a child container of `mount_at`, keyed by
+    /// its root name.
+    ///
+    /// Only the state is copied. The history of `other` is not carried over: the content
+    /// is recreated by the ops of this doc's peer, so the authors and the old versions of
+    /// `other` are lost. Use [LoroDoc::import] to merge docs with their history, where the
+    /// roots of `other` stay roots.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use loro::{LoroDoc, ToJson};
+    /// use serde_json::json;
+    ///
+    /// let doc = LoroDoc::new();
+    /// let other = LoroDoc::new();
+    /// other.get_text("text").insert(0, "Hello").unwrap();
+    /// other.commit();
+    /// doc.absorb_state(&other, &doc.get_map("notes").id()).unwrap();
+    /// assert_eq!(
+    ///     doc.get_deep_value().to_json_value(),
+    ///     json!({"notes": {"text": "Hello"}})
+    /// );
+    /// ```
+    pub fn absorb_state(&self, other: &LoroDoc, mount_at: &ContainerID) -> LoroResult<()> {
+        self.doc.absorb_state(&other.doc, mount_at)
+    }
+
+    /// Create a new doc that only contains the subtree of `container`.
+    ///
+    /// The container becomes a root container of the new doc. It keeps its name if it's
+    /// already a root container, otherwise it's named `"root"`.
+    ///
+    /// The current state of the subtree is recreated in a single change by a new random
+    /// peer. The new doc has its own history and should not be merged back into this doc.
+    pub fn extract(&self, container: &ContainerID) -> LoroResult<LoroDoc> {
+        let doc = self.doc.extract(container)?;
+        Ok(LoroDoc::_new(doc))
+    }
+
     /// Get a [LoroMovableList] by container id.
     ///
     /// If the provided id is string, it will be converted into a root container id with the name of the string.
@@ -238,15 +658,114 @@ impl LoroDoc {
     /// Get a [LoroMap] by container id.
     ///
     /// If the provided id is string, it will be converted into a root container id with the name of the string.
+    /// Like [LoroDoc::get_text], the root is only created by its first edit.
     pub fn get_map<I: IntoContainerId>(&self, id: I) -> LoroMap {
         LoroMap {
             handler: self.doc.get_map(id),
         }
     }
 
+    /// Get the map for the bookkeeping of the app, e.g. its schema version, feature flags or
+    /// migration markers.
+    ///
+    /// It's a root map like the others, with the same merge semantics, but it's hidden from
+    /// [LoroDoc::get_deep_value], so the bookkeeping doesn't mix with the data of the users.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{LoroDoc, LoroValue, ToJson};
+    /// # use serde_json::json;
+    /// let doc = LoroDoc::new();
+    /// doc.get_system_map().insert("schema_version", 2).unwrap();
+    /// doc.get_text("text").insert(0, "hi").unwrap();
+    /// doc.commit();
+    /// assert_eq!(doc.get_deep_value().to_json_value(), json!({"text": "hi"}));
+    ///
+    /// let other = LoroDoc::new();
+    /// other.import(&doc.export_snapshot()).unwrap();
+    /// let version = other.get_system_map().get("schema_version").unwrap().left();
+    /// assert_eq!(version, Some(LoroValue::from(2)));
+    /// ```
+    pub fn get_system_map(&self) -> LoroMap {
+        LoroMap {
+            handler: self.doc.get_system_map(),
+        }
+    }
+
+    /// Register `alias` as another name of the root container `name`.
+    ///
+    /// Getting a root container by `alias` returns the container named `name`, so a root
+    /// container can be renamed without migrating its history, while older clients keep
+    /// using `name`. The alias is stored in the document and synced with it. It's ignored
+    /// if the root container named `alias` has ops of its own, so it never hides data.
+    ///
+    /// # Example
+    /// ```
+    /// use loro::LoroDoc;
+    ///
+    /// let doc = LoroDoc::new();
+    /// doc.get_text("text").insert(0, "Hello").unwrap();
+    /// doc.alias_root("text", "body").unwrap();
+    /// assert_eq!(doc.get_text("body").to_string(), "Hello");
+    /// doc.get_text("body").insert(5, "!").unwrap();
+    /// assert_eq!(doc.get_text("text").to_string(), "Hello!");
+    /// assert!(doc.alias_root("body", "text").is_err());
+    /// ```
+    pub fn alias_root(&self, name: &str, alias: &str) -> LoroResult<()> {
+        self.doc.alias_root(name, alias)
+    }
+
+    /// Remove an alias registered by [LoroDoc::alias_root].
+    pub fn remove_root_alias(&self, alias: &str) -> LoroResult<()> {
+        self.doc.remove_root_alias(alias)
+    }
+
+    /// Set a field of the metadata of this doc's peer, e.g. its display name with
+    /// [PEER_NAME_KEY] or the URL of its avatar.
+    ///
+    /// The metadata is stored in the doc and synced like other changes, so "who edited
+    /// this" can be answered without an external lookup, even for peers that are long
+    /// gone. Each field is last-write-wins. The name is also given by
+    /// [ChangeMeta::author].
+    ///
+    /// # Example
+    /// ```
+    /// # use loro::{LoroDoc, ID, PEER_NAME_KEY};
+    /// let doc = LoroDoc::new();
+    /// doc.set_peer_id(1).unwrap();
+    /// doc.set_peer_metadata(PEER_NAME_KEY, "Alice").unwrap();
+    /// doc.set_peer_metadata("avatar", "https://example.com/alice.png").unwrap();
+    /// doc.get_text("text").insert(0, "hi").unwrap();
+    /// doc.commit();
+    ///
+    /// let new_doc = LoroDoc::new();
+    /// new_doc.import(&doc.export_snapshot()).unwrap();
+    /// assert_eq!(new_doc.peer_name(1).as_deref(), Some("Alice"));
+    /// assert_eq!(new_doc.peer_metadata(1).len(), 2);
+    /// let change = new_doc.get_change(ID::new(1, 0)).unwrap();
+    /// assert_eq!(change.author.as_deref(), Some("Alice"));
+    /// ```
+    pub fn set_peer_metadata(&self, field: &str, value: impl Into<LoroValue>) -> LoroResult<()> {
+        self.doc.set_peer_metadata(field, value)
+    }
+
+    /// Get the metadata of `peer` set by [LoroDoc::set_peer_metadata].
+    pub fn peer_metadata(&self, peer: PeerID) -> HashMap<String, LoroValue> {
+        self.doc.peer_metadata(peer).into_iter().collect()
+    }
+
+    /// Get the display name of `peer`, see [LoroDoc::set_peer_metadata].
+    pub fn peer_name(&self, peer: PeerID) -> Option<Arc<str>> {
+        self.doc.peer_name(peer)
+    }
+
     /// Get a [LoroText] by container id.
     ///
     /// If the provided id is string, it will be converted into a root container id with the name of the string.
+    ///
+    /// Getting a root container doesn't create it. It reads as empty, and it's only part of
+    /// [LoroDoc::get_deep_value] after its first edit, so a doc opened read-only stays as is.
     pub fn get_text<I: IntoContainerId>(&self, id: I) -> LoroText {
         LoroText {
             handler: self.doc.get_text(id),
@@ -281,6 +800,15 @@ impl LoroDoc {
         self.doc.commit_then_renew()
     }
 
+    /// Discard the ops of the cumulative auto commit transaction instead of committing them.
+    ///
+    /// The containers edited by the transaction are rebuilt from the history, so it costs as
+    /// much as checking them out from the empty version. No events are emitted for the
+    /// discarded ops, and the txn stream gets [`TxnStreamEvent::Aborted`].
+    pub fn abort_txn(&self) {
+        self.doc.abort_txn()
+    }
+
     /// Commit the cumulative auto commit transaction with custom configure.
     ///
     /// There is a transaction behind every operation.
@@ -290,6 +818,40 @@ impl LoroDoc {
         self.doc.commit_with(options)
     }
 
+    /// Call `f` with the changes made by the pending transaction, i.e. the events it will
+    /// emit when it's committed.
+    ///
+    /// It can be used to preview or validate the staged edits before [LoroDoc::commit].
+    /// Each changed container has one diff, and the diffs are sorted by the length of their
+    /// paths.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{LoroDoc, TextDelta};
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "Hello").unwrap();
+    /// text.insert(5, " world").unwrap();
+    /// doc.get_map("map").insert("key", 1).unwrap();
+    /// doc.current_diff(|diffs| {
+    ///     assert_eq!(diffs.len(), 2);
+    ///     let text_diff = diffs.iter().find_map(|d| d.diff.as_text()).unwrap();
+    ///     let [TextDelta::Insert { insert, .. }] = &text_diff[..] else {
+    ///         panic!("{:?}", text_diff)
+    ///     };
+    ///     assert_eq!(insert, "Hello world");
+    /// });
+    ///
+    /// doc.commit();
+    /// doc.current_diff(|diffs| assert!(diffs.is_empty()));
+    /// ```
+    pub fn current_diff<R>(&self, f: impl FnOnce(&[event::ContainerDiff<'_>]) -> R) -> R {
+        let diffs = self.doc.current_diff();
+        let diffs: Vec<event::ContainerDiff> = diffs.iter().map(|d| d.into()).collect();
+        f(&diffs)
+    }
+
     /// Whether the document is in detached mode, where the [loro_internal::DocState] is not
     /// synchronized with the latest version of the [loro_internal::OpLog].
     pub fn is_detached(&self) -> bool {
@@ -309,14 +871,33 @@ impl LoroDoc {
         self.doc.import_with(bytes, origin.into())
     }
 
+    /// Import updates/snapshot with a memory budget.
+    ///
+    /// If decoding `bytes` is estimated to need more than `budget` bytes of memory, it
+    /// returns [`LoroError::BudgetExceeded`] before anything is decoded, so a pathological
+    /// blob can't exhaust the memory of the app. The memory already used by the doc isn't
+    /// counted.
+    ///
+    /// The estimate is a heuristic that sums the rough sizes of the decoded ops, changes,
+    /// strings and container states. It isn't an exact cap, since the decoding itself isn't
+    /// metered: leave some headroom between the budget and the memory that must not be
+    /// exceeded.
+    pub fn import_with_budget(&self, bytes: &[u8], budget: usize) -> Result<(), LoroError> {
+        self.doc.import_with_budget(bytes, budget)
+    }
+
     /// Import the json schema updates.
     ///
-    /// only supports backward compatibility but not forward compatibility.
+    /// only supports backward compatibility but not forward compatibility. Updates with a newer
+    /// `schema_version` are rejected with [LoroError::IncompatibleFutureEncodingError].
     pub fn import_json_updates<T: TryInto<JsonSchema>>(&self, json: T) -> Result<(), LoroError> {
         self.doc.import_json_updates(json)
     }
 
-    /// Export the current state with json-string format of the document.
+    /// Export the changes between `start_vv` and `end_vv` in the json schema format.
+    ///
+    /// The result can be serialized with `serde_json`, and importing it is equivalent to
+    /// importing the binary updates of the same range.
     pub fn export_json_updates(
         &self,
         start_vv: &VersionVector,
@@ -330,11 +911,144 @@ impl LoroDoc {
         self.doc.export_from(vv)
     }
 
+    /// Export all the ops not included in the given `VersionVector` as a series of chunks,
+    /// each with about `chunk_size` ops.
+    ///
+    /// Each chunk is length-prefixed and only depends on the chunks before it, so they can
+    /// be written to a stream and imported incrementally by [`LoroDoc::import_reader`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{LoroDoc, VersionVector};
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// for i in 0..10 {
+    ///     text.insert(0, &i.to_string()).unwrap();
+    ///     doc.commit();
+    /// }
+    ///
+    /// let stream: Vec<u8> = doc.export_chunks(&VersionVector::new(), 3).flatten().collect();
+    /// let new_doc = LoroDoc::new();
+    /// new_doc.import_reader(stream.as_slice()).unwrap();
+    /// assert_eq!(new_doc.get_text("text").to_string(), text.to_string());
+    /// ```
+    pub fn export_chunks<'a>(
+        &'a self,
+        vv: &VersionVector,
+        chunk_size: usize,
+    ) -> impl Iterator<Item = Vec<u8>> + 'a {
+        self.doc.export_chunks(vv, chunk_size)
+    }
+
+    /// Import the chunks exported by [`LoroDoc::export_chunks`] from `reader` until it
+    /// reaches the end.
+    pub fn import_reader(&self, reader: impl std::io::Read) -> LoroResult<()> {
+        self.doc.import_reader(reader)
+    }
+
     /// Export the current state and history of the document.
     pub fn export_snapshot(&self) -> Vec<u8> {
         self.doc.export_snapshot()
     }
 
+    /// Export the history in a layout for rsync and dedup-based backups.
+    ///
+    /// The ops are split into chunks at boundaries decided by the op ids, so successive
+    /// exports of a growing document share most of their byte ranges. The blob is imported
+    /// by [`LoroDoc::import`]. It has no state, so importing it replays the history.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::LoroDoc;
+    /// let doc = LoroDoc::new();
+    /// doc.set_peer_id(1).unwrap();
+    /// let text = doc.get_text("text");
+    /// for i in 0..20000 {
+    ///     text.insert(text.len_unicode(), &(i % 10).to_string()).unwrap();
+    /// }
+    /// let backup = doc.export_for_backup();
+    /// text.insert(text.len_unicode(), "end").unwrap();
+    /// let new_backup = doc.export_for_backup();
+    /// // The chunks before the new edits are unchanged
+    /// let shared = backup.iter().zip(&new_backup).skip(20).take_while(|(a, b)| a == b).count();
+    /// assert!(shared > backup.len() / 2);
+    ///
+    /// let new_doc = LoroDoc::new();
+    /// new_doc.import(&new_backup).unwrap();
+    /// assert_eq!(new_doc.get_text("text").to_string(), text.to_string());
+    /// ```
+    pub fn export_for_backup(&self) -> Vec<u8> {
+        self.doc.export_for_backup()
+    }
+
+    /// Export the current state and the history as two sections that can be decoded on
+    /// their own.
+    ///
+    /// The state section is a snapshot, which a viewer takes with [`LoroDoc::state_section`]
+    /// without reading the history bytes, and an archival tool takes the history with
+    /// [`LoroDoc::history_section`]. [`LoroDoc::import`] inits an empty document by the
+    /// state section without replaying the history.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{LoroDoc, ToJson};
+    /// # use serde_json::json;
+    /// let doc = LoroDoc::new();
+    /// doc.get_text("text").insert(0, "hello").unwrap();
+    /// let blob = doc.export_sectioned_snapshot();
+    ///
+    /// let state = LoroDoc::decode_state_section(&blob).unwrap();
+    /// assert_eq!(state.to_json_value(), json!({"text": "hello"}));
+    ///
+    /// let viewer = LoroDoc::new();
+    /// viewer.import(LoroDoc::state_section(&blob).unwrap()).unwrap();
+    /// assert_eq!(viewer.get_text("text").to_string(), "hello");
+    ///
+    /// let archive = LoroDoc::new();
+    /// archive.import(LoroDoc::history_section(&blob).unwrap()).unwrap();
+    /// assert_eq!(archive.get_text("text").to_string(), "hello");
+    /// ```
+    pub fn export_sectioned_snapshot(&self) -> Vec<u8> {
+        self.doc.export_sectioned_snapshot()
+    }
+
+    /// Export only the current value of the document, for the services that render a
+    /// read-only preview, e.g. link previews and search indexing.
+    ///
+    /// Each text is kept as its rich text delta, so the preview can render the styles. The
+    /// blob has no history, tombstones or op metadata, so it's usually much smaller than a
+    /// snapshot. It can't be imported; it's read by [`LoroDoc::decode_preview`].
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{LoroDoc, ToJson};
+    /// # use serde_json::json;
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "hello world").unwrap();
+    /// text.delete(0, 6).unwrap();
+    /// text.mark(0..2, "bold", true).unwrap();
+    /// let preview = doc.export_preview().unwrap();
+    /// assert!(preview.len() < doc.export_snapshot().len());
+    ///
+    /// let value = LoroDoc::decode_preview(&preview).unwrap();
+    /// assert_eq!(
+    ///     value.to_json_value(),
+    ///     json!({"text": [
+    ///         {"insert": "wo", "attributes": {"bold": true}},
+    ///         {"insert": "rld"}
+    ///     ]})
+    /// );
+    /// assert!(LoroDoc::new().import(&preview).is_err());
+    /// ```
+    pub fn export_preview(&self) -> LoroResult<Vec<u8>> {
+        self.doc.export_preview()
+    }
+
     /// Convert `Frontiers` into `VersionVector`
     pub fn frontiers_to_vv(&self, frontiers: &Frontiers) -> Option<VersionVector> {
         self.doc.frontiers_to_vv(frontiers)
@@ -351,6 +1065,216 @@ impl LoroDoc {
         f(&oplog)
     }
 
+    /// Get the direct dependencies of the op with the given id.
+    ///
+    /// Return `None` if the op is not included in the `OpLog`.
+    pub fn deps_of(&self, id: ID) -> Option<Frontiers> {
+        self.doc.deps_of(id)
+    }
+
+    /// Get the id spans that this doc must import before the blob described by `meta`
+    /// can be applied.
+    ///
+    /// Only the direct dependencies of the blob are returned. Servers can use it to find
+    /// the other update blobs a client should fetch first.
+    pub fn missing_deps_for(&self, meta: &ImportBlobMetadata) -> Vec<IdSpan> {
+        self.doc.missing_deps_for(meta)
+    }
+
+    /// Compare the version of this doc with the version after importing `bytes`,
+    /// without importing it.
+    ///
+    /// Sync layers can use it to decide whether an incoming update is old, concurrent,
+    /// or strictly newer:
+    ///
+    /// - `Some(Ordering::Less)`: the update contains ops newer than this doc
+    /// - `Some(Ordering::Equal)` or `Some(Ordering::Greater)`: the update is already included
+    /// - `None`: the update is concurrent to this doc
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::LoroDoc;
+    /// # use std::cmp::Ordering;
+    /// let doc = LoroDoc::new();
+    /// doc.get_text("text").insert(0, "Hello").unwrap();
+    /// doc.commit();
+    /// let update = doc.export_from(&Default::default());
+    ///
+    /// let other = LoroDoc::new();
+    /// assert_eq!(other.vv_cmp_with_update_bytes(&update).unwrap(), Some(Ordering::Less));
+    /// other.import(&update).unwrap();
+    /// assert_eq!(other.vv_cmp_with_update_bytes(&update).unwrap(), Some(Ordering::Equal));
+    /// ```
+    pub fn vv_cmp_with_update_bytes(&self, bytes: &[u8]) -> LoroResult<Option<Ordering>> {
+        self.doc.vv_cmp_with_update_bytes(bytes)
+    }
+
+    /// Whether there are local ops that are not committed yet.
+    ///
+    /// Apps can use it to show an "unsaved changes" indicator.
+    pub fn has_uncommitted_txn(&self) -> bool {
+        self.doc.has_uncommitted_txn()
+    }
+
+    /// The number of local ops that are not committed yet.
+    pub fn uncommitted_ops_len(&self) -> usize {
+        self.doc.uncommitted_ops_len()
+    }
+
+    /// Get the committed ops that are not included in `vv`.
+    ///
+    /// Pass the version acknowledged by the server to find the changes that still need
+    /// to be synced. An empty result means everything committed is synced.
+    pub fn unsynced_since(&self, vv: &VersionVector) -> Vec<IdSpan> {
+        self.doc.unsynced_since(vv)
+    }
+
+    /// Get the frontiers of the ops on the container and the latest timestamp of their
+    /// changes, i.e. when the container was last modified.
+    ///
+    /// It's maintained incrementally, so it's cheap to query for every item of a list view.
+    /// The ops of the pending transaction are not included. Return `None` if the container
+    /// has no ops.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{CommitOptions, LoroDoc, ID};
+    /// let doc = LoroDoc::new();
+    /// doc.set_peer_id(1).unwrap();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "hello").unwrap();
+    /// doc.commit_with(CommitOptions::new().timestamp(100));
+    /// doc.get_map("map").insert("key", 1).unwrap();
+    /// doc.commit_with(CommitOptions::new().timestamp(200));
+    /// let (frontiers, timestamp) = doc.container_last_modified(&text.id()).unwrap();
+    /// assert_eq!(frontiers, vec![ID::new(1, 4)].into());
+    /// assert_eq!(timestamp, 100);
+    /// ```
+    pub fn container_last_modified(&self, id: &ContainerID) -> Option<(Frontiers, i64)> {
+        self.doc.container_last_modified(id)
+    }
+
+    /// Get the metadata of the change that contains the op with the given id.
+    ///
+    /// The commit message and the timestamp of a change can be set with
+    /// [`LoroDoc::commit_with`]. They are encoded with the change, so they are available
+    /// on the other peers too.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{CommitOptions, LoroDoc, ID};
+    /// let doc = LoroDoc::new();
+    /// doc.set_peer_id(1).unwrap();
+    /// doc.get_text("text").insert(0, "hello").unwrap();
+    /// doc.commit_with(CommitOptions::new().commit_msg("init").timestamp(100));
+    /// let new_doc = LoroDoc::new();
+    /// new_doc.import(&doc.export_snapshot()).unwrap();
+    /// let change = new_doc.get_change(ID::new(1, 2)).unwrap();
+    /// assert_eq!(change.message.as_deref(), Some("init"));
+    /// assert_eq!(change.timestamp, 100);
+    /// ```
+    pub fn get_change(&self, id: ID) -> Option<ChangeMeta> {
+        self.doc.get_change(id)
+    }
+
+    /// Iterate over the metadata of all the changes, sorted by Lamport timestamp,
+    /// which is also a causal order.
+    pub fn change_iter(&self) -> impl Iterator<Item = ChangeMeta> {
+        self.doc.change_iter()
+    }
+
+    /// Get a page of at most `limit` changes, from the newest to the oldest.
+    ///
+    /// Pass the returned cursor to get the next page, it's `None` after the oldest change.
+    /// The cursor is stable: the changes imported between the pages don't shift the pages.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::LoroDoc;
+    /// let doc = LoroDoc::new();
+    /// for i in 0..5 {
+    ///     doc.get_text("text").insert(0, "a").unwrap();
+    ///     doc.commit_with(loro::CommitOptions::new().commit_msg(&i.to_string()));
+    /// }
+    /// let mut cursor = None;
+    /// let mut messages = Vec::new();
+    /// loop {
+    ///     let (page, next) = doc.changes_page(cursor.as_ref(), 2);
+    ///     messages.extend(page.into_iter().map(|c| c.message.unwrap().to_string()));
+    ///     match next {
+    ///         Some(next) => cursor = Some(next),
+    ///         None => break,
+    ///     }
+    /// }
+    /// assert_eq!(messages, ["4", "3", "2", "1", "0"]);
+    /// ```
+    pub fn changes_page(
+        &self,
+        cursor: Option<&ChangesCursor>,
+        limit: usize,
+    ) -> (Vec<ChangeMeta>, Option<ChangesCursor>) {
+        self.doc.changes_page(cursor, limit)
+    }
+
+    /// Export the causal dependency graph of the changes, so that external tools can
+    /// check the causal order of the document.
+    ///
+    /// # Example
+    /// ```
+    /// use loro::{DepGraph, LoroDoc};
+    ///
+    /// let doc = LoroDoc::new();
+    /// doc.get_text("text").insert(0, "hello").unwrap();
+    /// doc.commit();
+    /// let graph = doc.export_dep_graph();
+    /// assert!(graph.check_causal_order().is_ok());
+    /// assert_eq!(DepGraph::decode(&graph.encode()).unwrap(), graph);
+    /// assert_eq!(DepGraph::from_json(&graph.to_json()).unwrap(), graph);
+    /// ```
+    pub fn export_dep_graph(&self) -> DepGraph {
+        self.doc.export_dep_graph()
+    }
+
+    /// Whether the `OpLog` has changes that are not included in `vv`.
+    pub fn changes_since(&self, vv: &VersionVector) -> bool {
+        self.doc.changes_since(vv)
+    }
+
+    /// Call `callback` once the `OpLog` includes `frontiers`.
+    ///
+    /// It's called immediately if the version is already included, otherwise after the
+    /// import that brings it in. It can be used to learn when a remote peer has received
+    /// a local edit without polling.
+    ///
+    /// # Example
+    /// ```
+    /// use std::sync::{atomic::{AtomicBool, Ordering}, Arc};
+    /// use loro::LoroDoc;
+    ///
+    /// let client = LoroDoc::new();
+    /// let server = LoroDoc::new();
+    /// client.get_text("text").insert(0, "saved").unwrap();
+    /// client.commit();
+    /// let saved = Arc::new(AtomicBool::new(false));
+    /// let saved_clone = saved.clone();
+    /// server.wait_for_version(
+    ///     client.oplog_frontiers(),
+    ///     Box::new(move || saved_clone.store(true, Ordering::Relaxed)),
+    /// );
+    /// assert!(!saved.load(Ordering::Relaxed));
+    /// assert!(client.changes_since(&server.oplog_vv()));
+    /// server.import(&client.export_from(&server.oplog_vv())).unwrap();
+    /// assert!(saved.load(Ordering::Relaxed));
+    /// assert!(!client.changes_since(&server.oplog_vv()));
+    /// ```
+    pub fn wait_for_version(&self, frontiers: Frontiers, callback: OnVersionReached) {
+        self.doc.wait_for_version(frontiers, callback)
+    }
+
     /// Get the `VersionVector` version of `OpLog`
     pub fn oplog_vv(&self) -> VersionVector {
         self.doc.oplog_vv()
@@ -372,10 +1296,55 @@ impl LoroDoc {
     }
 
     /// Get the current state of the document.
+    ///
+    /// A root container is only part of the value after its first edit, see
+    /// [LoroDoc::get_text].
     pub fn get_deep_value(&self) -> LoroValue {
         self.doc.get_deep_value()
     }
 
+    /// Get the values of the roots hidden from [`LoroDoc::get_deep_value`], because they
+    /// share their name with a root of another type that has precedence.
+    ///
+    /// It's a map from the name to a map from the type to the value, e.g.
+    /// `{"data": {"List": [1]}}`. See [`LoroDoc::set_on_root_type_conflict`].
+    pub fn get_shadowed_roots_value(&self) -> LoroValue {
+        self.doc.get_shadowed_roots_value()
+    }
+
+    /// The hash of the current state, e.g. for a server to check that the replicas have
+    /// converged after each sync round.
+    ///
+    /// It's updated with each op that is applied, so it's O(1) to read after every import.
+    /// The replicas that have imported the same ops have the same hash, whatever the order
+    /// of the imports.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::LoroDoc;
+    /// let doc = LoroDoc::new();
+    /// doc.get_text("text").insert(0, "Hello").unwrap();
+    /// let other = LoroDoc::new();
+    /// assert_ne!(doc.state_hash(), other.state_hash());
+    /// other.import(&doc.export_snapshot()).unwrap();
+    /// assert_eq!(doc.state_hash(), other.state_hash());
+    /// ```
+    #[inline]
+    pub fn state_hash(&self) -> u64 {
+        self.doc.state_hash()
+    }
+
+    /// Get the deep values of the given containers, materializing them in parallel.
+    ///
+    /// It's read-only and returns the values in the order of `containers`. The value of a
+    /// container that the doc doesn't know is [LoroValue::Null]. It needs the `parallel`
+    /// feature, which isn't available on wasm.
+    #[cfg(feature = "parallel")]
+    pub fn par_get_values(&self, containers: &[ContainerID]) -> Vec<LoroValue> {
+        self.doc.par_get_values(containers)
+    }
+
     /// Get the `Frontiers` version of `OpLog`
     pub fn oplog_frontiers(&self) -> Frontiers {
         self.doc.oplog_frontiers()
@@ -403,57 +1372,215 @@ impl LoroDoc {
 
     /// Subscribe the events of a container.
     ///
-    /// The callback will be invoked when the container is changed.
-    /// Returns a subscription id that can be used to unsubscribe.
+    /// The callback will be invoked when the container is changed.
+    /// Returns a subscription id that can be used to unsubscribe.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::LoroDoc;
+    /// # use std::sync::{atomic::AtomicBool, Arc};
+    /// # use loro::{event::DiffEvent, LoroResult, TextDelta};
+    /// #
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// let ran = Arc::new(AtomicBool::new(false));
+    /// let ran2 = ran.clone();
+    /// doc.subscribe(
+    ///     &text.id(),
+    ///     Arc::new(move |event| {
+    ///         assert!(event.triggered_by.is_local());
+    ///         for event in event.events {
+    ///             let delta = event.diff.as_text().unwrap();
+    ///             let d = TextDelta::Insert {
+    ///                 insert: "123".into(),
+    ///                 attributes: Default::default(),
+    ///             };
+    ///             assert_eq!(delta, &vec![d]);
+    ///             ran2.store(true, std::sync::atomic::Ordering::Relaxed);
+    ///         }
+    ///     }),
+    /// );
+    /// text.insert(0, "123").unwrap();
+    /// doc.commit();
+    /// assert!(ran.load(std::sync::atomic::Ordering::Relaxed));
+    /// ```
+    pub fn subscribe(&self, container_id: &ContainerID, callback: Subscriber) -> SubID {
+        self.doc.subscribe(
+            container_id,
+            Arc::new(move |e| {
+                callback(DiffEvent::from(e));
+            }),
+        )
+    }
+
+    /// Subscribe all the events.
+    ///
+    /// The callback will be invoked when any part of the [loro_internal::DocState] is changed.
+    /// Returns a subscription id that can be used to unsubscribe.
+    pub fn subscribe_root(&self, callback: Subscriber) -> SubID {
+        // self.doc.subscribe_root(callback)
+        self.doc.subscribe_root(Arc::new(move |e| {
+            callback(DiffEvent::from(e));
+        }))
+    }
+
+    /// Subscribe to a value projected from the given path.
+    ///
+    /// `projector` maps the deep value at `path` (`None` if the path doesn't exist) to the
+    /// selected value. `callback` receives the old and the new selected values, and it's only
+    /// invoked when the selected value changes. Edits to unrelated containers don't invoke
+    /// the projector at all.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{Index, LoroDoc, LoroValue};
+    /// # use std::sync::{Arc, Mutex};
+    /// let doc = LoroDoc::new();
+    /// let map = doc.get_map("user");
+    /// let names = Arc::new(Mutex::new(Vec::new()));
+    /// let names_cp = names.clone();
+    /// doc.subscribe_select(
+    ///     &[Index::Key("user".into())],
+    ///     Arc::new(|v| {
+    ///         v.and_then(|v| v.as_map().and_then(|m| m.get("name").cloned()))
+    ///             .unwrap_or(LoroValue::Null)
+    ///     }),
+    ///     Arc::new(move |_old, new| names_cp.lock().unwrap().push(new.clone())),
+    /// );
+    /// map.insert("name", "Alice").unwrap();
+    /// doc.commit();
+    /// map.insert("age", 20).unwrap();
+    /// doc.commit();
+    /// doc.get_text("text").insert(0, "hi").unwrap();
+    /// doc.commit();
+    /// assert_eq!(*names.lock().unwrap(), vec![LoroValue::from("Alice")]);
+    /// ```
+    pub fn subscribe_select(
+        &self,
+        path: &[Index],
+        projector: Projector,
+        callback: SelectSubscriber,
+    ) -> SubID {
+        self.doc.subscribe_select(path, projector, callback)
+    }
+
+    /// Subscribe to the edits of a text that touch `range`.
+    ///
+    /// The callback receives the part of each text delta in the range, with the positions
+    /// relative to the start of the range, and it isn't invoked for the edits outside of
+    /// it. The range is moved by the edits before it, see [event::VisibleRange::apply].
+    /// With [LoroText::spans_in_range], a renderer that only draws the visible part of a
+    /// long text doesn't have to go through the whole text on each frame.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{event::VisibleRange, LoroDoc, TextDelta};
+    /// # use std::sync::{Arc, Mutex};
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "0123456789").unwrap();
+    /// doc.commit();
+    ///
+    /// let range = VisibleRange::new(4..8);
+    /// let deltas = Arc::new(Mutex::new(Vec::new()));
+    /// let deltas_clone = deltas.clone();
+    /// let _sub = doc.subscribe_text_range(
+    ///     &text,
+    ///     &range,
+    ///     Arc::new(move |delta| deltas_clone.lock().unwrap().push(delta.to_vec())),
+    /// );
+    /// text.insert(0, "ab").unwrap();
+    /// doc.commit();
+    /// text.delete(7, 1).unwrap();
+    /// doc.commit();
+    /// assert_eq!(range.get(), 6..9);
+    /// assert_eq!(
+    ///     *deltas.lock().unwrap(),
+    ///     vec![vec![
+    ///         TextDelta::Retain { retain: 1, attributes: None },
+    ///         TextDelta::Delete { delete: 1 },
+    ///     ]]
+    /// );
+    /// ```
+    pub fn subscribe_text_range(
+        &self,
+        text: &LoroText,
+        range: &event::VisibleRange,
+        callback: event::RangeSubscriber,
+    ) -> SubID {
+        let range = range.clone();
+        self.subscribe(
+            &text.id(),
+            Arc::new(move |e| {
+                for event in e.events {
+                    let Some(delta) = event.diff.as_text() else {
+                        continue;
+                    };
+                    let delta = range.apply(delta);
+                    if !delta.is_empty() {
+                        callback(&delta);
+                    }
+                }
+            }),
+        )
+    }
+
+    /// Push the events of a container, or of the whole doc if `container_id` is `None`, to
+    /// a bounded queue.
+    ///
+    /// The subscribers are called synchronously when the events are emitted. A consumer
+    /// that is slower, e.g. one on another thread, can pop the events from the queue
+    /// instead, without the queue growing unbounded during a catch-up import. See
+    /// [event::EventQueue] for an example.
+    pub fn subscribe_queue(
+        &self,
+        container_id: Option<&ContainerID>,
+        queue: &event::EventQueue,
+    ) -> SubID {
+        self.doc.subscribe_queue(container_id, &queue.0)
+    }
+
+    /// Receive the changes of the document as [RFC 6902](https://www.rfc-editor.org/rfc/rfc6902)
+    /// JSON Patches, e.g. to stream them to a web client that doesn't load the CRDT.
+    ///
+    /// The patches apply to [LoroDoc::get_deep_value] right after this call. A text edit
+    /// replaces the whole string, and a tree edit replaces the whole tree. See
+    /// [json_patch::sse_message] to send a patch with Server-Sent Events.
     ///
     /// # Example
     ///
     /// ```
-    /// # use loro::LoroDoc;
-    /// # use std::sync::{atomic::AtomicBool, Arc};
-    /// # use loro::{event::DiffEvent, LoroResult, TextDelta};
-    /// #
+    /// # use loro::{LoroDoc, json_patch::JsonPatchOp};
+    /// # use std::sync::{Arc, Mutex};
     /// let doc = LoroDoc::new();
-    /// let text = doc.get_text("text");
-    /// let ran = Arc::new(AtomicBool::new(false));
-    /// let ran2 = ran.clone();
-    /// doc.subscribe(
-    ///     &text.id(),
-    ///     Arc::new(move |event| {
-    ///         assert!(event.triggered_by.is_local());
-    ///         for event in event.events {
-    ///             let delta = event.diff.as_text().unwrap();
-    ///             let d = TextDelta::Insert {
-    ///                 insert: "123".into(),
-    ///                 attributes: Default::default(),
-    ///             };
-    ///             assert_eq!(delta, &vec![d]);
-    ///             ran2.store(true, std::sync::atomic::Ordering::Relaxed);
-    ///         }
-    ///     }),
-    /// );
-    /// text.insert(0, "123").unwrap();
+    /// let patches = Arc::new(Mutex::new(Vec::new()));
+    /// let patches_clone = patches.clone();
+    /// let _sub = doc.subscribe_json_patch(Arc::new(move |patch| {
+    ///     patches_clone.lock().unwrap().extend_from_slice(patch);
+    /// }));
+    /// doc.get_map("meta").insert("title", "x").unwrap();
     /// doc.commit();
-    /// assert!(ran.load(std::sync::atomic::Ordering::Relaxed));
+    /// assert_eq!(
+    ///     *patches.lock().unwrap(),
+    ///     vec![
+    ///         JsonPatchOp::Add { path: "/meta".into(), value: loro::loro_value!({}) },
+    ///         JsonPatchOp::Add { path: "/meta/title".into(), value: "x".into() },
+    ///     ]
+    /// );
     /// ```
-    pub fn subscribe(&self, container_id: &ContainerID, callback: Subscriber) -> SubID {
-        self.doc.subscribe(
-            container_id,
-            Arc::new(move |e| {
-                callback(DiffEvent::from(e));
-            }),
-        )
+    pub fn subscribe_json_patch(&self, callback: json_patch::JsonPatchSubscriber) -> SubID {
+        self.doc.subscribe_json_patch(callback)
     }
 
-    /// Subscribe all the events.
+    /// Apply a JSON Patch, e.g. one sent by a web client, as a single commit.
     ///
-    /// The callback will be invoked when any part of the [loro_internal::DocState] is changed.
-    /// Returns a subscription id that can be used to unsubscribe.
-    pub fn subscribe_root(&self, callback: Subscriber) -> SubID {
-        // self.doc.subscribe_root(callback)
-        self.doc.subscribe_root(Arc::new(move |e| {
-            callback(DiffEvent::from(e));
-        }))
+    /// The whole patch is checked first, so an invalid patch changes nothing. A text or a
+    /// counter is edited to reach the new value, and trees can't be patched.
+    pub fn apply_json_patch(&self, patch: &[json_patch::JsonPatchOp]) -> LoroResult<()> {
+        self.doc.apply_json_patch(patch)
     }
 
     /// Remove a subscription.
@@ -484,10 +1611,16 @@ impl LoroDoc {
 
     /// Get the absolute position of the given cursor.
     ///
+    /// If the id of the cursor is not in the history of the doc, the position the cursor was
+    /// created at is returned, clamped to the length of the container, and
+    /// [PosQueryResult::approximate] is true.
+    ///
+    /// The cursor can be persisted with [Cursor::encode] or its string form.
+    ///
     /// # Example
     ///
     /// ```
-    /// # use loro::{LoroDoc, ToJson};
+    /// # use loro::{cursor::Cursor, LoroDoc, ToJson};
     /// let doc = LoroDoc::new();
     /// let text = &doc.get_text("text");
     /// text.insert(0, "01234").unwrap();
@@ -499,6 +1632,9 @@ impl LoroDoc {
     /// assert_eq!(doc.get_cursor_pos(&pos).unwrap().current.pos, 0);
     /// text.insert(0, "01234").unwrap();
     /// assert_eq!(doc.get_cursor_pos(&pos).unwrap().current.pos, 5);
+    ///
+    /// let pos = Cursor::try_from(pos.to_string().as_str()).unwrap();
+    /// assert_eq!(Cursor::decode(&pos.encode()).unwrap(), pos);
     /// ```
     pub fn get_cursor_pos(
         &self,
@@ -513,6 +1649,68 @@ impl LoroDoc {
     }
 }
 
+/// A read-only view of a [LoroDoc] at a version, created by [LoroDoc::view_at].
+///
+/// The containers it returns can be read like the ones of a [LoroDoc], but editing them
+/// fails.
+#[derive(Debug)]
+pub struct DocView {
+    doc: LoroDoc,
+}
+
+impl DocView {
+    /// The version of the view.
+    pub fn frontiers(&self) -> Frontiers {
+        self.doc.state_frontiers()
+    }
+
+    /// Get the value of the document at the version of the view.
+    pub fn get_deep_value(&self) -> LoroValue {
+        self.doc.get_deep_value()
+    }
+
+    /// Get the value or container at the given path, see [LoroDoc::get_by_str_path].
+    pub fn get_by_str_path(&self, path: &str) -> Option<ValueOrContainer> {
+        self.doc.get_by_str_path(path)
+    }
+
+    /// Get a [LoroText] by container id.
+    pub fn get_text<I: IntoContainerId>(&self, id: I) -> LoroText {
+        self.doc.get_text(id)
+    }
+
+    /// Get a [LoroMap] by container id.
+    pub fn get_map<I: IntoContainerId>(&self, id: I) -> LoroMap {
+        self.doc.get_map(id)
+    }
+
+    /// Get the map for the bookkeeping of the app, see [LoroDoc::get_system_map].
+    pub fn get_system_map(&self) -> LoroMap {
+        self.doc.get_system_map()
+    }
+
+    /// Get a [LoroList] by container id.
+    pub fn get_list<I: IntoContainerId>(&self, id: I) -> LoroList {
+        self.doc.get_list(id)
+    }
+
+    /// Get a [LoroMovableList] by container id.
+    pub fn get_movable_list<I: IntoContainerId>(&self, id: I) -> LoroMovableList {
+        self.doc.get_movable_list(id)
+    }
+
+    /// Get a [LoroTree] by container id.
+    pub fn get_tree<I: IntoContainerId>(&self, id: I) -> LoroTree {
+        self.doc.get_tree(id)
+    }
+
+    #[cfg(feature = "counter")]
+    /// Get a [LoroCounter] by container id.
+    pub fn get_counter<I: IntoContainerId>(&self, id: I) -> LoroCounter {
+        self.doc.get_counter(id)
+    }
+}
+
 /// It's used to prevent the user from implementing the trait directly.
 #[allow(private_bounds)]
 trait SealedTrait {}
@@ -714,6 +1912,42 @@ impl LoroList {
         ))
     }
 
+    /// Insert the containers at the given position with a single op, e.g. to instantiate a
+    /// template with many children.
+    ///
+    /// Inserting them one by one takes one op per container. A detached list inserts its
+    /// adjacent child containers this way too.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{ContainerTrait, LoroDoc, LoroMap, LoroText, ToJson};
+    /// # use serde_json::json;
+    /// let doc = LoroDoc::new();
+    /// let list = doc.get_list("cells");
+    /// let children = vec![LoroText::new().to_container(), LoroMap::new().to_container()];
+    /// let cells = list.insert_containers(0, children).unwrap();
+    /// cells[0].as_text().unwrap().insert(0, "A1").unwrap();
+    /// cells[1].as_map().unwrap().insert("bold", true).unwrap();
+    /// assert_eq!(
+    ///     doc.get_deep_value().to_json_value(),
+    ///     json!({"cells": ["A1", {"bold": true}]})
+    /// );
+    /// ```
+    pub fn insert_containers(
+        &self,
+        pos: usize,
+        children: Vec<Container>,
+    ) -> LoroResult<Vec<Container>> {
+        let children = children.iter().map(|x| x.to_handler()).collect();
+        Ok(self
+            .handler
+            .insert_containers(pos, children)?
+            .into_iter()
+            .map(Container::from_handler)
+            .collect())
+    }
+
     /// Get the cursor at the given position.
     ///
     /// Using "index" to denote cursor positions can be unstable, as positions may
@@ -856,6 +2090,39 @@ impl LoroMap {
         self.handler.insert(key, value)
     }
 
+    /// Insert a key-value pair that expires `ttl` after it's written.
+    ///
+    /// `ttl` has the same unit as the timestamps of the changes. The entry expires once the
+    /// timestamp of the doc's version reaches the timestamp of the change of this write plus
+    /// `ttl`. It doesn't depend on the local clock, so all the peers with the same version
+    /// agree on it. Overwriting the key drops the TTL.
+    ///
+    /// Expired entries are hidden from the reads of the map. Use [LoroMap::gc_expired] to
+    /// delete them.
+    ///
+    /// Return an error if the doc doesn't record timestamps, see
+    /// [LoroDoc::set_record_timestamp].
+    pub fn insert_with_ttl(
+        &self,
+        key: &str,
+        value: impl Into<LoroValue>,
+        ttl: i64,
+    ) -> LoroResult<()> {
+        self.handler.insert_with_ttl(key, value, ttl)
+    }
+
+    /// Delete the expired entries inserted by [LoroMap::insert_with_ttl].
+    ///
+    /// Return the deleted keys.
+    pub fn gc_expired(&self) -> LoroResult<Vec<String>> {
+        Ok(self
+            .handler
+            .gc_expired()?
+            .into_iter()
+            .map(|k| k.to_string())
+            .collect())
+    }
+
     /// Get the length of the map.
     pub fn len(&self) -> usize {
         self.handler.len()
@@ -880,6 +2147,26 @@ impl LoroMap {
         }
     }
 
+    /// Call `f` with the value at `key` without cloning it.
+    ///
+    /// This is cheaper than [LoroMap::get] on paths that read a lot. The doc is locked
+    /// while `f` runs, so `f` must not access the doc.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{LoroDoc, ValueRef};
+    /// let doc = LoroDoc::new();
+    /// let map = doc.get_map("m");
+    /// map.insert("name", "Alice").unwrap();
+    /// let len = map.get_ref("name", |v| v.and_then(|v| v.as_string().map(|s| s.len())));
+    /// assert_eq!(len, Some(5));
+    /// assert!(map.get_ref("age", |v| v.is_none()));
+    /// ```
+    pub fn get_ref<R>(&self, key: &str, f: impl FnOnce(Option<ValueRef<'_>>) -> R) -> R {
+        self.handler.get_ref(key, f)
+    }
+
     /// Insert a container with the given type at the given key.
     ///
     /// # Example
@@ -929,6 +2216,27 @@ impl Default for LoroMap {
     }
 }
 
+/// The unit of a position in a [LoroText], see [LoroText::convert_pos].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PosType {
+    /// UTF-8 byte index
+    Bytes,
+    /// Unicode code point index
+    Unicode,
+    /// UTF-16 code unit index
+    Utf16,
+}
+
+impl From<PosType> for loro_internal::handler::PosType {
+    fn from(value: PosType) -> Self {
+        match value {
+            PosType::Bytes => Self::Bytes,
+            PosType::Unicode => Self::Unicode,
+            PosType::Utf16 => Self::Utf16,
+        }
+    }
+}
+
 /// LoroText container. It's used to model plaintext/richtext.
 #[derive(Clone, Debug)]
 pub struct LoroText {
@@ -996,6 +2304,24 @@ impl LoroText {
         self.handler.iter(callback);
     }
 
+    /// Call `f` with an iterator over the chunks of the text, without copying them.
+    ///
+    /// The doc is locked while `f` runs, so `f` must not access the doc.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::LoroDoc;
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "Hello world").unwrap();
+    /// let len = text.as_chunks(|chunks| chunks.map(|c| c.len()).sum::<usize>());
+    /// assert_eq!(len, 11);
+    /// ```
+    pub fn as_chunks<R>(&self, f: impl FnOnce(&mut dyn Iterator<Item = &str>) -> R) -> R {
+        self.handler.as_chunks(f)
+    }
+
     /// Insert a string at the given unicode position.
     pub fn insert(&self, pos: usize, s: &str) -> LoroResult<()> {
         self.handler.insert(pos, s)
@@ -1051,12 +2377,114 @@ impl LoroText {
         self.handler.len_utf16()
     }
 
+    /// Insert a string at the given Unicode code point position.
+    pub fn insert_unicode(&self, pos: usize, s: &str) -> LoroResult<()> {
+        self.handler.insert_unicode(pos, s)
+    }
+
+    /// Delete `len` Unicode code points from the given Unicode position.
+    pub fn delete_unicode(&self, pos: usize, len: usize) -> LoroResult<()> {
+        self.handler.delete_unicode(pos, len)
+    }
+
+    /// Insert a string at the given UTF-16 position.
+    ///
+    /// It's useful for the editors in JS, whose positions are UTF-16 based. Return an error if
+    /// `pos` is inside a surrogate pair.
+    pub fn insert_utf16(&self, pos: usize, s: &str) -> LoroResult<()> {
+        self.handler.insert_utf16(pos, s)
+    }
+
+    /// Delete `len` UTF-16 code units from the given UTF-16 position.
+    pub fn delete_utf16(&self, pos: usize, len: usize) -> LoroResult<()> {
+        self.handler.delete_utf16(pos, len)
+    }
+
+    /// Convert a position between UTF-8, UTF-16 and Unicode indexes in O(log n).
+    ///
+    /// Return `None` if `index` is out of bound or it's not at the boundary of a code point.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{LoroDoc, PosType};
+    /// let doc = LoroDoc::new();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "a😀b").unwrap();
+    /// assert_eq!(text.convert_pos(2, PosType::Unicode, PosType::Utf16), Some(3));
+    /// assert_eq!(text.convert_pos(2, PosType::Utf16, PosType::Bytes), None);
+    /// ```
+    pub fn convert_pos(&self, index: usize, from: PosType, to: PosType) -> Option<usize> {
+        self.handler.convert_pos(index, from.into(), to.into())
+    }
+
+    /// Attribute each char in the Unicode range to the op that inserted it.
+    ///
+    /// It returns the id spans of the ops in the order of the text, with the adjacent
+    /// chars inserted by the same op merged into one span. The author of a span is
+    /// `span.peer`, whose display name is given by [`LoroDoc::peer_name`], and
+    /// [`LoroDoc::get_change`] gives the change that contains it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{IdSpan, LoroDoc};
+    /// let doc = LoroDoc::new();
+    /// doc.set_peer_id(1).unwrap();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "hello").unwrap();
+    /// doc.set_peer_id(2).unwrap();
+    /// text.insert(5, "!").unwrap();
+    /// assert_eq!(
+    ///     text.get_attribution(3..6).unwrap(),
+    ///     vec![IdSpan::new(1, 3, 5), IdSpan::new(2, 0, 1)]
+    /// );
+    /// ```
+    pub fn get_attribution(&self, range: Range<usize>) -> LoroResult<Vec<IdSpan>> {
+        self.handler.get_attribution(range)
+    }
+
+    /// Get the spans of text that overlap the Unicode range, with their ids and lengths.
+    ///
+    /// It only visits the part of the text in the range, so a renderer that draws the
+    /// visible part of a long text doesn't depend on the length of the text. The adjacent
+    /// chars inserted by consecutive ops of a peer are merged into one span. See
+    /// [LoroDoc::subscribe_text_range] to be notified of the edits in the range.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use loro::{LoroDoc, ID};
+    /// let doc = LoroDoc::new();
+    /// doc.set_peer_id(1).unwrap();
+    /// let text = doc.get_text("text");
+    /// text.insert(0, "hello").unwrap();
+    /// doc.set_peer_id(2).unwrap();
+    /// text.insert(5, " world").unwrap();
+    /// let spans = text.spans_in_range(3..8).unwrap();
+    /// assert_eq!(spans.len(), 2);
+    /// assert_eq!((spans[0].id, spans[0].pos, spans[0].len), (ID::new(1, 3), 3, 2));
+    /// assert_eq!(spans[0].text, "lo");
+    /// assert_eq!((spans[1].id, spans[1].pos, spans[1].len), (ID::new(2, 0), 5, 3));
+    /// assert_eq!(spans[1].text, " wo");
+    /// ```
+    pub fn spans_in_range(&self, range: Range<usize>) -> LoroResult<Vec<TextSpan>> {
+        self.handler.get_spans_in_range(range)
+    }
+
     /// Update the current text based on the provided text.
-    pub fn update(&self, text: &str) -> () {
-        self.handler.update(text);
+    ///
+    /// The text is unchanged if one of the edits fails, e.g. it's rejected by the
+    /// [pre-commit hook](LoroDoc::set_pre_commit_hook).
+    pub fn update(&self, text: &str) -> LoroResult<()> {
+        self.handler.update(text)
     }
 
     /// Apply a [delta](https://quilljs.com/docs/delta/) to the text container.
+    ///
+    /// The whole delta is applied in the current transaction. It's validated first, so if a
+    /// delete or a retain without attributes overruns the text, [LoroError::OutOfBound] is
+    /// returned and the text is left unchanged.
     pub fn apply_delta(&self, delta: &[TextDelta]) -> LoroResult<()> {
         self.handler.apply_delta(delta)
     }
@@ -1461,6 +2889,13 @@ impl LoroTree {
         self.handler.get_deep_value()
     }
 
+    /// Return the forest as nested nodes, ordered by their positions among the siblings.
+    ///
+    /// Each node is a map with `id`, `meta` and `children`.
+    pub fn get_nested_value(&self) -> LoroValue {
+        self.handler.get_nested_value()
+    }
+
     // This method is used for testing only.
     #[doc(hidden)]
     #[allow(non_snake_case)]
@@ -1538,6 +2973,14 @@ impl LoroMovableList {
         }
     }
 
+    /// Whether the container is attached to a document
+    ///
+    /// The edits on a detached container will not be persisted.
+    /// To attach the container to the document, please insert it into an attached container.
+    pub fn is_attached(&self) -> bool {
+        self.handler.is_attached()
+    }
+
     /// Get the container id.
     pub fn id(&self) -> ContainerID {
         self.handler.id().clone()
@@ -1619,6 +3062,14 @@ impl LoroMovableList {
         self.handler.mov(from, to)
     }
 
+    /// Iterate over the elements of the list.
+    pub fn for_each<I>(&self, f: I)
+    where
+        I: FnMut(ValueOrHandler),
+    {
+        self.handler.for_each(f)
+    }
+
     /// Insert a container at the given position.
     pub fn insert_container<C: ContainerTrait>(&self, pos: usize, child: C) -> LoroResult<C> {
         Ok(C::from_handler(
@@ -1919,6 +3370,20 @@ impl UndoManager {
         self.0.record_new_checkpoint(&doc.doc)
     }
 
+    /// Start a group of edits. The local changes committed before [UndoManager::group_end]
+    /// are merged into a single undo step.
+    ///
+    /// The pending changes of the doc are committed before the group starts.
+    /// Calling [UndoManager::undo] or [UndoManager::redo] ends the group.
+    pub fn group_start(&mut self, doc: &LoroDoc) -> LoroResult<()> {
+        self.0.group_start(&doc.doc)
+    }
+
+    /// End the group started by [UndoManager::group_start].
+    pub fn group_end(&mut self, doc: &LoroDoc) -> LoroResult<()> {
+        self.0.group_end(&doc.doc)
+    }
+
     /// Whether the undo manager can undo.
     pub fn can_undo(&self) -> bool {
         self.0.can_undo()
@@ -1945,6 +3410,15 @@ impl UndoManager {
         self.0.set_merge_interval(interval)
     }
 
+    /// Merge the consecutive local changes that have the same non-empty origin into one
+    /// undo step, whatever the time between them. The default value is `false`.
+    ///
+    /// The origin is set with [CommitOptions::origin]. The changes whose origin matches an
+    /// excluded prefix, see [UndoManager::add_exclude_origin_prefix], are never recorded.
+    pub fn set_merge_by_origin(&mut self, merge: bool) {
+        self.0.set_merge_by_origin(merge)
+    }
+
     /// Set the listener for push events.
     /// The listener will be called when a new undo/redo item is pushed into the stack.
     pub fn set_on_push(&mut self, on_push: Option<OnPush>) {
@@ -1956,4 +3430,119 @@ impl UndoManager {
     pub fn set_on_pop(&mut self, on_pop: Option<OnPop>) {
         self.0.set_on_pop(on_pop)
     }
+
+    /// The metadata of the step that the next [UndoManager::undo] will revert, as returned
+    /// by the on push listener, e.g. to show what will be undone.
+    ///
+    /// The cursors in it are transformed by the remote changes only when the step is
+    /// popped, so the on pop listener receives their up-to-date positions.
+    pub fn top_undo_meta(&self) -> Option<UndoItemMeta> {
+        self.0.top_undo_meta()
+    }
+
+    /// The metadata of the step that the next [UndoManager::redo] will reapply.
+    pub fn top_redo_meta(&self) -> Option<UndoItemMeta> {
+        self.0.top_redo_meta()
+    }
+}
+
+/// A queue of the local updates that haven't been acknowledged by the server yet.
+///
+/// It tracks the acks by version, so they can be lost, arrive out of order or overlap.
+/// It can be persisted with [OutboundQueue::encode] while the app is offline.
+///
+/// # Example
+///
+/// ```
+/// # use loro::{LoroDoc, OutboundQueue};
+/// let doc = LoroDoc::new();
+/// let server = LoroDoc::new();
+/// let mut queue = OutboundQueue::new();
+/// doc.get_text("text").insert(0, "Hello").unwrap();
+/// let first = queue.enqueue(&doc).unwrap().to.clone();
+/// doc.get_text("text").insert(5, " world").unwrap();
+/// queue.enqueue(&doc).unwrap();
+/// assert_eq!(queue.len(), 2);
+///
+/// // The first update is acked, the second one is lost
+/// queue.ack(&first);
+/// assert_eq!(queue.len(), 1);
+///
+/// // After a reconnect, the server reports what it has
+/// server.import(&doc.export_from(&Default::default())).unwrap();
+/// let update = queue.reconnect(&doc, &server.oplog_vv());
+/// assert!(update.is_none());
+/// assert!(queue.is_empty());
+/// ```
+#[derive(Debug, Clone, Default)]
+#[repr(transparent)]
+pub struct OutboundQueue(InnerOutboundQueue);
+
+impl OutboundQueue {
+    /// Create an empty queue.
+    pub fn new() -> Self {
+        Self(InnerOutboundQueue::new())
+    }
+
+    /// Create an empty queue for a doc that the server already has up to `acked`.
+    pub fn with_acked(acked: VersionVector) -> Self {
+        Self(InnerOutboundQueue::with_acked(acked))
+    }
+
+    /// The version the server is known to have.
+    pub fn acked(&self) -> &VersionVector {
+        self.0.acked()
+    }
+
+    /// The updates to send or resend, in the order they were exported.
+    pub fn pending(&self) -> impl Iterator<Item = &QueuedUpdate> {
+        self.0.pending()
+    }
+
+    /// The number of queued updates.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether all the queued updates have been acknowledged.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Export the ops of `doc` that are neither acknowledged nor queued, and queue them.
+    ///
+    /// The pending transaction is committed first. Returns `None` if there is nothing new.
+    pub fn enqueue(&mut self, doc: &LoroDoc) -> Option<&QueuedUpdate> {
+        self.0.enqueue(&doc.doc)
+    }
+
+    /// Record that the server has `version`, and drop the updates it covers.
+    ///
+    /// An update that is only partly covered stays in the queue.
+    pub fn ack(&mut self, version: &VersionVector) {
+        self.0.ack(version)
+    }
+
+    /// Replace the queue with the minimal update for a server that reports `server_version`
+    /// after a reconnect, and return it.
+    ///
+    /// The reported version replaces the acknowledged one, even if it's older. Returns
+    /// `None` if the server has all the ops of `doc`.
+    pub fn reconnect(
+        &mut self,
+        doc: &LoroDoc,
+        server_version: &VersionVector,
+    ) -> Option<&QueuedUpdate> {
+        self.0.reconnect(&doc.doc, server_version)
+    }
+
+    /// Encode the queue, e.g. to persist it while the app is offline.
+    pub fn encode(&self) -> Vec<u8> {
+        self.0.encode()
+    }
+
+    /// Decode a queue encoded by [OutboundQueue::encode].
+    pub fn decode(bytes: &[u8]) -> LoroResult<Self> {
+        InnerOutboundQueue::decode(bytes).map(Self)
+    }
 }
@@ -0,0 +1,124 @@
+//! Framework-agnostic handlers for hosting a [LoroDoc] behind HTTP endpoints.
+//!
+//! [DocEndpoint] implements the logic of three endpoints:
+//!
+//! - `GET snapshot`: [DocEndpoint::get_snapshot]
+//! - `POST update`: [DocEndpoint::post_update]
+//! - `GET updates?since=<vv>`: [DocEndpoint::get_updates]
+//!
+//! Each handler returns an [EndpointResponse] with a status code, an `ETag` and a body,
+//! which maps directly onto the response types of actix-web, axum or any other framework.
+//!
+//! The `ETag` is the version generation of the doc, i.e. the number of ops in its history.
+//! It only grows when new ops are imported, so a client sending it back in
+//! `If-None-Match` receives `304 Not Modified` until the doc changes.
+use crate::{LoroDoc, LoroError, VersionVector};
+
+/// The response of a [DocEndpoint] handler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndpointResponse {
+    /// The HTTP status code
+    pub status: u16,
+    /// The value of the `ETag` header
+    pub etag: String,
+    /// The response body. It's the error message if the status is `400`.
+    pub body: Vec<u8>,
+}
+
+/// Serve a [LoroDoc] through snapshot, update and incremental updates endpoints.
+#[derive(Debug)]
+pub struct DocEndpoint {
+    doc: LoroDoc,
+}
+
+impl DocEndpoint {
+    /// Create an endpoint serving the given doc.
+    pub fn new(doc: LoroDoc) -> Self {
+        Self { doc }
+    }
+
+    /// Get the doc served by the endpoint.
+    pub fn doc(&self) -> &LoroDoc {
+        &self.doc
+    }
+
+    /// The current `ETag` of the doc.
+    pub fn etag(&self) -> String {
+        format!("\"{}\"", self.doc.len_ops())
+    }
+
+    /// Handle `GET snapshot`.
+    ///
+    /// Return `304` if `if_none_match` matches the current `ETag`.
+    pub fn get_snapshot(&self, if_none_match: Option<&str>) -> EndpointResponse {
+        let etag = self.etag();
+        if self.is_not_modified(&etag, if_none_match) {
+            return Self::not_modified(etag);
+        }
+
+        EndpointResponse {
+            status: 200,
+            body: self.doc.export_snapshot(),
+            etag,
+        }
+    }
+
+    /// Handle `POST update`.
+    ///
+    /// The body can be any blob exported by [LoroDoc::export_from] or
+    /// [LoroDoc::export_snapshot]. Return `400` if it cannot be imported.
+    pub fn post_update(&self, body: &[u8]) -> EndpointResponse {
+        match self.doc.import(body) {
+            Ok(()) => EndpointResponse {
+                status: 204,
+                etag: self.etag(),
+                body: Vec::new(),
+            },
+            Err(e) => self.bad_request(e),
+        }
+    }
+
+    /// Handle `GET updates?since=<vv>`.
+    ///
+    /// `since` is a version vector encoded by [VersionVector::encode]. Decoding it from
+    /// the query string (e.g. base64) is left to the caller.
+    /// Return `304` if `if_none_match` matches the current `ETag`, and `400` if `since`
+    /// is malformed.
+    pub fn get_updates(&self, since: &[u8], if_none_match: Option<&str>) -> EndpointResponse {
+        let etag = self.etag();
+        if self.is_not_modified(&etag, if_none_match) {
+            return Self::not_modified(etag);
+        }
+
+        let since = match VersionVector::decode(since) {
+            Ok(vv) => vv,
+            Err(e) => return self.bad_request(e),
+        };
+
+        EndpointResponse {
+            status: 200,
+            body: self.doc.export_from(&since),
+            etag,
+        }
+    }
+
+    fn is_not_modified(&self, etag: &str, if_none_match: Option<&str>) -> bool {
+        if_none_match.is_some_and(|x| x.split(',').any(|tag| tag.trim() == etag))
+    }
+
+    fn not_modified(etag: String) -> EndpointResponse {
+        EndpointResponse {
+            status: 304,
+            etag,
+            body: Vec::new(),
+        }
+    }
+
+    fn bad_request(&self, error: LoroError) -> EndpointResponse {
+        EndpointResponse {
+            status: 400,
+            etag: self.etag(),
+            body: error.to_string().into_bytes(),
+        }
+    }
+}
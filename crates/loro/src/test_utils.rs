@@ -0,0 +1,135 @@
+//! A small DSL for the convergence scenarios in tests.
+//!
+//! Instead of exporting and importing the updates by hand, a scenario is written as the
+//! edits of each peer and the way they are synced:
+//!
+//! - [sync_pairs!](crate::sync_pairs) syncs each pair of docs both ways, e.g.
+//!   `sync_pairs!(a <-> b, b <-> c)`.
+//! - [partition!](crate::partition) syncs the docs within each group but not across the
+//!   groups, e.g. `partition!([a, b], [c])`.
+//! - [concurrent!](crate::concurrent) syncs the docs, runs the edits of each doc without
+//!   syncing in between, and then checks that the docs converge after syncing, e.g.
+//!   `concurrent! { a: text_a.insert(0, "A").unwrap(), b: text_b.insert(0, "B").unwrap() }`.
+//!
+//! ```
+//! use loro::{concurrent, partition, sync_pairs, LoroDoc};
+//!
+//! let (a, b, c) = (LoroDoc::new(), LoroDoc::new(), LoroDoc::new());
+//! concurrent! {
+//!     a: a.get_text("text").insert(0, "A").unwrap(),
+//!     b: b.get_text("text").insert(0, "B").unwrap(),
+//! }
+//!
+//! c.get_text("text").insert(0, "C").unwrap();
+//! partition!([a, b], [c]);
+//! assert_eq!(a.get_text("text").len_unicode(), 2);
+//! sync_pairs!(b <-> c, a <-> b);
+//! loro::test_utils::assert_converged(&[&a, &b, &c]);
+//! assert_eq!(a.get_text("text").len_unicode(), 3);
+//! ```
+//!
+//! It's only available with the `test-utils` feature.
+use crate::LoroDoc;
+
+/// Sync the docs with each other, so all of them have all the ops.
+pub fn sync_all(docs: &[&LoroDoc]) {
+    // After a doc exports its ops to all the others, the later ones have them too, so the
+    // last doc exports all the ops
+    for (i, from) in docs.iter().enumerate() {
+        for (j, to) in docs.iter().enumerate() {
+            if i != j {
+                to.import(&from.export_from(&to.oplog_vv())).unwrap();
+            }
+        }
+    }
+}
+
+/// Assert that the docs have the same ops and the same deep value.
+///
+/// # Panics
+///
+/// If any doc differs from the first one.
+pub fn assert_converged(docs: &[&LoroDoc]) {
+    let Some((first, rest)) = docs.split_first() else {
+        return;
+    };
+
+    for (i, doc) in rest.iter().enumerate() {
+        assert_eq!(
+            first.oplog_vv(),
+            doc.oplog_vv(),
+            "the version of doc {} differs from doc 0",
+            i + 1
+        );
+        assert_eq!(
+            first.get_deep_value(),
+            doc.get_deep_value(),
+            "the value of doc {} differs from doc 0",
+            i + 1
+        );
+    }
+}
+
+/// Sync each pair of docs both ways, in the given order.
+///
+/// ```
+/// # use loro::{sync_pairs, LoroDoc};
+/// let (a, b, c) = (LoroDoc::new(), LoroDoc::new(), LoroDoc::new());
+/// a.get_map("map").insert("a", 1).unwrap();
+/// sync_pairs!(a <-> b, b <-> c);
+/// assert_eq!(c.get_map("map").len(), 1);
+/// ```
+#[macro_export]
+macro_rules! sync_pairs {
+    ($($a:ident <-> $b:ident),+ $(,)?) => {
+        $($crate::test_utils::sync_all(&[&$a, &$b]);)+
+    };
+}
+
+/// Sync the docs within each group, but not across the groups.
+///
+/// ```
+/// # use loro::{partition, LoroDoc};
+/// let (a, b, c) = (LoroDoc::new(), LoroDoc::new(), LoroDoc::new());
+/// a.get_map("map").insert("a", 1).unwrap();
+/// partition!([a, b], [c]);
+/// assert_eq!(b.get_map("map").len(), 1);
+/// assert!(c.get_map("map").is_empty());
+/// ```
+#[macro_export]
+macro_rules! partition {
+    ($([$($doc:ident),+ $(,)?]),+ $(,)?) => {
+        $($crate::test_utils::sync_all(&[$(&$doc),+]);)+
+    };
+}
+
+/// Run the edits of each doc concurrently from a common version, then sync the docs and
+/// assert that they converge.
+///
+/// Each edit is an expression that can use its doc. The docs are synced before the edits,
+/// and each doc is committed after its edit.
+///
+/// ```
+/// # use loro::{concurrent, LoroDoc};
+/// let (a, b) = (LoroDoc::new(), LoroDoc::new());
+/// concurrent! {
+///     a: a.get_map("map").insert("key", "a").unwrap(),
+///     b: {
+///         b.get_map("map").insert("key", "b").unwrap();
+///         b.get_map("map").insert("other", "b").unwrap();
+///     },
+/// }
+/// assert_eq!(a.get_map("map").len(), 2);
+/// ```
+#[macro_export]
+macro_rules! concurrent {
+    ($($doc:ident : $edit:expr),+ $(,)?) => {{
+        $crate::test_utils::sync_all(&[$(&$doc),+]);
+        $(
+            $edit;
+            $doc.commit();
+        )+
+        $crate::test_utils::sync_all(&[$(&$doc),+]);
+        $crate::test_utils::assert_converged(&[$(&$doc),+]);
+    }};
+}
@@ -0,0 +1,271 @@
+//! Scripted sessions of a collaborative text editor built on the public API.
+//!
+//! Two peers, alice and bob, edit the same text. Their docs only talk to each other
+//! through channels of update blobs, like they would over a network, so the edits are only
+//! exchanged on `sync`. Each peer keeps its caret as a cursor, has its own undo stack and
+//! logs its events.
+//!
+//! Each line of a script is `<peer> <action>` (the peer is `a` or `b`) or a global command:
+//!
+//! ```text
+//! a|b insert <text>   insert at the caret
+//! a|b backspace       delete the char before the caret
+//! a|b left|right      move the caret
+//! a|b undo|redo       undo or redo the local edits of the peer
+//! sync                exchange the updates between the peers
+//! at <n>              check out alice's doc at the n-th version
+//! latest              go back to the latest version
+//! ```
+use std::sync::{
+    mpsc::{channel, Receiver, Sender},
+    Arc, Mutex,
+};
+
+use loro::{
+    cursor::{Cursor, Side},
+    event::DiffEvent,
+    Frontiers, LoroDoc, LoroResult, UndoManager, VersionVector, ID,
+};
+
+struct Peer {
+    name: &'static str,
+    doc: LoroDoc,
+    undo: UndoManager,
+    /// The caret is a cursor, so it stays at the same place when remote edits arrive
+    caret: Option<Cursor>,
+    outbox: Sender<Vec<u8>>,
+    inbox: Receiver<Vec<u8>>,
+    /// The version sent to the other peer last time
+    sent: VersionVector,
+    log: Arc<Mutex<Vec<String>>>,
+}
+
+impl Peer {
+    fn new(
+        name: &'static str,
+        peer_id: u64,
+        outbox: Sender<Vec<u8>>,
+        inbox: Receiver<Vec<u8>>,
+    ) -> LoroResult<Self> {
+        let doc = LoroDoc::new();
+        doc.set_peer_id(peer_id)?;
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let log_clone = log.clone();
+        doc.subscribe_root(Arc::new(move |e: DiffEvent| {
+            log_clone
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", name, e.triggered_by));
+        }));
+        Ok(Self {
+            name,
+            undo: UndoManager::new(&doc),
+            doc,
+            caret: None,
+            outbox,
+            inbox,
+            sent: Default::default(),
+            log,
+        })
+    }
+
+    fn text(&self) -> String {
+        self.doc.get_text("text").to_string()
+    }
+
+    fn caret_pos(&self) -> usize {
+        let len = self.doc.get_text("text").len_unicode();
+        self.caret
+            .as_ref()
+            .and_then(|c| self.doc.get_cursor_pos(c).ok())
+            .map_or(len, |x| x.current.pos)
+            .min(len)
+    }
+
+    fn set_caret(&mut self, pos: usize) {
+        self.caret = self.doc.get_text("text").get_cursor(pos, Side::Middle);
+    }
+
+    fn run(&mut self, action: &str, arg: &str) -> LoroResult<()> {
+        let text = self.doc.get_text("text");
+        let pos = self.caret_pos();
+        match action {
+            "insert" => {
+                text.insert(pos, arg)?;
+                self.doc.commit();
+                self.set_caret(pos + arg.chars().count());
+            }
+            "backspace" if pos > 0 => {
+                text.delete(pos - 1, 1)?;
+                self.doc.commit();
+                self.set_caret(pos - 1);
+            }
+            "backspace" => {}
+            "left" => self.set_caret(pos.saturating_sub(1)),
+            "right" => self.set_caret((pos + 1).min(text.len_unicode())),
+            "undo" => {
+                self.undo.undo(&self.doc)?;
+            }
+            "redo" => {
+                self.undo.redo(&self.doc)?;
+            }
+            _ => panic!("unknown action `{}` of {}", action, self.name),
+        }
+
+        Ok(())
+    }
+
+    fn send(&mut self) {
+        let vv = self.doc.oplog_vv();
+        if vv != self.sent {
+            self.outbox.send(self.doc.export_from(&self.sent)).unwrap();
+            self.sent = vv;
+        }
+    }
+
+    fn receive(&mut self) -> LoroResult<()> {
+        while let Ok(update) = self.inbox.try_recv() {
+            self.doc.import(&update)?;
+        }
+
+        Ok(())
+    }
+
+    fn take_log(&self) -> Vec<String> {
+        std::mem::take(&mut *self.log.lock().unwrap())
+    }
+}
+
+struct Session {
+    alice: Peer,
+    bob: Peer,
+}
+
+impl Session {
+    fn new() -> LoroResult<Self> {
+        let (to_bob, from_alice) = channel();
+        let (to_alice, from_bob) = channel();
+        Ok(Self {
+            alice: Peer::new("alice", 1, to_bob, from_bob)?,
+            bob: Peer::new("bob", 2, to_alice, from_alice)?,
+        })
+    }
+
+    /// Run the lines of `script` in order, and stop at the first error
+    fn run(&mut self, script: &[&str]) -> LoroResult<()> {
+        for line in script {
+            self.run_line(line)?;
+        }
+
+        Ok(())
+    }
+
+    fn run_line(&mut self, line: &str) -> LoroResult<()> {
+        let mut parts = line.trim().splitn(3, ' ');
+        let command = parts.next().unwrap_or_default();
+        let action = parts.next().unwrap_or_default();
+        let arg = parts.next().unwrap_or_default();
+        match command {
+            "a" => self.alice.run(action, arg),
+            "b" => self.bob.run(action, arg),
+            "sync" => {
+                self.alice.send();
+                self.bob.send();
+                self.alice.receive()?;
+                self.bob.receive()
+            }
+            "at" => {
+                let i: usize = action.parse().unwrap();
+                let version = versions(&self.alice.doc)[i].clone();
+                self.alice.doc.checkout(&version)
+            }
+            "latest" => {
+                self.alice.doc.checkout_to_latest();
+                Ok(())
+            }
+            _ => panic!("unknown command `{}`", command),
+        }
+    }
+
+    fn assert_converged(&self) {
+        assert_eq!(self.alice.doc.oplog_vv(), self.bob.doc.oplog_vv());
+        assert_eq!(self.alice.text(), self.bob.text());
+    }
+}
+
+/// The versions of the doc, one for the end of each change in causal order
+fn versions(doc: &LoroDoc) -> Vec<Frontiers> {
+    let mut vv = VersionVector::default();
+    doc.change_iter()
+        .map(|c| {
+            vv.extend_to_include_last_id(ID::new(c.id.peer, c.id.counter + c.len as i32 - 1));
+            doc.vv_to_frontiers(&vv)
+        })
+        .collect()
+}
+
+#[test]
+fn carets_follow_remote_edits() -> LoroResult<()> {
+    let mut session = Session::new()?;
+    session.run(&["a insert Hello", "sync"])?;
+    session.assert_converged();
+    assert_eq!(session.bob.take_log(), vec!["bob import"]);
+    assert_eq!(session.alice.take_log(), vec!["alice local"]);
+
+    // bob moves to the start while alice keeps typing at the end
+    session.run(&[
+        "b left",
+        "b left",
+        "b left",
+        "b left",
+        "b left",
+        "b insert >",
+        "a insert !",
+        "a backspace",
+        "a insert ?",
+    ])?;
+    assert_eq!(session.alice.text(), "Hello?");
+    assert_eq!(session.bob.text(), ">Hello");
+    session.run(&["sync"])?;
+    session.assert_converged();
+    assert_eq!(session.alice.text(), ">Hello?");
+    assert_eq!(session.alice.caret_pos(), 7);
+    assert_eq!(session.bob.caret_pos(), 1);
+    Ok(())
+}
+
+#[test]
+fn undo_only_reverts_local_edits() -> LoroResult<()> {
+    let mut session = Session::new()?;
+    session.run(&["a insert Hello", "sync", "b insert  world", "sync"])?;
+    session.assert_converged();
+    assert_eq!(session.alice.text(), "Hello world");
+
+    session.run(&["a undo", "sync"])?;
+    session.assert_converged();
+    assert_eq!(session.bob.text(), " world");
+
+    session.run(&["a redo", "sync"])?;
+    session.assert_converged();
+    assert_eq!(session.bob.text(), "Hello world");
+    Ok(())
+}
+
+#[test]
+fn time_travel_is_read_only() -> LoroResult<()> {
+    let mut session = Session::new()?;
+    session.run(&["a insert Hello", "sync", "b insert !", "sync"])?;
+    assert_eq!(versions(&session.alice.doc).len(), 2);
+
+    session.run(&["at 0"])?;
+    assert!(session.alice.doc.is_detached());
+    assert_eq!(session.alice.text(), "Hello");
+    assert_eq!(session.alice.take_log().last().unwrap(), "alice checkout");
+    assert!(session.run(&["a insert x"]).is_err());
+
+    session.run(&["latest"])?;
+    assert!(!session.alice.doc.is_detached());
+    assert_eq!(session.alice.text(), "Hello!");
+    session.assert_converged();
+    Ok(())
+}
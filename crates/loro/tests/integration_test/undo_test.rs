@@ -1637,3 +1637,119 @@ fn undo_transform_cursor_position() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn undo_group() -> LoroResult<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    let mut undo = UndoManager::new(&doc);
+    let text = doc.get_text("text");
+    text.insert(0, "Hello")?;
+    doc.commit();
+    undo.group_start(&doc)?;
+    assert!(undo.group_start(&doc).is_err());
+    text.insert(5, " ")?;
+    doc.commit();
+    text.insert(6, "World")?;
+    doc.commit();
+    undo.group_end(&doc)?;
+    text.insert(11, "!")?;
+    doc.commit();
+
+    // A remote edit in between is preserved
+    let remote = LoroDoc::new();
+    remote.set_peer_id(2)?;
+    remote.import(&doc.export_snapshot())?;
+    remote.get_text("text").insert(0, ">")?;
+    remote.commit();
+    doc.import(&remote.export_from(&doc.oplog_vv()))?;
+
+    undo.undo(&doc)?;
+    assert_eq!(text.to_string(), ">Hello World");
+    undo.undo(&doc)?;
+    assert_eq!(text.to_string(), ">Hello");
+    undo.undo(&doc)?;
+    assert_eq!(text.to_string(), ">");
+    undo.redo(&doc)?;
+    assert_eq!(text.to_string(), ">Hello");
+    Ok(())
+}
+
+#[test]
+fn undo_merge_by_origin() -> LoroResult<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    let mut undo = UndoManager::new(&doc);
+    undo.set_merge_by_origin(true);
+    undo.add_exclude_origin_prefix("sys:");
+    let text = doc.get_text("text");
+    text.insert(0, "Hel")?;
+    doc.commit_with(CommitOptions::new().origin("typing"));
+    text.insert(3, "lo")?;
+    doc.commit_with(CommitOptions::new().origin("typing"));
+    text.insert(5, " world")?;
+    doc.commit_with(CommitOptions::new().origin("typing"));
+    // An excluded change isn't undone
+    text.insert(0, "> ")?;
+    doc.commit_with(CommitOptions::new().origin("sys:normalize"));
+    // The changes without origin aren't merged
+    text.insert(13, "!")?;
+    doc.commit();
+    text.insert(14, "?")?;
+    doc.commit();
+    assert_eq!(text.to_string(), "> Hello world!?");
+
+    undo.undo(&doc)?;
+    assert_eq!(text.to_string(), "> Hello world!");
+    undo.undo(&doc)?;
+    assert_eq!(text.to_string(), "> Hello world");
+    undo.undo(&doc)?;
+    assert_eq!(text.to_string(), "> ");
+    assert!(!undo.can_undo());
+    undo.redo(&doc)?;
+    assert_eq!(text.to_string(), "> Hello world");
+    Ok(())
+}
+
+#[test]
+fn undo_top_item_meta() -> LoroResult<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    let mut undo = UndoManager::new(&doc);
+    let text = doc.get_text("text");
+    // The selection of the app, attached to each step
+    let selection = Arc::new(Mutex::new(String::from("0..0")));
+    let selection_clone = selection.clone();
+    undo.set_on_push(Some(Box::new(move |_, _| {
+        let mut meta = UndoItemMeta::new();
+        meta.set_value(selection_clone.lock().unwrap().as_str().into());
+        meta
+    })));
+    let restored = Arc::new(Mutex::new(LoroValue::Null));
+    let restored_clone = restored.clone();
+    undo.set_on_pop(Some(Box::new(move |_, _, meta| {
+        *restored_clone.lock().unwrap() = meta.value;
+    })));
+    assert!(undo.top_undo_meta().is_none());
+
+    text.insert(0, "Hello")?;
+    doc.commit();
+    *selection.lock().unwrap() = "5..5".into();
+    text.insert(5, " world")?;
+    doc.commit();
+    assert_eq!(undo.top_undo_meta().unwrap().value, LoroValue::from("5..5"));
+    assert!(undo.top_redo_meta().is_none());
+
+    *selection.lock().unwrap() = "11..11".into();
+    undo.undo(&doc)?;
+    assert_eq!(*restored.lock().unwrap(), LoroValue::from("5..5"));
+    assert_eq!(undo.top_undo_meta().unwrap().value, LoroValue::from("0..0"));
+    assert_eq!(
+        undo.top_redo_meta().unwrap().value,
+        LoroValue::from("11..11")
+    );
+    undo.redo(&doc)?;
+    assert_eq!(*restored.lock().unwrap(), LoroValue::from("11..11"));
+    assert!(undo.top_redo_meta().is_none());
+    Ok(())
+}
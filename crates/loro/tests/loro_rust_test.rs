@@ -1,11 +1,22 @@
 use std::{
     cmp::Ordering,
-    sync::{atomic::AtomicBool, Arc},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize},
+        Arc,
+    },
 };
 
 use loro::{
-    awareness::Awareness, FrontiersNotIncluded, LoroDoc, LoroError, LoroList, LoroMap, LoroText,
-    ToJson,
+    awareness::{Awareness, AwarenessChange},
+    concurrent,
+    cursor::{Cursor, Side},
+    event::{EventQueue, OverflowPolicy, Point, TextEditTracker, VisibleRange},
+    json_patch::{self, JsonPatchOp},
+    lease::{sub_peer, PeerLease},
+    partition, sync_pairs,
+    test_utils::assert_converged,
+    CommitOptions, ContainerTrait, DocBuilder, FrontiersNotIncluded, IdSpan, LoroDoc, LoroError,
+    LoroList, LoroMap, LoroText, LoroValue, OutboundQueue, ToJson,
 };
 use loro_internal::{handler::TextDelta, id::ID, vv, LoroResult};
 use serde_json::json;
@@ -104,6 +115,34 @@ fn movable_list() -> Result<(), LoroError> {
     Ok(())
 }
 
+#[test]
+fn movable_list_concurrent_moves() -> Result<(), LoroError> {
+    let doc_a = DocBuilder::new()
+        .peer_id(1)
+        .movable_list("list", |list| {
+            list.push(1)?;
+            list.push(2)?;
+            list.push(3)
+        })
+        .try_build()?;
+    let list_a = doc_a.get_movable_list("list");
+    let doc_b = LoroDoc::new();
+    doc_b.set_peer_id(2)?;
+    let list_b = doc_b.get_movable_list("list");
+
+    // Both peers move the same element to different places
+    concurrent! {
+        doc_a: list_a.mov(0, 2)?,
+        doc_b: list_b.mov(0, 1)?,
+    }
+    let mut values = vec![];
+    list_a.for_each(|v| values.push(v.into_value().unwrap().into_i64().unwrap()));
+    assert_eq!(values.len(), 3);
+    values.sort();
+    assert_eq!(values, vec![1, 2, 3]);
+    Ok(())
+}
+
 #[test]
 fn list_checkout() -> Result<(), LoroError> {
     let doc = LoroDoc::new();
@@ -406,6 +445,43 @@ fn tree() {
     )
 }
 
+#[test]
+fn tree_nested_value() {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1).unwrap();
+    let tree = doc.get_tree("tree");
+    let root = tree.create(None).unwrap();
+    let child_a = tree.create(root).unwrap();
+    let child_b = tree.create(root).unwrap();
+    tree.mov_before(child_b, child_a).unwrap();
+    tree.get_meta(child_b).unwrap().insert("name", "b").unwrap();
+    assert_eq!(
+        tree.get_nested_value().to_json_value(),
+        json!([{
+            "id": "0@1",
+            "meta": {},
+            "children": [
+                { "id": "2@1", "meta": { "name": "b" }, "children": [] },
+                { "id": "1@1", "meta": {}, "children": [] }
+            ]
+        }])
+    );
+
+    // Concurrent moves that would create a cycle are resolved to a tree
+    let other_root = tree.create(None).unwrap();
+    doc.commit();
+    let doc_b = LoroDoc::new();
+    doc_b.set_peer_id(2).unwrap();
+    concurrent! {
+        doc: tree.mov(root, other_root).unwrap(),
+        doc_b: doc_b.get_tree("tree").mov(other_root, root).unwrap(),
+    }
+    let value = tree.get_nested_value();
+    assert_eq!(value, doc_b.get_tree("tree").get_nested_value());
+    assert_eq!(value.as_list().unwrap().len(), 1);
+    assert_eq!(tree.nodes().len(), 4);
+}
+
 fn check_sync_send(_doc: impl Sync + Send) {}
 
 #[test]
@@ -617,6 +693,316 @@ fn decode_import_blob_meta() -> LoroResult<()> {
     Ok(())
 }
 
+#[test]
+fn missing_deps_for_update_blob() -> LoroResult<()> {
+    let doc_1 = DocBuilder::new()
+        .peer_id(1)
+        .text("text", "123")
+        .try_build()?;
+    let doc_2 = LoroDoc::new();
+    doc_2.set_peer_id(2)?;
+    doc_2.import(&doc_1.export_snapshot())?;
+    doc_2.get_text("text").insert(0, "abc")?;
+    doc_2.commit();
+    assert_eq!(
+        doc_2.deps_of(ID::new(2, 0)),
+        Some(vec![ID::new(1, 2)].into())
+    );
+    assert_eq!(doc_2.deps_of(ID::new(2, 1)), Some(ID::new(2, 0).into()));
+    assert_eq!(doc_2.deps_of(ID::new(3, 0)), None);
+
+    let update = doc_2.export_from(&doc_1.oplog_vv());
+    let meta = LoroDoc::decode_import_blob_meta(&update)?;
+    let empty = LoroDoc::new();
+    assert_eq!(empty.missing_deps_for(&meta), vec![IdSpan::new(1, 0, 3)]);
+    let partial = LoroDoc::new();
+    partial.import(&doc_1.export_from(&Default::default()))?;
+    assert!(partial.missing_deps_for(&meta).is_empty());
+
+    let update = doc_2.export_from(&vv!(1 => 1));
+    let meta = LoroDoc::decode_import_blob_meta(&update)?;
+    assert_eq!(empty.missing_deps_for(&meta), vec![IdSpan::new(1, 0, 1)]);
+    Ok(())
+}
+
+#[test]
+fn vv_cmp_with_update_bytes() -> LoroResult<()> {
+    let doc_1 = DocBuilder::new()
+        .peer_id(1)
+        .text("text", "123")
+        .try_build()?;
+    let doc_2 = LoroDoc::new();
+    doc_2.set_peer_id(2)?;
+    doc_2.import(&doc_1.export_snapshot())?;
+    doc_2.get_text("text").insert(0, "abc")?;
+    doc_2.commit();
+    assert_eq!(
+        doc_1.oplog_vv().partial_cmp(&doc_2.oplog_vv()),
+        Some(Ordering::Less)
+    );
+    assert_eq!(
+        doc_2.frontiers_to_vv(&doc_2.state_frontiers()),
+        Some(doc_2.oplog_vv())
+    );
+
+    let update = doc_2.export_from(&doc_1.oplog_vv());
+    assert_eq!(
+        doc_1.vv_cmp_with_update_bytes(&update)?,
+        Some(Ordering::Less)
+    );
+    assert_eq!(
+        doc_2.vv_cmp_with_update_bytes(&update)?,
+        Some(Ordering::Equal)
+    );
+    let snapshot = doc_2.export_snapshot();
+    assert_eq!(
+        doc_1.vv_cmp_with_update_bytes(&snapshot)?,
+        Some(Ordering::Less)
+    );
+
+    // The blob only contains the ops of peer 2, but it depends on the ops of peer 1
+    let empty = LoroDoc::new();
+    assert_eq!(
+        empty.vv_cmp_with_update_bytes(&update)?,
+        Some(Ordering::Less)
+    );
+
+    doc_1.get_text("text").insert(0, "x")?;
+    doc_1.commit();
+    assert_eq!(doc_1.vv_cmp_with_update_bytes(&update)?, None);
+    doc_1.import(&update)?;
+    assert_eq!(
+        doc_1.vv_cmp_with_update_bytes(&update)?,
+        Some(Ordering::Greater)
+    );
+    assert!(doc_1.vv_cmp_with_update_bytes(&[1, 2, 3]).is_err());
+    Ok(())
+}
+
+#[test]
+fn uncommitted_and_unsynced_changes() -> LoroResult<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    assert!(!doc.has_uncommitted_txn());
+    assert_eq!(doc.uncommitted_ops_len(), 0);
+    doc.get_text("text").insert(0, "hello")?;
+    doc.get_map("map").insert("key", 1)?;
+    assert!(doc.has_uncommitted_txn());
+    assert_eq!(doc.uncommitted_ops_len(), 6);
+    assert!(doc.unsynced_since(&Default::default()).is_empty());
+
+    doc.commit();
+    assert!(!doc.has_uncommitted_txn());
+    assert_eq!(doc.uncommitted_ops_len(), 0);
+    assert_eq!(
+        doc.unsynced_since(&Default::default()),
+        vec![IdSpan::new(1, 0, 6)]
+    );
+
+    let synced = doc.oplog_vv();
+    assert!(doc.unsynced_since(&synced).is_empty());
+    doc.get_text("text").insert(5, " world")?;
+    doc.commit();
+    assert_eq!(doc.unsynced_since(&synced), vec![IdSpan::new(1, 6, 12)]);
+
+    // Ops from other peers are unsynced too
+    let other = DocBuilder::new().peer_id(2).text("text", "a").try_build()?;
+    doc.import(&other.export_from(&Default::default()))?;
+    let mut spans = doc.unsynced_since(&synced);
+    spans.sort_by_key(|x| x.peer);
+    assert_eq!(spans, vec![IdSpan::new(1, 6, 12), IdSpan::new(2, 0, 1)]);
+    Ok(())
+}
+
+#[test]
+fn auto_commit_ops_limit() -> LoroResult<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    let count = Arc::new(AtomicUsize::new(0));
+    let count_cloned = count.clone();
+    doc.subscribe_root(Arc::new(move |e| {
+        assert!(e.triggered_by.is_local());
+        count_cloned.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }));
+    let text = doc.get_text("text");
+    text.insert(0, "a")?;
+    text.insert(1, "b")?;
+    assert_eq!(doc.uncommitted_ops_len(), 2);
+
+    doc.set_auto_commit_ops_limit(Some(3))?;
+    text.insert(2, "c")?;
+    assert!(!doc.has_uncommitted_txn());
+    assert_eq!(count.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+    // A single edit is never split
+    text.insert(3, "defg")?;
+    assert!(!doc.has_uncommitted_txn());
+    assert_eq!(count.load(std::sync::atomic::Ordering::Relaxed), 2);
+    text.insert(7, "h")?;
+    assert_eq!(doc.uncommitted_ops_len(), 1);
+
+    doc.set_auto_commit_ops_limit(None)?;
+    for i in 8..20 {
+        text.insert(i, "x")?;
+    }
+    assert_eq!(doc.uncommitted_ops_len(), 13);
+    assert_eq!(count.load(std::sync::atomic::Ordering::Relaxed), 2);
+    doc.commit();
+    assert_eq!(count.load(std::sync::atomic::Ordering::Relaxed), 3);
+    assert_eq!(doc.oplog_vv(), vv!(1 => 20));
+    Ok(())
+}
+
+#[test]
+fn container_last_modified() -> LoroResult<()> {
+    let doc_a = LoroDoc::new();
+    doc_a.set_peer_id(1)?;
+    let list_a = doc_a.get_list("list");
+    assert!(doc_a.container_last_modified(&list_a.id()).is_none());
+    list_a.insert(0, 1)?;
+    list_a.insert(1, 2)?;
+    doc_a.commit_with(CommitOptions::new().timestamp(10));
+    let (frontiers, timestamp) = doc_a.container_last_modified(&list_a.id()).unwrap();
+    assert_eq!(frontiers, vec![ID::new(1, 1)].into());
+    assert_eq!(timestamp, 10);
+
+    let doc_b = LoroDoc::new();
+    doc_b.set_peer_id(2)?;
+    doc_b.import(&doc_a.export_snapshot())?;
+    doc_b.get_list("list").insert(0, 3)?;
+    doc_b.commit_with(CommitOptions::new().timestamp(30));
+    doc_a.get_text("text").insert(0, "a")?;
+    list_a.insert(0, 4)?;
+    doc_a.commit_with(CommitOptions::new().timestamp(20));
+
+    // concurrent edits are all in the frontiers
+    doc_a.import(&doc_b.export_from(&doc_a.oplog_vv()))?;
+    let (frontiers, timestamp) = doc_a.container_last_modified(&list_a.id()).unwrap();
+    assert_eq!(frontiers, vec![ID::new(1, 3), ID::new(2, 0)].into());
+    assert_eq!(timestamp, 30);
+    let text_id = doc_a.get_text("text").id();
+    assert_eq!(
+        doc_a.container_last_modified(&text_id),
+        Some((vec![ID::new(1, 2)].into(), 20))
+    );
+
+    // an edit that has seen both of them overrides them
+    list_a.delete(0, 1)?;
+    doc_a.commit_with(CommitOptions::new().timestamp(40));
+    let (frontiers, timestamp) = doc_a.container_last_modified(&list_a.id()).unwrap();
+    assert_eq!(frontiers, vec![ID::new(1, 4)].into());
+    assert_eq!(timestamp, 40);
+
+    let doc_c = LoroDoc::new();
+    doc_c.import(&doc_a.export_snapshot())?;
+    assert_eq!(
+        doc_c.container_last_modified(&list_a.id()),
+        doc_a.container_last_modified(&list_a.id())
+    );
+    Ok(())
+}
+
+#[test]
+fn map_insert_with_ttl() -> LoroResult<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    doc.set_record_timestamp(true);
+    let map = doc.get_map("map");
+    map.insert("plain", 0)?;
+    doc.commit_with(CommitOptions::new().timestamp(100));
+    // expires at 160
+    map.insert_with_ttl("a", 1, 50)?;
+    doc.commit_with(CommitOptions::new().timestamp(110));
+    assert_eq!(map.get_value().to_json_value(), json!({"plain": 0, "a": 1}));
+    assert_eq!(map.len(), 2);
+    // the TTL is stored outside of the value
+    assert_eq!(
+        doc.get_deep_value().to_json_value(),
+        json!({"map": {"plain": 0, "a": 1}})
+    );
+
+    let doc_b = LoroDoc::new();
+    doc_b.set_peer_id(2)?;
+    doc_b.set_record_timestamp(true);
+    doc_b.import(&doc.export_snapshot())?;
+    let map_b = doc_b.get_map("map");
+    // any change moves the clock
+    doc_b.get_text("text").insert(0, "x")?;
+    doc_b.commit_with(CommitOptions::new().timestamp(200));
+    assert_eq!(map_b.get_value().to_json_value(), json!({"plain": 0}));
+    assert!(map_b.get("a").is_none());
+    assert_eq!(map_b.len(), 1);
+
+    // overwriting the key drops its TTL
+    map_b.insert_with_ttl("b", 2, 10)?;
+    doc_b.commit_with(CommitOptions::new().timestamp(210));
+    map_b.insert("b", 3)?;
+    doc_b.commit_with(CommitOptions::new().timestamp(300));
+    assert_eq!(map_b.get("b").unwrap().left().unwrap(), 3.into());
+
+    doc.import(&doc_b.export_from(&doc.oplog_vv()))?;
+    assert_eq!(map.get_value(), map_b.get_value());
+    assert_eq!(map.gc_expired()?, vec!["a".to_string()]);
+    doc.commit();
+    doc_b.import(&doc.export_from(&doc_b.oplog_vv()))?;
+    assert_eq!(
+        doc_b.get_deep_value().to_json_value(),
+        json!({"map": {"plain": 0, "b": 3}, "text": "x"})
+    );
+    Ok(())
+}
+
+#[test]
+fn map_insert_with_ttl_needs_timestamps() {
+    let doc = LoroDoc::new();
+    let map = doc.get_map("map");
+    assert!(map.insert_with_ttl("a", 1, 50).is_err());
+    assert!(map.get("a").is_none());
+}
+
+#[test]
+fn map_with_nested_containers() -> LoroResult<()> {
+    let doc_a = LoroDoc::new();
+    doc_a.set_peer_id(1)?;
+    let map = doc_a.get_map("map");
+    map.insert("title", "poll")?;
+    let text = map.insert_container("text", LoroText::new())?;
+    text.insert(0, "hi")?;
+    let list = map.insert_container("options", LoroList::new())?;
+    list.insert(0, "yes")?;
+    let nested = list.insert_container(1, LoroMap::new())?;
+    nested.insert("label", "no")?;
+    map.insert("tmp", 1)?;
+    map.delete("tmp")?;
+    doc_a.commit();
+    assert_eq!(
+        doc_a.get_deep_value().to_json_value(),
+        json!({"map": {"title": "poll", "text": "hi", "options": ["yes", {"label": "no"}]}})
+    );
+
+    // The deep value is a plain tree that can be serialized by serde
+    let value = map.get_deep_value();
+    let json = serde_json::to_string(&value).unwrap();
+    let parsed: LoroValue = serde_json::from_str(&json).unwrap();
+    assert_eq!(parsed, value);
+
+    // Concurrent writes to the same key are resolved by last-write-wins,
+    // the one with the larger peer id wins on the same lamport
+    let doc_b = LoroDoc::new();
+    doc_b.set_peer_id(2)?;
+    let map_b = doc_b.get_map("map");
+    concurrent! {
+        doc_a: map.insert_container("text", LoroMap::new())?.insert("from", "a")?,
+        doc_b: map_b.insert_container("text", LoroList::new())?.insert(0, "from b")?,
+    }
+    assert_eq!(
+        map.get_deep_value().to_json_value()["text"],
+        json!(["from b"])
+    );
+    assert!(map.get("text").unwrap().right().unwrap().is_list());
+    Ok(())
+}
+
 #[test]
 fn init_example() {
     // create meta/users/0/new_user/{name: string, bio: Text}
@@ -748,6 +1134,37 @@ fn get_cursor() {
     assert_eq!(pos_info.current.pos, 5); // should not be affected by rich text mark
 }
 
+#[test]
+fn selection_survives_remote_edits() {
+    let doc_a = DocBuilder::new()
+        .peer_id(1)
+        .text("text", "Hello World")
+        .build();
+    let text_a = doc_a.get_text("text");
+    let doc_b = LoroDoc::new();
+    doc_b.set_peer_id(2).unwrap();
+    doc_b.import(&doc_a.export_snapshot()).unwrap();
+
+    // Select "World" on doc_a
+    let start = text_a.get_cursor(6, Side::Left).unwrap();
+    let end = text_a.get_cursor(11, Side::Right).unwrap();
+
+    let text_b = doc_b.get_text("text");
+    text_b.insert(0, ">> ").unwrap();
+    text_b.insert(9, "big ").unwrap();
+    doc_b.commit();
+    doc_a.import(&doc_b.export_from(&doc_a.oplog_vv())).unwrap();
+    assert_eq!(text_a.to_string(), ">> Hello big World");
+    let start_pos = doc_a.get_cursor_pos(&start).unwrap().current.pos;
+    let end_pos = doc_a.get_cursor_pos(&end).unwrap().current.pos;
+    assert_eq!(&text_a.to_string()[start_pos..end_pos], "World");
+
+    // The cursors are stable across docs after encoding
+    let start = Cursor::decode(&start.encode()).unwrap();
+    doc_b.import(&doc_a.export_from(&doc_b.oplog_vv())).unwrap();
+    assert_eq!(doc_b.get_cursor_pos(&start).unwrap().current.pos, start_pos);
+}
+
 #[test]
 fn get_cursor_at_the_end() {
     let doc = LoroDoc::new();
@@ -855,6 +1272,528 @@ fn awareness() {
     assert_eq!(b.get_all_states().get(&2).map(|x| x.state.clone()), None);
 }
 
+#[test]
+fn awareness_change_callback() {
+    let changes = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let changes_cp = changes.clone();
+    let mut a = Awareness::new(1, 30_000);
+    a.set_local_state(1);
+    let mut b = Awareness::new(2, 0);
+    b.set_on_change(Some(Arc::new(move |change: &AwarenessChange| {
+        changes_cp.lock().unwrap().push(change.clone());
+    })));
+    b.set_local_state("b");
+    b.apply(&a.encode_all());
+    // applying the same state again doesn't change anything
+    b.apply(&a.encode_all());
+    a.set_local_state(2);
+    b.apply(&a.encode(&[1]));
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    let mut removed = b.remove_outdated();
+    removed.sort();
+    assert_eq!(removed, vec![1, 2]);
+
+    let changes = changes.lock().unwrap();
+    assert_eq!(changes.len(), 4);
+    assert_eq!(changes[0].added, vec![2]);
+    assert!(changes[0].by_local);
+    assert_eq!(changes[1].added, vec![1]);
+    assert!(!changes[1].by_local);
+    assert_eq!(changes[2].updated, vec![1]);
+    assert_eq!(changes[3].removed.len(), 2);
+}
+
+#[test]
+fn peer_lease() {
+    let mut a = PeerLease::new(1, 30_000);
+    let mut b = PeerLease::new(1, 30_000);
+    assert_eq!(a.peer(), 1);
+    assert_eq!(b.peer(), 1);
+    assert!(!a.is_acquired());
+
+    // Exactly one of them keeps the base peer
+    let a_moved = a.apply(&b.heartbeat()).unwrap();
+    let b_moved = b.apply(&a.heartbeat()).unwrap();
+    assert!(a_moved != b_moved);
+    assert!(!a.apply(&b.heartbeat()).unwrap());
+    assert!(!b.apply(&a.heartbeat()).unwrap());
+    let mut peers = [a.peer(), b.peer()];
+    peers.sort();
+    let mut expected = [1, sub_peer(1, 1)];
+    expected.sort();
+    assert_eq!(peers, expected);
+
+    // A later one takes the next free slot
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    let mut c = PeerLease::new(1, 30_000);
+    c.apply(&a.heartbeat()).unwrap();
+    c.apply(&b.heartbeat()).unwrap();
+    assert_eq!(c.slot(), 2);
+    assert_eq!(c.peer(), sub_peer(1, 2));
+
+    // Released slots are reused, and other base peers are ignored
+    let (winner, loser) = if a.slot() == 0 {
+        (&a, &mut b)
+    } else {
+        (&b, &mut a)
+    };
+    let mut d = PeerLease::new(1, 30_000);
+    d.apply(&loser.heartbeat()).unwrap();
+    d.apply(&loser.release()).unwrap();
+    assert_eq!(d.get_all_holders().len(), 0);
+    assert!(d.apply(&winner.heartbeat()).unwrap());
+    assert_eq!(d.slot(), 1);
+    assert!(!loser.apply(&PeerLease::new(2, 30_000).heartbeat()).unwrap());
+    assert!(loser.apply(&[255, 255]).is_err());
+    assert_ne!(sub_peer(1, 1), sub_peer(2, 1));
+}
+
+#[test]
+fn outbound_queue_overlapping_acks() -> LoroResult<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    let mut queue = OutboundQueue::new();
+    let text = doc.get_text("text");
+    let mut versions = Vec::new();
+    let mut first_update = Vec::new();
+    for i in 0..3 {
+        text.insert(0, &i.to_string())?;
+        let update = queue.enqueue(&doc).unwrap();
+        if i == 0 {
+            first_update = update.bytes.clone();
+        } else {
+            assert_eq!(update.from, versions[i - 1]);
+        }
+        versions.push(update.to.clone());
+    }
+    assert!(queue.enqueue(&doc).is_none());
+    assert_eq!(queue.len(), 3);
+
+    // The acks arrive out of order
+    queue.ack(&versions[1]);
+    assert_eq!(queue.len(), 1);
+    queue.ack(&versions[0]);
+    assert_eq!(queue.len(), 1);
+    assert_eq!(queue.acked(), &versions[1]);
+
+    // The queue survives a restart
+    let mut queue = OutboundQueue::decode(&queue.encode())?;
+    assert_eq!(queue.pending().next().unwrap().to, versions[2]);
+
+    // The server was restored from a backup that only has the first update, so the
+    // resubmission starts before the acked version
+    let server = LoroDoc::new();
+    server.import(&first_update)?;
+    let update = queue.reconnect(&doc, &server.oplog_vv()).unwrap();
+    assert_eq!(update.from, versions[0]);
+    assert_eq!(update.deps, doc.vv_to_frontiers(&versions[0]));
+    server.import(&update.bytes)?;
+    assert_eq!(server.get_text("text").to_string(), "210");
+    assert_eq!(queue.len(), 1);
+    queue.ack(&doc.oplog_vv());
+    assert!(queue.is_empty());
+    Ok(())
+}
+
+#[test]
+fn views_at_several_versions() -> LoroResult<()> {
+    let doc = LoroDoc::new();
+    let map = doc.get_map("map");
+    let mut versions = Vec::new();
+    for i in 0..3 {
+        map.insert("key", i)?;
+        doc.commit();
+        versions.push(doc.oplog_frontiers());
+    }
+
+    let views = versions
+        .iter()
+        .map(|v| doc.view_at(v))
+        .collect::<LoroResult<Vec<_>>>()?;
+    map.insert("key", 3)?;
+    doc.commit();
+    for (i, view) in views.iter().enumerate() {
+        assert_eq!(view.frontiers(), versions[i]);
+        assert_eq!(
+            view.get_deep_value().to_json_value(),
+            json!({"map": {"key": i}})
+        );
+        assert!(view.get_map("map").insert("key", 10).is_err());
+    }
+
+    assert_eq!(map.get("key").unwrap().left().unwrap(), 3.into());
+    assert!(matches!(
+        doc.view_at(&ID::new(123, 0).into()),
+        Err(LoroError::FrontiersNotFound(_))
+    ));
+    Ok(())
+}
+
+#[test]
+fn doc_builder() -> LoroResult<()> {
+    let doc = DocBuilder::new()
+        .peer_id(7)
+        .text("text", "Hello")
+        .text("text", " world")
+        .movable_list("list", |l| {
+            l.push(1)?;
+            l.push(2)
+        })
+        .tree("tree", |t| t.create(None))
+        .edit(|doc| doc.get_map("map").insert("key", "value"))
+        .build();
+    assert_eq!(doc.peer_id(), 7);
+    assert_eq!(doc.len_changes(), 1);
+    assert_eq!(doc.get_text("text").to_string(), "Hello world");
+    assert_eq!(doc.get_tree("tree").nodes().len(), 1);
+    assert_eq!(
+        doc.get_by_str_path("map/key")
+            .unwrap()
+            .into_value()
+            .unwrap(),
+        "value".into()
+    );
+
+    let err = DocBuilder::new()
+        .text_with("text", |t| t.delete(0, 1))
+        .try_build()
+        .unwrap_err();
+    assert!(matches!(err, LoroError::OutOfBound { .. }));
+    Ok(())
+}
+
+#[test]
+fn text_edit_tracker_counts_bytes() -> LoroResult<()> {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    text.insert(0, "héllo\nwörld")?;
+    doc.commit();
+    let mut tracker = TextEditTracker::new(&text);
+    let deltas = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let deltas_clone = deltas.clone();
+    let _sub = doc.subscribe(
+        &text.id(),
+        Arc::new(move |e| {
+            for d in e.events {
+                deltas_clone
+                    .lock()
+                    .unwrap()
+                    .push(d.diff.as_text().unwrap().clone());
+            }
+        }),
+    );
+    // Replace "wö" with "W"
+    text.delete(6, 2)?;
+    text.insert(6, "W")?;
+    doc.commit();
+
+    let delta = deltas.lock().unwrap().pop().unwrap();
+    let edits = tracker.apply(&delta);
+    assert_eq!(tracker.text(), text.to_string());
+    let start = "héllo\n".len();
+    let start_position = Point { row: 1, column: 0 };
+    assert_eq!(edits.len(), 2);
+    let (insert, delete) = if edits[0].old_end_byte == edits[0].start_byte {
+        (edits[0], edits[1])
+    } else {
+        (edits[1], edits[0])
+    };
+    assert_eq!(insert.start_byte, start);
+    assert_eq!(insert.new_end_byte, start + 1);
+    assert_eq!(insert.start_position, start_position);
+    assert_eq!(insert.new_end_position, Point { row: 1, column: 1 });
+    assert_eq!(delete.old_end_byte - delete.start_byte, "wö".len());
+    assert_eq!(delete.new_end_byte, delete.start_byte);
+    Ok(())
+}
+
+#[test]
+fn spans_and_edits_in_visible_range() -> LoroResult<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    let text = doc.get_text("text");
+    text.insert(0, &"0123456789".repeat(10))?;
+    doc.commit();
+
+    let range = VisibleRange::new(40..60);
+    let spans = text.spans_in_range(range.get())?;
+    assert_eq!(spans.len(), 1);
+    assert_eq!(
+        (spans[0].id, spans[0].pos, spans[0].len),
+        (ID::new(1, 40), 40, 20)
+    );
+    let visible = Arc::new(std::sync::Mutex::new(spans[0].text.clone()));
+    let visible_clone = visible.clone();
+    let count = Arc::new(AtomicUsize::new(0));
+    let count_clone = count.clone();
+    let _sub = doc.subscribe_text_range(
+        &text,
+        &range,
+        Arc::new(move |delta| {
+            count_clone.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            let mut visible = visible_clone.lock().unwrap();
+            let mut chars: Vec<char> = visible.chars().collect();
+            let mut pos = 0;
+            for item in delta {
+                match item {
+                    TextDelta::Retain { retain, .. } => pos += retain,
+                    TextDelta::Insert { insert, .. } => {
+                        let len = insert.chars().count();
+                        chars.splice(pos..pos, insert.chars());
+                        pos += len;
+                    }
+                    TextDelta::Delete { delete } => {
+                        chars.drain(pos..pos + delete);
+                    }
+                }
+            }
+            *visible = chars.into_iter().collect();
+        }),
+    );
+
+    // A remote peer edits before, in and after the range
+    let doc2 = LoroDoc::new();
+    doc2.set_peer_id(2)?;
+    doc2.import(&doc.export_snapshot())?;
+    let text2 = doc2.get_text("text");
+    text2.insert(10, "ab")?;
+    text2.delete(50, 2)?;
+    text2.insert(50, "xyz")?;
+    text2.insert(95, "cd")?;
+    doc2.commit();
+    doc.import(&doc2.export_from(&doc.oplog_vv()))?;
+    assert_eq!(count.load(std::sync::atomic::Ordering::Relaxed), 1);
+    assert_eq!(range.get(), 42..63);
+    assert_eq!(*visible.lock().unwrap(), text.slice(42, 63)?);
+
+    let spans = text.spans_in_range(range.get())?;
+    assert_eq!(spans.iter().map(|s| s.len).sum::<usize>(), 21);
+    assert_eq!(
+        spans.iter().map(|s| s.text.as_str()).collect::<String>(),
+        text.slice(42, 63)?
+    );
+    // The deletion took the counters 2 and 3
+    assert_eq!(spans[1].id, ID::new(2, 4));
+    assert_eq!((spans[1].pos, spans[1].text.as_str()), (50, "xyz"));
+
+    // Style changes in the range are notified too
+    text.mark(40..45, "bold", true)?;
+    doc.commit();
+    assert_eq!(count.load(std::sync::atomic::Ordering::Relaxed), 2);
+
+    // After scrolling, the edits in the old range aren't notified
+    range.set(0..5);
+    *visible.lock().unwrap() = text.slice(0, 5)?;
+    text.insert(50, "!")?;
+    doc.commit();
+    assert_eq!(count.load(std::sync::atomic::Ordering::Relaxed), 2);
+    text.insert(5, "?")?;
+    text.delete(0, 1)?;
+    doc.commit();
+    assert_eq!(count.load(std::sync::atomic::Ordering::Relaxed), 3);
+    assert_eq!(range.get(), 0..4);
+    assert_eq!(*visible.lock().unwrap(), text.slice(0, 4)?);
+    assert!(text.spans_in_range(0..200).is_err());
+    Ok(())
+}
+
+#[test]
+fn system_map_is_hidden_and_synced() -> LoroResult<()> {
+    let doc = LoroDoc::new();
+    doc.set_peer_id(1)?;
+    doc.get_map("settings").insert("theme", "dark")?;
+    doc.get_system_map().insert("schema_version", 1)?;
+    doc.commit();
+    let v1 = doc.oplog_frontiers();
+    assert_eq!(
+        doc.get_deep_value().to_json_value(),
+        json!({"settings": {"theme": "dark"}})
+    );
+
+    // Concurrent flags are merged like any other map
+    let doc2 = LoroDoc::new();
+    doc2.set_peer_id(2)?;
+    doc2.import(&doc.export_snapshot())?;
+    doc.get_system_map().insert("migrated_tags", true)?;
+    doc2.get_system_map().insert("schema_version", 2)?;
+    doc.import(&doc2.export_from(&doc.oplog_vv()))?;
+    doc2.import(&doc.export_from(&doc2.oplog_vv()))?;
+    let flags = json!({"schema_version": 2, "migrated_tags": true});
+    assert_eq!(doc.get_system_map().get_deep_value().to_json_value(), flags);
+    assert_eq!(
+        doc2.get_system_map().get_deep_value().to_json_value(),
+        flags
+    );
+    assert_eq!(doc.get_deep_value(), doc2.get_deep_value());
+
+    let view = doc.view_at(&v1)?;
+    assert_eq!(
+        view.get_system_map().get_deep_value().to_json_value(),
+        json!({"schema_version": 1})
+    );
+    Ok(())
+}
+
+#[test]
+fn event_queue_overflow() {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("text");
+    let queue = EventQueue::new(1, OverflowPolicy::Coalesce);
+    let _sub = doc.subscribe_queue(Some(&text.id()), &queue);
+    for s in ["a", "b", "c"] {
+        text.insert(text.len_unicode(), s).unwrap();
+        doc.commit();
+    }
+
+    assert_eq!(queue.len(), 1);
+    assert!(!queue.take_needs_resync());
+    let inserted = queue.pop_with(|event| {
+        let mut ans = String::new();
+        for item in event.events[0].diff.as_text().unwrap() {
+            match item {
+                TextDelta::Insert { insert, .. } => ans.push_str(insert),
+                _ => unreachable!(),
+            }
+        }
+        ans
+    });
+    assert_eq!(inserted.as_deref(), Some("abc"));
+    assert!(queue.is_empty());
+
+    // The thread that emits the events waits for the consumer
+    let queue = EventQueue::new(1, OverflowPolicy::Block);
+    let _sub = doc.subscribe_queue(None, &queue);
+    let consumer = {
+        let queue = queue.clone();
+        std::thread::spawn(move || {
+            let mut n = 0;
+            while n < 5 {
+                match queue.pop_with(|_| ()) {
+                    Some(_) => n += 1,
+                    None => std::thread::yield_now(),
+                }
+            }
+        })
+    };
+    for _ in 0..5 {
+        text.insert(0, "x").unwrap();
+        doc.commit();
+        assert!(queue.len() <= 1);
+    }
+
+    consumer.join().unwrap();
+    assert_eq!(queue.peak_len(), 1);
+}
+
+#[test]
+fn json_patch() {
+    let doc = DocBuilder::new()
+        .text("body", "hello")
+        .map("meta", |m| m.insert("title", "x"))
+        .list("tags", |l| l.push("a"))
+        .build();
+    let patches = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let patches_clone = patches.clone();
+    let _sub = doc.subscribe_json_patch(Arc::new(move |patch| {
+        patches_clone.lock().unwrap().push(patch.to_vec());
+    }));
+    doc.get_list("tags").insert(0, "b").unwrap();
+    doc.get_map("meta").delete("title").unwrap();
+    doc.get_text("body").insert(5, "!").unwrap();
+    doc.commit();
+
+    let patches = std::mem::take(&mut *patches.lock().unwrap());
+    assert_eq!(patches.len(), 1);
+    let mut ops = serde_json::to_value(&patches[0])
+        .unwrap()
+        .as_array()
+        .unwrap()
+        .clone();
+    ops.sort_by_key(|x| x["path"].as_str().unwrap().to_string());
+    assert_eq!(
+        serde_json::Value::Array(ops),
+        json!([
+            {"op": "replace", "path": "/body", "value": "hello!"},
+            {"op": "remove", "path": "/meta/title"},
+            {"op": "add", "path": "/tags/0", "value": "b"},
+        ])
+    );
+    assert_eq!(
+        json_patch::sse_message(&[JsonPatchOp::Remove { path: "/a".into() }]),
+        "event: patch\ndata: [{\"op\":\"remove\",\"path\":\"/a\"}]\n\n"
+    );
+
+    let patch: Vec<JsonPatchOp> = serde_json::from_value(json!([
+        {"op": "replace", "path": "/body", "value": "hi!"},
+        {"op": "add", "path": "/tags/-", "value": "c"},
+        {"op": "add", "path": "/meta/title", "value": {"en": "y"}},
+    ]))
+    .unwrap();
+    doc.apply_json_patch(&patch).unwrap();
+    let value = json!({"body": "hi!", "meta": {"title": {"en": "y"}}, "tags": ["b", "a", "c"]});
+    assert_eq!(doc.get_deep_value().to_json_value(), value);
+
+    // Nothing is applied if an op is invalid
+    let patch: Vec<JsonPatchOp> = serde_json::from_value(json!([
+        {"op": "replace", "path": "/meta/title/en", "value": "z"},
+        {"op": "remove", "path": "/tags/3"},
+    ]))
+    .unwrap();
+    assert!(doc.apply_json_patch(&patch).is_err());
+    assert_eq!(doc.get_deep_value().to_json_value(), value);
+}
+
+#[test]
+fn insert_containers_in_one_op() {
+    let one_by_one = LoroDoc::new();
+    let list = one_by_one.get_list("list");
+    for i in 0..100 {
+        let text = list.insert_container(i, LoroText::new()).unwrap();
+        text.insert(0, "x").unwrap();
+    }
+    one_by_one.commit();
+
+    let batched = LoroDoc::new();
+    let children = (0..100).map(|_| LoroText::new().to_container()).collect();
+    for text in batched
+        .get_list("list")
+        .insert_containers(0, children)
+        .unwrap()
+    {
+        text.as_text().unwrap().insert(0, "x").unwrap();
+    }
+    batched.commit();
+
+    assert_eq!(batched.get_deep_value(), one_by_one.get_deep_value());
+    let updates = batched.export_from(&Default::default());
+    assert!(updates.len() < one_by_one.export_from(&Default::default()).len());
+    let doc = LoroDoc::new();
+    doc.import(&updates).unwrap();
+    assert_eq!(doc.get_deep_value(), batched.get_deep_value());
+    assert_eq!(
+        doc.get_list("list").get_value(),
+        batched.get_list("list").get_value()
+    );
+
+    // The adjacent children of a detached list are created by one op too
+    let template = LoroList::new();
+    template.push(1).unwrap();
+    for s in ["a", "b"] {
+        let text = template.push_container(LoroText::new()).unwrap();
+        text.insert(0, s).unwrap();
+    }
+    template.push(2).unwrap();
+    let map = doc.get_map("map");
+    map.insert_container("template", template).unwrap();
+    doc.commit();
+    let copy = LoroDoc::new();
+    copy.import(&doc.export_snapshot()).unwrap();
+    assert_eq!(
+        copy.get_map("map").get_deep_value().to_json_value(),
+        json!({"template": [1, "a", "b", 2]})
+    );
+}
+
 #[test]
 // https://github.com/loro-dev/loro/issues/397
 fn len_and_is_empty_inconsistency() {
@@ -871,3 +1810,144 @@ fn len_and_is_empty_inconsistency() {
     assert_eq!(map.len(), 0);
     assert!(map.is_empty());
 }
+
+#[cfg(feature = "server")]
+#[test]
+fn server_endpoints() -> LoroResult<()> {
+    use loro::server::DocEndpoint;
+
+    let endpoint = DocEndpoint::new(LoroDoc::new());
+    let snapshot = endpoint.get_snapshot(None);
+    assert_eq!(snapshot.status, 200);
+    assert_eq!(endpoint.get_snapshot(Some(&snapshot.etag)).status, 304);
+
+    let client = LoroDoc::new();
+    client.get_text("text").insert(0, "Hello")?;
+    client.commit();
+    let res = endpoint.post_update(&client.export_from(&Default::default()));
+    assert_eq!(res.status, 204);
+    assert_ne!(res.etag, snapshot.etag);
+    assert_eq!(endpoint.get_snapshot(Some(&snapshot.etag)).status, 200);
+    assert_eq!(endpoint.post_update(b"not an update").status, 400);
+
+    let other = LoroDoc::new();
+    let updates = endpoint.get_updates(&other.oplog_vv().encode(), None);
+    assert_eq!(updates.status, 200);
+    other.import(&updates.body)?;
+    assert_eq!(other.get_text("text").to_string(), "Hello");
+    assert_eq!(
+        endpoint
+            .get_updates(&other.oplog_vv().encode(), Some(&updates.etag))
+            .status,
+        304
+    );
+    assert_eq!(endpoint.get_updates(&[255, 255], None).status, 400);
+    Ok(())
+}
+
+#[test]
+fn conformance_vectors() -> LoroResult<()> {
+    use loro::conformance::{canonical_vectors, verify_all, ConformanceError, TestVector};
+
+    // The published vectors must still be imported, and the current exports must be the
+    // same bytes, so that a change of the encoding doesn't go unnoticed
+    let LoroValue::List(published) =
+        LoroValue::from_json(include_str!("fixtures/conformance_vectors.json"))
+    else {
+        unreachable!()
+    };
+    let published = published
+        .iter()
+        .map(|x| TestVector::from_value(x).unwrap())
+        .collect::<Vec<_>>();
+    verify_all(&published).unwrap();
+    let vectors = canonical_vectors()?;
+    assert_eq!(vectors, published);
+
+    let published: Vec<LoroValue> = vectors.iter().map(|x| x.to_value()).collect();
+    let published = LoroValue::List(published.into());
+    let LoroValue::List(parsed) = LoroValue::from_json(&published.to_json()) else {
+        unreachable!()
+    };
+    for (parsed, vector) in parsed.iter().zip(vectors.iter()) {
+        assert_eq!(&TestVector::from_value(parsed).unwrap(), vector);
+    }
+
+    let mut broken = vectors[0].clone();
+    broken.expected_value = LoroValue::Null;
+    assert!(matches!(
+        broken.verify(),
+        Err(ConformanceError::ValueMismatch { .. })
+    ));
+    broken.updates[0].truncate(10);
+    assert!(matches!(
+        broken.verify(),
+        Err(ConformanceError::Import { .. })
+    ));
+    Ok(())
+}
+
+#[test]
+fn convergence_dsl() {
+    let (a, b, c) = (LoroDoc::new(), LoroDoc::new(), LoroDoc::new());
+    a.set_peer_id(1).unwrap();
+    b.set_peer_id(2).unwrap();
+    c.set_peer_id(3).unwrap();
+    concurrent! {
+        a: a.get_text("text").insert(0, "a").unwrap(),
+        b: b.get_text("text").insert(0, "b").unwrap(),
+        c: c.get_list("list").push(1).unwrap(),
+    }
+    let merged = a.get_text("text").to_string();
+    assert_eq!(merged.len(), 2);
+
+    // c is partitioned from a and b
+    partition!([a, b], [c]);
+    a.get_text("text").insert(0, "x").unwrap();
+    c.get_text("text").delete(0, 2).unwrap();
+    partition!([a, b], [c]);
+    assert_eq!(b.get_text("text").to_string(), format!("x{merged}"));
+    assert_eq!(c.get_text("text").to_string(), "");
+
+    sync_pairs!(a <-> c, b <-> c);
+    assert_converged(&[&a, &b, &c]);
+    assert_eq!(a.get_text("text").to_string(), "x");
+}
+
+#[test]
+fn error_code_and_category() {
+    use loro::ErrorCategory;
+
+    let doc = LoroDoc::new();
+    let err = doc.get_text("text").insert(1, "a").unwrap_err();
+    assert_eq!(err.code(), 12);
+    assert_eq!(err.category(), ErrorCategory::InvalidArgument);
+    let err = doc.import(&[0, 1, 2]).unwrap_err();
+    assert_eq!(err.category(), ErrorCategory::Decode);
+    let err = doc.checkout(&ID::new(1, 0).into()).unwrap_err();
+    assert_eq!(err.code(), 18);
+    assert_eq!(err.category().as_str(), "not_found");
+    let tree = doc.get_tree("tree");
+    let node = tree.create(None).unwrap();
+    let child = tree.create(node).unwrap();
+    let err = tree.mov(node, child).unwrap_err();
+    assert_eq!(err.code(), 101);
+    assert_eq!(err.category(), ErrorCategory::Tree);
+}
+
+#[test]
+fn roots_are_created_by_their_first_edit() -> LoroResult<()> {
+    let doc = LoroDoc::new();
+    let text = doc.get_text("a");
+    assert_eq!(text.to_string(), "");
+    assert_eq!(doc.get_deep_value().to_json_value(), json!({}));
+    let remote = LoroDoc::new();
+    remote.import(&doc.export_snapshot())?;
+    assert_eq!(remote.get_deep_value().to_json_value(), json!({}));
+
+    text.insert(0, "x")?;
+    text.delete(0, 1)?;
+    doc.commit();
+    assert_eq!(doc.get_deep_value().to_json_value(), json!({"a": ""}));
+    Ok(())
+}